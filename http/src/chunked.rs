@@ -0,0 +1,460 @@
+// 导入所需的库
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+pub use std::sync::mpsc::TryRecvError;
+use std::sync::{Arc, Mutex};
+
+// 流式响应写入器默认的缓冲大小，攒够后作为一个分块发送
+const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+// 生产者和连接之间最多排队的分块数，队列满时写入会阻塞（背压）
+const DEFAULT_QUEUE_LEN: usize = 16;
+
+// 单个分块大小的上限，防止恶意的长度字段
+const MAX_CHUNK_SIZE: usize = 64 * 1024 * 1024;
+
+// 分块长度行和单个尾部头部的最大字节数，不完整的行等待更多数据时会重新查找 CRLF，需要限制长度
+const MAX_LINE_LEN: usize = 8 * 1024;
+
+// 尾部头部的最大总字节数
+const MAX_TRAILERS_SIZE: usize = 32 * 1024;
+
+// 尾部头部（名称，值）
+pub type Trailers = Vec<(String, String)>;
+
+// 请求的尾部头部，服务器附加到请求的 extensions 上
+// 尾部头部在消息体之后才到达，不能合并到请求头中（RFC 9110 第 6.5.1 节），否则可以在事后覆盖 Host、Authorization 等请求头
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestTrailers(pub Trailers);
+
+// 写入一个分块：十六进制长度、CRLF、数据、CRLF
+pub fn write_chunk(w: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    if data.is_empty() {
+        // 长度为 0 的分块表示结束，不能用来发送空数据
+        return Ok(());
+    }
+    write!(w, "{:x}\r\n", data.len())?;
+    w.write_all(data)?;
+    w.write_all(b"\r\n")
+}
+
+// 写入最后一个分块和尾部头部
+pub fn write_last_chunk(w: &mut impl Write, trailers: &[(String, String)]) -> io::Result<()> {
+    w.write_all(b"0\r\n")?;
+    for (name, value) in trailers {
+        write!(w, "{}: {}\r\n", name, value)?;
+    }
+    w.write_all(b"\r\n")
+}
+
+// 分块编码解析错误
+#[derive(Debug, PartialEq)]
+pub enum ChunkedError {
+    InvalidSize,    // 分块长度不是合法的十六进制数或过大
+    InvalidFraming, // 分块数据后缺少 CRLF
+    InvalidTrailer, // 尾部头部格式错误或过大
+    TooLarge,       // 消息体超过解码器的上限
+}
+
+impl fmt::Display for ChunkedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChunkedError::InvalidSize => write!(f, "invalid chunk size"),
+            ChunkedError::InvalidFraming => write!(f, "missing CRLF after chunk data"),
+            ChunkedError::InvalidTrailer => write!(f, "invalid trailer field"),
+            ChunkedError::TooLarge => write!(f, "chunked body too large"),
+        }
+    }
+}
+
+impl std::error::Error for ChunkedError {}
+
+// 解码完成的分块消息体
+#[derive(Debug, PartialEq)]
+pub struct DecodedBody {
+    pub body: Vec<u8>,                    // 拼接后的消息体
+    pub trailers: Trailers,               // 尾部头部
+    pub consumed: usize,                  // 消耗的输入字节数，之后是下一个消息
+}
+
+// 解码分块编码的消息体
+// 数据还不完整时返回 Ok(None)，调用方应读取更多数据后重试；逐步收到数据时使用 ChunkedDecoder 避免重复解析
+pub fn decode(data: &[u8]) -> Result<Option<DecodedBody>, ChunkedError> {
+    let mut decoder = ChunkedDecoder::new();
+    decoder.feed(data)?;
+    Ok(decoder.finish())
+}
+
+// 增量解码的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DecodeState {
+    Size,        // 等待分块长度行
+    Data(usize), // 分块数据，还剩多少字节
+    DataEnd,     // 分块数据之后的 CRLF
+    Trailers,    // 尾部头部，以空行结束
+    Done,        // 消息体已结束
+}
+
+// 增量的分块编码解码器：每次传入尚未消耗的数据，已解析的部分不会重复解析
+// 消息体超过 max_body 时在读取分块数据之前返回 TooLarge
+#[derive(Debug)]
+pub struct ChunkedDecoder {
+    state: DecodeState,     // 当前解析的位置
+    body: Vec<u8>,          // 已解码的消息体
+    trailers: Trailers,     // 已解析的尾部头部
+    trailers_size: usize,   // 尾部头部的总字节数
+    consumed: usize,        // 已消耗的输入字节总数
+    max_body: usize,        // 消息体的最大字节数
+}
+
+impl Default for ChunkedDecoder {
+    fn default() -> Self {
+        ChunkedDecoder {
+            state: DecodeState::Size,
+            body: Vec::new(),
+            trailers: Vec::new(),
+            trailers_size: 0,
+            consumed: 0,
+            max_body: usize::MAX,
+        }
+    }
+}
+
+impl ChunkedDecoder {
+    // 创建不限制消息体大小的解码器
+    pub fn new() -> Self {
+        ChunkedDecoder::default()
+    }
+
+    // 设置消息体的最大字节数
+    pub fn max_body(mut self, max_body: usize) -> Self {
+        self.max_body = max_body;
+        self
+    }
+
+    // 解析 data 中尽可能多的数据，返回消耗的字节数
+    // 调用方丢弃消耗的字节，下次传入剩余的数据和新收到的数据；不完整的长度行和尾部头部不消耗
+    pub fn feed(&mut self, data: &[u8]) -> Result<usize, ChunkedError> {
+        let mut pos = 0;
+        loop {
+            match self.state {
+                DecodeState::Size => {
+                    // 分块长度行，忽略分块扩展（";" 之后的部分）
+                    let Some(line) = next_line(&data[pos..], ChunkedError::InvalidSize)? else { break };
+                    let text = std::str::from_utf8(line).map_err(|_| ChunkedError::InvalidSize)?;
+                    let size_str = text.split(';').next().unwrap_or_default().trim();
+                    if size_str.is_empty() || !size_str.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Err(ChunkedError::InvalidSize);
+                    }
+                    let size = usize::from_str_radix(size_str, 16).map_err(|_| ChunkedError::InvalidSize)?;
+                    if size > MAX_CHUNK_SIZE {
+                        return Err(ChunkedError::InvalidSize);
+                    }
+                    if size > self.max_body - self.body.len() {
+                        return Err(ChunkedError::TooLarge);
+                    }
+                    pos += line.len() + 2;
+                    self.state = if size == 0 { DecodeState::Trailers } else { DecodeState::Data(size) };
+                }
+                DecodeState::Data(remaining) => {
+                    let available = remaining.min(data.len() - pos);
+                    if available == 0 {
+                        break;
+                    }
+                    self.body.extend_from_slice(&data[pos..pos + available]);
+                    pos += available;
+                    self.state = if available == remaining { DecodeState::DataEnd } else { DecodeState::Data(remaining - available) };
+                }
+                DecodeState::DataEnd => {
+                    if data.len() - pos < 2 {
+                        break;
+                    }
+                    if &data[pos..pos + 2] != b"\r\n" {
+                        return Err(ChunkedError::InvalidFraming);
+                    }
+                    pos += 2;
+                    self.state = DecodeState::Size;
+                }
+                DecodeState::Trailers => {
+                    let Some(line) = next_line(&data[pos..], ChunkedError::InvalidTrailer)? else { break };
+                    pos += line.len() + 2;
+                    if line.is_empty() {
+                        self.state = DecodeState::Done;
+                        continue;
+                    }
+                    self.trailers_size += line.len();
+                    if self.trailers_size > MAX_TRAILERS_SIZE {
+                        return Err(ChunkedError::InvalidTrailer);
+                    }
+                    let line = std::str::from_utf8(line).map_err(|_| ChunkedError::InvalidTrailer)?;
+                    let (name, value) = line.split_once(':').ok_or(ChunkedError::InvalidTrailer)?;
+                    if name.is_empty() || name.trim() != name {
+                        return Err(ChunkedError::InvalidTrailer);
+                    }
+                    self.trailers.push((name.to_string(), value.trim().to_string()));
+                }
+                DecodeState::Done => break,
+            }
+        }
+        self.consumed += pos;
+        Ok(pos)
+    }
+
+    // 消息体是否已经结束
+    pub fn is_done(&self) -> bool {
+        self.state == DecodeState::Done
+    }
+
+    // 取出解码的消息体，还没有结束时返回 None
+    pub fn finish(self) -> Option<DecodedBody> {
+        self.is_done().then_some(DecodedBody {
+            body: self.body,
+            trailers: self.trailers,
+            consumed: self.consumed,
+        })
+    }
+}
+
+// 读取一行（不含 CRLF），还没有完整的一行时返回 None；超过 MAX_LINE_LEN 时返回 error
+fn next_line(data: &[u8], error: ChunkedError) -> Result<Option<&[u8]>, ChunkedError> {
+    match find_crlf(&data[..data.len().min(MAX_LINE_LEN + 2)]) {
+        Some(end) => Ok(Some(&data[..end])),
+        None if data.len() > MAX_LINE_LEN => Err(error),
+        None => Ok(None),
+    }
+}
+
+// 查找 CRLF 的位置
+fn find_crlf(data: &[u8]) -> Option<usize> {
+    data.windows(2).position(|w| w == b"\r\n")
+}
+
+// 流式消息体中的一项
+#[derive(Debug, PartialEq)]
+pub enum Chunk {
+    Data(Vec<u8>),                 // 一段数据
+    End(Trailers),                 // 结束，附带尾部头部
+}
+
+// 创建流式消息体：处理器通过 BodyWriter 写入，连接从 BodyStream 读取后发送
+pub fn body_channel() -> (BodyWriter, BodyStream) {
+    let (sender, receiver) = mpsc::sync_channel(DEFAULT_QUEUE_LEN);
+    let writer = BodyWriter {
+        sender,
+        buffer: Vec::new(),
+        chunk_size: DEFAULT_CHUNK_SIZE,
+        trailers: Vec::new(),
+    };
+    let stream = BodyStream {
+        receiver: Arc::new(Mutex::new(receiver)),
+    };
+    (writer, stream)
+}
+
+// 流式消息体的写入端
+// 数据先写入缓冲区，攒够一个分块或调用 flush 时发送；连接来不及发送时写入会阻塞
+pub struct BodyWriter {
+    sender: SyncSender<Chunk>,         // 发送到连接的队列
+    buffer: Vec<u8>,                   // 尚未发送的数据
+    chunk_size: usize,                 // 攒够多少字节发送一次
+    trailers: Trailers,                // 结束时发送的尾部头部
+}
+
+impl BodyWriter {
+    // 设置攒够多少字节发送一个分块
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    // 添加一个尾部头部，在消息体结束后发送，HTTP/1.0 不支持尾部头部会丢弃
+    pub fn trailer(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.trailers.push((name.into(), value.into()));
+    }
+
+    // 发送剩余数据和尾部头部，结束消息体
+    // 没有调用 finish 就丢弃写入器时，连接会被中断，客户端可以据此发现消息体不完整
+    pub fn finish(mut self) -> io::Result<()> {
+        self.flush()?;
+        let trailers = std::mem::take(&mut self.trailers);
+        self.send(Chunk::End(trailers))
+    }
+
+    // 把一项发送到队列，连接已经关闭时返回 BrokenPipe
+    fn send(&self, chunk: Chunk) -> io::Result<()> {
+        self.sender
+            .send(chunk)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "response stream closed"))
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= self.chunk_size {
+            self.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let data = std::mem::take(&mut self.buffer);
+        self.send(Chunk::Data(data))
+    }
+}
+
+// 流式消息体的读取端，克隆的副本共享同一个队列
+#[derive(Clone)]
+pub struct BodyStream {
+    receiver: Arc<Mutex<Receiver<Chunk>>>,
+}
+
+impl BodyStream {
+    // 阻塞直到下一项可用，写入器没有结束就被丢弃时返回 None
+    pub fn recv(&self) -> Option<Chunk> {
+        self.receiver.lock().unwrap_or_else(|e| e.into_inner()).recv().ok()
+    }
+
+    // 不阻塞地读取下一项，暂时没有数据时返回 Empty，写入器没有结束就被丢弃时返回 Disconnected
+    pub fn try_recv(&self) -> Result<Chunk, TryRecvError> {
+        self.receiver.lock().unwrap_or_else(|e| e.into_inner()).try_recv()
+    }
+
+    // 读取全部数据，不支持流式发送的场景使用
+    pub fn collect(&self) -> io::Result<(Vec<u8>, Trailers)> {
+        let mut body = Vec::new();
+        loop {
+            match self.recv() {
+                Some(Chunk::Data(data)) => body.extend_from_slice(&data),
+                Some(Chunk::End(trailers)) => return Ok((body, trailers)),
+                None => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "response stream aborted")),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for BodyStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("BodyStream")
+    }
+}
+
+impl PartialEq for BodyStream {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.receiver, &other.receiver)
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // 测试分块编码和解码互为逆操作
+    #[test]
+    fn test_encode_decode() {
+        let mut wire = Vec::new();
+        write_chunk(&mut wire, b"hello ").unwrap();
+        write_chunk(&mut wire, b"").unwrap();
+        write_chunk(&mut wire, &[b'x'; 20]).unwrap();
+        write_last_chunk(&mut wire, &[("X-Checksum".into(), "abc".into())]).unwrap();
+        assert!(wire.starts_with(b"6\r\nhello \r\n14\r\n"));
+        wire.extend_from_slice(b"GET / HTTP/1.1\r\n");
+
+        let decoded = decode(&wire).unwrap().unwrap();
+        assert_eq!(decoded.body.len(), 26);
+        assert!(decoded.body.starts_with(b"hello x"));
+        assert_eq!(decoded.trailers, vec![("X-Checksum".to_string(), "abc".to_string())]);
+        assert_eq!(&wire[decoded.consumed..], b"GET / HTTP/1.1\r\n");
+
+        // 不完整的输入需要更多数据
+        for end in [0, 3, 10, wire.len() - 20] {
+            assert_eq!(decode(&wire[..end]), Ok(None));
+        }
+    }
+
+    // 测试分块扩展和错误的编码
+    #[test]
+    fn test_decode_errors() {
+        let decoded = decode(b"3;name=value\r\nabc\r\n0\r\n\r\n").unwrap().unwrap();
+        assert_eq!(decoded.body, b"abc");
+        assert_eq!(decode(b"zz\r\nabc\r\n0\r\n\r\n"), Err(ChunkedError::InvalidSize));
+        assert_eq!(decode(b"-1\r\n\r\n"), Err(ChunkedError::InvalidSize));
+        assert_eq!(decode(b"ffffffffffffffffff\r\n"), Err(ChunkedError::InvalidSize));
+        assert_eq!(decode(b"3\r\nabcd\r\n0\r\n\r\n"), Err(ChunkedError::InvalidFraming));
+        assert_eq!(decode(b"0\r\nbad trailer\r\n\r\n"), Err(ChunkedError::InvalidTrailer));
+        let long_line = format!("1;{}\r\n", "x".repeat(MAX_LINE_LEN));
+        assert_eq!(decode(long_line.as_bytes()), Err(ChunkedError::InvalidSize));
+        assert_eq!(decode(&[b'1'; MAX_LINE_LEN + 1]), Err(ChunkedError::InvalidSize));
+    }
+
+    // 测试逐字节传入数据时增量解码，消耗的字节不再传入
+    #[test]
+    fn test_incremental_decode() {
+        let wire = b"4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nX-Sum: 9\r\n\r\nnext";
+        let mut decoder = ChunkedDecoder::new();
+        let mut pending = Vec::new();
+        for &byte in wire.iter() {
+            pending.push(byte);
+            let consumed = decoder.feed(&pending).unwrap();
+            pending.drain(..consumed);
+        }
+        assert!(decoder.is_done());
+        assert_eq!(pending, b"next");
+        let decoded = decoder.finish().unwrap();
+        assert_eq!(decoded.body, b"Wikipedia");
+        assert_eq!(decoded.trailers, vec![("X-Sum".to_string(), "9".to_string())]);
+        assert_eq!(decoded.consumed, wire.len() - 4);
+        assert!(ChunkedDecoder::new().finish().is_none());
+    }
+
+    // 测试消息体超过上限时在读取分块数据之前返回错误
+    #[test]
+    fn test_decode_limit() {
+        let mut decoder = ChunkedDecoder::new().max_body(8);
+        assert_eq!(decoder.feed(b"5\r\nhello\r\n"), Ok(10));
+        assert_eq!(decoder.feed(b"4\r\n"), Err(ChunkedError::TooLarge));
+        let mut decoder = ChunkedDecoder::new().max_body(8);
+        assert_eq!(decoder.feed(b"8\r\n12345678\r\n0\r\n\r\n"), Ok(18));
+        assert!(decoder.is_done());
+    }
+
+    // 测试写入器攒够分块后发送，并在队列满时阻塞
+    #[test]
+    fn test_body_channel() {
+        let (writer, stream) = body_channel();
+        let mut writer = writer.chunk_size(4);
+        let producer = thread::spawn(move || {
+            for i in 0..100 {
+                write!(writer, "{:02}", i).unwrap();
+            }
+            writer.trailer("X-Count", "100");
+            writer.finish()
+        });
+        let (body, trailers) = stream.collect().unwrap();
+        producer.join().unwrap().unwrap();
+        assert_eq!(body.len(), 200);
+        assert!(body.starts_with(b"000102"));
+        assert_eq!(trailers, vec![("X-Count".to_string(), "100".to_string())]);
+    }
+
+    // 测试读取端关闭后写入返回错误，写入端未结束就丢弃时读取端得到 None
+    #[test]
+    fn test_body_channel_closed() {
+        let (mut writer, stream) = body_channel();
+        writer.write_all(b"partial").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        assert_eq!(stream.recv(), Some(Chunk::Data(b"partial".to_vec())));
+        assert_eq!(stream.recv(), None);
+
+        let (mut writer, stream) = body_channel();
+        drop(stream);
+        assert_eq!(writer.write_all(b"x").and_then(|_| writer.flush()).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+}
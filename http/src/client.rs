@@ -0,0 +1,1077 @@
+// 导入所需的库
+use super::chunked::Trailers;
+use super::httprequest::{HttpRequest, Method, Resource, Version};
+use super::httpresponse::{body_framing, parse_head, BodyFraming, ParseError};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// 建立连接的默认超时
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 读写的默认超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 默认最多跟随的重定向次数
+const DEFAULT_MAX_REDIRECTS: usize = 10;
+
+// 每个主机默认最多保留的空闲连接数
+const DEFAULT_MAX_IDLE_PER_HOST: usize = 8;
+
+// 空闲连接的默认保留时间，服务器通常会关闭更久的空闲连接
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+
+// 分块长度行和尾部头部的长度上限
+const MAX_LINE_SIZE: usize = 64 * 1024;
+
+// 定义客户端请求失败的原因
+#[derive(Debug)]
+pub enum ClientError {
+    InvalidUrl(String),          // URL 无法解析
+    UnsupportedScheme(String),   // 只支持 http
+    Io(io::Error),               // 连接或读写错误
+    Timeout,                     // 连接或读写超时
+    InvalidResponse(String),     // 响应不符合 HTTP/1.x 格式
+    TooManyRedirects(usize),     // 重定向次数超过上限
+    Json(serde_json::Error),     // JSON 序列化或反序列化失败
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::InvalidUrl(url) => write!(f, "invalid url: {}", url),
+            ClientError::UnsupportedScheme(scheme) => write!(f, "unsupported scheme: {}", scheme),
+            ClientError::Io(e) => write!(f, "io error: {}", e),
+            ClientError::Timeout => write!(f, "operation timed out"),
+            ClientError::InvalidResponse(message) => write!(f, "invalid response: {}", message),
+            ClientError::TooManyRedirects(max) => write!(f, "more than {} redirects", max),
+            ClientError::Json(e) => write!(f, "json error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+// 超时的读写错误转换为 Timeout
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ClientError::Timeout,
+            io::ErrorKind::InvalidData => ClientError::InvalidResponse(e.to_string()),
+            _ => ClientError::Io(e),
+        }
+    }
+}
+
+impl From<ParseError> for ClientError {
+    fn from(e: ParseError) -> Self {
+        ClientError::InvalidResponse(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Json(e)
+    }
+}
+
+// 请求的目标地址，只支持 http
+#[derive(Debug, Clone, PartialEq)]
+pub struct Url {
+    pub host: String, // 主机名或 IP 地址，IPv6 地址不带方括号
+    pub port: u16,    // 端口
+    pub path: String, // 路径和查询字符串，以 / 开头
+}
+
+impl Url {
+    // 解析 http://host[:port][/path][?query]，片段（# 之后的部分）被忽略
+    pub fn parse(url: &str) -> Result<Url, ClientError> {
+        let invalid = || ClientError::InvalidUrl(url.to_string());
+        let (scheme, rest) = url.trim().split_once("://").ok_or_else(invalid)?;
+        if !scheme.eq_ignore_ascii_case("http") {
+            return Err(ClientError::UnsupportedScheme(scheme.to_string()));
+        }
+        let rest = rest.split('#').next().unwrap_or_default();
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/"),
+        };
+        let path = if path.starts_with('?') { format!("/{}", path) } else { path.to_string() };
+        if authority.contains('@') {
+            return Err(invalid());
+        }
+
+        // IPv6 地址写在方括号中
+        let (host, port) = match authority.strip_prefix('[') {
+            Some(rest) => {
+                let (host, port) = rest.split_once(']').ok_or_else(invalid)?;
+                (host, port.strip_prefix(':'))
+            }
+            None => match authority.rsplit_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (authority, None),
+            },
+        };
+        if host.is_empty() || host.contains(char::is_whitespace) {
+            return Err(invalid());
+        }
+        let port = match port {
+            Some(port) => port.parse().map_err(|_| invalid())?,
+            None => 80,
+        };
+        Ok(Url {
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        })
+    }
+
+    // Host 请求头的值，默认端口省略
+    pub fn authority(&self) -> String {
+        let host = if self.host.contains(':') { format!("[{}]", self.host) } else { self.host.clone() };
+        match self.port {
+            80 => host,
+            port => format!("{}:{}", host, port),
+        }
+    }
+
+    // 按重定向的 Location 解析新的地址：可以是完整的 URL、//主机、绝对路径或相对路径
+    pub fn join(&self, location: &str) -> Result<Url, ClientError> {
+        let location = location.trim();
+        if location.contains("://") {
+            return Url::parse(location);
+        }
+        if let Some(rest) = location.strip_prefix("//") {
+            return Url::parse(&format!("http://{}", rest));
+        }
+        let location = location.split('#').next().unwrap_or_default();
+        let path = if location.starts_with('/') {
+            location.to_string()
+        } else if location.starts_with('?') {
+            format!("{}{}", self.path.split('?').next().unwrap_or("/"), location)
+        } else {
+            let base = self.path.split('?').next().unwrap_or("/");
+            let dir = &base[..base.rfind('/').map_or(0, |i| i + 1)];
+            format!("{}{}", dir, location)
+        };
+        Ok(Url {
+            host: self.host.clone(),
+            port: self.port,
+            path: remove_dot_segments(&path),
+        })
+    }
+}
+
+impl fmt::Display for Url {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "http://{}{}", self.authority(), self.path)
+    }
+}
+
+// 去掉路径中的 . 和 .. 段（RFC 3986 第 5.2.4 节）
+fn remove_dot_segments(path: &str) -> String {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut segments: Vec<&str> = Vec::new();
+    let parts: Vec<&str> = path.split('/').skip(1).collect();
+    for (i, segment) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        match *segment {
+            "." if last => segments.push(""),
+            "." => {}
+            ".." => {
+                segments.pop();
+                if last {
+                    segments.push("");
+                }
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut result = format!("/{}", segments.join("/"));
+    if let Some(query) = query {
+        result.push('?');
+        result.push_str(query);
+    }
+    result
+}
+
+// 客户端收到的响应
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub version: Version,               // HTTP 版本
+    pub status: u16,                    // 状态码
+    pub reason: String,                 // 状态文本
+    pub headers: Vec<(String, String)>, // 响应头，按收到的顺序保存，同名头部可以出现多次
+    pub body: Vec<u8>,                  // 已解码的消息体
+    pub trailers: Trailers,             // 分块编码的尾部头部
+    pub url: Url,                       // 跟随重定向后最终的地址
+}
+
+impl Response {
+    // 按名称查找响应头，名称不区分大小写
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // 状态码是否是 2xx
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    // 消息体作为文本，无效的 UTF-8 被替换
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    // 把消息体解析为 JSON
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ClientError> {
+        Ok(serde_json::from_slice(&self.body)?)
+    }
+}
+
+// 连接池：每个主机（"主机:端口"）的空闲连接和放回的时间
+type Pool = Arc<Mutex<HashMap<String, Vec<(TcpStream, Instant)>>>>;
+
+// 阻塞的 HTTP/1.1 客户端
+// 同一个主机的连接在响应后保留在连接池中复用，客户端可以在多个线程之间共享，克隆的客户端共享连接池
+#[derive(Clone)]
+pub struct Client {
+    pool: Pool,                    // 空闲连接
+    connect_timeout: Duration,     // 建立连接的超时
+    timeout: Option<Duration>,     // 每次读写的超时
+    max_redirects: usize,          // 最多跟随的重定向次数，0 表示不跟随
+    max_idle_per_host: usize,      // 每个主机最多保留的空闲连接数
+    idle_timeout: Duration,        // 空闲连接的保留时间
+    user_agent: String,            // 默认的 User-Agent
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Client {
+    // 使用默认设置创建客户端
+    pub fn new() -> Self {
+        Client {
+            pool: Pool::default(),
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            timeout: Some(DEFAULT_TIMEOUT),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_idle_per_host: DEFAULT_MAX_IDLE_PER_HOST,
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            user_agent: format!("http-client/{}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    // 设置建立连接的超时
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    // 设置每次读写的超时，None 表示一直等待
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // 设置最多跟随的重定向次数，0 表示直接返回 3xx 响应
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    // 设置每个主机最多保留的空闲连接数，0 表示不复用连接
+    pub fn max_idle_per_host(mut self, max: usize) -> Self {
+        self.max_idle_per_host = max;
+        self
+    }
+
+    // 设置空闲连接的保留时间
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    // 设置默认的 User-Agent
+    pub fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    // 创建 GET 请求
+    pub fn get(&self, url: &str) -> RequestBuilder<'_> {
+        self.request(Method::Get, url)
+    }
+
+    // 创建 POST 请求
+    pub fn post(&self, url: &str) -> RequestBuilder<'_> {
+        self.request(Method::Post, url)
+    }
+
+    // 创建 PUT 请求
+    pub fn put(&self, url: &str) -> RequestBuilder<'_> {
+        self.request(Method::Put, url)
+    }
+
+    // 创建 DELETE 请求
+    pub fn delete(&self, url: &str) -> RequestBuilder<'_> {
+        self.request(Method::Delete, url)
+    }
+
+    // 创建 HEAD 请求
+    pub fn head(&self, url: &str) -> RequestBuilder<'_> {
+        self.request(Method::Head, url)
+    }
+
+    // 创建任意方法的请求
+    pub fn request(&self, method: Method, url: &str) -> RequestBuilder<'_> {
+        let url = Url::parse(url);
+        let path = url.as_ref().map(|u| u.path.clone()).unwrap_or_default();
+        RequestBuilder {
+            client: self,
+            url,
+            request: HttpRequest::new(method, path),
+            timeout: self.timeout,
+            max_redirects: self.max_redirects,
+            error: None,
+        }
+    }
+
+    // 当前连接池中的空闲连接数
+    pub fn idle_connections(&self) -> usize {
+        lock_pool(&self.pool).values().map(Vec::len).sum()
+    }
+
+    // 发送请求，按客户端的设置跟随重定向
+    pub fn execute(&self, url: &Url, req: HttpRequest) -> Result<Response, ClientError> {
+        self.execute_with(url, req, self.timeout, self.max_redirects)
+    }
+
+    // 按原样发送请求（不补全请求头，不跟随重定向），读取响应头后返回，消息体由返回的读取器逐步读取
+    // 返回的响应中 body 为空；代理等需要边接收边转发消息体的场景使用
+    pub fn send_streaming(&self, url: &Url, req: &HttpRequest) -> Result<(Response, BodyReader), ClientError> {
+        self.open(url, req, self.timeout)
+    }
+
+    // 发送请求并跟随重定向
+    // 303 以及 POST 请求的 301/302 改为不带消息体的 GET，307/308 保持方法和消息体；跨主机时不转发凭据
+    fn execute_with(
+        &self,
+        url: &Url,
+        mut req: HttpRequest,
+        timeout: Option<Duration>,
+        max_redirects: usize,
+    ) -> Result<Response, ClientError> {
+        let mut url = url.clone();
+        let mut redirects = 0;
+        loop {
+            self.prepare(&url, &mut req);
+            let mut resp = self.send_once(&url, &req, timeout)?;
+            let location = match resp.status {
+                301 | 302 | 303 | 307 | 308 if max_redirects > 0 => resp.header("Location"),
+                _ => None,
+            };
+            let Some(location) = location else {
+                resp.url = url;
+                return Ok(resp);
+            };
+            if redirects == max_redirects {
+                return Err(ClientError::TooManyRedirects(max_redirects));
+            }
+            redirects += 1;
+
+            let next = url.join(location)?;
+            let to_get = resp.status == 303 || (matches!(resp.status, 301 | 302) && req.method == Method::Post);
+            if to_get {
+                if req.method != Method::Head {
+                    req.method = Method::Get;
+                }
+                req.set_body(Vec::new());
+                req.remove_header("Content-Type");
+            }
+            if (&next.host, next.port) != (&url.host, url.port) {
+                req.remove_header("Authorization");
+                req.remove_header("Cookie");
+            }
+            url = next;
+        }
+    }
+
+    // 按目标地址补全请求：路径、Host、User-Agent、Accept 和 Content-Length
+    fn prepare(&self, url: &Url, req: &mut HttpRequest) {
+        req.resource = Resource::Path(url.path.clone());
+        req.version = Version::V1_1;
+        req.set_header("Host", url.authority());
+        if req.header("User-Agent").is_none() {
+            req.set_header("User-Agent", self.user_agent.clone());
+        }
+        if req.header("Accept").is_none() {
+            req.set_header("Accept", "*/*");
+        }
+        req.remove_header("Transfer-Encoding");
+        if !req.raw_body.is_empty() || matches!(req.method, Method::Post | Method::Put | Method::Patch) {
+            req.set_header("Content-Length", req.raw_body.len().to_string());
+        } else {
+            req.remove_header("Content-Length");
+        }
+    }
+
+    // 发送一次请求并读取完整的响应
+    fn send_once(&self, url: &Url, req: &HttpRequest, timeout: Option<Duration>) -> Result<Response, ClientError> {
+        let (mut resp, mut body) = self.open(url, req, timeout)?;
+        body.read_to_end(&mut resp.body)?;
+        resp.trailers = body.trailers().to_vec();
+        Ok(resp)
+    }
+
+    // 发送请求并读取响应头，优先使用连接池中的连接
+    // 复用的连接可能已被服务器关闭，还没有收到任何响应数据就失败时换一个新连接重试
+    fn open(&self, url: &Url, req: &HttpRequest, timeout: Option<Duration>) -> Result<(Response, BodyReader), ClientError> {
+        let key = format!("{}:{}", url.host, url.port);
+        let data = req.to_bytes();
+        let head_request = req.method == Method::Head;
+        if let Some(stream) = self.checkout(&key) {
+            match self.start(stream, &key, &data, head_request, timeout, url) {
+                Ok(opened) => return Ok(opened),
+                Err((e, received)) if received || matches!(e, ClientError::Timeout) => return Err(e),
+                Err(_) => {}
+            }
+        }
+        let stream = self.connect(url)?;
+        self.start(stream, &key, &data, head_request, timeout, url).map_err(|(e, _)| e)
+    }
+
+    // 在连接上发送请求并读取响应头，跳过 100 Continue 等中间响应
+    // 失败时同时返回是否已经收到响应数据
+    fn start(
+        &self,
+        mut stream: TcpStream,
+        key: &str,
+        data: &[u8],
+        head_request: bool,
+        timeout: Option<Duration>,
+        url: &Url,
+    ) -> Result<(Response, BodyReader), (ClientError, bool)> {
+        let setup = stream
+            .set_read_timeout(timeout)
+            .and_then(|_| stream.set_write_timeout(timeout))
+            .and_then(|_| stream.write_all(data))
+            .and_then(|_| stream.flush());
+        if let Err(e) = setup {
+            return Err((e.into(), false));
+        }
+        let mut reader = Reader::new(stream);
+        let head = loop {
+            match reader.read_head(head_request) {
+                Ok(head) if (100..200).contains(&head.status) && head.status != 101 => {}
+                Ok(head) => break head,
+                Err(e) => return Err((e, reader.received > 0)),
+            }
+        };
+
+        let connection = |token: &str| {
+            head.headers
+                .iter()
+                .filter(|(k, _)| k.eq_ignore_ascii_case("Connection"))
+                .flat_map(|(_, v)| v.split(','))
+                .any(|t| t.trim().eq_ignore_ascii_case(token))
+        };
+        let keep_alive = match head.version {
+            Version::V1_1 => !connection("close"),
+            _ => connection("keep-alive"),
+        };
+        // 101 之后连接属于新的协议，以关闭连接结束的消息体之后连接已不可用
+        let reusable = keep_alive && head.status != 101 && head.framing != BodyFraming::Close;
+        let release = (reusable && self.max_idle_per_host > 0).then(|| Release {
+            pool: self.pool.clone(),
+            key: key.to_string(),
+            max_idle: self.max_idle_per_host,
+        });
+        let body = BodyReader::new(reader, head.framing, release);
+        let resp = Response {
+            version: head.version,
+            status: head.status,
+            reason: head.reason,
+            headers: head.headers,
+            body: Vec::new(),
+            trailers: Vec::new(),
+            url: url.clone(),
+        };
+        Ok((resp, body))
+    }
+
+    // 建立新连接，依次尝试解析出的每个地址
+    fn connect(&self, url: &Url) -> Result<TcpStream, ClientError> {
+        let mut last_error = None;
+        for addr in (url.host.as_str(), url.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.connect_timeout) {
+                Ok(stream) => {
+                    let _ = stream.set_nodelay(true);
+                    return Ok(stream);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error
+            .map(ClientError::from)
+            .unwrap_or_else(|| ClientError::InvalidUrl(url.to_string())))
+    }
+
+    // 从连接池取出最近放回的连接，丢弃空闲太久的连接
+    fn checkout(&self, key: &str) -> Option<TcpStream> {
+        let mut pool = lock_pool(&self.pool);
+        let idle = pool.get_mut(key)?;
+        idle.retain(|(_, since)| since.elapsed() < self.idle_timeout);
+        idle.pop().map(|(stream, _)| stream)
+    }
+}
+
+// 获取连接池的锁，持有锁的线程崩溃时继续使用其中的连接
+fn lock_pool(pool: &Pool) -> std::sync::MutexGuard<'_, HashMap<String, Vec<(TcpStream, Instant)>>> {
+    pool.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// 构造一个请求，send 发送
+pub struct RequestBuilder<'c> {
+    client: &'c Client,                  // 发送请求的客户端
+    url: Result<Url, ClientError>,       // 目标地址
+    request: HttpRequest,                // 要发送的请求
+    timeout: Option<Duration>,           // 读写超时
+    max_redirects: usize,                // 最多跟随的重定向次数
+    error: Option<ClientError>,          // 构造时发生的错误，send 时返回
+}
+
+impl RequestBuilder<'_> {
+    // 设置请求头
+    pub fn header(mut self, name: &str, value: impl Into<String>) -> Self {
+        self.request.set_header(name, value);
+        self
+    }
+
+    // 设置消息体
+    pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.request.set_body(body);
+        self
+    }
+
+    // 把值序列化为 JSON 作为消息体
+    pub fn json<T: Serialize + ?Sized>(mut self, value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(body) => {
+                self.request.set_body(body);
+                self.request.set_header("Content-Type", "application/json");
+            }
+            Err(e) => self.error = Some(e.into()),
+        }
+        self
+    }
+
+    // 设置这个请求的读写超时
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // 设置这个请求最多跟随的重定向次数
+    pub fn max_redirects(mut self, max: usize) -> Self {
+        self.max_redirects = max;
+        self
+    }
+
+    // 发送请求
+    pub fn send(self) -> Result<Response, ClientError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        let url = self.url?;
+        self.client.execute_with(&url, self.request, self.timeout, self.max_redirects)
+    }
+}
+
+// 消息体读取完后把连接放回连接池需要的信息
+struct Release {
+    pool: Pool,       // 客户端的连接池
+    key: String,      // 连接所属的主机
+    max_idle: usize,  // 每个主机最多保留的空闲连接数
+}
+
+impl Release {
+    // 把连接放回连接池，超过上限时关闭最旧的连接
+    fn checkin(self, stream: TcpStream) {
+        let mut pool = lock_pool(&self.pool);
+        let idle = pool.entry(self.key).or_default();
+        if idle.len() >= self.max_idle {
+            idle.remove(0);
+        }
+        idle.push((stream, Instant::now()));
+    }
+}
+
+// 消息体的读取状态
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BodyState {
+    Length(usize),    // 按 Content-Length 读取，剩余的字节数
+    ChunkSize,        // 等待分块长度行
+    ChunkData(usize), // 分块数据，剩余的字节数
+    ChunkEnd,         // 分块数据之后的 CRLF
+    Close,            // 读取到连接关闭
+    Done,             // 已读取完
+}
+
+// 响应消息体的读取器，按响应的分隔方式解码
+// 完整读取后可以复用的连接放回连接池；没有读完就丢弃时连接被关闭
+pub struct BodyReader {
+    reader: Option<Reader<TcpStream>>, // 连接，读取完后移出
+    state: BodyState,                  // 读取状态
+    trailers: Trailers,                // 分块编码的尾部头部
+    release: Option<Release>,          // 连接可以复用时放回连接池的信息
+}
+
+impl BodyReader {
+    fn new(reader: Reader<TcpStream>, framing: BodyFraming, release: Option<Release>) -> Self {
+        let state = match framing {
+            BodyFraming::Empty | BodyFraming::Length(0) => BodyState::Done,
+            BodyFraming::Length(length) => BodyState::Length(length),
+            BodyFraming::Chunked => BodyState::ChunkSize,
+            BodyFraming::Close => BodyState::Close,
+        };
+        let mut body = BodyReader {
+            reader: Some(reader),
+            state,
+            trailers: Vec::new(),
+            release,
+        };
+        if state == BodyState::Done {
+            body.finish();
+        }
+        body
+    }
+
+    // 消息体是否已读取完，没有消息体的响应一开始就是读取完的
+    pub fn is_finished(&self) -> bool {
+        self.state == BodyState::Done
+    }
+
+    // 分块编码的尾部头部，消息体读取完后才有
+    pub fn trailers(&self) -> &[(String, String)] {
+        &self.trailers
+    }
+
+    // 消息体已读取完，连接可以复用且没有多余的数据时放回连接池
+    fn finish(&mut self) {
+        self.state = BodyState::Done;
+        let (Some(reader), Some(release)) = (self.reader.take(), self.release.take()) else {
+            return;
+        };
+        if reader.buffer.is_empty() {
+            release.checkin(reader.stream);
+        }
+    }
+
+    // 读取分块编码的尾部头部，直到空行
+    fn read_trailers(&mut self) -> io::Result<()> {
+        let Some(reader) = self.reader.as_mut() else {
+            return Ok(());
+        };
+        loop {
+            let line = reader.read_line()?;
+            if line.is_empty() {
+                return Ok(());
+            }
+            let (name, value) = line.split_once(':').ok_or_else(|| invalid_data("invalid trailer field"))?;
+            self.trailers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+}
+
+impl Read for BodyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let Some(reader) = self.reader.as_mut().filter(|_| !buf.is_empty()) else {
+                return Ok(0);
+            };
+            match self.state {
+                BodyState::Done => return Ok(0),
+                BodyState::Length(remaining) | BodyState::ChunkData(remaining) => {
+                    let limit = remaining.min(buf.len());
+                    let n = reader.read(&mut buf[..limit])?;
+                    if n == 0 {
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    self.state = match (self.state, remaining - n) {
+                        (BodyState::Length(_), 0) => BodyState::Done,
+                        (BodyState::Length(_), left) => BodyState::Length(left),
+                        (_, 0) => BodyState::ChunkEnd,
+                        (_, left) => BodyState::ChunkData(left),
+                    };
+                    if self.state == BodyState::Done {
+                        self.finish();
+                    }
+                    return Ok(n);
+                }
+                BodyState::ChunkSize => {
+                    let line = reader.read_line()?;
+                    let size = line.split(';').next().unwrap_or_default().trim();
+                    if size.is_empty() || !size.bytes().all(|b| b.is_ascii_hexdigit()) {
+                        return Err(invalid_data("invalid chunk size"));
+                    }
+                    match usize::from_str_radix(size, 16).map_err(|_| invalid_data("invalid chunk size"))? {
+                        0 => {
+                            self.read_trailers()?;
+                            self.finish();
+                        }
+                        size => self.state = BodyState::ChunkData(size),
+                    }
+                }
+                BodyState::ChunkEnd => {
+                    if !reader.read_line()?.is_empty() {
+                        return Err(invalid_data("missing CRLF after chunk data"));
+                    }
+                    self.state = BodyState::ChunkSize;
+                }
+                BodyState::Close => {
+                    let n = reader.read(buf)?;
+                    if n == 0 {
+                        self.state = BodyState::Done;
+                        self.reader = None;
+                    }
+                    return Ok(n);
+                }
+            }
+        }
+    }
+}
+
+// 响应格式错误
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// 状态行和响应头
+struct Head {
+    version: Version,               // HTTP 版本
+    status: u16,                    // 状态码
+    reason: String,                 // 状态文本
+    headers: Vec<(String, String)>, // 响应头
+    framing: BodyFraming,           // 消息体的分隔方式
+}
+
+// 带缓冲区的读取器，多读的数据留在缓冲区中
+struct Reader<R> {
+    stream: R,        // 底层连接
+    buffer: Vec<u8>,  // 已读取但尚未使用的数据
+    received: usize,  // 已读取的字节数
+}
+
+impl<R: Read> Reader<R> {
+    fn new(stream: R) -> Self {
+        Reader {
+            stream,
+            buffer: Vec::new(),
+            received: 0,
+        }
+    }
+
+    // 再读取一些数据到缓冲区，连接已关闭时返回 0
+    fn fill(&mut self) -> io::Result<usize> {
+        let mut read_buffer = [0u8; 16 * 1024];
+        let n = self.read_stream(&mut read_buffer)?;
+        self.buffer.extend_from_slice(&read_buffer[..n]);
+        Ok(n)
+    }
+
+    // 直接从连接读取
+    fn read_stream(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.stream.read(buf) {
+                Ok(n) => {
+                    self.received += n;
+                    return Ok(n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // 读取状态行和响应头
+    fn read_head(&mut self, head_request: bool) -> Result<Head, ClientError> {
+        loop {
+            if let Some(head) = parse_head(&self.buffer)? {
+                let result = Head {
+                    version: Version::from(head.version),
+                    status: head.status(),
+                    reason: head.status_text.to_string(),
+                    headers: head.headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+                    framing: body_framing(&head, head_request)?,
+                };
+                let len = head.len;
+                self.buffer.drain(..len);
+                return Ok(result);
+            }
+            if self.fill()? == 0 {
+                return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+    }
+
+    // 读取一行（不含行尾的 CRLF 或 LF）
+    fn read_line(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = self.buffer.drain(..=pos).collect();
+                let line = line.strip_suffix(b"\n").unwrap_or(&line);
+                let line = line.strip_suffix(b"\r").unwrap_or(line);
+                return Ok(String::from_utf8_lossy(line).into_owned());
+            }
+            if self.buffer.len() > MAX_LINE_SIZE {
+                return Err(invalid_data("line too long"));
+            }
+            if self.fill()? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+// 先返回缓冲区中的数据，缓冲区为空时直接从连接读取
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.buffer.is_empty() {
+            return self.read_stream(buf);
+        }
+        let n = buf.len().min(self.buffer.len());
+        buf[..n].copy_from_slice(&self.buffer[..n]);
+        self.buffer.drain(..n);
+        Ok(n)
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    // 测试用的服务器：每个连接上依次读取请求，交给 respond 生成原始响应
+    // respond 返回 None 或 HTTP/1.0、Connection: close 的响应后关闭连接；返回服务器地址和已接受的连接数
+    fn serve(respond: fn(&HttpRequest) -> Option<String>) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let counter = connections.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                counter.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || loop {
+                    let mut reader = Reader::new(&mut stream);
+                    let mut raw = Vec::new();
+                    loop {
+                        let Ok(line) = reader.read_line() else { return };
+                        raw.extend_from_slice(format!("{}\r\n", line).as_bytes());
+                        if line.is_empty() {
+                            break;
+                        }
+                    }
+                    let req: HttpRequest = raw.into();
+                    let length = req.header("Content-Length").map_or(0, |l| l.parse().unwrap());
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    let mut req = req;
+                    req.set_body(body);
+                    let Some(resp) = respond(&req) else { return };
+                    stream.write_all(resp.as_bytes()).unwrap();
+                    if resp.starts_with("HTTP/1.0") || resp.contains("Connection: close") {
+                        return;
+                    }
+                });
+            }
+        });
+        (addr, connections)
+    }
+
+    // 测试 URL 的解析和重定向地址的拼接
+    #[test]
+    fn test_url() {
+        let url = Url::parse("http://Example.com:8080/a/b/c?x=1#frag").unwrap();
+        assert_eq!((url.host.as_str(), url.port, url.path.as_str()), ("example.com", 8080, "/a/b/c?x=1"));
+        assert_eq!(url.authority(), "example.com:8080");
+        assert_eq!(Url::parse("http://[::1]/").unwrap().authority(), "[::1]");
+        assert_eq!(Url::parse("http://h?q").unwrap().path, "/?q");
+        assert!(matches!(Url::parse("https://h/"), Err(ClientError::UnsupportedScheme(_))));
+        for bad in ["h/path", "http://", "http://h:port/", "http://u@h/"] {
+            assert!(matches!(Url::parse(bad), Err(ClientError::InvalidUrl(_))), "{}", bad);
+        }
+
+        let join = |location: &str| url.join(location).unwrap().to_string();
+        assert_eq!(join("d"), "http://example.com:8080/a/b/d");
+        assert_eq!(join("../d?y=2"), "http://example.com:8080/a/d?y=2");
+        assert_eq!(join("/root"), "http://example.com:8080/root");
+        assert_eq!(join("?z"), "http://example.com:8080/a/b/c?z");
+        assert_eq!(join("//other/x"), "http://other/x");
+        assert_eq!(join("http://other:81/"), "http://other:81/");
+    }
+
+    // 测试连接复用、Content-Length 和分块编码的消息体
+    #[test]
+    fn test_keep_alive_and_chunked() {
+        let (addr, connections) = serve(|req| {
+            let Resource::Path(path) = &req.resource;
+            Some(match path.as_str() {
+                "/chunked" => "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\nX-Sum: 11\r\n\r\n".into(),
+                // HEAD 的响应只有头部，Content-Length 是 GET 时消息体的长度
+                _ if req.method == Method::Head => "HTTP/1.1 200 OK\r\nContent-Length: 3\r\n\r\n".into(),
+                _ => format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\nok:{}", req.raw_body.len() + 3, req.msg_body),
+            })
+        });
+        let client = Client::new();
+        let resp = client.get(&format!("{}/chunked", addr)).send().unwrap();
+        assert_eq!(resp.text(), "hello world");
+        assert_eq!(resp.trailers, vec![("X-Sum".to_string(), "11".to_string())]);
+        let resp = client.post(&format!("{}/echo", addr)).body("data").send().unwrap();
+        assert_eq!((resp.status, resp.text()), (200, "ok:data".to_string()));
+        let resp = client.head(&format!("{}/echo", addr)).send().unwrap();
+        assert_eq!(resp.header("content-length"), Some("3"));
+        assert!(resp.body.is_empty());
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(client.idle_connections(), 1);
+    }
+
+    // 测试逐步读取消息体：读完后连接放回连接池，没有读完就丢弃时连接被关闭
+    #[test]
+    fn test_send_streaming() {
+        let (addr, connections) = serve(|_| {
+            Some("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n2\r\nde\r\n0\r\nX-Done: 1\r\n\r\n".into())
+        });
+        let client = Client::new();
+        let url = Url::parse(&addr).unwrap();
+        let mut req = HttpRequest::new(Method::Get, "/");
+        req.set_header("Host", url.authority());
+
+        let (resp, mut body) = client.send_streaming(&url, &req).unwrap();
+        assert_eq!((resp.status, resp.body.len()), (200, 0));
+        let mut buf = [0u8; 2];
+        assert_eq!(body.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf, b"ab");
+        assert_eq!(client.idle_connections(), 0);
+        let mut rest = Vec::new();
+        body.read_to_end(&mut rest).unwrap();
+        assert_eq!(rest, b"cde");
+        assert_eq!(body.trailers(), [("X-Done".to_string(), "1".to_string())]);
+        assert_eq!(client.idle_connections(), 1);
+
+        let (_, body) = client.send_streaming(&url, &req).unwrap();
+        drop(body);
+        assert_eq!(client.idle_connections(), 0);
+        client.get(&addr).send().unwrap();
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    // 测试 Connection: close 和以关闭连接结束的消息体，这样的连接不放回连接池
+    #[test]
+    fn test_close_delimited() {
+        let (addr, connections) = serve(|req| {
+            Some(match req.header("X-Close") {
+                Some(_) => "HTTP/1.1 200 OK\r\nConnection: close\r\nContent-Length: 2\r\n\r\nok".into(),
+                None => "HTTP/1.0 200 OK\r\n\r\nuntil close".into(),
+            })
+        });
+        let client = Client::new();
+        let resp = client.get(&addr).send().unwrap();
+        assert_eq!((resp.version, resp.text()), (Version::V1_0, "until close".to_string()));
+        assert_eq!(client.idle_connections(), 0);
+        let resp = client.get(&addr).header("X-Close", "1").send().unwrap();
+        assert_eq!(resp.text(), "ok");
+        assert_eq!(client.idle_connections(), 0);
+        assert_eq!(connections.load(Ordering::SeqCst), 2);
+    }
+
+    // 测试重定向：303 改为 GET，307 保持方法和消息体，超过次数返回错误
+    #[test]
+    fn test_redirects() {
+        let (addr, _) = serve(|req| {
+            let Resource::Path(path) = &req.resource;
+            let method = req.method.as_str();
+            Some(match path.as_str() {
+                "/see-other" => "HTTP/1.1 303 See Other\r\nLocation: /target\r\nContent-Length: 0\r\n\r\n".into(),
+                "/temporary" => "HTTP/1.1 307 Temporary Redirect\r\nLocation: target\r\nContent-Length: 0\r\n\r\n".into(),
+                "/loop" => "HTTP/1.1 302 Found\r\nLocation: /loop\r\nContent-Length: 0\r\n\r\n".into(),
+                _ => {
+                    let body = format!("{} {} {}", method, path, req.msg_body);
+                    format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body)
+                }
+            })
+        });
+        let client = Client::new();
+        let resp = client.post(&format!("{}/see-other", addr)).body("x").send().unwrap();
+        assert_eq!(resp.text(), "GET /target ");
+        assert_eq!(resp.url.path, "/target");
+        let resp = client.post(&format!("{}/temporary", addr)).body("x").send().unwrap();
+        assert_eq!(resp.text(), "POST /target x");
+        let error = client.get(&format!("{}/loop", addr)).max_redirects(3).send().unwrap_err();
+        assert!(matches!(error, ClientError::TooManyRedirects(3)));
+        let resp = client.get(&format!("{}/loop", addr)).max_redirects(0).send().unwrap();
+        assert_eq!((resp.status, resp.header("location")), (302, Some("/loop")));
+    }
+
+    // 测试 JSON 请求和响应
+    #[test]
+    fn test_json() {
+        let (addr, _) = serve(|req| {
+            assert_eq!(req.header("Content-Type"), Some("application/json"));
+            let body = format!("{{\"received\":{}}}", req.msg_body);
+            Some(format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}", body.len(), body))
+        });
+        let resp = Client::new().post(&addr).json(&serde_json::json!({"n": 1})).send().unwrap();
+        let value: serde_json::Value = resp.json().unwrap();
+        assert_eq!(value["received"]["n"], 1);
+        assert!(matches!(resp.json::<Vec<u8>>(), Err(ClientError::Json(_))));
+    }
+
+    // 测试读取超时，以及服务器关闭空闲连接后自动换新连接
+    #[test]
+    fn test_timeout_and_stale_connection() {
+        let (addr, connections) = serve(|req| {
+            if req.header("X-Slow").is_some() {
+                thread::sleep(Duration::from_millis(300));
+            }
+            Some("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".into())
+        });
+        let client = Client::new().timeout(Some(Duration::from_millis(100)));
+        let error = client.get(&addr).header("X-Slow", "1").send().unwrap_err();
+        assert!(matches!(error, ClientError::Timeout), "{}", error);
+
+        // 服务器在第二个请求时关闭连接，客户端重新连接后成功
+        let (addr, connections2) = serve(|req| {
+            if req.header("X-Close").is_some() {
+                return None;
+            }
+            Some("HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok".into())
+        });
+        let client = Client::new();
+        client.get(&addr).send().unwrap();
+        assert_eq!(client.idle_connections(), 1);
+        let error = client.get(&addr).header("X-Close", "1").send().unwrap_err();
+        assert!(matches!(error, ClientError::Io(_)), "{}", error);
+        assert_eq!(client.get(&addr).send().unwrap().text(), "ok");
+        assert_eq!(connections2.load(Ordering::SeqCst), 3);
+        drop(connections);
+    }
+}
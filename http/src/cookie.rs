@@ -0,0 +1,360 @@
+// 导入所需的模块
+use super::httpdate::format_http_date; // 导入 HTTP 日期格式化函数
+use super::httprequest::HttpRequest; // 导入 HTTP 请求结构
+use super::httpresponse::is_token; // 导入 token 校验
+use base64::engine::general_purpose::URL_SAFE_NO_PAD; // 导入 URL 安全的 base64 编码
+use base64::Engine; // 导入 base64 编解码特性
+use hmac::{Hmac, Mac}; // 导入 HMAC
+use sha2::Sha256; // 导入 SHA-256
+use std::collections::HashMap; // 导入 HashMap
+use std::fmt; // 导入格式化模块
+use std::time::SystemTime; // 导入时间模块
+
+// 定义 SameSite 属性的取值
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SameSite {
+    Strict, // 只在同站请求中发送
+    Lax,    // 跨站的顶层导航也会发送
+    None,   // 总是发送，要求同时设置 Secure
+}
+
+impl fmt::Display for SameSite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SameSite::Strict => write!(f, "Strict"),
+            SameSite::Lax => write!(f, "Lax"),
+            SameSite::None => write!(f, "None"),
+        }
+    }
+}
+
+// 定义创建 Cookie 时可能出现的错误
+#[derive(Debug, PartialEq, Clone)]
+pub enum CookieError {
+    InvalidName(String),                    // 名称不是 token
+    InvalidValue(String),                   // 值包含不允许的字符
+    InvalidAttribute(&'static str, String), // 属性值包含控制字符或分号（属性名，值）
+}
+
+impl fmt::Display for CookieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CookieError::InvalidName(name) => write!(f, "invalid cookie name: {:?}", name),
+            CookieError::InvalidValue(value) => write!(f, "invalid cookie value: {:?}", value),
+            CookieError::InvalidAttribute(attr, value) => write!(f, "invalid cookie {}: {:?}", attr, value),
+        }
+    }
+}
+
+impl std::error::Error for CookieError {}
+
+// 是否是 RFC 6265 的 cookie-value：可见 ASCII 字符，不含空格、双引号、逗号、分号和反斜杠，可以整体用双引号括起来
+fn is_cookie_value(value: &str) -> bool {
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value);
+    value
+        .bytes()
+        .all(|b| matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E))
+}
+
+// 校验 Path、Domain 等属性的值：不能包含控制字符和分号
+fn attribute(name: &'static str, value: String) -> Result<String, CookieError> {
+    match value.bytes().any(|b| b.is_ascii_control() || b == b';') {
+        true => Err(CookieError::InvalidAttribute(name, value)),
+        false => Ok(value),
+    }
+}
+
+// 定义用于生成 Set-Cookie 头的 Cookie
+#[derive(Debug, PartialEq, Clone)]
+pub struct Cookie {
+    pub name: String,                // Cookie 名称
+    pub value: String,               // Cookie 值
+    pub path: Option<String>,        // Path 属性
+    pub domain: Option<String>,      // Domain 属性
+    pub max_age: Option<i64>,        // Max-Age 属性（秒）
+    pub expires: Option<SystemTime>, // Expires 属性
+    pub secure: bool,                // Secure 属性
+    pub http_only: bool,             // HttpOnly 属性
+    pub same_site: Option<SameSite>, // SameSite 属性
+}
+
+impl Cookie {
+    // 创建一个只有名称和值的 Cookie，名称必须是 token，值必须是合法的 cookie-value
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Result<Self, CookieError> {
+        let (name, value) = (name.into(), value.into());
+        if !is_token(&name) {
+            return Err(CookieError::InvalidName(name));
+        }
+        if !is_cookie_value(&value) {
+            return Err(CookieError::InvalidValue(value));
+        }
+        Ok(Cookie {
+            name,
+            value,
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        })
+    }
+
+    // 创建一个让浏览器立即删除指定 Cookie 的 Set-Cookie
+    pub fn removal(name: impl Into<String>) -> Result<Self, CookieError> {
+        Ok(Cookie::new(name, "")?
+            .max_age(0)
+            .expires(SystemTime::UNIX_EPOCH))
+    }
+
+    // 设置 Path 属性，值包含控制字符或分号时返回错误
+    pub fn path(mut self, path: impl Into<String>) -> Result<Self, CookieError> {
+        self.path = Some(attribute("path", path.into())?);
+        Ok(self)
+    }
+
+    // 设置 Domain 属性，值包含控制字符或分号时返回错误
+    pub fn domain(mut self, domain: impl Into<String>) -> Result<Self, CookieError> {
+        self.domain = Some(attribute("domain", domain.into())?);
+        Ok(self)
+    }
+
+    // 设置 Max-Age 属性
+    pub fn max_age(mut self, seconds: i64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    // 设置 Expires 属性
+    pub fn expires(mut self, time: SystemTime) -> Self {
+        self.expires = Some(time);
+        self
+    }
+
+    // 设置 Secure 属性
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    // 设置 HttpOnly 属性
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    // 设置 SameSite 属性
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    // 用密钥对 Cookie 值签名，签名附加在值的末尾
+    pub fn signed(mut self, key: &CookieKey) -> Self {
+        self.value = key.sign(&self.name, &self.value);
+        self
+    }
+}
+
+// 将 Cookie 格式化为 Set-Cookie 头的值
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age)?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", format_http_date(expires))?;
+        }
+        // 浏览器会拒绝没有 Secure 的 SameSite=None
+        if self.secure || self.same_site == Some(SameSite::None) {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+// 定义用于签名 Cookie 的 HMAC-SHA256 密钥
+#[derive(Clone)]
+pub struct CookieKey {
+    secret: Vec<u8>, // 密钥
+}
+
+impl CookieKey {
+    // 使用给定的密钥创建 CookieKey，密钥应至少 32 字节
+    pub fn new(secret: impl AsRef<[u8]>) -> Self {
+        CookieKey {
+            secret: secret.as_ref().to_vec(),
+        }
+    }
+
+    // 计算名称和值的 HMAC，名称参与签名，防止把一个 Cookie 的值挪用到另一个 Cookie
+    fn mac(&self, name: &str, value: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+        mac.update(name.as_bytes());
+        mac.update(b"=");
+        mac.update(value.as_bytes());
+        mac
+    }
+
+    // 返回 "值.签名" 形式的签名值
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        let tag = self.mac(name, value).finalize().into_bytes();
+        format!("{}.{}", value, URL_SAFE_NO_PAD.encode(tag))
+    }
+
+    // 校验签名值，成功时返回原始值
+    pub fn verify(&self, name: &str, signed_value: &str) -> Option<String> {
+        let (value, tag) = signed_value.rsplit_once('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+        // verify_slice 以常量时间比较签名
+        self.mac(name, value).verify_slice(&tag).ok()?;
+        Some(value.to_string())
+    }
+}
+
+impl fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CookieKey(..)") // 不在日志中输出密钥
+    }
+}
+
+// 解析 Cookie 请求头，例如 "a=1; b=2"
+pub fn parse_cookie_header(header: &str) -> Vec<(String, String)> {
+    header
+        .split(';')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(name, value)| {
+            let value = value.trim();
+            // 值两侧的双引号不属于值本身
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            (name.trim().to_string(), value.to_string())
+        })
+        .filter(|(name, _)| !name.is_empty())
+        .collect()
+}
+
+// 为 HttpRequest 实现 Cookie 读取
+impl HttpRequest {
+    // 返回请求中的全部 Cookie，同名 Cookie 以第一个为准
+    pub fn cookies(&self) -> HashMap<String, String> {
+        let mut cookies = HashMap::new();
+        if let Some(header) = self.header("Cookie") {
+            for (name, value) in parse_cookie_header(header) {
+                cookies.entry(name).or_insert(value);
+            }
+        }
+        cookies
+    }
+
+    // 返回指定名称的 Cookie 值
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    // 返回指定名称并通过签名校验的 Cookie 值
+    pub fn signed_cookie(&self, name: &str, key: &CookieKey) -> Option<String> {
+        self.cookie(name).and_then(|value| key.verify(name, &value))
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+    use std::time::{Duration, UNIX_EPOCH}; // 导入时间模块
+
+    // 测试 Cookie 请求头的解析
+    #[test]
+    fn test_request_cookies() {
+        let req: HttpRequest = String::from(
+            "GET / HTTP/1.1\r\nCookie: session=abc; theme=\"dark\"; session=ignored; broken\r\n\r\n",
+        )
+        .into();
+        let cookies = req.cookies();
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(req.cookie("session"), Some("abc".into()));
+        assert_eq!(req.cookie("theme"), Some("dark".into()));
+        assert_eq!(req.cookie("missing"), None);
+    }
+
+    // 测试 Set-Cookie 的全部属性
+    #[test]
+    fn test_set_cookie_attributes() {
+        let cookie = Cookie::new("session", "abc")
+            .and_then(|c| c.path("/"))
+            .and_then(|c| c.domain("example.com"))
+            .unwrap()
+            .max_age(3600)
+            .expires(UNIX_EPOCH + Duration::from_secs(784_111_777))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Lax);
+        assert_eq!(
+            cookie.to_string(),
+            "session=abc; Path=/; Domain=example.com; Max-Age=3600; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Secure; HttpOnly; SameSite=Lax"
+        );
+        assert_eq!(
+            Cookie::new("a", "b").unwrap().same_site(SameSite::None).to_string(),
+            "a=b; Secure; SameSite=None"
+        );
+    }
+
+    // 测试拒绝会破坏 Set-Cookie 头的名称、值和属性
+    #[test]
+    fn test_invalid_cookies() {
+        for name in ["", "a b", "a=b", "a;b", "a\r\nb", "\"a\""] {
+            assert_eq!(Cookie::new(name, "1"), Err(CookieError::InvalidName(name.to_string())));
+        }
+        for value in ["a b", "a;b", "a,b", "a\\b", "a\"b", "a\r\nSet-Cookie: x=1", "\"a"] {
+            assert_eq!(Cookie::new("a", value), Err(CookieError::InvalidValue(value.to_string())));
+        }
+        assert!(Cookie::new("a", "\"quoted\"").is_ok());
+        assert!(Cookie::new("a", "").is_ok());
+
+        let cookie = Cookie::new("a", "1").unwrap();
+        assert_eq!(
+            cookie.clone().path("/; Domain=evil.com"),
+            Err(CookieError::InvalidAttribute("path", "/; Domain=evil.com".to_string()))
+        );
+        assert!(cookie.clone().domain("example.com\r\nX: 1").is_err());
+        assert!(Cookie::removal("a b").is_err());
+    }
+
+    // 测试签名 Cookie 的校验
+    #[test]
+    fn test_signed_cookie() {
+        let key = CookieKey::new("a very secret key that is long enough");
+        let cookie = Cookie::new("user", "42").unwrap().signed(&key);
+        assert_eq!(key.verify("user", &cookie.value), Some("42".into()));
+
+        // 篡改值、签名或名称都会校验失败
+        let tampered = cookie.value.replacen("42", "43", 1);
+        assert_eq!(key.verify("user", &tampered), None);
+        assert_eq!(key.verify("admin", &cookie.value), None);
+        assert_eq!(CookieKey::new("another key").verify("user", &cookie.value), None);
+
+        let req: HttpRequest =
+            format!("GET / HTTP/1.1\r\nCookie: user={}\r\n\r\n", cookie.value).into();
+        assert_eq!(req.signed_cookie("user", &key), Some("42".into()));
+    }
+}
@@ -0,0 +1,75 @@
+// 导入所需的模块
+use std::any::{Any, TypeId}; // 导入类型标识和动态类型
+use std::collections::HashMap; // 导入 HashMap
+use std::fmt; // 导入格式化模块
+
+// 按类型存放附加数据的容器，中间件用它把会话、认证信息等传递给处理器
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>, // 类型到值的映射
+}
+
+impl Extensions {
+    // 创建一个空的容器
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 插入一个值，返回之前同类型的值
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    // 获取指定类型的值
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.map
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    // 获取指定类型的可变引用
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.map
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    // 移除并返回指定类型的值
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+}
+
+impl fmt::Debug for Extensions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Extensions({} entries)", self.map.len())
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    #[derive(Debug, PartialEq)]
+    struct UserId(i32);
+
+    // 测试按类型插入、读取和移除
+    #[test]
+    fn test_extensions() {
+        let mut ext = Extensions::new();
+        assert_eq!(ext.insert(UserId(1)), None);
+        assert_eq!(ext.insert(UserId(2)), Some(UserId(1)));
+        ext.insert(String::from("admin"));
+
+        assert_eq!(ext.get::<UserId>(), Some(&UserId(2)));
+        ext.get_mut::<String>().unwrap().push_str("istrator");
+        assert_eq!(ext.get::<String>().map(|s| s.as_str()), Some("administrator"));
+        assert_eq!(ext.remove::<UserId>(), Some(UserId(2)));
+        assert_eq!(ext.get::<UserId>(), None);
+    }
+}
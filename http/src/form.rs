@@ -0,0 +1,196 @@
+// 导入所需的模块
+use super::httprequest::HttpRequest; // 导入 HTTP 请求结构
+use std::fmt; // 导入格式化模块
+
+// 定义解析后的表单，按提交顺序保存所有字段（同名字段可以出现多次）
+#[derive(Debug, PartialEq, Default, Clone)]
+pub struct Form {
+    pub fields: Vec<(String, String)>, // 字段名和值
+}
+
+impl Form {
+    // 返回指定字段的第一个值
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // 返回指定字段的全部值
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.fields
+            .iter()
+            .filter(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+}
+
+// 定义表单解析失败的原因
+#[derive(Debug, PartialEq)]
+pub enum FormError {
+    // Content-Type 不是 application/x-www-form-urlencoded，对应 415
+    UnsupportedMediaType { content_type: Option<String> },
+    // 消息体超过大小限制，对应 413
+    PayloadTooLarge { limit: usize, size: usize },
+}
+
+impl FormError {
+    // 返回错误对应的 HTTP 状态码
+    pub fn status_code(&self) -> &'static str {
+        match self {
+            FormError::UnsupportedMediaType { .. } => "415",
+            FormError::PayloadTooLarge { .. } => "413",
+        }
+    }
+}
+
+impl fmt::Display for FormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType { content_type } => write!(
+                f,
+                "expected Content-Type application/x-www-form-urlencoded, got {}",
+                content_type.as_deref().unwrap_or("none")
+            ),
+            FormError::PayloadTooLarge { limit, size } => {
+                write!(f, "body of {} bytes exceeds the limit of {} bytes", size, limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+// 默认允许的表单消息体大小上限（64 KiB）
+pub const DEFAULT_FORM_LIMIT: usize = 64 * 1024;
+
+// 解析 application/x-www-form-urlencoded 格式的字符串（也适用于查询字符串）
+pub fn parse_urlencoded(input: &str) -> Form {
+    let fields = input
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect();
+    Form { fields }
+}
+
+// 对表单编码的字符串进行百分号解码，'+' 表示空格，非法的转义序列按原样保留
+pub fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                match (hex_value(bytes[i + 1]), hex_value(bytes[i + 2])) {
+                    (Some(h), Some(l)) => {
+                        decoded.push(h * 16 + l);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            b => decoded.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+// 对字符串进行表单编码，字母数字和 -._* 以外的字节都会被转义
+pub fn percent_encode(input: &str) -> String {
+    let mut encoded = String::with_capacity(input.len());
+    for b in input.bytes() {
+        match b {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => {
+                encoded.push(b as char)
+            }
+            b' ' => encoded.push('+'),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+// 将十六进制字符转换为数值
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+// 为 HttpRequest 实现表单提取
+impl HttpRequest {
+    // 将 application/x-www-form-urlencoded 消息体解析为表单
+    pub fn form(&self) -> Result<Form, FormError> {
+        let content_type = self.header("Content-Type");
+        let is_form = content_type.is_some_and(|ct| {
+            ct.split(';')
+                .next()
+                .unwrap_or("")
+                .trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        });
+        if !is_form {
+            return Err(FormError::UnsupportedMediaType {
+                content_type: content_type.map(|ct| ct.to_string()),
+            });
+        }
+        let size = self.raw_body.len();
+        if size > DEFAULT_FORM_LIMIT {
+            return Err(FormError::PayloadTooLarge {
+                limit: DEFAULT_FORM_LIMIT,
+                size,
+            });
+        }
+        Ok(parse_urlencoded(&self.msg_body))
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 测试解析表单编码的字符串
+    #[test]
+    fn test_parse_urlencoded() {
+        let form = parse_urlencoded("order_status=In+Transit&note=caf%C3%A9%21&tag=a&tag=b&empty");
+        assert_eq!(form.get("order_status"), Some("In Transit"));
+        assert_eq!(form.get("note"), Some("café!"));
+        assert_eq!(form.get_all("tag"), vec!["a", "b"]);
+        assert_eq!(form.get("empty"), Some(""));
+        assert_eq!(form.get("missing"), None);
+    }
+
+    // 测试非法的转义序列按原样保留
+    #[test]
+    fn test_percent_decode_invalid() {
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+        assert_eq!(percent_decode(&percent_encode("a b&c=d/é")), "a b&c=d/é");
+    }
+
+    // 测试从请求中提取表单
+    #[test]
+    fn test_request_form() {
+        let req: HttpRequest = String::from(
+            "POST /api/shipping/orders/2/status HTTP/1.1\r\nContent-Type: application/x-www-form-urlencoded\r\n\r\norder_status=Shipped",
+        )
+        .into();
+        assert_eq!(req.form().unwrap().get("order_status"), Some("Shipped"));
+
+        let req: HttpRequest =
+            String::from("POST / HTTP/1.1\r\nContent-Type: text/plain\r\n\r\na=b").into();
+        assert_eq!(req.form().unwrap_err().status_code(), "415");
+    }
+}
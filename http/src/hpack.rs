@@ -0,0 +1,568 @@
+// 导入所需的模块
+use std::collections::VecDeque; // 导入双端队列，用于动态表
+use std::fmt; // 导入格式化模块
+use std::sync::OnceLock; // 导入一次性初始化
+
+// 每个动态表条目额外计入的字节数（RFC 7541 第 4.1 节）
+const ENTRY_OVERHEAD: usize = 32;
+
+// 解码时允许的最大整数，防止溢出
+const MAX_INTEGER: usize = 1 << 28;
+
+// 静态表（RFC 7541 附录 A），索引从 1 开始
+const STATIC_TABLE: [(&str, &str); 61] = [
+    (":authority", ""),
+    (":method", "GET"),
+    (":method", "POST"),
+    (":path", "/"),
+    (":path", "/index.html"),
+    (":scheme", "http"),
+    (":scheme", "https"),
+    (":status", "200"),
+    (":status", "204"),
+    (":status", "206"),
+    (":status", "304"),
+    (":status", "400"),
+    (":status", "404"),
+    (":status", "500"),
+    ("accept-charset", ""),
+    ("accept-encoding", "gzip, deflate"),
+    ("accept-language", ""),
+    ("accept-ranges", ""),
+    ("accept", ""),
+    ("access-control-allow-origin", ""),
+    ("age", ""),
+    ("allow", ""),
+    ("authorization", ""),
+    ("cache-control", ""),
+    ("content-disposition", ""),
+    ("content-encoding", ""),
+    ("content-language", ""),
+    ("content-length", ""),
+    ("content-location", ""),
+    ("content-range", ""),
+    ("content-type", ""),
+    ("cookie", ""),
+    ("date", ""),
+    ("etag", ""),
+    ("expect", ""),
+    ("expires", ""),
+    ("from", ""),
+    ("host", ""),
+    ("if-match", ""),
+    ("if-modified-since", ""),
+    ("if-none-match", ""),
+    ("if-range", ""),
+    ("if-unmodified-since", ""),
+    ("last-modified", ""),
+    ("link", ""),
+    ("location", ""),
+    ("max-forwards", ""),
+    ("proxy-authenticate", ""),
+    ("proxy-authorization", ""),
+    ("range", ""),
+    ("referer", ""),
+    ("refresh", ""),
+    ("retry-after", ""),
+    ("server", ""),
+    ("set-cookie", ""),
+    ("strict-transport-security", ""),
+    ("transfer-encoding", ""),
+    ("user-agent", ""),
+    ("vary", ""),
+    ("via", ""),
+    ("www-authenticate", ""),
+];
+
+// Huffman 编码表（RFC 7541 附录 B），按符号排列的（编码，位数），最后一项是 EOS
+const HUFFMAN_CODES: [(u32, u8); 257] = [
+    (0x1ff8, 13), (0x7fffd8, 23), (0xfffffe2, 28), (0xfffffe3, 28), (0xfffffe4, 28), (0xfffffe5, 28),
+    (0xfffffe6, 28), (0xfffffe7, 28), (0xfffffe8, 28), (0xffffea, 24), (0x3ffffffc, 30), (0xfffffe9, 28),
+    (0xfffffea, 28), (0x3ffffffd, 30), (0xfffffeb, 28), (0xfffffec, 28), (0xfffffed, 28), (0xfffffee, 28),
+    (0xfffffef, 28), (0xffffff0, 28), (0xffffff1, 28), (0xffffff2, 28), (0x3ffffffe, 30), (0xffffff3, 28),
+    (0xffffff4, 28), (0xffffff5, 28), (0xffffff6, 28), (0xffffff7, 28), (0xffffff8, 28), (0xffffff9, 28),
+    (0xffffffa, 28), (0xffffffb, 28), (0x14, 6), (0x3f8, 10), (0x3f9, 10), (0xffa, 12),
+    (0x1ff9, 13), (0x15, 6), (0xf8, 8), (0x7fa, 11), (0x3fa, 10), (0x3fb, 10),
+    (0xf9, 8), (0x7fb, 11), (0xfa, 8), (0x16, 6), (0x17, 6), (0x18, 6),
+    (0x0, 5), (0x1, 5), (0x2, 5), (0x19, 6), (0x1a, 6), (0x1b, 6),
+    (0x1c, 6), (0x1d, 6), (0x1e, 6), (0x1f, 6), (0x5c, 7), (0xfb, 8),
+    (0x7ffc, 15), (0x20, 6), (0xffb, 12), (0x3fc, 10), (0x1ffa, 13), (0x21, 6),
+    (0x5d, 7), (0x5e, 7), (0x5f, 7), (0x60, 7), (0x61, 7), (0x62, 7),
+    (0x63, 7), (0x64, 7), (0x65, 7), (0x66, 7), (0x67, 7), (0x68, 7),
+    (0x69, 7), (0x6a, 7), (0x6b, 7), (0x6c, 7), (0x6d, 7), (0x6e, 7),
+    (0x6f, 7), (0x70, 7), (0x71, 7), (0x72, 7), (0xfc, 8), (0x73, 7),
+    (0xfd, 8), (0x1ffb, 13), (0x7fff0, 19), (0x1ffc, 13), (0x3ffc, 14), (0x22, 6),
+    (0x7ffd, 15), (0x3, 5), (0x23, 6), (0x4, 5), (0x24, 6), (0x5, 5),
+    (0x25, 6), (0x26, 6), (0x27, 6), (0x6, 5), (0x74, 7), (0x75, 7),
+    (0x28, 6), (0x29, 6), (0x2a, 6), (0x7, 5), (0x2b, 6), (0x76, 7),
+    (0x2c, 6), (0x8, 5), (0x9, 5), (0x2d, 6), (0x77, 7), (0x78, 7),
+    (0x79, 7), (0x7a, 7), (0x7b, 7), (0x7ffe, 15), (0x7fc, 11), (0x3ffd, 14),
+    (0x1ffd, 13), (0xffffffc, 28), (0xfffe6, 20), (0x3fffd2, 22), (0xfffe7, 20), (0xfffe8, 20),
+    (0x3fffd3, 22), (0x3fffd4, 22), (0x3fffd5, 22), (0x7fffd9, 23), (0x3fffd6, 22), (0x7fffda, 23),
+    (0x7fffdb, 23), (0x7fffdc, 23), (0x7fffdd, 23), (0x7fffde, 23), (0xffffeb, 24), (0x7fffdf, 23),
+    (0xffffec, 24), (0xffffed, 24), (0x3fffd7, 22), (0x7fffe0, 23), (0xffffee, 24), (0x7fffe1, 23),
+    (0x7fffe2, 23), (0x7fffe3, 23), (0x7fffe4, 23), (0x1fffdc, 21), (0x3fffd8, 22), (0x7fffe5, 23),
+    (0x3fffd9, 22), (0x7fffe6, 23), (0x7fffe7, 23), (0xffffef, 24), (0x3fffda, 22), (0x1fffdd, 21),
+    (0xfffe9, 20), (0x3fffdb, 22), (0x3fffdc, 22), (0x7fffe8, 23), (0x7fffe9, 23), (0x1fffde, 21),
+    (0x7fffea, 23), (0x3fffdd, 22), (0x3fffde, 22), (0xfffff0, 24), (0x1fffdf, 21), (0x3fffdf, 22),
+    (0x7fffeb, 23), (0x7fffec, 23), (0x1fffe0, 21), (0x1fffe1, 21), (0x3fffe0, 22), (0x1fffe2, 21),
+    (0x7fffed, 23), (0x3fffe1, 22), (0x7fffee, 23), (0x7fffef, 23), (0xfffea, 20), (0x3fffe2, 22),
+    (0x3fffe3, 22), (0x3fffe4, 22), (0x7ffff0, 23), (0x3fffe5, 22), (0x3fffe6, 22), (0x7ffff1, 23),
+    (0x3ffffe0, 26), (0x3ffffe1, 26), (0xfffeb, 20), (0x7fff1, 19), (0x3fffe7, 22), (0x7ffff2, 23),
+    (0x3fffe8, 22), (0x1ffffec, 25), (0x3ffffe2, 26), (0x3ffffe3, 26), (0x3ffffe4, 26), (0x7ffffde, 27),
+    (0x7ffffdf, 27), (0x3ffffe5, 26), (0xfffff1, 24), (0x1ffffed, 25), (0x7fff2, 19), (0x1fffe3, 21),
+    (0x3ffffe6, 26), (0x7ffffe0, 27), (0x7ffffe1, 27), (0x3ffffe7, 26), (0x7ffffe2, 27), (0xfffff2, 24),
+    (0x1fffe4, 21), (0x1fffe5, 21), (0x3ffffe8, 26), (0x3ffffe9, 26), (0xffffffd, 28), (0x7ffffe3, 27),
+    (0x7ffffe4, 27), (0x7ffffe5, 27), (0xfffec, 20), (0xfffff3, 24), (0xfffed, 20), (0x1fffe6, 21),
+    (0x3fffe9, 22), (0x1fffe7, 21), (0x1fffe8, 21), (0x7ffff3, 23), (0x3fffea, 22), (0x3fffeb, 22),
+    (0x1ffffee, 25), (0x1ffffef, 25), (0xfffff4, 24), (0xfffff5, 24), (0x3ffffea, 26), (0x7ffff4, 23),
+    (0x3ffffeb, 26), (0x7ffffe6, 27), (0x3ffffec, 26), (0x3ffffed, 26), (0x7ffffe7, 27), (0x7ffffe8, 27),
+    (0x7ffffe9, 27), (0x7ffffea, 27), (0x7ffffeb, 27), (0xffffffe, 28), (0x7ffffec, 27), (0x7ffffed, 27),
+    (0x7ffffee, 27), (0x7ffffef, 27), (0x7fffff0, 27), (0x3ffffee, 26), (0x3fffffff, 30),
+];
+
+// EOS 符号
+const EOS: usize = 256;
+
+// 定义 HPACK 解码错误，在 HTTP/2 中都属于 COMPRESSION_ERROR
+#[derive(Debug, Clone, PartialEq)]
+pub enum HpackError {
+    Truncated,              // 头部块在字段中间结束
+    IntegerOverflow,        // 整数超出允许范围
+    InvalidIndex(usize),    // 索引不存在
+    InvalidHuffman,         // Huffman 编码错误（包括非法的填充和 EOS）
+    InvalidTableSizeUpdate, // 动态表大小更新超出上限或不在头部块开头
+    HeaderListTooLarge,     // 解码后的头部列表超过上限
+}
+
+impl fmt::Display for HpackError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HpackError::Truncated => write!(f, "header block truncated"),
+            HpackError::IntegerOverflow => write!(f, "integer overflow"),
+            HpackError::InvalidIndex(index) => write!(f, "invalid table index {}", index),
+            HpackError::InvalidHuffman => write!(f, "invalid Huffman encoding"),
+            HpackError::InvalidTableSizeUpdate => write!(f, "invalid dynamic table size update"),
+            HpackError::HeaderListTooLarge => write!(f, "header list too large"),
+        }
+    }
+}
+
+impl std::error::Error for HpackError {}
+
+// 解码出的头部字段（名称，值），值不一定是合法的 UTF-8
+pub type HeaderField = (Vec<u8>, Vec<u8>);
+
+// 动态表，最新的条目在最前面
+#[derive(Debug)]
+struct DynamicTable {
+    entries: VecDeque<HeaderField>, // 条目
+    size: usize,                    // 当前大小
+    max_size: usize,                // 当前允许的最大大小
+}
+
+impl DynamicTable {
+    // 创建指定大小的动态表
+    fn new(max_size: usize) -> Self {
+        DynamicTable {
+            entries: VecDeque::new(),
+            size: 0,
+            max_size,
+        }
+    }
+
+    // 插入条目，必要时淘汰最旧的条目；条目本身超过上限时清空动态表
+    fn insert(&mut self, field: HeaderField) {
+        let size = field.0.len() + field.1.len() + ENTRY_OVERHEAD;
+        self.size += size;
+        self.entries.push_front(field);
+        self.evict();
+    }
+
+    // 修改上限并淘汰超出的条目
+    fn resize(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.evict();
+    }
+
+    // 淘汰条目直到不超过上限
+    fn evict(&mut self) {
+        while self.size > self.max_size {
+            match self.entries.pop_back() {
+                Some((name, value)) => self.size -= name.len() + value.len() + ENTRY_OVERHEAD,
+                None => break,
+            }
+        }
+    }
+
+    // 按 HPACK 索引查找条目，静态表之后是动态表
+    fn get(&self, index: usize) -> Result<(&[u8], &[u8]), HpackError> {
+        match index {
+            0 => Err(HpackError::InvalidIndex(0)),
+            1..=61 => {
+                let (name, value) = STATIC_TABLE[index - 1];
+                Ok((name.as_bytes(), value.as_bytes()))
+            }
+            _ => self
+                .entries
+                .get(index - 62)
+                .map(|(name, value)| (name.as_slice(), value.as_slice()))
+                .ok_or(HpackError::InvalidIndex(index)),
+        }
+    }
+}
+
+// HPACK 解码器，每个 HTTP/2 连接一个
+#[derive(Debug)]
+pub struct Decoder {
+    table: DynamicTable, // 动态表
+    max_size_limit: usize, // 通过 SETTINGS_HEADER_TABLE_SIZE 通告的上限
+    max_list_size: usize, // 解码后头部列表的上限，按 SETTINGS_MAX_HEADER_LIST_SIZE 的方式计算
+}
+
+impl Decoder {
+    // 创建解码器，max_table_size 是通告给对端的动态表上限
+    pub fn new(max_table_size: usize) -> Self {
+        Decoder {
+            table: DynamicTable::new(max_table_size),
+            max_size_limit: max_table_size,
+            max_list_size: usize::MAX,
+        }
+    }
+
+    // 设置解码后头部列表的上限（每个字段的名称、值加 32 字节），默认不限制
+    // 很短的头部块可以反复引用动态表中的大条目，不限制时解码结果可能比头部块大几千倍
+    pub fn max_header_list_size(mut self, size: usize) -> Self {
+        self.max_list_size = size;
+        self
+    }
+
+    // 解码一个完整的头部块，头部列表超过上限时返回 HeaderListTooLarge
+    pub fn decode(&mut self, block: &[u8]) -> Result<Vec<HeaderField>, HpackError> {
+        let mut fields = Vec::new();
+        let mut list_size = 0;
+        let mut pos = 0;
+        while pos < block.len() {
+            let byte = block[pos];
+            if byte & 0x80 != 0 {
+                // 索引字段
+                let index = decode_integer(block, &mut pos, 7)?;
+                let (name, value) = self.table.get(index)?;
+                fields.push((name.to_vec(), value.to_vec()));
+            } else if byte & 0x40 != 0 {
+                // 带增量索引的字面量
+                let field = self.decode_literal(block, &mut pos, 6)?;
+                self.table.insert(field.clone());
+                fields.push(field);
+            } else if byte & 0x20 != 0 {
+                // 动态表大小更新只能出现在头部块开头
+                if !fields.is_empty() {
+                    return Err(HpackError::InvalidTableSizeUpdate);
+                }
+                let size = decode_integer(block, &mut pos, 5)?;
+                if size > self.max_size_limit {
+                    return Err(HpackError::InvalidTableSizeUpdate);
+                }
+                self.table.resize(size);
+            } else {
+                // 不索引或永不索引的字面量
+                fields.push(self.decode_literal(block, &mut pos, 4)?);
+            }
+            // 动态表大小更新只出现在字段之前，这里的最后一个字段就是刚解码的字段
+            if let Some((name, value)) = fields.last() {
+                list_size += name.len() + value.len() + ENTRY_OVERHEAD;
+                if list_size > self.max_list_size {
+                    return Err(HpackError::HeaderListTooLarge);
+                }
+            }
+        }
+        Ok(fields)
+    }
+
+    // 解码字面量字段，名称可以引用表中的条目
+    fn decode_literal(&self, block: &[u8], pos: &mut usize, prefix: u8) -> Result<HeaderField, HpackError> {
+        let index = decode_integer(block, pos, prefix)?;
+        let name = match index {
+            0 => decode_string(block, pos)?,
+            _ => self.table.get(index)?.0.to_vec(),
+        };
+        Ok((name, decode_string(block, pos)?))
+    }
+}
+
+// HPACK 编码器，不使用动态表，因此不需要和对端同步状态
+#[derive(Debug, Default)]
+pub struct Encoder;
+
+impl Encoder {
+    // 创建编码器
+    pub fn new() -> Self {
+        Encoder
+    }
+
+    // 编码头部字段，名称必须是小写
+    pub fn encode<'a>(&self, fields: impl IntoIterator<Item = (&'a str, &'a str)>) -> Vec<u8> {
+        let mut block = Vec::new();
+        for (name, value) in fields {
+            if let Some(index) = STATIC_TABLE.iter().position(|&f| f == (name, value)) {
+                encode_integer(&mut block, index + 1, 7, 0x80);
+                continue;
+            }
+            // 不索引的字面量，名称尽量引用静态表
+            match STATIC_TABLE.iter().position(|&(n, _)| n == name) {
+                Some(index) => encode_integer(&mut block, index + 1, 4, 0x00),
+                None => {
+                    block.push(0x00);
+                    encode_string(&mut block, name.as_bytes());
+                }
+            }
+            encode_string(&mut block, value.as_bytes());
+        }
+        block
+    }
+}
+
+// 解码带 N 位前缀的整数（RFC 7541 第 5.1 节）
+fn decode_integer(block: &[u8], pos: &mut usize, prefix: u8) -> Result<usize, HpackError> {
+    let mask = (1usize << prefix) - 1;
+    let first = *block.get(*pos).ok_or(HpackError::Truncated)?;
+    *pos += 1;
+    let mut value = first as usize & mask;
+    if value < mask {
+        return Ok(value);
+    }
+    let mut shift = 0;
+    loop {
+        // 过多的续接字节会使移位溢出，在移位前拒绝
+        if shift > 28 {
+            return Err(HpackError::IntegerOverflow);
+        }
+        let byte = *block.get(*pos).ok_or(HpackError::Truncated)?;
+        *pos += 1;
+        value += ((byte & 0x7f) as usize) << shift;
+        if value > MAX_INTEGER {
+            return Err(HpackError::IntegerOverflow);
+        }
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+// 编码带 N 位前缀的整数，flags 是第一个字节中前缀以外的位
+fn encode_integer(block: &mut Vec<u8>, value: usize, prefix: u8, flags: u8) {
+    let mask = (1usize << prefix) - 1;
+    if value < mask {
+        block.push(flags | value as u8);
+        return;
+    }
+    block.push(flags | mask as u8);
+    let mut rest = value - mask;
+    while rest >= 0x80 {
+        block.push((rest & 0x7f) as u8 | 0x80);
+        rest >>= 7;
+    }
+    block.push(rest as u8);
+}
+
+// 解码字符串，可能经过 Huffman 编码
+fn decode_string(block: &[u8], pos: &mut usize) -> Result<Vec<u8>, HpackError> {
+    let huffman = block.get(*pos).ok_or(HpackError::Truncated)? & 0x80 != 0;
+    let len = decode_integer(block, pos, 7)?;
+    let end = pos.checked_add(len).filter(|&end| end <= block.len()).ok_or(HpackError::Truncated)?;
+    let data = &block[*pos..end];
+    *pos = end;
+    match huffman {
+        true => huffman_decode(data),
+        false => Ok(data.to_vec()),
+    }
+}
+
+// 编码字符串，Huffman 编码更短时使用 Huffman 编码
+fn encode_string(block: &mut Vec<u8>, data: &[u8]) {
+    let bits: usize = data.iter().map(|&b| HUFFMAN_CODES[b as usize].1 as usize).sum();
+    let huffman_len = bits.div_ceil(8);
+    if huffman_len >= data.len() {
+        encode_integer(block, data.len(), 7, 0x00);
+        block.extend_from_slice(data);
+        return;
+    }
+    encode_integer(block, huffman_len, 7, 0x80);
+    let mut acc: u64 = 0;
+    let mut acc_bits = 0;
+    for &b in data {
+        let (code, len) = HUFFMAN_CODES[b as usize];
+        acc = (acc << len) | code as u64;
+        acc_bits += len as usize;
+        while acc_bits >= 8 {
+            acc_bits -= 8;
+            block.push((acc >> acc_bits) as u8);
+        }
+    }
+    // 用 EOS 的高位（全 1）填充最后一个字节
+    if acc_bits > 0 {
+        block.push(((acc << (8 - acc_bits)) as u8) | (0xff >> acc_bits));
+    }
+}
+
+// Huffman 解码树，节点的两个子节点为非负数时是节点下标，为负数时是 -(符号 + 1)
+fn huffman_tree() -> &'static Vec<[i32; 2]> {
+    static TREE: OnceLock<Vec<[i32; 2]>> = OnceLock::new();
+    TREE.get_or_init(|| {
+        let mut tree = vec![[0i32; 2]];
+        for (symbol, &(code, len)) in HUFFMAN_CODES.iter().enumerate() {
+            let mut node = 0;
+            for i in (0..len).rev() {
+                let bit = ((code >> i) & 1) as usize;
+                if i == 0 {
+                    tree[node][bit] = -(symbol as i32 + 1);
+                } else {
+                    if tree[node][bit] == 0 {
+                        tree.push([0, 0]);
+                        tree[node][bit] = (tree.len() - 1) as i32;
+                    }
+                    node = tree[node][bit] as usize;
+                }
+            }
+        }
+        tree
+    })
+}
+
+// Huffman 解码，结尾的填充必须是不超过 7 位的全 1
+fn huffman_decode(data: &[u8]) -> Result<Vec<u8>, HpackError> {
+    let tree = huffman_tree();
+    let mut out = Vec::with_capacity(data.len() * 8 / 5);
+    let mut node = 0usize;
+    let mut pending_bits = 0; // 上一个符号之后读取的位数
+    let mut all_ones = true; // 这些位是否全是 1
+    for &byte in data {
+        for i in (0..8).rev() {
+            let bit = ((byte >> i) & 1) as usize;
+            pending_bits += 1;
+            all_ones &= bit == 1;
+            match tree[node][bit] {
+                0 => return Err(HpackError::InvalidHuffman),
+                next if next > 0 => node = next as usize,
+                leaf => {
+                    let symbol = (-leaf - 1) as usize;
+                    if symbol == EOS {
+                        return Err(HpackError::InvalidHuffman);
+                    }
+                    out.push(symbol as u8);
+                    node = 0;
+                    pending_bits = 0;
+                    all_ones = true;
+                }
+            }
+        }
+    }
+    if pending_bits > 7 || !all_ones {
+        return Err(HpackError::InvalidHuffman);
+    }
+    Ok(out)
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 把十六进制字符串转换为字节
+    fn hex(s: &str) -> Vec<u8> {
+        let s: String = s.split_whitespace().collect();
+        (0..s.len()).step_by(2).map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap()).collect()
+    }
+
+    // 把解码结果转换为字符串对
+    fn strings(fields: Vec<HeaderField>) -> Vec<(String, String)> {
+        fields
+            .into_iter()
+            .map(|(n, v)| (String::from_utf8(n).unwrap(), String::from_utf8(v).unwrap()))
+            .collect()
+    }
+
+    // 测试整数编解码（RFC 7541 C.1）
+    #[test]
+    fn test_integer() {
+        let mut block = Vec::new();
+        encode_integer(&mut block, 1337, 5, 0);
+        assert_eq!(block, vec![0x1f, 0x9a, 0x0a]);
+        let mut pos = 0;
+        assert_eq!(decode_integer(&block, &mut pos, 5), Ok(1337));
+        assert_eq!(decode_integer(&[0x1f, 0xff, 0xff, 0xff, 0xff, 0x7f], &mut 0, 5), Err(HpackError::IntegerOverflow));
+    }
+
+    // 测试 RFC 7541 C.4 中使用 Huffman 编码的连续请求，验证动态表
+    #[test]
+    fn test_rfc_requests_with_huffman() {
+        let mut decoder = Decoder::new(4096);
+        let first = decoder.decode(&hex("8286 8441 8cf1 e3c2 e5f2 3a6b a0ab 90f4 ff")).unwrap();
+        assert_eq!(
+            strings(first),
+            vec![
+                (":method".into(), "GET".into()),
+                (":scheme".into(), "http".into()),
+                (":path".into(), "/".into()),
+                (":authority".into(), "www.example.com".into()),
+            ]
+        );
+        let second = strings(decoder.decode(&hex("8286 84be 5886 a8eb 1064 9cbf")).unwrap());
+        assert_eq!(second[3], (":authority".into(), "www.example.com".into()));
+        assert_eq!(second[4], ("cache-control".into(), "no-cache".into()));
+        let third = decoder.decode(&hex("8287 85bf 4088 25a8 49e9 5ba9 7d7f 8925 a849 e95b b8e8 b4bf")).unwrap();
+        assert_eq!(strings(third)[4], ("custom-key".into(), "custom-value".into()));
+        assert_eq!(decoder.table.size, 164);
+    }
+
+    // 测试编码结果可以被解码
+    #[test]
+    fn test_encode_round_trip() {
+        let fields = [
+            (":status", "200"),
+            ("content-type", "application/json"),
+            ("set-cookie", "session_id=abc; Path=/; HttpOnly"),
+            ("x-custom", "\u{7f}binary\t"),
+        ];
+        let block = Encoder::new().encode(fields);
+        assert_eq!(block[0], 0x88);
+        let decoded = strings(Decoder::new(4096).decode(&block).unwrap());
+        let expected: Vec<(String, String)> = fields.iter().map(|(n, v)| (n.to_string(), v.to_string())).collect();
+        assert_eq!(decoded, expected);
+    }
+
+    // 测试非法输入
+    #[test]
+    fn test_invalid_blocks() {
+        let mut decoder = Decoder::new(4096);
+        assert_eq!(decoder.decode(&[0x80]), Err(HpackError::InvalidIndex(0)));
+        assert_eq!(decoder.decode(&[0xbe]), Err(HpackError::InvalidIndex(62)));
+        assert_eq!(decoder.decode(&[0x04, 0x83, 0x61]), Err(HpackError::Truncated));
+        // 填充超过 7 位
+        assert_eq!(decoder.decode(&[0x04, 0x82, 0x63, 0xff]), Err(HpackError::InvalidHuffman));
+        // 动态表大小更新超出上限，或出现在字段之后
+        assert_eq!(decoder.decode(&[0x3f, 0xe2, 0x1f]), Err(HpackError::InvalidTableSizeUpdate));
+        assert_eq!(decoder.decode(&[0x82, 0x20]), Err(HpackError::InvalidTableSizeUpdate));
+        assert!(decoder.decode(&[0x20, 0x82]).is_ok());
+        // 续接字节过多的整数
+        let mut block = vec![0xff];
+        block.extend_from_slice(&[0x80; 12]);
+        block.push(0x00);
+        assert_eq!(decoder.decode(&block), Err(HpackError::IntegerOverflow));
+    }
+
+    // 测试反复引用动态表中的大条目时头部列表受到限制
+    #[test]
+    fn test_header_list_limit() {
+        let mut decoder = Decoder::new(4096).max_header_list_size(1024);
+        let value = "x".repeat(500);
+        // 带增量索引的字面量插入动态表，之后每个 0xbe 都引用它
+        let mut block = vec![0x40];
+        encode_string(&mut block, b"big");
+        encode_string(&mut block, value.as_bytes());
+        assert_eq!(decoder.decode(&block).unwrap().len(), 1);
+        assert_eq!(decoder.decode(&[0xbe]).unwrap().len(), 1);
+        assert_eq!(decoder.decode(&[0xbe; 100]), Err(HpackError::HeaderListTooLarge));
+    }
+}
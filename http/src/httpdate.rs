@@ -0,0 +1,106 @@
+// 导入所需的模块
+use std::time::{Duration, SystemTime, UNIX_EPOCH}; // 导入时间模块
+
+// 星期和月份的英文缩写
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+// 将 1970-01-01 以来的天数转换为 (年, 月, 日)，算法来自 Howard Hinnant 的 civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+// 将 UTC 时间拆分为 (年, 月, 日, 时, 分, 秒, 星期索引)
+pub(crate) fn split_time(time: SystemTime) -> (i64, u32, u32, u64, u64, u64, usize) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let days = (secs / 86_400) as i64;
+    let (year, month, day) = civil_from_days(days);
+    let rem = secs % 86_400;
+    (
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60,
+        (days % 7) as usize,
+    )
+}
+
+// 将时间格式化为 HTTP 日期（IMF-fixdate），例如 "Sun, 06 Nov 1994 08:49:37 GMT"
+pub fn format_http_date(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, weekday) = split_time(time);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        WEEKDAYS[weekday],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+// 将时间格式化为通用日志格式（CLF）中的时间，例如 "06/Nov/1994:08:49:37 +0000"
+pub fn format_log_date(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, _) = split_time(time);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+// 将时间格式化为带毫秒的 RFC 3339 UTC 时间，例如 "1994-11-06T08:49:37.250Z"
+pub fn format_rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second, _) = split_time(time);
+    let millis = time.duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO).subsec_millis();
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, hour, minute, second, millis
+    )
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 测试 HTTP 日期格式
+    #[test]
+    fn test_format_http_date() {
+        let time = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        assert_eq!(format_http_date(time), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_164_800);
+        assert_eq!(format_http_date(leap_day), "Thu, 29 Feb 2024 00:00:00 GMT");
+    }
+
+    // 测试日志中使用的时间格式
+    #[test]
+    fn test_format_log_dates() {
+        let time = UNIX_EPOCH + Duration::from_millis(784_111_777_250);
+        assert_eq!(format_log_date(time), "06/Nov/1994:08:49:37 +0000");
+        assert_eq!(format_rfc3339(time), "1994-11-06T08:49:37.250Z");
+        assert_eq!(format_rfc3339(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+    }
+}
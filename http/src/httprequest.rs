@@ -1,11 +1,18 @@
 // 导入标准库中的 HashMap，用于存储请求头
 use std::collections::HashMap;
+use super::chunked::RequestTrailers; // 导入请求的尾部头部
+use super::extensions::Extensions; // 导入附加数据容器
 
 // 定义一个枚举类型 Method，表示 HTTP 方法
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Method {
     Get,         // GET 方法
     Post,        // POST 方法
+    Put,         // PUT 方法
+    Patch,       // PATCH 方法
+    Delete,      // DELETE 方法
+    Head,        // HEAD 方法，只返回响应头
+    Options,     // OPTIONS 方法，用于 CORS 预检
     Uninitialized, // 未初始化的状态
 }
 
@@ -15,14 +22,37 @@ impl From<&str> for Method {
         match value {
             "GET" => Method::Get,        // 将字符串 "GET" 转换为 Method::Get
             "POST" => Method::Post,      // 将字符串 "POST" 转换为 Method::Post
+            "PUT" => Method::Put,        // 将字符串 "PUT" 转换为 Method::Put
+            "PATCH" => Method::Patch,    // 将字符串 "PATCH" 转换为 Method::Patch
+            "DELETE" => Method::Delete,  // 将字符串 "DELETE" 转换为 Method::Delete
+            "HEAD" => Method::Head,      // 将字符串 "HEAD" 转换为 Method::Head
+            "OPTIONS" => Method::Options, // 将字符串 "OPTIONS" 转换为 Method::Options
             _ => Method::Uninitialized,  // 其他情况返回未初始化状态
         }
     }
 }
 
+// 为 Method 实现辅助方法
+impl Method {
+    // 返回请求行中使用的方法名
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Head => "HEAD",
+            Method::Options => "OPTIONS",
+            Method::Uninitialized => "",
+        }
+    }
+}
+
 // 定义一个枚举类型 Version，表示 HTTP 版本
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Version {
+    V1_0,             // HTTP/1.0 版本
     V1_1,             // HTTP/1.1 版本
     V2_0,             // HTTP/2.0 版本
     Uninitialized,    // 未初始化的状态
@@ -32,12 +62,33 @@ pub enum Version {
 impl From<&str> for Version {
     fn from(value: &str) -> Version {
         match value {
+            "HTTP/1.0" => Version::V1_0, // 将字符串 "HTTP/1.0" 转换为 Version::V1_0
             "HTTP/1.1" => Version::V1_1, // 将字符串 "HTTP/1.1" 转换为 Version::V1_1
+            "HTTP/2.0" => Version::V2_0, // 将字符串 "HTTP/2.0" 转换为 Version::V2_0
+            // 更高的 1.x 次版本按支持的最高次版本 1.1 处理（RFC 9110 第 2.5 节）
+            v if v.strip_prefix("HTTP/1.").is_some_and(|minor| !minor.is_empty() && minor.bytes().all(|b| b.is_ascii_digit())) => Version::V1_1,
             _ => Version::Uninitialized,   // 其他情况返回未初始化状态
         }
     }
 }
 
+// 为 Version 实现辅助方法
+impl Version {
+    // 返回状态行中使用的版本字符串
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::V1_0 => "HTTP/1.0",
+            Version::V2_0 => "HTTP/2.0",
+            _ => "HTTP/1.1",
+        }
+    }
+
+    // 是否支持分块传输编码，HTTP/1.0 不支持
+    pub fn supports_chunked(&self) -> bool {
+        *self == Version::V1_1
+    }
+}
+
 // 定义一个枚举类型 Resource，表示请求的资源
 #[derive(Debug, PartialEq)]
 pub enum Resource {
@@ -52,6 +103,8 @@ pub struct HttpRequest {
     pub resource: Resource,        // 请求资源
     pub headers: HashMap<String, String>, // 请求头
     pub msg_body: String,          // 请求消息体
+    pub raw_body: Vec<u8>,         // 原始字节形式的消息体，用于文件上传等二进制内容
+    pub extensions: Extensions,    // 中间件附加到请求上的数据
 }
 
 // 为 HttpRequest 实现从字符串转换的功能
@@ -62,22 +115,28 @@ impl From<String> for HttpRequest {
         let mut parsed_version = Version::V1_1;        // 解析后的 HTTP 版本
         let mut parsed_resource = Resource::Path("".to_string()); // 解析后的资源路径
         let mut parsed_headers = HashMap::new();       // 解析后的请求头
-        let mut parsed_msg_body = "";                   // 解析后的消息体
-        
-        // 按行解析请求字符串
-        for line in req.lines() {
-            if line.contains("HTTP") { // 检查是否是请求行
-                let (method, resource, version) = process_req_line(line); // 处理请求行
+
+        // 以第一个空行为界，将请求分为头部和消息体
+        let (head, parsed_msg_body) = match req.find("\r\n\r\n") {
+            Some(pos) => (&req[..pos], &req[pos + 4..]),
+            None => match req.find("\n\n") {
+                Some(pos) => (&req[..pos], &req[pos + 2..]),
+                None => (req.as_str(), ""),
+            },
+        };
+
+        // 按行解析请求头部
+        for (i, line) in head.lines().enumerate() {
+            if i == 0 { // 第一行是请求行
+                // 请求行不是“方法 资源 版本”三部分时方法和版本都是未初始化状态，服务器据此返回 400
+                let (method, resource, version) = process_req_line(line)
+                    .unwrap_or((Method::Uninitialized, Resource::Path(String::new()), Version::Uninitialized));
                 parsed_method = method; // 设置解析后的请求方法
                 parsed_resource = resource; // 设置解析后的资源路径
                 parsed_version = version; // 设置解析后的 HTTP 版本
             } else if line.contains(":") { // 检查是否是请求头行
                 let (key, value) = process_header_line(line); // 处理请求头行
                 parsed_headers.insert(key, value); // 将请求头插入 HashMap
-            } else if line.len() == 0 { // 检查是否是空行，表示请求头结束
-                break; // 退出循环
-            } else { // 处理消息体
-                parsed_msg_body = line; // 设置消息体
             }
         }
 
@@ -88,27 +147,126 @@ impl From<String> for HttpRequest {
             resource: parsed_resource,   // 请求资源
             headers: parsed_headers,     // 请求头
             msg_body: parsed_msg_body.to_string(), // 消息体
+            raw_body: parsed_msg_body.as_bytes().to_vec(), // 原始消息体
+            extensions: Extensions::new(), // 附加数据
         }
     }
 }
 
-// 处理请求行的函数，返回请求方法、资源和版本
-fn process_req_line(s: &str) -> (Method, Resource, Version) {
+// 为 HttpRequest 实现从字节转换的功能，消息体按原始字节保留
+impl From<Vec<u8>> for HttpRequest {
+    fn from(req: Vec<u8>) -> Self {
+        // 查找请求头结束的空行
+        let header_end = req
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|pos| pos + 4)
+            .unwrap_or(req.len());
+        let head = String::from_utf8_lossy(&req[..header_end]).into_owned();
+        let mut parsed: HttpRequest = head.into();
+        parsed.raw_body = req[header_end..].to_vec();
+        parsed.msg_body = String::from_utf8_lossy(&parsed.raw_body).into_owned();
+        parsed
+    }
+}
+
+// 为 HttpRequest 实现辅助方法
+impl HttpRequest {
+    // 创建一个没有请求头和消息体的 HTTP/1.1 请求，客户端发送请求时使用
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        HttpRequest {
+            method,
+            version: Version::V1_1,
+            resource: Resource::Path(path.into()),
+            headers: HashMap::new(),
+            msg_body: String::new(),
+            raw_body: Vec::new(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    // 设置请求头，替换已有的同名头部（名称不区分大小写）
+    pub fn set_header(&mut self, name: &str, value: impl Into<String>) {
+        self.headers.retain(|k, _| !k.eq_ignore_ascii_case(name));
+        self.headers.insert(name.to_string(), value.into());
+    }
+
+    // 删除请求头（名称不区分大小写）
+    pub fn remove_header(&mut self, name: &str) {
+        self.headers.retain(|k, _| !k.eq_ignore_ascii_case(name));
+    }
+
+    // 设置消息体
+    pub fn set_body(&mut self, body: impl Into<Vec<u8>>) {
+        self.raw_body = body.into();
+        self.msg_body = String::from_utf8_lossy(&self.raw_body).into_owned();
+    }
+
+    // 序列化为发送到连接上的字节：请求行、请求头、空行和消息体
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let Resource::Path(path) = &self.resource;
+        let mut data = format!("{} {} {}\r\n", self.method.as_str(), path, self.version.as_str()).into_bytes();
+        for (name, value) in &self.headers {
+            data.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        data.extend_from_slice(b"\r\n");
+        data.extend_from_slice(&self.raw_body);
+        data
+    }
+
+    // 按名称查找请求头，名称不区分大小写
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // 按名称查找请求的尾部头部，名称不区分大小写；尾部头部与请求头分开保存
+    pub fn trailer(&self, name: &str) -> Option<&str> {
+        self.extensions
+            .get::<RequestTrailers>()?
+            .0
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    // 连接在响应后是否保持打开
+    // HTTP/1.1 默认保持，除非 Connection: close；HTTP/1.0 只有 Connection: keep-alive 时保持
+    pub fn keep_alive(&self) -> bool {
+        let has_token = |token: &str| {
+            self.header("Connection")
+                .is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+        };
+        match self.version {
+            Version::V1_1 => !has_token("close"),
+            Version::V1_0 => has_token("keep-alive") && !has_token("close"),
+            _ => false,
+        }
+    }
+}
+
+// 处理请求行的函数，返回请求方法、资源和版本；请求行不是恰好三部分时返回 None
+fn process_req_line(s: &str) -> Option<(Method, Resource, Version)> {
     let mut words = s.split_whitespace(); // 按空白字符分割请求行
-    let method = words.next().unwrap(); // 获取请求方法
-    let resource = words.next().unwrap(); // 获取请求资源
-    let version = words.next().unwrap(); // 获取 HTTP 版本
+    let method = words.next()?; // 获取请求方法
+    let resource = words.next()?; // 获取请求资源
+    let version = words.next()?; // 获取 HTTP 版本
+    if words.next().is_some() {
+        return None;
+    }
 
-    (
+    Some((
         method.into(), // 转换请求方法
         Resource::Path(resource.to_string()), // 创建 Resource::Path
         version.into(), // 转换 HTTP 版本
-    )
+    ))
 }
 
 // 处理请求头行的函数，返回键值对
 fn process_header_line(s: &str) -> (String, String) {
-    let mut header_items = s.split(':'); // 按冒号分割请求头
+    let mut header_items = s.splitn(2, ':'); // 按第一个冒号分割请求头，值中可能含有冒号
     let mut key = String::from(""); // 初始化键
     let mut value = String::from(""); // 初始化值
     if let Some(k) = header_items.next() { // 获取键
@@ -131,6 +289,8 @@ mod tests {
     fn test_method_into() {
         let m: Method = "GET".into(); // 从字符串转换为 Method
         assert_eq!(m, Method::Get); // 断言转换结果
+        let m: Method = "OPTIONS".into();
+        assert_eq!(m, Method::Options);
     }
 
     // 测试 Version 从字符串转换的功能
@@ -138,6 +298,21 @@ mod tests {
     fn test_version_into() {
         let v: Version = "HTTP/1.1".into(); // 从字符串转换为 Version
         assert_eq!(v, Version::V1_1); // 断言转换结果
+        assert_eq!(Version::from("HTTP/1.0"), Version::V1_0);
+        assert_eq!(Version::from("HTTP/1.2"), Version::V1_1);
+        assert_eq!(Version::from("HTTP/3.0"), Version::Uninitialized);
+        assert_eq!(Version::V1_0.as_str(), "HTTP/1.0");
+        assert!(!Version::V1_0.supports_chunked());
+    }
+
+    // 测试不同版本的默认连接保持行为
+    #[test]
+    fn test_keep_alive() {
+        let req = |s: &str| HttpRequest::from(s.to_string());
+        assert!(req("GET / HTTP/1.1\r\nHost: a\r\n\r\n").keep_alive());
+        assert!(!req("GET / HTTP/1.1\r\nConnection: close\r\n\r\n").keep_alive());
+        assert!(!req("GET / HTTP/1.0\r\n\r\n").keep_alive());
+        assert!(req("GET / HTTP/1.0\r\nConnection: Keep-Alive\r\n\r\n").keep_alive());
     }
 
     // 测试 HTTP 请求的解析功能
@@ -159,4 +334,59 @@ mod tests {
         // 直接比较 HashMap
         assert_eq!(headers_expected, req.headers); // 断言解析后的请求头与预期一致
     }
+
+    // 测试请求头的值中包含 "HTTP" 时不会被当作请求行
+    #[test]
+    fn test_header_containing_http() {
+        let s = String::from("GET / HTTP/1.1\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQCAAAAAAIAAAAA\r\n\r\n");
+        let req: HttpRequest = s.into();
+        assert_eq!(Resource::Path("/".to_string()), req.resource);
+        assert_eq!(Some("AAMAAABkAAQCAAAAAAIAAAAA"), req.header("http2-settings"));
+    }
+
+    // 测试不完整的请求行不会导致 panic，方法和版本为未初始化状态
+    #[test]
+    fn test_malformed_request_line() {
+        for raw in ["GET\r\n\r\n", "GET /\r\n\r\n", "GET HTTP\r\n\r\n", "GET / HTTP/1.1 x\r\n\r\n"] {
+            let req: HttpRequest = raw.to_string().into();
+            assert_eq!(req.method, Method::Uninitialized);
+            assert_eq!(req.version, Version::Uninitialized);
+        }
+    }
+
+    // 测试多行消息体会被完整保留
+    #[test]
+    fn test_read_http_body() {
+        let s: String = String::from("POST /api/shipping/orders/2/status HTTP/1.1\r\nContent-Type: application/json\r\n\r\n{\n  \"order_status\": \"Shipped\"\n}");
+        let req: HttpRequest = s.into();
+        assert_eq!(Method::Post, req.method);
+        assert_eq!(Some("application/json"), req.header("content-type"));
+        assert_eq!("{\n  \"order_status\": \"Shipped\"\n}", req.msg_body);
+    }
+
+    // 测试从字节解析时保留二进制消息体
+    #[test]
+    fn test_read_http_bytes() {
+        let mut raw = b"POST /upload HTTP/1.1\r\nContent-Length: 3\r\n\r\n".to_vec();
+        raw.extend_from_slice(&[0xff, 0x00, 0x41]);
+        let req: HttpRequest = raw.into();
+        assert_eq!(Method::Post, req.method);
+        assert_eq!(vec![0xff, 0x00, 0x41], req.raw_body);
+    }
+
+    // 测试构造的请求序列化后可以被解析回来
+    #[test]
+    fn test_request_to_bytes() {
+        let mut req = HttpRequest::new(Method::Put, "/api/orders/1?x=y");
+        req.set_header("Content-Type", "text/plain");
+        req.set_header("content-type", "application/json");
+        req.set_body(b"{}".to_vec());
+        let parsed: HttpRequest = req.to_bytes().into();
+        assert_eq!(parsed.method, Method::Put);
+        assert_eq!(parsed.resource, Resource::Path("/api/orders/1?x=y".into()));
+        assert_eq!(parsed.headers.len(), 1);
+        assert_eq!(parsed.header("Content-Type"), Some("application/json"));
+        assert_eq!(parsed.raw_body, b"{}");
+        assert_eq!(Method::from("DELETE").as_str(), "DELETE");
+    }
 }
@@ -1,6 +1,24 @@
 // 导入标准库中的 HashMap 和 Result, Write 模块
 use std::collections::HashMap;
+use std::fmt;
 use std::io::{Result, Write};
+use std::sync::OnceLock;
+use super::chunked::{self, BodyStream, BodyWriter, Chunk, ChunkedError, Trailers};
+use super::cookie::Cookie;
+
+// 状态行和响应头的大小上限
+const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+// 是否是 RFC 9110 的 token，头部名称和 Cookie 名称都必须是 token
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+}
+
+// 是否是合法的头部值：除水平制表符外不能包含控制字符
+fn is_field_value(s: &str) -> bool {
+    !s.bytes().any(|b| b.is_ascii_control() && b != b'\t')
+}
 
 // 定义 HttpResponse 结构体，表示 HTTP 响应
 #[derive(Debug, PartialEq, Clone)]
@@ -9,18 +27,22 @@ pub struct HttpResponse<'a> {
     status_code: &'a str,                 // 状态码
     status_text: &'a str,                  // 状态文本
     headers: Option<HashMap<&'a str, &'a str>>, // 可选的请求头
+    extra_headers: Vec<(String, String)>,  // 运行时生成的响应头，同名头部可以出现多次（如 Set-Cookie）
     body: Option<String>,                  // 可选的消息体
+    stream: Option<BodyStream>,            // 流式消息体，设置时使用分块传输编码发送
 }
 
 // 为 HttpResponse 实现 Default trait，提供默认值
 impl<'a> Default for HttpResponse<'a> {
     fn default() -> Self {
         Self {
-            version: "HTTP/1.1".into(),   // 默认版本为 HTTP/1.1
-            status_code: "200".into(),     // 默认状态码为 200
-            status_text: "OK".into(),      // 默认状态文本为 OK
+            version: "HTTP/1.1",   // 默认版本为 HTTP/1.1
+            status_code: "200",     // 默认状态码为 200
+            status_text: "OK",      // 默认状态文本为 OK
             headers: None,                  // 默认无请求头
+            extra_headers: Vec::new(),      // 默认无额外响应头
             body: None,                     // 默认无消息体
+            stream: None,                   // 默认不是流式响应
         }
     }
 }
@@ -38,7 +60,7 @@ impl<'a> HttpResponse<'a> {
 
         // 如果状态码不是 200，则设置状态码
         if status_code != "200" {
-            response.status_code = status_code.into();
+            response.status_code = status_code;
         };
 
         // 设置响应头，如果未提供则使用默认的 Content-Type
@@ -52,42 +74,126 @@ impl<'a> HttpResponse<'a> {
         };
 
         // 根据状态码设置状态文本
-        response.status_text = match response.status_code {
-            "200" => "OK".into(),                     // 200 状态返回 OK
-            "400" => "Bad Request".into(),             // 400 状态返回 Bad Request
-            "404" => "Not Found".into(),               // 404 状态返回 Not Found
-            "500" => "Internal Server Error".into(),  // 500 状态返回 Internal Server Error
-            _ => "Not Found".into(),                   // 其他状态返回 Not Found
-        };
+        response.status_text = reason_phrase(response.status_code);
 
         // 设置消息体
         response.body = body;
         response // 返回创建的 HttpResponse
     }
 
+    // 创建一个流式响应，消息体由处理器通过返回的写入器逐步写入
+    // HTTP/1.1 使用分块传输编码发送，HTTP/1.0 直接发送数据并在结束时关闭连接
+    pub fn streaming(status_code: &'a str, headers: Option<HashMap<&'a str, &'a str>>) -> (HttpResponse<'a>, BodyWriter) {
+        let (writer, stream) = chunked::body_channel();
+        let mut response = HttpResponse::new(status_code, headers, None);
+        response.stream = Some(stream);
+        (response, writer)
+    }
+
+    // 设置状态行中的 HTTP 版本，应与请求的版本一致
+    pub fn set_version(&mut self, version: &'a str) {
+        self.version = version;
+    }
+
+    // 追加一个响应头，不会覆盖已有的同名头部
+    // 名称不是 token 或值包含控制字符（如 CR、LF）时拒绝添加，防止注入额外的头部或响应
+    pub fn add_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let (name, value) = (name.into(), value.into());
+        if !is_token(&name) || !is_field_value(&value) {
+            eprintln!("Rejected invalid response header {:?}", name);
+            return;
+        }
+        self.extra_headers.push((name, value));
+    }
+
+    // 追加一个 Set-Cookie 响应头
+    pub fn add_cookie(&mut self, cookie: &Cookie) {
+        self.add_header("Set-Cookie", cookie.to_string());
+    }
+
     // 发送响应到写入流
     pub fn send_response(&self, write_stream: &mut impl Write) -> Result<()> {
         let res = self.clone(); // 克隆响应
         let response_string: String = String::from(res); // 将响应转换为字符串
-        let _ = write!(write_stream, "{}", response_string); // 将字符串写入流
-        Ok(()) // 返回成功结果
+        let Some(stream) = &self.stream else {
+            let _ = write!(write_stream, "{}", response_string); // 将字符串写入流
+            return Ok(()); // 返回成功结果
+        };
+
+        // 流式响应：先发送响应头，再逐个发送生产者写入的分块
+        write_stream.write_all(response_string.as_bytes())?;
+        write_stream.flush()?;
+        let chunked = self.is_chunked();
+        loop {
+            match stream.recv() {
+                Some(Chunk::Data(data)) if chunked => chunked::write_chunk(write_stream, &data)?,
+                Some(Chunk::Data(data)) => write_stream.write_all(&data)?,
+                Some(Chunk::End(trailers)) => {
+                    if chunked {
+                        chunked::write_last_chunk(write_stream, &trailers)?;
+                    }
+                    return write_stream.flush();
+                }
+                // 生产者没有正常结束，返回错误让调用方关闭连接
+                None => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "response stream aborted",
+                    ))
+                }
+            }
+            write_stream.flush()?;
+        }
+    }
+
+    // 转换为不借用任何数据的响应，可以交给其他线程发送
+    // 响应头都转为运行时生成的响应头，状态文本按状态码重新查表，版本由发送方通过 set_version 设置
+    pub fn into_owned(self) -> HttpResponse<'static> {
+        let status_code = self.status_code.parse().ok().and_then(status_code_str).unwrap_or("500");
+        let mut extra_headers: Vec<(String, String)> =
+            self.headers.iter().flatten().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        extra_headers.extend(self.extra_headers);
+        HttpResponse {
+            status_code,
+            status_text: reason_phrase(status_code),
+            headers: Some(HashMap::new()),
+            extra_headers,
+            body: self.body,
+            stream: self.stream,
+            ..HttpResponse::default()
+        }
+    }
+
+    // 是否是流式响应
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    // 流式响应的消息体
+    pub fn stream(&self) -> Option<&BodyStream> {
+        self.stream.as_ref()
+    }
+
+    // 是否使用分块传输编码：流式响应且不是 HTTP/1.0
+    fn is_chunked(&self) -> bool {
+        self.stream.is_some() && self.version != "HTTP/1.0"
     }
 }
 
 // 为 HttpResponse 实现访问器方法
 impl<'a> HttpResponse<'a> {
     // 返回 HTTP 版本
-    fn version(&self) -> &str {
+    pub fn version(&self) -> &str {
         self.version
     }
 
     // 返回状态码
-    fn status_code(&self) -> &str {
+    pub fn status_code(&self) -> &str {
         self.status_code
     }
 
     // 返回状态文本
-    fn status_text(&self) -> &str {
+    pub fn status_text(&self) -> &str {
         self.status_text
     }
 
@@ -99,9 +205,28 @@ impl<'a> HttpResponse<'a> {
         for (k, v) in map.iter() {
             header_string = format!("{}{}:{}\r\n", header_string, k, v); // 添加头部
         }
+        // 追加运行时生成的响应头
+        for (k, v) in self.extra_headers.iter() {
+            header_string = format!("{}{}:{}\r\n", header_string, k, v);
+        }
         header_string // 返回格式化后的头字符串
     }
 
+    // 返回全部响应头（名称，值），HTTP/2 等需要逐个编码头部的场景使用
+    pub fn header_list(&self) -> Vec<(&str, &str)> {
+        let mut list: Vec<(&str, &str)> = self.headers.iter().flatten().map(|(k, v)| (*k, *v)).collect();
+        list.extend(self.extra_headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        list
+    }
+
+    // 按名称查找响应头，名称不区分大小写
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.header_list()
+            .into_iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
     // 返回消息体
     pub fn body(&self) -> &str {
         match &self.body {
@@ -116,23 +241,349 @@ impl <'a> From<HttpResponse<'a>> for String {
     fn from(res: HttpResponse<'a>) -> Self {
         let res1 = res.clone(); // 克隆响应
         let body_len = res.body.as_ref().map_or(0, |b| b.len()); // 计算消息体长度，如果为 None 则返回 0
+        // 1xx、204 和 304 响应不能携带消息体，不发送 Content-Length；流式响应的长度未知
+        let content_length = match res.status_code {
+            "101" | "204" | "304" => String::new(),
+            _ if res.is_chunked() => "Transfer-Encoding: chunked\r\n".to_string(),
+            _ if res.is_streaming() => String::new(),
+            _ => format!("Content-Length: {}\r\n", body_len),
+        };
         format!(
-            "{} {} {}\r\n{}Content-Length: {}\r\n\r\n{}", // 格式化字符串
+            "{} {} {}\r\n{}{}\r\n{}", // 格式化字符串
             &res1.version(), // 添加版本
             &res1.status_code(), // 添加状态码
             &res1.status_text(), // 添加状态文本
             &res1.headers(), // 添加请求头
-            body_len, // 添加消息体长度
+            content_length, // 添加消息体长度
             &res1.body() // 添加消息体
         )
     }
 }
 
+// 状态码对应的状态文本，代理转发的任意状态码都经过这里
+fn reason_phrase(status_code: &str) -> &'static str {
+    match status_code {
+        "100" => "Continue",                // 100 状态返回 Continue
+        "101" => "Switching Protocols",     // 101 状态返回 Switching Protocols
+        "200" => "OK",                     // 200 状态返回 OK
+        "201" => "Created",                 // 201 状态返回 Created
+        "202" => "Accepted",                // 202 状态返回 Accepted
+        "203" => "Non-Authoritative Information", // 203 状态返回 Non-Authoritative Information
+        "204" => "No Content",              // 204 状态返回 No Content
+        "205" => "Reset Content",           // 205 状态返回 Reset Content
+        "206" => "Partial Content",         // 206 状态返回 Partial Content
+        "300" => "Multiple Choices",        // 300 状态返回 Multiple Choices
+        "301" => "Moved Permanently",       // 301 状态返回 Moved Permanently
+        "302" => "Found",                   // 302 状态返回 Found
+        "303" => "See Other",               // 303 状态返回 See Other
+        "304" => "Not Modified",            // 304 状态返回 Not Modified
+        "307" => "Temporary Redirect",      // 307 状态返回 Temporary Redirect
+        "308" => "Permanent Redirect",      // 308 状态返回 Permanent Redirect
+        "400" => "Bad Request",             // 400 状态返回 Bad Request
+        "401" => "Unauthorized",            // 401 状态返回 Unauthorized
+        "403" => "Forbidden",               // 403 状态返回 Forbidden
+        "404" => "Not Found",               // 404 状态返回 Not Found
+        "405" => "Method Not Allowed",      // 405 状态返回 Method Not Allowed
+        "406" => "Not Acceptable",          // 406 状态返回 Not Acceptable
+        "407" => "Proxy Authentication Required", // 407 状态返回 Proxy Authentication Required
+        "408" => "Request Timeout",         // 408 状态返回 Request Timeout
+        "409" => "Conflict",                // 409 状态返回 Conflict
+        "410" => "Gone",                    // 410 状态返回 Gone
+        "411" => "Length Required",         // 411 状态返回 Length Required
+        "412" => "Precondition Failed",     // 412 状态返回 Precondition Failed
+        "413" => "Payload Too Large",       // 413 状态返回 Payload Too Large
+        "414" => "URI Too Long",            // 414 状态返回 URI Too Long
+        "415" => "Unsupported Media Type",  // 415 状态返回 Unsupported Media Type
+        "416" => "Range Not Satisfiable",   // 416 状态返回 Range Not Satisfiable
+        "417" => "Expectation Failed",      // 417 状态返回 Expectation Failed
+        "421" => "Misdirected Request",     // 421 状态返回 Misdirected Request
+        "422" => "Unprocessable Entity",    // 422 状态返回 Unprocessable Entity
+        "423" => "Locked",                  // 423 状态返回 Locked
+        "424" => "Failed Dependency",       // 424 状态返回 Failed Dependency
+        "426" => "Upgrade Required",        // 426 状态返回 Upgrade Required
+        "428" => "Precondition Required",   // 428 状态返回 Precondition Required
+        "429" => "Too Many Requests",       // 429 状态返回 Too Many Requests
+        "431" => "Request Header Fields Too Large", // 431 状态返回 Request Header Fields Too Large
+        "451" => "Unavailable For Legal Reasons", // 451 状态返回 Unavailable For Legal Reasons
+        "500" => "Internal Server Error",  // 500 状态返回 Internal Server Error
+        "501" => "Not Implemented",         // 501 状态返回 Not Implemented
+        "502" => "Bad Gateway",             // 502 状态返回 Bad Gateway
+        "503" => "Service Unavailable",     // 503 状态返回 Service Unavailable
+        "504" => "Gateway Timeout",         // 504 状态返回 Gateway Timeout
+        "505" => "HTTP Version Not Supported", // 505 状态返回 HTTP Version Not Supported
+        "507" => "Insufficient Storage",    // 507 状态返回 Insufficient Storage
+        "511" => "Network Authentication Required", // 511 状态返回 Network Authentication Required
+        _ => "",                            // 其他状态的状态文本为空（RFC 9112 允许），不能误报为其他状态
+    }
+}
+
+// 把数字状态码转换为 HttpResponse 使用的 &'static str，代理转发上游的任意状态码时使用
+// 不是三位数字的状态码返回 None
+pub fn status_code_str(code: u16) -> Option<&'static str> {
+    static CODES: OnceLock<Vec<String>> = OnceLock::new();
+    let codes = CODES.get_or_init(|| (100..600).map(|code: u16| code.to_string()).collect());
+    codes.get(usize::from(code.checked_sub(100)?)).map(String::as_str)
+}
+
+// 响应解析错误
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    InvalidStatusLine,      // 状态行格式错误或版本不是 HTTP/1.x
+    InvalidHeader,          // 响应头格式错误
+    InvalidContentLength,   // Content-Length 不是数字或多个值不一致
+    HeadTooLarge,           // 状态行和响应头超过大小上限
+    Chunked(ChunkedError),  // 分块编码的消息体格式错误
+    Incomplete,             // 数据在响应结束前中断
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidStatusLine => write!(f, "invalid status line"),
+            ParseError::InvalidHeader => write!(f, "invalid header field"),
+            ParseError::InvalidContentLength => write!(f, "invalid Content-Length"),
+            ParseError::HeadTooLarge => write!(f, "response head too large"),
+            ParseError::Chunked(e) => write!(f, "invalid chunked body: {}", e),
+            ParseError::Incomplete => write!(f, "incomplete response"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<ChunkedError> for ParseError {
+    fn from(e: ChunkedError) -> Self {
+        ParseError::Chunked(e)
+    }
+}
+
+// 状态行和响应头，字段引用输入的数据
+#[derive(Debug, PartialEq)]
+pub struct ResponseHead<'a> {
+    pub version: &'a str,                // HTTP 版本
+    pub status_code: &'a str,            // 三位数字的状态码
+    pub status_text: &'a str,            // 状态文本，可以为空
+    pub headers: Vec<(&'a str, &'a str)>, // 响应头，按收到的顺序保存
+    pub len: usize,                      // 包括结尾空行在内的字节数，之后是消息体
+}
+
+impl ResponseHead<'_> {
+    // 数字形式的状态码
+    pub fn status(&self) -> u16 {
+        self.status_code.parse().unwrap_or_default()
+    }
+}
+
+// 解析状态行和响应头，行尾可以是 CRLF 或 LF
+// 数据还不完整时返回 Ok(None)，调用方应读取更多数据后重试
+pub fn parse_head(data: &[u8]) -> std::result::Result<Option<ResponseHead<'_>>, ParseError> {
+    // 查找结尾的空行
+    let mut line_start = 0;
+    let head_end = loop {
+        let Some(pos) = data[line_start..].iter().position(|&b| b == b'\n') else {
+            if data.len() > MAX_HEAD_SIZE {
+                return Err(ParseError::HeadTooLarge);
+            }
+            return Ok(None);
+        };
+        let line_end = line_start + pos + 1;
+        if line_start > 0 && matches!(&data[line_start..line_end], b"\r\n" | b"\n") {
+            break line_end;
+        }
+        line_start = line_end;
+    };
+    if head_end > MAX_HEAD_SIZE {
+        return Err(ParseError::HeadTooLarge);
+    }
+
+    let head = std::str::from_utf8(&data[..head_end]).map_err(|_| ParseError::InvalidHeader)?;
+    let mut lines = head.lines();
+    let status_line = lines.next().unwrap_or_default();
+    let (version, rest) = status_line.split_once(' ').ok_or(ParseError::InvalidStatusLine)?;
+    let (status_code, status_text) = rest.split_once(' ').unwrap_or((rest, ""));
+    let valid_code = status_code.len() == 3
+        && status_code.bytes().all(|b| b.is_ascii_digit())
+        && (b'1'..=b'5').contains(&status_code.as_bytes()[0]);
+    if !matches!(version, "HTTP/1.0" | "HTTP/1.1") || !valid_code {
+        return Err(ParseError::InvalidStatusLine);
+    }
+
+    let mut headers = Vec::new();
+    for line in lines.filter(|line| !line.is_empty()) {
+        // 头部名称中不能有空白，以空白开头的续行（obs-fold）已被废弃
+        let (name, value) = line.split_once(':').ok_or(ParseError::InvalidHeader)?;
+        if name.is_empty() || name.bytes().any(|b| b.is_ascii_whitespace() || b.is_ascii_control()) {
+            return Err(ParseError::InvalidHeader);
+        }
+        headers.push((name, value.trim()));
+    }
+    Ok(Some(ResponseHead {
+        version,
+        status_code,
+        status_text: status_text.trim_end_matches('\r'),
+        headers,
+        len: head_end,
+    }))
+}
+
+// 响应消息体的长度由什么决定（RFC 9112 第 6.3 节）
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BodyFraming {
+    Empty,         // 没有消息体：HEAD 请求的响应，1xx、204 和 304
+    Chunked,       // 分块传输编码
+    Length(usize), // Content-Length 指定的长度
+    Close,         // 读取到连接关闭为止
+}
+
+// 根据状态码和响应头确定消息体的分隔方式
+pub fn body_framing(head: &ResponseHead, head_request: bool) -> std::result::Result<BodyFraming, ParseError> {
+    let status = head.status();
+    if head_request || (100..200).contains(&status) || status == 204 || status == 304 {
+        return Ok(BodyFraming::Empty);
+    }
+    let values = |name: &'static str| {
+        head.headers
+            .iter()
+            .filter(move |(k, _)| k.eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.split(','))
+            .map(str::trim)
+    };
+    // 同时有 Transfer-Encoding 和 Content-Length 时以 Transfer-Encoding 为准
+    if let Some(coding) = values("Transfer-Encoding").next_back() {
+        return Ok(match coding.eq_ignore_ascii_case("chunked") {
+            true => BodyFraming::Chunked,
+            false => BodyFraming::Close,
+        });
+    }
+    let mut length = None;
+    for value in values("Content-Length") {
+        if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        let value: usize = value.parse().map_err(|_| ParseError::InvalidContentLength)?;
+        if length.is_some_and(|length| length != value) {
+            return Err(ParseError::InvalidContentLength);
+        }
+        length = Some(value);
+    }
+    Ok(length.map_or(BodyFraming::Close, BodyFraming::Length))
+}
+
+// 解析响应时需要的上下文
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParseOptions {
+    pub head_request: bool, // 响应对应的是 HEAD 请求，没有消息体
+    pub eof: bool,          // 连接已关闭，数据不会再增加
+}
+
+// 解析出的响应
+#[derive(Debug)]
+pub struct ParsedResponse<'a> {
+    pub response: HttpResponse<'a>, // 响应，消息体已解码
+    pub trailers: Trailers,         // 分块编码的尾部头部
+    pub consumed: usize,            // 消耗的输入字节数，之后是下一个响应
+}
+
+impl<'a> HttpResponse<'a> {
+    // 从收到的数据解析一个响应，与请求的解析对应
+    // 同名的响应头第一个放在 headers 中，其余的放在 extra_headers 中；无效的 UTF-8 消息体被替换
+    // 数据还不完整时返回 Ok(None)；没有长度信息的消息体在 options.eof 为 true 时才算完整
+    pub fn parse(data: &'a [u8], options: ParseOptions) -> std::result::Result<Option<ParsedResponse<'a>>, ParseError> {
+        let Some(head) = parse_head(data)? else {
+            return if options.eof && !data.is_empty() { Err(ParseError::Incomplete) } else { Ok(None) };
+        };
+        let rest = &data[head.len..];
+        let (body, trailers, body_len) = match body_framing(&head, options.head_request)? {
+            BodyFraming::Empty => (None, Vec::new(), 0),
+            BodyFraming::Length(length) if rest.len() >= length => (Some(rest[..length].to_vec()), Vec::new(), length),
+            BodyFraming::Length(_) => return Self::incomplete(options),
+            BodyFraming::Chunked => match chunked::decode(rest)? {
+                Some(decoded) => (Some(decoded.body), decoded.trailers, decoded.consumed),
+                None => return Self::incomplete(options),
+            },
+            BodyFraming::Close if options.eof => (Some(rest.to_vec()), Vec::new(), rest.len()),
+            BodyFraming::Close => return Ok(None),
+        };
+
+        let mut headers = HashMap::new();
+        let mut extra_headers = Vec::new();
+        for (name, value) in head.headers {
+            if headers.contains_key(name) {
+                extra_headers.push((name.to_string(), value.to_string()));
+            } else {
+                headers.insert(name, value);
+            }
+        }
+        let response = HttpResponse {
+            version: head.version,
+            status_code: head.status_code,
+            status_text: head.status_text,
+            headers: Some(headers),
+            extra_headers,
+            body: body.map(|b| String::from_utf8_lossy(&b).into_owned()),
+            stream: None,
+        };
+        Ok(Some(ParsedResponse {
+            response,
+            trailers,
+            consumed: head.len + body_len,
+        }))
+    }
+
+    // 消息体不完整：连接已关闭时是错误，否则等待更多数据
+    fn incomplete(options: ParseOptions) -> std::result::Result<Option<ParsedResponse<'a>>, ParseError> {
+        match options.eof {
+            true => Err(ParseError::Incomplete),
+            false => Ok(None),
+        }
+    }
+}
+
+// 为 HttpResponse 实现从完整的响应文本解析的功能，与转换为 String 对应
+impl<'a> TryFrom<&'a str> for HttpResponse<'a> {
+    type Error = ParseError;
+
+    fn try_from(data: &'a str) -> std::result::Result<Self, Self::Error> {
+        let options = ParseOptions { head_request: false, eof: true };
+        match HttpResponse::parse(data.as_bytes(), options)? {
+            Some(parsed) => Ok(parsed.response),
+            None => Err(ParseError::Incomplete),
+        }
+    }
+}
+
 // 测试模块
 #[cfg(test)]
 mod tests {
     use super::*; // 引入外部模块
 
+    // 测试状态文本：不在表中的状态码使用空的状态文本，而不是其他状态的文本
+    #[test]
+    fn test_reason_phrase() {
+        assert_eq!(HttpResponse::new("422", None, None).status_text(), "Unprocessable Entity");
+        assert_eq!(HttpResponse::new("451", None, None).status_text(), "Unavailable For Legal Reasons");
+        let resp = HttpResponse::new("299", None, None);
+        assert_eq!(resp.status_text(), "");
+        assert!(String::from(resp).starts_with("HTTP/1.1 299 \r\n"));
+        assert_eq!(HttpResponse::new("599", None, None).into_owned().status_text(), "");
+    }
+
+    // 测试转换为不借用数据的响应后头部、状态和消息体不变
+    #[test]
+    fn test_into_owned() {
+        let name = String::from("X-Request");
+        let mut headers = HashMap::new();
+        headers.insert(name.as_str(), "1");
+        let mut resp = HttpResponse::new("201", Some(headers), Some("done".to_string()));
+        resp.add_header("Set-Cookie", "a=1");
+        let expected = String::from(resp.clone());
+        let owned = resp.into_owned();
+        drop(name);
+        assert_eq!(String::from(owned.clone()), expected);
+        assert_eq!(owned.header("x-request"), Some("1"));
+        assert_eq!(owned.status_text(), "Created");
+    }
+
     // 测试创建 HTTP 响应结构体（状态码为 200）
     #[test]
     fn test_response_struct_creation_200() {
@@ -150,7 +601,9 @@ mod tests {
                 h.insert("Content-Type", "text/html"); // 设置默认 Content-Type
                 Some(h) // 返回请求头
             },
+            extra_headers: Vec::new(), // 无额外响应头
             body: Some("Item was shipped on 21st Dec 2020".into()), // 消息体
+            stream: None, // 非流式响应
         };
         assert_eq!(response_actual, response_expected); // 断言实际响应与预期响应相等
     }
@@ -172,7 +625,9 @@ mod tests {
                 h.insert("Content-Type", "text/html"); // 设置默认 Content-Type
                 Some(h) // 返回请求头
             },
+            extra_headers: Vec::new(), // 无额外响应头
             body: Some("Item was shipped on 21st Dec 2020".into()), // 消息体
+            stream: None, // 非流式响应
         };
         assert_eq!(response_actual, response_expected); // 断言实际响应与预期响应相等
     }
@@ -189,10 +644,218 @@ mod tests {
                 h.insert("Content-Type", "text/html"); // 设置默认 Content-Type
                 Some(h) // 返回请求头
             },
+            extra_headers: Vec::new(), // 无额外响应头
             body: Some("Item was shipped on 21st Dec 2020".into()), // 消息体
+            stream: None, // 非流式响应
         };
         let http_string: String = response_expected.into(); // 将 HttpResponse 转换为字符串
         let response_actual = "HTTP/1.1 404 Not Found\r\nContent-Type:text/html\r\nContent-Length: 33\r\n\r\nItem was shipped on 21st Dec 2020"; // 预期的 HTTP 字符串
         assert_eq!(http_string, response_actual); // 断言实际字符串与预期字符串相等
     }
+
+    // 测试同一个响应可以包含多个 Set-Cookie 头
+    #[test]
+    fn test_multiple_set_cookie_headers() {
+        let mut response = HttpResponse::new("200", None, None);
+        response.add_cookie(&Cookie::new("a", "1").unwrap());
+        response.add_cookie(&Cookie::new("b", "2").unwrap().http_only(true));
+        let http_string: String = response.into();
+        assert!(http_string.contains("Set-Cookie:a=1\r\n"));
+        assert!(http_string.contains("Set-Cookie:b=2; HttpOnly\r\n"));
+    }
+
+    // 测试拒绝会注入额外头部的名称和值
+    #[test]
+    fn test_add_header_rejects_injection() {
+        let mut response = HttpResponse::new("200", None, None);
+        response.add_header("X-Note", "a\r\nSet-Cookie: admin=1");
+        response.add_header("X-Note", "a\nb");
+        response.add_header("X-Note", "a\0b");
+        response.add_header("X-Bad Name", "1");
+        response.add_header("X-Bad:Name", "1");
+        response.add_header("", "1");
+        response.add_header("X-Note", "tab\tand \"quotes\"");
+        assert_eq!(response.header_list()[1..], [("X-Note", "tab\tand \"quotes\"")]);
+    }
+
+    // 测试流式响应使用分块传输编码并发送尾部头部
+    #[test]
+    fn test_streaming_response() {
+        let (response, mut writer) = HttpResponse::streaming("200", None);
+        let producer = std::thread::spawn(move || {
+            writer.write_all(b"first,").unwrap();
+            writer.flush().unwrap();
+            writer.write_all(b"second").unwrap();
+            writer.trailer("X-Rows", "2");
+            writer.finish().unwrap();
+        });
+        let mut out = Vec::new();
+        response.send_response(&mut out).unwrap();
+        producer.join().unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(out.contains("Transfer-Encoding: chunked\r\n"));
+        assert!(!out.contains("Content-Length"));
+        assert!(out.ends_with("\r\n\r\n6\r\nfirst,\r\n6\r\nsecond\r\n0\r\nX-Rows: 2\r\n\r\n"));
+    }
+
+    // 测试 HTTP/1.0 的流式响应不使用分块编码，生产者中断时返回错误
+    #[test]
+    fn test_streaming_response_http10() {
+        let (mut response, mut writer) = HttpResponse::streaming("200", None);
+        response.set_version("HTTP/1.0");
+        writer.write_all(b"raw").unwrap();
+        writer.flush().unwrap();
+        drop(writer);
+        let mut out = Vec::new();
+        assert!(response.send_response(&mut out).is_err());
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("HTTP/1.0 200 OK\r\n"));
+        assert!(!out.contains("Transfer-Encoding"));
+        assert!(out.ends_with("\r\n\r\nraw"));
+    }
+
+    // 测试按不同方式分隔消息体的响应的解析
+    #[test]
+    fn test_parse_response() {
+        let data = "HTTP/1.1 404 Not Found\r\nContent-Type: text/plain\r\nSet-Cookie: a=1\r\nSet-Cookie: b=2\r\nContent-Length: 5\r\n\r\nabcdeHTTP/1.1 200 OK\r\n";
+        let parsed = HttpResponse::parse(data.as_bytes(), ParseOptions::default()).unwrap().unwrap();
+        let response = parsed.response;
+        assert_eq!((response.version(), response.status_code(), response.status_text()), ("HTTP/1.1", "404", "Not Found"));
+        assert_eq!(response.header("content-type"), Some("text/plain"));
+        assert_eq!(response.extra_headers, vec![("Set-Cookie".to_string(), "b=2".to_string())]);
+        assert_eq!(response.body(), "abcde");
+        assert_eq!(&data[parsed.consumed..], "HTTP/1.1 200 OK\r\n");
+
+        // 分块编码和尾部头部，Transfer-Encoding 优先于 Content-Length
+        let data = b"HTTP/1.1 200 OK\r\nContent-Length: 99\r\nTransfer-Encoding: chunked\r\n\r\n3\r\nabc\r\n0\r\nX-Rows: 1\r\n\r\n";
+        let parsed = HttpResponse::parse(data, ParseOptions::default()).unwrap().unwrap();
+        assert_eq!(parsed.response.body(), "abc");
+        assert_eq!(parsed.trailers, vec![("X-Rows".to_string(), "1".to_string())]);
+        assert_eq!(parsed.consumed, data.len());
+
+        // 没有长度信息的消息体直到连接关闭，LF 行尾和空的状态文本
+        let data = b"HTTP/1.0 200\nServer: x\n\nuntil close";
+        assert!(HttpResponse::parse(data, ParseOptions::default()).unwrap().is_none());
+        let eof = ParseOptions { eof: true, ..Default::default() };
+        let response = HttpResponse::parse(data, eof).unwrap().unwrap().response;
+        assert_eq!((response.status_text(), response.body()), ("", "until close"));
+
+        // HEAD 请求和 204 的响应没有消息体
+        let head = ParseOptions { head_request: true, ..Default::default() };
+        let parsed = HttpResponse::parse(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", head).unwrap().unwrap();
+        assert_eq!((parsed.response.body, parsed.consumed), (None, 38));
+        let response = HttpResponse::try_from("HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+        assert_eq!(response.body, None);
+    }
+
+    // 测试格式错误和不完整的响应
+    #[test]
+    fn test_parse_response_errors() {
+        let parse = |data: &str| HttpResponse::try_from(data).map(|_| ());
+        assert_eq!(parse("HTTP/2 200 OK\r\n\r\n"), Err(ParseError::InvalidStatusLine));
+        assert_eq!(parse("HTTP/1.1 20 OK\r\n\r\n"), Err(ParseError::InvalidStatusLine));
+        assert_eq!(parse("HTTP/1.1 600 X\r\n\r\n"), Err(ParseError::InvalidStatusLine));
+        assert_eq!(parse("HTTP/1.1 200 OK\r\nNo colon\r\n\r\n"), Err(ParseError::InvalidHeader));
+        assert_eq!(parse("HTTP/1.1 200 OK\r\nName : v\r\n\r\n"), Err(ParseError::InvalidHeader));
+        assert_eq!(parse("HTTP/1.1 200 OK\r\nA: b\r\n folded\r\n\r\n"), Err(ParseError::InvalidHeader));
+        assert_eq!(parse("HTTP/1.1 200 OK\r\nContent-Length: -1\r\n\r\n"), Err(ParseError::InvalidContentLength));
+        assert_eq!(
+            parse("HTTP/1.1 200 OK\r\nContent-Length: 1\r\nContent-Length: 2\r\n\r\nab"),
+            Err(ParseError::InvalidContentLength)
+        );
+        assert_eq!(
+            parse("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n"),
+            Err(ParseError::Chunked(ChunkedError::InvalidSize))
+        );
+        assert_eq!(parse("HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nab"), Err(ParseError::Incomplete));
+        assert_eq!(parse("HTTP/1.1 200 OK\r\n"), Err(ParseError::Incomplete));
+
+        assert_eq!(status_code_str(201), Some("201"));
+        assert_eq!((status_code_str(99), status_code_str(600)), (None, None));
+
+        let huge = format!("HTTP/1.1 200 OK\r\nX: {}", "a".repeat(MAX_HEAD_SIZE));
+        assert_eq!(HttpResponse::parse(huge.as_bytes(), ParseOptions::default()).unwrap_err(), ParseError::HeadTooLarge);
+    }
+
+    // 序列化后再解析的属性测试
+    mod roundtrip {
+        use super::*;
+        use proptest::prelude::*;
+
+        // new() 认识的状态码
+        const STATUS_CODES: &[&str] = &[
+            "101", "200", "204", "303", "400", "401", "403", "404", "413", "415", "422", "426", "429", "500", "505",
+        ];
+
+        // 不影响消息体分隔的响应头，值两端没有空白
+        fn header() -> impl Strategy<Value = (String, String)> {
+            ("X-[A-Za-z0-9-]{1,12}", "[!-~]([ -~]{0,20}[!-~])?")
+        }
+
+        proptest! {
+            // 按 Content-Length 发送的响应解析后与原来的一致
+            #[test]
+            fn test_roundtrip(
+                code in proptest::sample::select(STATUS_CODES),
+                version in prop_oneof![Just("HTTP/1.0"), Just("HTTP/1.1")],
+                headers in proptest::collection::vec(header(), 0..6),
+                extra in proptest::collection::vec(header(), 0..4),
+                body in "\\PC{0,200}",
+            ) {
+                let map: HashMap<&str, &str> = headers.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+                let body = (!matches!(code, "101" | "204")).then_some(body);
+                let mut response = HttpResponse::new(code, Some(map), body.clone());
+                response.set_version(version);
+                for (name, value) in &extra {
+                    response.add_header(name.clone(), value.clone());
+                }
+                let mut expected: Vec<(String, String)> =
+                    response.header_list().iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+                if body.is_some() {
+                    expected.push(("Content-Length".into(), body.as_ref().unwrap().len().to_string()));
+                }
+                expected.sort();
+
+                let wire: String = response.clone().into();
+                let parsed = HttpResponse::try_from(wire.as_str()).unwrap();
+                prop_assert_eq!(parsed.version(), response.version());
+                prop_assert_eq!(parsed.status_code(), response.status_code());
+                prop_assert_eq!(parsed.status_text(), response.status_text());
+                prop_assert_eq!(&parsed.body, &body);
+                let mut actual: Vec<(String, String)> =
+                    parsed.header_list().iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+                actual.sort();
+                prop_assert_eq!(actual, expected);
+            }
+
+            // 分块发送的流式响应解析后得到拼接的消息体和尾部头部，任何前缀都被判断为不完整
+            #[test]
+            fn test_chunked_roundtrip(
+                chunks in proptest::collection::vec(proptest::collection::vec(any::<u8>(), 0..64), 0..8),
+                trailers in proptest::collection::vec(header(), 0..3),
+            ) {
+                let (response, mut writer) = HttpResponse::streaming("200", None);
+                for chunk in &chunks {
+                    writer.write_all(chunk).unwrap();
+                    writer.flush().unwrap();
+                }
+                for (name, value) in &trailers {
+                    writer.trailer(name.clone(), value.clone());
+                }
+                writer.finish().unwrap();
+                let mut wire = Vec::new();
+                response.send_response(&mut wire).unwrap();
+
+                let parsed = HttpResponse::parse(&wire, ParseOptions::default()).unwrap().unwrap();
+                prop_assert_eq!(parsed.consumed, wire.len());
+                let body = chunks.concat();
+                prop_assert_eq!(parsed.response.body(), String::from_utf8_lossy(&body));
+                prop_assert_eq!(parsed.trailers, trailers);
+                for end in 0..wire.len() {
+                    prop_assert!(HttpResponse::parse(&wire[..end], ParseOptions::default()).unwrap().is_none());
+                }
+            }
+        }
+    }
 }
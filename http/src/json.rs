@@ -0,0 +1,240 @@
+// 导入所需的模块
+use super::httprequest::HttpRequest; // 导入 HTTP 请求结构
+use super::httpresponse::HttpResponse; // 导入 HTTP 响应结构
+use serde::de::DeserializeOwned; // 导入反序列化特性
+use serde_json::json; // 导入 json! 宏
+use std::collections::HashMap; // 导入 HashMap
+use std::fmt; // 导入格式化模块
+
+// 默认允许的 JSON 消息体大小上限（1 MiB）
+pub const DEFAULT_JSON_LIMIT: usize = 1024 * 1024;
+
+// 定义 JSON 消息体提取失败的原因
+#[derive(Debug, PartialEq)]
+pub enum JsonError {
+    // Content-Type 不是 JSON，对应 415
+    UnsupportedMediaType { content_type: Option<String> },
+    // 消息体超过大小限制，对应 413
+    PayloadTooLarge { limit: usize, size: usize },
+    // 消息体不是合法的 JSON，对应 422
+    Syntax { message: String, line: usize, column: usize },
+    // JSON 合法但与目标类型不匹配，对应 422；field 指向出错的字段路径
+    Validation { field: Option<String>, message: String },
+}
+
+impl JsonError {
+    // 返回错误对应的 HTTP 状态码
+    pub fn status_code(&self) -> &'static str {
+        match self {
+            JsonError::UnsupportedMediaType { .. } => "415",
+            JsonError::PayloadTooLarge { .. } => "413",
+            JsonError::Syntax { .. } | JsonError::Validation { .. } => "422",
+        }
+    }
+
+    // 返回机器可读的错误码
+    pub fn code(&self) -> &'static str {
+        match self {
+            JsonError::UnsupportedMediaType { .. } => "unsupported_media_type",
+            JsonError::PayloadTooLarge { .. } => "payload_too_large",
+            JsonError::Syntax { .. } => "invalid_json",
+            JsonError::Validation { .. } => "validation_failed",
+        }
+    }
+
+    // 将错误转换为 JSON 格式的错误响应体
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut body = json!({
+            "error": self.code(),
+            "message": self.to_string(),
+        });
+        match self {
+            JsonError::UnsupportedMediaType { content_type } => {
+                body["content_type"] = json!(content_type);
+            }
+            JsonError::PayloadTooLarge { limit, size } => {
+                body["limit"] = json!(limit);
+                body["size"] = json!(size);
+            }
+            JsonError::Syntax { line, column, .. } => {
+                body["line"] = json!(line);
+                body["column"] = json!(column);
+            }
+            JsonError::Validation { field, .. } => {
+                body["field"] = json!(field);
+            }
+        }
+        body
+    }
+
+    // 将错误转换为可以直接发送的 HTTP 响应
+    pub fn to_response(&self) -> HttpResponse<'static> {
+        let mut headers: HashMap<&str, &str> = HashMap::new();
+        headers.insert("Content-Type", "application/json");
+        HttpResponse::new(
+            self.status_code(),
+            Some(headers),
+            Some(self.to_json().to_string()),
+        )
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonError::UnsupportedMediaType { content_type: Some(ct) } => {
+                write!(f, "expected Content-Type application/json, got {}", ct)
+            }
+            JsonError::UnsupportedMediaType { content_type: None } => {
+                write!(f, "expected Content-Type application/json")
+            }
+            JsonError::PayloadTooLarge { limit, size } => {
+                write!(f, "body of {} bytes exceeds the limit of {} bytes", size, limit)
+            }
+            JsonError::Syntax { message, .. } => write!(f, "{}", message),
+            JsonError::Validation { field: Some(field), message } => {
+                write!(f, "{}: {}", field, message)
+            }
+            JsonError::Validation { field: None, message } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+// 判断 Content-Type 是否为 JSON（application/json 或 application/*+json）
+fn is_json_content_type(content_type: &str) -> bool {
+    let media_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    media_type == "application/json"
+        || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
+
+// 为 HttpRequest 实现 JSON 消息体提取
+impl HttpRequest {
+    // 使用默认大小限制将消息体反序列化为 T
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, JsonError> {
+        self.json_with_limit(DEFAULT_JSON_LIMIT)
+    }
+
+    // 检查 Content-Type 和大小限制后，将消息体反序列化为 T
+    pub fn json_with_limit<T: DeserializeOwned>(&self, limit: usize) -> Result<T, JsonError> {
+        let content_type = self.header("Content-Type");
+        if !content_type.is_some_and(is_json_content_type) {
+            return Err(JsonError::UnsupportedMediaType {
+                content_type: content_type.map(|ct| ct.to_string()),
+            });
+        }
+
+        let size = self.msg_body.len();
+        if size > limit {
+            return Err(JsonError::PayloadTooLarge { limit, size });
+        }
+
+        // 借助 serde_path_to_error 记录出错字段的路径，例如 items[0].order_id
+        let de = &mut serde_json::Deserializer::from_str(&self.msg_body);
+        serde_path_to_error::deserialize(de).map_err(|err| {
+            let field = err.path().to_string();
+            let inner = err.into_inner();
+            if inner.is_syntax() || inner.is_eof() {
+                JsonError::Syntax {
+                    message: inner.to_string(),
+                    line: inner.line(),
+                    column: inner.column(),
+                }
+            } else {
+                JsonError::Validation {
+                    // 根路径用 "." 表示，此时没有具体字段
+                    field: if field == "." { None } else { Some(field) },
+                    message: strip_position(&inner.to_string()),
+                }
+            }
+        })
+    }
+}
+
+// 去掉 serde_json 错误信息末尾的 " at line X column Y"
+fn strip_position(message: &str) -> String {
+    match message.rfind(" at line ") {
+        Some(pos) => message[..pos].to_string(),
+        None => message.to_string(),
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+    use serde::Deserialize; // 导入反序列化派生宏
+
+    #[derive(Deserialize, Debug, PartialEq)]
+    struct StatusUpdate {
+        order_status: String,
+        order_id: i32,
+    }
+
+    // 构造一个带有指定 Content-Type 和消息体的 POST 请求
+    fn request(content_type: &str, body: &str) -> HttpRequest {
+        format!(
+            "POST /api/shipping/orders HTTP/1.1\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n{}",
+            content_type,
+            body.len(),
+            body
+        )
+        .into()
+    }
+
+    // 测试正常的 JSON 消息体提取
+    #[test]
+    fn test_json_extraction() {
+        let req = request("application/json; charset=utf-8", r#"{"order_status":"Shipped","order_id":2}"#);
+        let update: StatusUpdate = req.json().unwrap();
+        assert_eq!(
+            update,
+            StatusUpdate {
+                order_status: "Shipped".into(),
+                order_id: 2
+            }
+        );
+    }
+
+    // 测试错误的 Content-Type 返回 415
+    #[test]
+    fn test_json_unsupported_media_type() {
+        let req = request("text/plain", "{}");
+        let err = req.json::<StatusUpdate>().unwrap_err();
+        assert_eq!(err.status_code(), "415");
+    }
+
+    // 测试超过大小限制返回 413
+    #[test]
+    fn test_json_payload_too_large() {
+        let req = request("application/json", r#"{"order_status":"Shipped","order_id":2}"#);
+        let err = req.json_with_limit::<StatusUpdate>(8).unwrap_err();
+        assert_eq!(err.status_code(), "413");
+    }
+
+    // 测试字段类型错误时返回 422 并指出出错字段
+    #[test]
+    fn test_json_validation_points_at_field() {
+        let req = request("application/json", r#"{"order_status":"Shipped","order_id":"two"}"#);
+        let err = req.json::<StatusUpdate>().unwrap_err();
+        assert_eq!(err.status_code(), "422");
+        let body = err.to_json();
+        assert_eq!(body["error"], "validation_failed");
+        assert_eq!(body["field"], "order_id");
+    }
+
+    // 测试非法 JSON 返回 422 并给出位置
+    #[test]
+    fn test_json_syntax_error() {
+        let req = request("application/json", r#"{"order_status":"#);
+        let err = req.json::<StatusUpdate>().unwrap_err();
+        assert_eq!(err.code(), "invalid_json");
+        assert_eq!(err.status_code(), "422");
+    }
+}
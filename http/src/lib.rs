@@ -1,2 +1,13 @@
+pub mod chunked;
+pub mod client;
+pub mod cookie;
+pub mod extensions;
+pub mod form;
+pub mod hpack;
+pub mod httpdate;
 pub mod httprequest;
 pub mod httpresponse;
+pub mod json;
+pub mod multipart;
+pub mod sse;
+pub mod websocket;
@@ -0,0 +1,629 @@
+// 导入所需的模块
+use super::httprequest::HttpRequest; // 导入 HTTP 请求结构
+use std::env; // 导入环境变量模块
+use std::fmt; // 导入格式化模块
+use std::fs::{self, File}; // 导入文件系统模块
+use std::io::{self, Read, Write}; // 导入读写模块
+use std::path::{Path, PathBuf}; // 导入路径模块
+use std::sync::atomic::{AtomicUsize, Ordering}; // 导入原子计数器，用于生成临时文件名
+
+// 每次从底层读取的字节数
+const READ_CHUNK: usize = 8 * 1024;
+// 单个分段头部的最大字节数
+const MAX_PART_HEADER_SIZE: usize = 8 * 1024;
+
+// 定义 multipart 解析的各项限制
+#[derive(Debug, Clone)]
+pub struct MultipartLimits {
+    pub max_part_size: usize,    // 单个分段内容的最大字节数
+    pub max_total_size: usize,   // 整个消息体的最大字节数
+    pub max_parts: usize,        // 最多允许的分段数量
+    pub memory_threshold: usize, // 文件分段超过该大小后写入磁盘
+    pub temp_dir: PathBuf,       // 临时文件目录
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        Self {
+            max_part_size: 10 * 1024 * 1024,  // 默认单个分段 10 MiB
+            max_total_size: 50 * 1024 * 1024, // 默认整个消息体 50 MiB
+            max_parts: 100,                   // 默认最多 100 个分段
+            memory_threshold: 64 * 1024,      // 默认超过 64 KiB 写入磁盘
+            temp_dir: env::temp_dir(),        // 默认使用系统临时目录
+        }
+    }
+}
+
+// 定义 multipart 解析失败的原因
+#[derive(Debug)]
+pub enum MultipartError {
+    // Content-Type 不是 multipart/form-data 或缺少 boundary，对应 415
+    UnsupportedMediaType { content_type: Option<String> },
+    // 消息体格式错误，对应 400
+    Malformed(String),
+    // 单个分段超过大小限制，对应 413
+    PartTooLarge { name: String, limit: usize },
+    // 整个消息体超过大小限制，对应 413
+    TotalTooLarge { limit: usize },
+    // 分段数量超过限制，对应 413
+    TooManyParts { limit: usize },
+    // 读取消息体或写入临时文件失败，对应 500
+    Io(io::Error),
+}
+
+impl MultipartError {
+    // 返回错误对应的 HTTP 状态码
+    pub fn status_code(&self) -> &'static str {
+        match self {
+            MultipartError::UnsupportedMediaType { .. } => "415",
+            MultipartError::Malformed(_) => "400",
+            MultipartError::PartTooLarge { .. }
+            | MultipartError::TotalTooLarge { .. }
+            | MultipartError::TooManyParts { .. } => "413",
+            MultipartError::Io(_) => "500",
+        }
+    }
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultipartError::UnsupportedMediaType { content_type } => write!(
+                f,
+                "expected Content-Type multipart/form-data with a boundary, got {}",
+                content_type.as_deref().unwrap_or("none")
+            ),
+            MultipartError::Malformed(msg) => write!(f, "malformed multipart body: {}", msg),
+            MultipartError::PartTooLarge { name, limit } => {
+                write!(f, "part {} exceeds the limit of {} bytes", name, limit)
+            }
+            MultipartError::TotalTooLarge { limit } => {
+                write!(f, "multipart body exceeds the limit of {} bytes", limit)
+            }
+            MultipartError::TooManyParts { limit } => {
+                write!(f, "multipart body has more than {} parts", limit)
+            }
+            MultipartError::Io(e) => write!(f, "io error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+impl From<io::Error> for MultipartError {
+    fn from(e: io::Error) -> Self {
+        MultipartError::Io(e)
+    }
+}
+
+// 磁盘上的临时文件，未调用 persist 时在释放时自动删除
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,  // 临时文件路径
+    persisted: bool, // 是否已移动到最终位置
+}
+
+impl TempFile {
+    // 在指定目录中创建一个唯一命名的临时文件
+    fn create(dir: &Path) -> io::Result<(TempFile, File)> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let name = format!(
+            "upload-{}-{}.part",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        let file = File::options().write(true).create_new(true).open(&path)?;
+        Ok((
+            TempFile {
+                path,
+                persisted: false,
+            },
+            file,
+        ))
+    }
+
+    // 返回临时文件路径
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // 将临时文件移动到目标路径并保留
+    pub fn persist(mut self, target: impl AsRef<Path>) -> io::Result<()> {
+        if fs::rename(&self.path, target.as_ref()).is_err() {
+            // 跨文件系统时无法重命名，改为复制
+            fs::copy(&self.path, target.as_ref())?;
+            let _ = fs::remove_file(&self.path);
+        }
+        self.persisted = true;
+        Ok(())
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.persisted {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+// 定义分段内容的存放位置
+#[derive(Debug)]
+pub enum PartData {
+    Memory(Vec<u8>), // 小分段保存在内存中
+    File(TempFile),  // 大文件分段保存在临时文件中
+}
+
+// 定义 multipart 消息中的一个分段
+#[derive(Debug)]
+pub struct Part {
+    pub name: String,                  // 字段名
+    pub filename: Option<String>,      // 上传的文件名，普通字段为 None
+    pub content_type: Option<String>,  // 分段的 Content-Type
+    pub headers: Vec<(String, String)>, // 分段的全部头部
+    pub size: usize,                   // 分段内容的字节数
+    pub data: PartData,                // 分段内容
+}
+
+impl Part {
+    // 是否为文件分段
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    // 以文本形式返回内存中的分段内容
+    pub fn text(&self) -> Option<String> {
+        match &self.data {
+            PartData::Memory(bytes) => Some(String::from_utf8_lossy(bytes).into_owned()),
+            PartData::File(_) => None,
+        }
+    }
+
+    // 读取分段的全部字节（无论保存在内存还是磁盘上）
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(bytes.clone()),
+            PartData::File(file) => fs::read(file.path()),
+        }
+    }
+
+    // 将分段内容保存到目标路径
+    pub fn save_to(self, target: impl AsRef<Path>) -> io::Result<()> {
+        match self.data {
+            PartData::Memory(bytes) => fs::write(target, bytes),
+            PartData::File(file) => file.persist(target),
+        }
+    }
+}
+
+// 分段内容的写入目标：先写内存，超过阈值后转存到磁盘
+enum Sink {
+    Memory(Vec<u8>),
+    File(TempFile, File),
+}
+
+// 流式 multipart/form-data 解析器，每次从底层读取一块数据，逐个返回分段
+// 内存占用取决于数据源：从连接读取时只缓冲当前的一块数据，从已经读入内存的消息体读取时不会减少内存占用
+pub struct MultipartReader<R: Read> {
+    reader: R,              // 底层数据源
+    boundary: Vec<u8>,      // 分隔符（含前导的 "--"）
+    buf: Vec<u8>,           // 尚未处理的数据
+    limits: MultipartLimits, // 解析限制
+    total_read: usize,      // 已读取的总字节数
+    parts_read: usize,      // 已返回的分段数
+    eof: bool,              // 底层数据源是否已读完
+    started: bool,          // 是否已越过第一个分隔符
+    finished: bool,         // 是否已遇到结束分隔符
+}
+
+impl<R: Read> MultipartReader<R> {
+    // 创建一个新的解析器，boundary 为 Content-Type 中的参数值
+    pub fn new(reader: R, boundary: &str, limits: MultipartLimits) -> Self {
+        MultipartReader {
+            reader,
+            boundary: format!("--{}", boundary).into_bytes(),
+            buf: Vec::new(),
+            limits,
+            total_read: 0,
+            parts_read: 0,
+            eof: false,
+            started: false,
+            finished: false,
+        }
+    }
+
+    // 从底层读取一块数据追加到缓冲区，返回读取的字节数
+    fn fill(&mut self) -> Result<usize, MultipartError> {
+        if self.eof {
+            return Ok(0);
+        }
+        let mut chunk = [0u8; READ_CHUNK];
+        let n = self.reader.read(&mut chunk)?;
+        if n == 0 {
+            self.eof = true;
+        }
+        self.total_read += n;
+        if self.total_read > self.limits.max_total_size {
+            return Err(MultipartError::TotalTooLarge {
+                limit: self.limits.max_total_size,
+            });
+        }
+        self.buf.extend_from_slice(&chunk[..n]);
+        Ok(n)
+    }
+
+    // 确保缓冲区中至少有 len 个字节，数据不足时返回 false
+    fn ensure(&mut self, len: usize) -> Result<bool, MultipartError> {
+        while self.buf.len() < len {
+            if self.fill()? == 0 {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    // 处理分隔符之后的两个字节："--" 表示结束，"\r\n" 表示后面还有分段
+    fn after_boundary(&mut self) -> Result<bool, MultipartError> {
+        if !self.ensure(2)? {
+            return Err(MultipartError::Malformed("unexpected end after boundary".into()));
+        }
+        if &self.buf[..2] == b"--" {
+            self.finished = true;
+            self.buf.clear();
+            return Ok(false);
+        }
+        if &self.buf[..2] != b"\r\n" {
+            return Err(MultipartError::Malformed("invalid boundary line".into()));
+        }
+        self.buf.drain(..2);
+        Ok(true)
+    }
+
+    // 跳过第一个分隔符之前的前导内容
+    fn skip_preamble(&mut self) -> Result<bool, MultipartError> {
+        loop {
+            if let Some(pos) = find(&self.buf, &self.boundary) {
+                self.buf.drain(..pos + self.boundary.len());
+                self.started = true;
+                return self.after_boundary();
+            }
+            // 保留可能是分隔符开头的尾部数据
+            let keep = self.boundary.len().min(self.buf.len());
+            self.buf.drain(..self.buf.len() - keep);
+            if self.fill()? == 0 {
+                return Err(MultipartError::Malformed("missing opening boundary".into()));
+            }
+        }
+    }
+
+    // 读取并解析分段的头部
+    fn read_part_headers(&mut self) -> Result<Vec<(String, String)>, MultipartError> {
+        let end = loop {
+            if let Some(pos) = find(&self.buf, b"\r\n\r\n") {
+                break pos;
+            }
+            if self.buf.len() > MAX_PART_HEADER_SIZE {
+                return Err(MultipartError::Malformed("part headers too large".into()));
+            }
+            if self.fill()? == 0 {
+                return Err(MultipartError::Malformed("unexpected end in part headers".into()));
+            }
+        };
+        let head = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+        self.buf.drain(..end + 4);
+        Ok(head
+            .split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+            .collect())
+    }
+
+    // 返回下一个分段，全部读完后返回 None
+    pub fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.finished {
+            return Ok(None);
+        }
+        if !self.started && !self.skip_preamble()? {
+            return Ok(None);
+        }
+        if self.parts_read >= self.limits.max_parts {
+            return Err(MultipartError::TooManyParts {
+                limit: self.limits.max_parts,
+            });
+        }
+
+        // 解析分段头部
+        let headers = self.read_part_headers()?;
+        let disposition = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("Content-Disposition"))
+            .map(|(_, v)| v.clone())
+            .ok_or_else(|| MultipartError::Malformed("missing Content-Disposition".into()))?;
+        let params = parse_header_params(&disposition);
+        let name = param(&params, "name")
+            .ok_or_else(|| MultipartError::Malformed("missing field name".into()))?;
+        let filename = param(&params, "filename");
+        let content_type = headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, v)| v.clone());
+
+        // 流式读取分段内容，直到遇到下一个分隔符
+        let delimiter = [b"\r\n".as_slice(), &self.boundary].concat();
+        let mut sink = Sink::Memory(Vec::new());
+        let mut size = 0;
+        loop {
+            let (chunk_end, found) = match find(&self.buf, &delimiter) {
+                Some(pos) => (pos, true),
+                // 未找到分隔符时，保留可能是分隔符开头的尾部数据
+                None => (self.buf.len().saturating_sub(delimiter.len() - 1), false),
+            };
+
+            size += chunk_end;
+            if size > self.limits.max_part_size {
+                return Err(MultipartError::PartTooLarge {
+                    name,
+                    limit: self.limits.max_part_size,
+                });
+            }
+            self.write_to_sink(&mut sink, chunk_end, filename.is_some())?;
+
+            if found {
+                self.buf.drain(..delimiter.len());
+                break;
+            }
+            if self.fill()? == 0 {
+                return Err(MultipartError::Malformed("unexpected end in part body".into()));
+            }
+        }
+
+        let data = match sink {
+            Sink::Memory(bytes) => PartData::Memory(bytes),
+            Sink::File(temp, mut file) => {
+                file.flush()?;
+                PartData::File(temp)
+            }
+        };
+        self.parts_read += 1;
+        self.after_boundary()?;
+
+        Ok(Some(Part {
+            name,
+            filename,
+            content_type,
+            headers,
+            size,
+            data,
+        }))
+    }
+
+    // 将缓冲区前 len 个字节写入分段内容，文件分段超过阈值时转存到磁盘
+    fn write_to_sink(&mut self, sink: &mut Sink, len: usize, is_file: bool) -> Result<(), MultipartError> {
+        let chunk: Vec<u8> = self.buf.drain(..len).collect();
+        match sink {
+            Sink::Memory(bytes) => {
+                bytes.extend_from_slice(&chunk);
+                if is_file && bytes.len() > self.limits.memory_threshold {
+                    let (temp, mut file) = TempFile::create(&self.limits.temp_dir)?;
+                    file.write_all(bytes)?;
+                    *sink = Sink::File(temp, file);
+                }
+            }
+            Sink::File(_, file) => file.write_all(&chunk)?,
+        }
+        Ok(())
+    }
+}
+
+// 一次性解析完成的 multipart 表单
+#[derive(Debug, Default)]
+pub struct Multipart {
+    pub fields: Vec<(String, String)>, // 普通文本字段
+    pub files: Vec<Part>,              // 文件分段
+}
+
+impl Multipart {
+    // 返回指定普通字段的第一个值
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    // 返回指定名称的第一个文件分段
+    pub fn file(&self, name: &str) -> Option<&Part> {
+        self.files.iter().find(|p| p.name == name)
+    }
+}
+
+// 从 Content-Type 中提取 multipart/form-data 的 boundary
+pub fn boundary(content_type: &str) -> Option<String> {
+    let (media_type, params) = content_type.split_once(';')?;
+    if !media_type.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    param(&parse_header_params(params), "boundary").filter(|b| !b.is_empty())
+}
+
+// 解析形如 `form-data; name="a"; filename="b.txt"` 的头部参数，引号内的分号不会被拆分
+fn parse_header_params(value: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for c in value.chars() {
+        match c {
+            _ if escaped => {
+                current.push(c);
+                escaped = false;
+            }
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => segments.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    segments.push(current);
+    for segment in segments {
+        if let Some((k, v)) = segment.split_once('=') {
+            params.push((k.trim().to_ascii_lowercase(), v.trim().to_string()));
+        }
+    }
+    params
+}
+
+// 按名称查找头部参数
+fn param(params: &[(String, String)], name: &str) -> Option<String> {
+    params.iter().find(|(k, _)| k == name).map(|(_, v)| v.clone())
+}
+
+// 在 haystack 中查找 needle 第一次出现的位置
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.len() > haystack.len() {
+        return None;
+    }
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// 为 HttpRequest 实现 multipart 表单提取
+impl HttpRequest {
+    // 使用默认限制解析 multipart/form-data 消息体
+    // 解析的是已经完整读入内存的 raw_body，超过阈值的文件分段会复制到临时文件，但消息体本身仍在内存中
+    pub fn multipart(&self) -> Result<Multipart, MultipartError> {
+        self.multipart_with_limits(MultipartLimits::default())
+    }
+
+    // 使用指定限制解析 multipart/form-data 消息体
+    pub fn multipart_with_limits(&self, limits: MultipartLimits) -> Result<Multipart, MultipartError> {
+        let content_type = self.header("Content-Type");
+        let boundary = content_type.and_then(boundary).ok_or_else(|| {
+            MultipartError::UnsupportedMediaType {
+                content_type: content_type.map(|ct| ct.to_string()),
+            }
+        })?;
+
+        let mut reader = MultipartReader::new(self.raw_body.as_slice(), &boundary, limits);
+        let mut multipart = Multipart::default();
+        while let Some(part) = reader.next_part()? {
+            if part.is_file() {
+                multipart.files.push(part);
+            } else {
+                let value = part.text().unwrap_or_default();
+                multipart.fields.push((part.name, value));
+            }
+        }
+        Ok(multipart)
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 每次最多返回 n 个字节的读取器，用于模拟分块到达的数据
+    struct Trickle<'a>(&'a [u8], usize);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.1.min(buf.len()).min(self.0.len());
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    // 构造一个包含普通字段和文件的示例消息体
+    fn sample_body(file_contents: &[u8]) -> Vec<u8> {
+        let mut body = b"preamble\r\n--XyZ\r\nContent-Disposition: form-data; name=\"order_id\"\r\n\r\n2\r\n--XyZ\r\nContent-Disposition: form-data; name=\"invoice\"; filename=\"in;voice.txt\"\r\nContent-Type: text/plain\r\n\r\n".to_vec();
+        body.extend_from_slice(file_contents);
+        body.extend_from_slice(b"\r\n--XyZ--\r\n");
+        body
+    }
+
+    // 测试从 Content-Type 提取 boundary
+    #[test]
+    fn test_boundary() {
+        assert_eq!(boundary("multipart/form-data; boundary=XyZ"), Some("XyZ".into()));
+        assert_eq!(boundary("multipart/form-data; boundary=\"a b\""), Some("a b".into()));
+        assert_eq!(boundary("application/json"), None);
+        assert_eq!(boundary("multipart/form-data"), None);
+    }
+
+    // 测试分块到达的数据也能正确解析字段和文件
+    #[test]
+    fn test_streaming_parse() {
+        let body = sample_body(b"line one\r\n--Xy is only a partial boundary\r\n");
+        let mut reader = MultipartReader::new(Trickle(&body, 3), "XyZ", MultipartLimits::default());
+
+        let field = reader.next_part().unwrap().unwrap();
+        assert_eq!(field.name, "order_id");
+        assert_eq!(field.text(), Some("2".into()));
+
+        let file = reader.next_part().unwrap().unwrap();
+        assert_eq!(file.name, "invoice");
+        assert_eq!(file.filename.as_deref(), Some("in;voice.txt"));
+        assert_eq!(file.content_type.as_deref(), Some("text/plain"));
+        assert_eq!(file.bytes().unwrap(), b"line one\r\n--Xy is only a partial boundary\r\n");
+
+        assert!(reader.next_part().unwrap().is_none());
+    }
+
+    // 测试大文件分段写入磁盘，释放后临时文件被删除
+    #[test]
+    fn test_large_file_goes_to_disk() {
+        let contents = vec![b'x'; 1000];
+        let body = sample_body(&contents);
+        let limits = MultipartLimits {
+            memory_threshold: 100,
+            ..MultipartLimits::default()
+        };
+        let mut reader = MultipartReader::new(body.as_slice(), "XyZ", limits);
+        reader.next_part().unwrap();
+        let file = reader.next_part().unwrap().unwrap();
+        let path = match &file.data {
+            PartData::File(temp) => temp.path().to_path_buf(),
+            PartData::Memory(_) => panic!("expected the part to be stored on disk"),
+        };
+        assert_eq!(file.size, 1000);
+        assert_eq!(file.bytes().unwrap(), contents);
+        drop(file);
+        assert!(!path.exists());
+    }
+
+    // 测试单个分段和总大小限制
+    #[test]
+    fn test_limits() {
+        let body = sample_body(&[b'x'; 500]);
+        let limits = MultipartLimits {
+            max_part_size: 100,
+            ..MultipartLimits::default()
+        };
+        let mut reader = MultipartReader::new(body.as_slice(), "XyZ", limits);
+        reader.next_part().unwrap();
+        let err = reader.next_part().unwrap_err();
+        assert_eq!(err.status_code(), "413");
+        assert!(matches!(err, MultipartError::PartTooLarge { .. }));
+
+        let limits = MultipartLimits {
+            max_total_size: 100,
+            ..MultipartLimits::default()
+        };
+        let err = MultipartReader::new(body.as_slice(), "XyZ", limits).next_part().unwrap_err();
+        assert!(matches!(err, MultipartError::TotalTooLarge { .. }));
+    }
+
+    // 测试从请求中提取 multipart 表单
+    #[test]
+    fn test_request_multipart() {
+        let mut raw = b"POST /api/uploads HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=XyZ\r\n\r\n".to_vec();
+        raw.extend_from_slice(&sample_body(&[0xff, 0x00]));
+        let req: HttpRequest = raw.into();
+        let form = req.multipart().unwrap();
+        assert_eq!(form.field("order_id"), Some("2"));
+        assert_eq!(form.file("invoice").unwrap().bytes().unwrap(), vec![0xff, 0x00]);
+    }
+}
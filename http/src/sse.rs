@@ -0,0 +1,148 @@
+// 导入所需的库
+use super::chunked::BodyWriter;
+use super::httpresponse::HttpResponse;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Write};
+
+// 一个服务器发送事件（text/event-stream 格式）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    pub id: Option<String>,    // 事件 ID，客户端重连时通过 Last-Event-ID 带回
+    pub event: Option<String>, // 事件类型，缺省时客户端按 message 处理
+    pub data: String,          // 事件数据，可以包含多行
+    pub retry: Option<u64>,    // 建议客户端断开后重连的等待时间（毫秒）
+}
+
+impl Event {
+    // 创建只有数据的事件
+    pub fn new(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    // 设置事件 ID
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    // 设置事件类型
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    // 设置重连等待时间
+    pub fn retry(mut self, millis: u64) -> Self {
+        self.retry = Some(millis);
+        self
+    }
+}
+
+// 按 text/event-stream 格式输出，以空行结束
+// id 和 event 中的换行会被去掉，防止注入额外的字段；多行数据每行一个 data 字段
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let single_line = |s: &str| s.replace(['\r', '\n'], "");
+        if let Some(event) = &self.event {
+            writeln!(f, "event: {}", single_line(event))?;
+        }
+        // ID 不能包含空字符，否则客户端会忽略
+        if let Some(id) = &self.id {
+            writeln!(f, "id: {}", single_line(id).replace('\0', ""))?;
+        }
+        if let Some(retry) = self.retry {
+            writeln!(f, "retry: {}", retry)?;
+        }
+        for line in self.data.split('\n') {
+            writeln!(f, "data: {}", line.strip_suffix('\r').unwrap_or(line))?;
+        }
+        writeln!(f)
+    }
+}
+
+// 事件流的发送端，每次发送后立即刷新，客户端断开后返回 BrokenPipe
+pub struct EventSender {
+    writer: BodyWriter,
+}
+
+impl EventSender {
+    // 发送一个事件
+    pub fn send(&mut self, event: &Event) -> io::Result<()> {
+        write!(self.writer, "{}", event)?;
+        self.writer.flush()
+    }
+
+    // 只设置客户端的重连等待时间，不触发任何事件
+    pub fn retry(&mut self, millis: u64) -> io::Result<()> {
+        write!(self.writer, "retry: {}\n\n", millis)?;
+        self.writer.flush()
+    }
+
+    // 发送注释行作为心跳，防止代理因空闲断开连接，同时用来发现已断开的客户端
+    pub fn heartbeat(&mut self) -> io::Result<()> {
+        self.writer.write_all(b": heartbeat\n\n")?;
+        self.writer.flush()
+    }
+
+    // 正常结束事件流
+    pub fn close(self) -> io::Result<()> {
+        self.writer.finish()
+    }
+}
+
+// 创建事件流响应，处理器返回响应后在其他线程通过 EventSender 发送事件
+pub fn event_stream<'a>() -> (HttpResponse<'a>, EventSender) {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type", "text/event-stream");
+    headers.insert("Cache-Control", "no-cache");
+    // 让 nginx 等反向代理不缓冲事件
+    headers.insert("X-Accel-Buffering", "no");
+    let (response, writer) = HttpResponse::streaming("200", Some(headers));
+    (response, EventSender { writer })
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunked::Chunk;
+
+    // 测试事件的格式
+    #[test]
+    fn test_event_format() {
+        assert_eq!(Event::new("hello").to_string(), "data: hello\n\n");
+        let event = Event::new("line 1\nline 2\r\n").id("7").event("order").retry(3000);
+        assert_eq!(
+            event.to_string(),
+            "event: order\nid: 7\nretry: 3000\ndata: line 1\ndata: line 2\ndata: \n\n"
+        );
+        // 字段中的换行不能产生新的字段
+        let event = Event::new("x").id("1\ndata: injected").event("a\r\nb");
+        assert_eq!(event.to_string(), "event: ab\nid: 1data: injected\ndata: x\n\n");
+    }
+
+    // 测试事件流的响应头和发送的内容
+    #[test]
+    fn test_event_stream() {
+        let (response, mut sender) = event_stream();
+        let headers = response.header_list();
+        assert!(headers.contains(&("Content-Type", "text/event-stream")));
+        assert!(headers.contains(&("Cache-Control", "no-cache")));
+        assert!(response.is_streaming());
+
+        sender.retry(3000).unwrap();
+        sender.send(&Event::new("{}").id("1")).unwrap();
+        sender.heartbeat().unwrap();
+        sender.close().unwrap();
+
+        let stream = response.stream().unwrap();
+        assert_eq!(stream.recv(), Some(Chunk::Data(b"retry: 3000\n\n".to_vec())));
+        assert_eq!(stream.recv(), Some(Chunk::Data(b"id: 1\ndata: {}\n\n".to_vec())));
+        assert_eq!(stream.recv(), Some(Chunk::Data(b": heartbeat\n\n".to_vec())));
+        assert_eq!(stream.recv(), Some(Chunk::End(Vec::new())));
+    }
+}
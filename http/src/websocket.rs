@@ -0,0 +1,967 @@
+// 导入所需的库
+use super::httprequest::{HttpRequest, Method, Version};
+use super::httpresponse::HttpResponse;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress};
+use sha1::{Digest, Sha1};
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Read, Write};
+
+// 计算 Sec-WebSocket-Accept 时附加在密钥后面的固定字符串
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+// 默认的最大消息大小（解压后），超过时以 1009 关闭连接
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+// 发送时每个帧的默认最大负载，更大的消息分片发送
+const DEFAULT_FRAME_SIZE: usize = 64 * 1024;
+
+// 控制帧负载的上限
+const MAX_CONTROL_PAYLOAD: usize = 125;
+
+// permessage-deflate 的每条消息以同步刷新结束，发送时去掉这 4 个字节，接收时补上
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+// 帧的操作码
+const OP_CONTINUATION: u8 = 0x0;
+const OP_TEXT: u8 = 0x1;
+const OP_BINARY: u8 = 0x2;
+const OP_CLOSE: u8 = 0x8;
+const OP_PING: u8 = 0x9;
+const OP_PONG: u8 = 0xa;
+
+// 关闭代码
+pub const CLOSE_NORMAL: u16 = 1000;
+pub const CLOSE_GOING_AWAY: u16 = 1001;
+pub const CLOSE_PROTOCOL_ERROR: u16 = 1002;
+pub const CLOSE_UNSUPPORTED_DATA: u16 = 1003;
+pub const CLOSE_INVALID_PAYLOAD: u16 = 1007;
+pub const CLOSE_POLICY_VIOLATION: u16 = 1008;
+pub const CLOSE_MESSAGE_TOO_BIG: u16 = 1009;
+pub const CLOSE_INTERNAL_ERROR: u16 = 1011;
+
+// 计算握手响应的 Sec-WebSocket-Accept：base64(SHA-1(密钥 + GUID))
+pub fn accept_key(key: &str) -> String {
+    let mut sha1 = Sha1::new();
+    sha1.update(key.trim().as_bytes());
+    sha1.update(GUID.as_bytes());
+    STANDARD.encode(sha1.finalize())
+}
+
+// 逗号分隔的头部值中是否包含某个标记（不区分大小写）
+fn has_token(value: Option<&str>, token: &str) -> bool {
+    value.is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+}
+
+// 请求是否要求升级到 WebSocket
+pub fn is_upgrade(req: &HttpRequest) -> bool {
+    has_token(req.header("Upgrade"), "websocket") && has_token(req.header("Connection"), "upgrade")
+}
+
+// 握手失败的原因
+#[derive(Debug, Clone, PartialEq)]
+pub enum HandshakeError {
+    NotUpgrade,         // 没有 Upgrade: websocket 和 Connection: Upgrade
+    InvalidRequest,     // 不是 HTTP/1.1 的 GET 请求
+    InvalidKey,         // Sec-WebSocket-Key 缺失或不是 16 字节的 base64
+    UnsupportedVersion, // Sec-WebSocket-Version 不是 13
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::NotUpgrade => write!(f, "websocket upgrade required"),
+            HandshakeError::InvalidRequest => write!(f, "websocket handshake must be an HTTP/1.1 GET request"),
+            HandshakeError::InvalidKey => write!(f, "invalid Sec-WebSocket-Key"),
+            HandshakeError::UnsupportedVersion => write!(f, "unsupported Sec-WebSocket-Version"),
+        }
+    }
+}
+
+impl std::error::Error for HandshakeError {}
+
+impl HandshakeError {
+    // 握手失败的响应：需要升级或版本不支持时返回 426 并说明支持的协议，其他情况返回 400
+    pub fn to_response(&self) -> HttpResponse<'static> {
+        let status = match self {
+            HandshakeError::NotUpgrade | HandshakeError::UnsupportedVersion => "426",
+            HandshakeError::InvalidRequest | HandshakeError::InvalidKey => "400",
+        };
+        let mut resp = HttpResponse::new(status, None, Some(self.to_string()));
+        match self {
+            HandshakeError::NotUpgrade => {
+                resp.add_header("Upgrade", "websocket");
+                resp.add_header("Connection", "Upgrade");
+            }
+            HandshakeError::UnsupportedVersion => resp.add_header("Sec-WebSocket-Version", "13"),
+            _ => {}
+        }
+        resp
+    }
+}
+
+// 握手成功后协商的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handshake {
+    accept: String,                     // Sec-WebSocket-Accept 的值
+    pub protocol: Option<String>,       // 选中的子协议
+    pub deflate: Option<DeflateConfig>, // 协商的 permessage-deflate 参数
+}
+
+// 检查升级请求并协商子协议和扩展，protocols 是服务器支持的子协议
+pub fn handshake(req: &HttpRequest, protocols: &[&str]) -> Result<Handshake, HandshakeError> {
+    if !is_upgrade(req) {
+        return Err(HandshakeError::NotUpgrade);
+    }
+    if req.method != Method::Get || req.version != Version::V1_1 {
+        return Err(HandshakeError::InvalidRequest);
+    }
+    let key = req.header("Sec-WebSocket-Key").ok_or(HandshakeError::InvalidKey)?;
+    if STANDARD.decode(key.trim()).map_or(true, |k| k.len() != 16) {
+        return Err(HandshakeError::InvalidKey);
+    }
+    if req.header("Sec-WebSocket-Version").map(str::trim) != Some("13") {
+        return Err(HandshakeError::UnsupportedVersion);
+    }
+    // 按客户端给出的顺序选择第一个服务器支持的子协议
+    let protocol = req
+        .header("Sec-WebSocket-Protocol")
+        .into_iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .find(|p| protocols.contains(p))
+        .map(str::to_string);
+    Ok(Handshake {
+        accept: accept_key(key),
+        protocol,
+        deflate: req.header("Sec-WebSocket-Extensions").and_then(DeflateConfig::negotiate),
+    })
+}
+
+impl Handshake {
+    // 101 Switching Protocols 响应
+    pub fn response(&self) -> HttpResponse<'static> {
+        let mut resp = HttpResponse::new("101", Some(HashMap::new()), None);
+        resp.add_header("Upgrade", "websocket");
+        resp.add_header("Connection", "Upgrade");
+        resp.add_header("Sec-WebSocket-Accept", self.accept.clone());
+        if let Some(protocol) = &self.protocol {
+            resp.add_header("Sec-WebSocket-Protocol", protocol.clone());
+        }
+        if let Some(deflate) = &self.deflate {
+            resp.add_header("Sec-WebSocket-Extensions", deflate.to_string());
+        }
+        resp
+    }
+}
+
+// permessage-deflate 扩展的参数（RFC 7692）
+// 压缩总是使用 15 位的窗口，所以不接受要求服务器使用更小窗口的提议
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DeflateConfig {
+    pub server_no_context_takeover: bool, // 服务器每条消息都重置压缩上下文
+    pub client_no_context_takeover: bool, // 客户端每条消息都重置压缩上下文
+}
+
+impl DeflateConfig {
+    // 从 Sec-WebSocket-Extensions 中选择第一个可以接受的 permessage-deflate 提议
+    pub fn negotiate(extensions: &str) -> Option<Self> {
+        extensions.split(',').find_map(Self::parse_offer)
+    }
+
+    // 解析一个扩展提议，参数未知、重复或无法满足时返回 None
+    fn parse_offer(offer: &str) -> Option<Self> {
+        let mut params = offer.split(';').map(str::trim);
+        if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+            return None;
+        }
+        let mut config = DeflateConfig::default();
+        let mut seen = Vec::new();
+        for param in params {
+            let (name, value) = match param.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                None => (param, None),
+            };
+            if seen.contains(&name) {
+                return None;
+            }
+            seen.push(name);
+            let valid_bits = |bits: &str| bits.parse::<u8>().is_ok_and(|b| (8..=15).contains(&b));
+            match (name, value) {
+                ("server_no_context_takeover", None) => config.server_no_context_takeover = true,
+                ("client_no_context_takeover", None) => config.client_no_context_takeover = true,
+                // 解压总是使用最大的窗口，可以处理客户端使用的任意窗口
+                ("client_max_window_bits", None) => {}
+                ("client_max_window_bits", Some(bits)) if valid_bits(bits) => {}
+                ("server_max_window_bits", Some("15")) => {}
+                _ => return None,
+            }
+        }
+        Some(config)
+    }
+}
+
+// 握手响应中的 Sec-WebSocket-Extensions 值
+impl fmt::Display for DeflateConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "permessage-deflate")?;
+        if self.server_no_context_takeover {
+            write!(f, "; server_no_context_takeover")?;
+        }
+        if self.client_no_context_takeover {
+            write!(f, "; client_no_context_takeover")?;
+        }
+        Ok(())
+    }
+}
+
+// 关闭帧中的代码和原因
+#[derive(Debug, Clone, PartialEq)]
+pub struct CloseFrame {
+    pub code: u16,      // 关闭代码
+    pub reason: String, // 关闭原因，最多 123 字节
+}
+
+// 一条完整的消息（分片已合并、已解压）或控制帧
+#[derive(Debug, Clone, PartialEq)]
+pub enum Message {
+    Text(String),              // 文本消息
+    Binary(Vec<u8>),           // 二进制消息
+    Ping(Vec<u8>),             // 对端的 Ping，已自动回复 Pong
+    Pong(Vec<u8>),             // 对端的 Pong
+    Close(Option<CloseFrame>), // 对端的关闭帧，已自动回复
+}
+
+// WebSocket 连接的错误
+#[derive(Debug)]
+pub enum WsError {
+    Io(io::Error),                // 读写错误，读超时（WouldBlock/TimedOut）后可以再次调用 recv
+    Protocol(&'static str),       // 违反协议，以 1002 关闭
+    InvalidPayload(&'static str), // 文本不是 UTF-8 或压缩数据无效，以 1007 关闭
+    MessageTooBig,                // 消息超过大小限制，以 1009 关闭
+    Closed,                       // 连接已经关闭
+}
+
+impl fmt::Display for WsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WsError::Io(e) => write!(f, "io error: {}", e),
+            WsError::Protocol(message) => write!(f, "protocol error: {}", message),
+            WsError::InvalidPayload(message) => write!(f, "invalid payload: {}", message),
+            WsError::MessageTooBig => write!(f, "message too big"),
+            WsError::Closed => write!(f, "connection closed"),
+        }
+    }
+}
+
+impl std::error::Error for WsError {}
+
+impl From<io::Error> for WsError {
+    fn from(e: io::Error) -> Self {
+        WsError::Io(e)
+    }
+}
+
+impl WsError {
+    // 发生错误时发送给对端的关闭代码，读写错误和已关闭时不发送关闭帧
+    pub fn close_code(&self) -> Option<u16> {
+        match self {
+            WsError::Protocol(_) => Some(CLOSE_PROTOCOL_ERROR),
+            WsError::InvalidPayload(_) => Some(CLOSE_INVALID_PAYLOAD),
+            WsError::MessageTooBig => Some(CLOSE_MESSAGE_TOO_BIG),
+            WsError::Io(_) | WsError::Closed => None,
+        }
+    }
+}
+
+// 连接的一端：服务器接收的帧必须带掩码，客户端发送的帧必须带掩码
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Server,
+    Client,
+}
+
+// 一个帧
+struct Frame {
+    fin: bool,        // 是否是消息的最后一个分片
+    rsv1: bool,       // permessage-deflate 用来标记压缩的消息
+    opcode: u8,       // 操作码
+    payload: Vec<u8>, // 已去掉掩码的负载
+}
+
+// 正在接收的分片消息
+struct Partial {
+    opcode: u8,       // 第一个分片的操作码
+    compressed: bool, // 是否压缩
+    data: Vec<u8>,    // 已收到的数据
+}
+
+// permessage-deflate 的压缩和解压上下文
+struct Deflate {
+    compress: Compress,     // 发送方向
+    decompress: Decompress, // 接收方向
+    reset_compress: bool,   // 每条消息后重置压缩上下文
+    reset_decompress: bool, // 每条消息后重置解压上下文
+}
+
+impl Deflate {
+    // 根据协商的参数和本端的角色创建上下文
+    fn new(config: DeflateConfig, role: Role) -> Self {
+        let (reset_compress, reset_decompress) = match role {
+            Role::Server => (config.server_no_context_takeover, config.client_no_context_takeover),
+            Role::Client => (config.client_no_context_takeover, config.server_no_context_takeover),
+        };
+        Deflate {
+            compress: Compress::new(Compression::default(), false),
+            decompress: Decompress::new(false),
+            reset_compress,
+            reset_decompress,
+        }
+    }
+
+    // 压缩一条消息，去掉同步刷新产生的结尾
+    fn compress(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let start = self.compress.total_in();
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            self.compress
+                .compress_vec(&data[consumed..], &mut out, FlushCompress::Sync)
+                .map_err(io::Error::other)?;
+            // 输入全部处理且输出缓冲区没有写满时刷新完成
+            if (self.compress.total_in() - start) as usize == data.len() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(out.capacity().max(1024));
+        }
+        if out.ends_with(&DEFLATE_TAIL) {
+            out.truncate(out.len() - DEFLATE_TAIL.len());
+        }
+        if self.reset_compress {
+            self.compress.reset();
+        }
+        Ok(out)
+    }
+
+    // 解压一条消息，解压后的大小超过 max 时停止，防止压缩炸弹
+    fn decompress(&mut self, data: &[u8], max: usize) -> Result<Vec<u8>, WsError> {
+        let mut input = data.to_vec();
+        input.extend_from_slice(&DEFLATE_TAIL);
+        let start = self.decompress.total_in();
+        let mut out = Vec::with_capacity((data.len() * 4).clamp(64, max + 1));
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let produced = out.len();
+            self.decompress
+                .decompress_vec(&input[consumed..], &mut out, FlushDecompress::Sync)
+                .map_err(|_| WsError::InvalidPayload("invalid compressed data"))?;
+            if out.len() > max {
+                return Err(WsError::MessageTooBig);
+            }
+            let done = (self.decompress.total_in() - start) as usize == input.len();
+            if done && out.len() < out.capacity() {
+                break;
+            }
+            if out.len() == out.capacity() {
+                out.reserve(out.capacity().min(max + 1 - out.len()).max(1));
+            } else if out.len() == produced && (self.decompress.total_in() - start) as usize == consumed {
+                return Err(WsError::InvalidPayload("invalid compressed data"));
+            }
+        }
+        if self.reset_decompress {
+            self.decompress.reset(false);
+        }
+        Ok(out)
+    }
+}
+
+// 已完成握手的 WebSocket 连接
+// recv 读取下一条消息，send 发送消息；收到 Ping 和关闭帧时自动回复
+pub struct WebSocket<S> {
+    stream: S,                  // 底层连接
+    role: Role,                 // 本端的角色
+    buffered: Vec<u8>,          // 已读取但尚未处理的字节
+    max_message_size: usize,    // 接收消息的大小上限
+    frame_size: usize,          // 发送时每个帧的最大负载
+    deflate: Option<Deflate>,   // permessage-deflate 上下文
+    partial: Option<Partial>,   // 正在接收的分片消息
+    close_sent: bool,           // 是否已发送关闭帧
+    closed: bool,               // 是否已收到关闭帧或连接已失败，不再读取
+}
+
+impl<S: Read + Write> WebSocket<S> {
+    // 在已完成握手的连接上创建 WebSocket，deflate 是协商的压缩参数
+    pub fn new(stream: S, role: Role, deflate: Option<DeflateConfig>) -> Self {
+        WebSocket {
+            stream,
+            role,
+            buffered: Vec::new(),
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            frame_size: DEFAULT_FRAME_SIZE,
+            deflate: deflate.map(|config| Deflate::new(config, role)),
+            partial: None,
+            close_sent: false,
+            closed: false,
+        }
+    }
+
+    // 服务器端的连接，使用握手协商的参数
+    pub fn server(stream: S, handshake: &Handshake) -> Self {
+        Self::new(stream, Role::Server, handshake.deflate)
+    }
+
+    // 设置握手之后已经读取的数据
+    pub fn buffered(mut self, data: Vec<u8>) -> Self {
+        self.buffered = data;
+        self
+    }
+
+    // 设置接收消息的大小上限
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    // 设置发送时每个帧的最大负载
+    pub fn frame_size(mut self, size: usize) -> Self {
+        self.frame_size = size.max(1);
+        self
+    }
+
+    // 底层连接，用来设置读超时等
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    // 底层连接的可变引用
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    // 读取下一条消息
+    // 违反协议时发送相应的关闭帧并返回错误；读超时返回 Io 错误，已读取的数据保留，可以再次调用
+    pub fn recv(&mut self) -> Result<Message, WsError> {
+        if self.closed {
+            return Err(WsError::Closed);
+        }
+        let result = self.read_message();
+        if let Err(e) = &result {
+            if let Some(code) = e.close_code() {
+                self.closed = true;
+                if !self.close_sent {
+                    let reason = e.to_string();
+                    let _ = self.send(&Message::Close(Some(CloseFrame { code, reason })));
+                }
+            }
+        }
+        result
+    }
+
+    // 发送一条消息，文本和二进制消息超过帧大小时分片发送
+    pub fn send(&mut self, message: &Message) -> Result<(), WsError> {
+        if self.close_sent {
+            return Err(WsError::Closed);
+        }
+        match message {
+            Message::Text(text) => self.send_data(OP_TEXT, text.as_bytes()),
+            Message::Binary(data) => self.send_data(OP_BINARY, data),
+            Message::Ping(data) => self.send_control(OP_PING, data),
+            Message::Pong(data) => self.send_control(OP_PONG, data),
+            Message::Close(frame) => {
+                self.send_control(OP_CLOSE, &close_payload(frame.as_ref()))?;
+                self.close_sent = true;
+                Ok(())
+            }
+        }
+    }
+
+    // 发送关闭帧并等待对端的关闭帧，期间收到的消息被丢弃
+    pub fn close(&mut self, code: u16, reason: &str) -> Result<(), WsError> {
+        if !self.close_sent {
+            let reason = reason.to_string();
+            self.send(&Message::Close(Some(CloseFrame { code, reason })))?;
+        }
+        while !self.closed {
+            match self.recv() {
+                Ok(_) => {}
+                Err(WsError::Closed) => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // 读取帧直到得到一条完整的消息或控制帧
+    fn read_message(&mut self) -> Result<Message, WsError> {
+        loop {
+            let frame = self.read_frame()?;
+            match frame.opcode {
+                OP_PING => {
+                    if !self.close_sent {
+                        self.write_frame(true, false, OP_PONG, &frame.payload)?;
+                    }
+                    return Ok(Message::Ping(frame.payload));
+                }
+                OP_PONG => return Ok(Message::Pong(frame.payload)),
+                OP_CLOSE => {
+                    let close = parse_close(&frame.payload)?;
+                    self.closed = true;
+                    // 回复同样的关闭代码，完成关闭握手
+                    if !self.close_sent {
+                        self.send_control(OP_CLOSE, &frame.payload[..frame.payload.len().min(2)])?;
+                        self.close_sent = true;
+                    }
+                    return Ok(Message::Close(close));
+                }
+                OP_TEXT | OP_BINARY => {
+                    if self.partial.is_some() {
+                        return Err(WsError::Protocol("new message before the previous one finished"));
+                    }
+                    if frame.fin {
+                        return self.finish_message(frame.opcode, frame.rsv1, frame.payload);
+                    }
+                    self.partial = Some(Partial {
+                        opcode: frame.opcode,
+                        compressed: frame.rsv1,
+                        data: frame.payload,
+                    });
+                }
+                _ => {
+                    let Some(partial) = self.partial.as_mut() else {
+                        return Err(WsError::Protocol("continuation frame without a message"));
+                    };
+                    if frame.rsv1 {
+                        return Err(WsError::Protocol("RSV1 set on continuation frame"));
+                    }
+                    partial.data.extend_from_slice(&frame.payload);
+                    if frame.fin {
+                        let partial = self.partial.take().expect("partial message");
+                        return self.finish_message(partial.opcode, partial.compressed, partial.data);
+                    }
+                }
+            }
+        }
+    }
+
+    // 解压并检查完整的消息
+    fn finish_message(&mut self, opcode: u8, compressed: bool, data: Vec<u8>) -> Result<Message, WsError> {
+        let data = match (&mut self.deflate, compressed) {
+            (Some(deflate), true) => deflate.decompress(&data, self.max_message_size)?,
+            _ => data,
+        };
+        if opcode == OP_BINARY {
+            return Ok(Message::Binary(data));
+        }
+        String::from_utf8(data)
+            .map(Message::Text)
+            .map_err(|_| WsError::InvalidPayload("text message is not valid UTF-8"))
+    }
+
+    // 从连接读取数据，直到缓冲区中至少有 n 字节，对端关闭连接返回 UnexpectedEof
+    fn fill(&mut self, n: usize) -> io::Result<()> {
+        let mut read_buffer = [0u8; 16 * 1024];
+        while self.buffered.len() < n {
+            match self.stream.read(&mut read_buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.buffered.extend_from_slice(&read_buffer[..read]),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    // 读取并检查一个帧，帧完整之前数据保留在缓冲区中
+    fn read_frame(&mut self) -> Result<Frame, WsError> {
+        self.fill(2)?;
+        let (b0, b1) = (self.buffered[0], self.buffered[1]);
+        let (len, header_len) = match b1 & 0x7f {
+            126 => {
+                self.fill(4)?;
+                (u16::from_be_bytes([self.buffered[2], self.buffered[3]]) as u64, 4)
+            }
+            127 => {
+                self.fill(10)?;
+                let len = u64::from_be_bytes(self.buffered[2..10].try_into().expect("8 bytes"));
+                if len >> 63 != 0 {
+                    return Err(WsError::Protocol("invalid payload length"));
+                }
+                (len, 10)
+            }
+            len => (len as u64, 2),
+        };
+
+        let fin = b0 & 0x80 != 0;
+        let rsv1 = b0 & 0x40 != 0;
+        let opcode = b0 & 0x0f;
+        let masked = b1 & 0x80 != 0;
+        if b0 & 0x30 != 0 {
+            return Err(WsError::Protocol("reserved bits set"));
+        }
+        if !matches!(opcode, OP_CONTINUATION | OP_TEXT | OP_BINARY | OP_CLOSE | OP_PING | OP_PONG) {
+            return Err(WsError::Protocol("unknown opcode"));
+        }
+        let control = opcode & 0x8 != 0;
+        if rsv1 && (self.deflate.is_none() || control) {
+            return Err(WsError::Protocol("unexpected RSV1"));
+        }
+        if control && (!fin || len > MAX_CONTROL_PAYLOAD as u64) {
+            return Err(WsError::Protocol("invalid control frame"));
+        }
+        if masked != (self.role == Role::Server) {
+            return Err(WsError::Protocol("invalid frame masking"));
+        }
+        let received = self.partial.as_ref().map_or(0, |p| p.data.len());
+        if !control && len > (self.max_message_size - received.min(self.max_message_size)) as u64 {
+            return Err(WsError::MessageTooBig);
+        }
+
+        let mask_len = if masked { 4 } else { 0 };
+        let total = header_len + mask_len + len as usize;
+        self.fill(total)?;
+        let mut payload = self.buffered[header_len + mask_len..total].to_vec();
+        if masked {
+            let key: [u8; 4] = self.buffered[header_len..header_len + 4].try_into().expect("4 bytes");
+            apply_mask(&mut payload, key);
+        }
+        self.buffered.drain(..total);
+        Ok(Frame { fin, rsv1, opcode, payload })
+    }
+
+    // 发送文本或二进制消息，协商了压缩时先压缩整条消息
+    fn send_data(&mut self, opcode: u8, data: &[u8]) -> Result<(), WsError> {
+        let compressed = self.deflate.is_some();
+        let payload = match &mut self.deflate {
+            Some(deflate) => deflate.compress(data)?,
+            None => data.to_vec(),
+        };
+        let frames = payload.len().div_ceil(self.frame_size).max(1);
+        for i in 0..frames {
+            let chunk = &payload[(i * self.frame_size).min(payload.len())..((i + 1) * self.frame_size).min(payload.len())];
+            let opcode = if i == 0 { opcode } else { OP_CONTINUATION };
+            self.write_frame(i + 1 == frames, compressed && i == 0, opcode, chunk)?;
+        }
+        Ok(())
+    }
+
+    // 发送控制帧
+    fn send_control(&mut self, opcode: u8, payload: &[u8]) -> Result<(), WsError> {
+        if payload.len() > MAX_CONTROL_PAYLOAD {
+            return Err(WsError::Protocol("control frame payload too long"));
+        }
+        self.write_frame(true, false, opcode, payload)
+    }
+
+    // 编码并写出一个帧，客户端使用随机掩码
+    fn write_frame(&mut self, fin: bool, rsv1: bool, opcode: u8, payload: &[u8]) -> Result<(), WsError> {
+        let mut frame = Vec::with_capacity(payload.len() + 14);
+        frame.push((fin as u8) << 7 | (rsv1 as u8) << 6 | opcode);
+        let mask_bit = if self.role == Role::Client { 0x80 } else { 0 };
+        match payload.len() {
+            len if len <= MAX_CONTROL_PAYLOAD => frame.push(mask_bit | len as u8),
+            len if len <= u16::MAX as usize => {
+                frame.push(mask_bit | 126);
+                frame.extend_from_slice(&(len as u16).to_be_bytes());
+            }
+            len => {
+                frame.push(mask_bit | 127);
+                frame.extend_from_slice(&(len as u64).to_be_bytes());
+            }
+        }
+        if self.role == Role::Client {
+            let key = random_mask();
+            frame.extend_from_slice(&key);
+            let start = frame.len();
+            frame.extend_from_slice(payload);
+            apply_mask(&mut frame[start..], key);
+        } else {
+            frame.extend_from_slice(payload);
+        }
+        self.stream.write_all(&frame)?;
+        self.stream.flush()?;
+        Ok(())
+    }
+}
+
+// 对负载按字节异或掩码，加掩码和去掩码是同一个操作
+fn apply_mask(data: &mut [u8], key: [u8; 4]) {
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % 4];
+    }
+}
+
+// 客户端帧的掩码，使用标准库随机初始化的哈希密钥作为随机源
+fn random_mask() -> [u8; 4] {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    (hasher.finish() as u32).to_be_bytes()
+}
+
+// 允许出现在关闭帧中的代码，1005、1006 和 1015 只在本地使用
+fn valid_close_code(code: u16) -> bool {
+    matches!(code, 1000..=1003 | 1007..=1011 | 3000..=4999)
+}
+
+// 解析关闭帧的负载
+fn parse_close(payload: &[u8]) -> Result<Option<CloseFrame>, WsError> {
+    match payload {
+        [] => Ok(None),
+        [_] => Err(WsError::Protocol("invalid close frame")),
+        [hi, lo, reason @ ..] => {
+            let code = u16::from_be_bytes([*hi, *lo]);
+            if !valid_close_code(code) {
+                return Err(WsError::Protocol("invalid close code"));
+            }
+            let reason = String::from_utf8(reason.to_vec()).map_err(|_| WsError::InvalidPayload("close reason is not valid UTF-8"))?;
+            Ok(Some(CloseFrame { code, reason }))
+        }
+    }
+}
+
+// 编码关闭帧的负载，原因超长时在字符边界处截断
+fn close_payload(frame: Option<&CloseFrame>) -> Vec<u8> {
+    let Some(frame) = frame else {
+        return Vec::new();
+    };
+    let mut end = frame.reason.len().min(MAX_CONTROL_PAYLOAD - 2);
+    while !frame.reason.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut payload = frame.code.to_be_bytes().to_vec();
+    payload.extend_from_slice(&frame.reason.as_bytes()[..end]);
+    payload
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // 内存中的连接：从 input 读取，写入 output
+    #[derive(Default)]
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 用一端发送的消息构造另一端，返回接收端
+    fn connect(
+        deflate: Option<DeflateConfig>,
+        send: impl FnOnce(&mut WebSocket<Pipe>),
+    ) -> WebSocket<Pipe> {
+        let mut client = WebSocket::new(Pipe::default(), Role::Client, deflate);
+        send(&mut client);
+        let pipe = Pipe {
+            input: Cursor::new(std::mem::take(&mut client.get_mut().output)),
+            output: Vec::new(),
+        };
+        WebSocket::new(pipe, Role::Server, deflate)
+    }
+
+    // 服务器收到的原始字节（带掩码的客户端帧）
+    fn server_for(input: Vec<u8>) -> WebSocket<Pipe> {
+        let pipe = Pipe {
+            input: Cursor::new(input),
+            output: Vec::new(),
+        };
+        WebSocket::new(pipe, Role::Server, None)
+    }
+
+    // 构造带掩码的客户端帧
+    fn masked(b0: u8, payload: &[u8]) -> Vec<u8> {
+        let key = [1, 2, 3, 4];
+        let mut frame = vec![b0, 0x80 | payload.len() as u8];
+        frame.extend_from_slice(&key);
+        let start = frame.len();
+        frame.extend_from_slice(payload);
+        apply_mask(&mut frame[start..], key);
+        frame
+    }
+
+    fn request(headers: &str) -> HttpRequest {
+        format!("GET /ws HTTP/1.1\r\nHost: test\r\n{}\r\n", headers).into()
+    }
+
+    // 测试 RFC 6455 中的握手示例
+    #[test]
+    fn test_handshake() {
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+
+        let headers = "Upgrade: websocket\r\nConnection: keep-alive, Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: chat, superchat\r\nSec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n";
+        let accepted = handshake(&request(headers), &["superchat"]).unwrap();
+        assert_eq!(accepted.protocol.as_deref(), Some("superchat"));
+        let response: String = accepted.response().into();
+        assert!(response.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(response.contains("Sec-WebSocket-Accept:s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        assert!(response.contains("Sec-WebSocket-Extensions:permessage-deflate\r\n"));
+        assert!(!response.contains("Content-Length"));
+
+        let base = "Upgrade: websocket\r\nConnection: Upgrade\r\n";
+        let cases = [
+            ("", HandshakeError::NotUpgrade),
+            ("Sec-WebSocket-Version: 13\r\n", HandshakeError::InvalidKey),
+            ("Sec-WebSocket-Key: c2hvcnQ=\r\nSec-WebSocket-Version: 13\r\n", HandshakeError::InvalidKey),
+            ("Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 8\r\n", HandshakeError::UnsupportedVersion),
+        ];
+        for (headers, expected) in cases {
+            let headers = if headers.is_empty() { String::new() } else { format!("{}{}", base, headers) };
+            assert_eq!(handshake(&request(&headers), &[]), Err(expected));
+        }
+        assert_eq!(HandshakeError::UnsupportedVersion.to_response().status_code(), "426");
+    }
+
+    // 测试 permessage-deflate 的协商
+    #[test]
+    fn test_deflate_negotiation() {
+        let config = DeflateConfig::negotiate("permessage-deflate; server_no_context_takeover; client_max_window_bits=10").unwrap();
+        assert!(config.server_no_context_takeover && !config.client_no_context_takeover);
+        assert_eq!(config.to_string(), "permessage-deflate; server_no_context_takeover");
+        // 无法满足的提议被跳过
+        let config = DeflateConfig::negotiate("permessage-deflate; server_max_window_bits=10, permessage-deflate; client_no_context_takeover");
+        assert_eq!(config.map(|c| c.client_no_context_takeover), Some(true));
+        assert_eq!(DeflateConfig::negotiate("permessage-deflate; unknown"), None);
+        assert_eq!(DeflateConfig::negotiate("permessage-deflate; server_no_context_takeover; server_no_context_takeover"), None);
+        assert_eq!(DeflateConfig::negotiate("x-webkit-deflate-frame"), None);
+    }
+
+    // 测试消息的发送和接收，长消息分片发送
+    #[test]
+    fn test_messages() {
+        let large = vec![7u8; 200_000];
+        let mut server = connect(None, |client| {
+            client.send(&Message::Text("hello".into())).unwrap();
+            client.send(&Message::Binary(large.clone())).unwrap();
+            client.send(&Message::Text(String::new())).unwrap();
+            client.send(&Message::Ping(b"p".to_vec())).unwrap();
+        });
+        assert_eq!(server.recv().unwrap(), Message::Text("hello".into()));
+        assert_eq!(server.recv().unwrap(), Message::Binary(large));
+        assert_eq!(server.recv().unwrap(), Message::Text(String::new()));
+        assert_eq!(server.recv().unwrap(), Message::Ping(b"p".to_vec()));
+        // 自动回复的 Pong 不带掩码
+        assert_eq!(server.get_ref().output, [0x8a, 0x01, b'p']);
+        assert!(matches!(server.recv(), Err(WsError::Io(e)) if e.kind() == io::ErrorKind::UnexpectedEof));
+    }
+
+    // 测试分片消息中间可以插入控制帧
+    #[test]
+    fn test_fragmentation() {
+        let mut input = masked(OP_TEXT, b"Hel");
+        input.extend(masked(0x80 | OP_PING, b""));
+        input.extend(masked(OP_CONTINUATION, b"lo, "));
+        input.extend(masked(0x80 | OP_CONTINUATION, "世界".as_bytes()));
+        let mut server = server_for(input);
+        assert_eq!(server.recv().unwrap(), Message::Ping(Vec::new()));
+        assert_eq!(server.recv().unwrap(), Message::Text("Hello, 世界".into()));
+    }
+
+    // 测试 permessage-deflate 在多条消息之间保留上下文
+    #[test]
+    fn test_deflate() {
+        // RFC 7692 中 "Hello" 的压缩示例
+        let mut input = masked(0xc0 | OP_TEXT, &[0xf2, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00]);
+        input.extend(masked(0xc0 | OP_TEXT, &[0xf2, 0x00, 0x11, 0x00, 0x00]));
+        let pipe = Pipe {
+            input: Cursor::new(input),
+            output: Vec::new(),
+        };
+        let mut server = WebSocket::new(pipe, Role::Server, Some(DeflateConfig::default()));
+        assert_eq!(server.recv().unwrap(), Message::Text("Hello".into()));
+        assert_eq!(server.recv().unwrap(), Message::Text("Hello".into()));
+
+        for config in [DeflateConfig::default(), DeflateConfig { server_no_context_takeover: true, client_no_context_takeover: true }] {
+            let text = "compressible ".repeat(1000);
+            let mut server = connect(Some(config), |client| {
+                client.send(&Message::Text(text.clone())).unwrap();
+                client.send(&Message::Text(text.clone())).unwrap();
+                client.send(&Message::Binary(Vec::new())).unwrap();
+            });
+            assert!(server.get_ref().input.get_ref().len() < text.len() / 10);
+            assert_eq!(server.recv().unwrap(), Message::Text(text.clone()));
+            assert_eq!(server.recv().unwrap(), Message::Text(text.clone()));
+            assert_eq!(server.recv().unwrap(), Message::Binary(Vec::new()));
+        }
+    }
+
+    // 测试最大消息大小，包括压缩后很小的消息
+    #[test]
+    fn test_max_message_size() {
+        let mut server = connect(None, |client| client.send(&Message::Binary(vec![0; 101])).unwrap()).max_message_size(100);
+        assert!(matches!(server.recv(), Err(WsError::MessageTooBig)));
+        assert_eq!(&server.get_ref().output[..4], &[0x88, 0x11, 0x03, 0xf1]);
+        assert!(matches!(server.recv(), Err(WsError::Closed)));
+
+        let deflate = Some(DeflateConfig::default());
+        let mut server = connect(deflate, |client| client.send(&Message::Binary(vec![0; 100_000])).unwrap()).max_message_size(1000);
+        assert!(matches!(server.recv(), Err(WsError::MessageTooBig)));
+    }
+
+    // 测试违反协议时以相应的代码关闭
+    #[test]
+    fn test_protocol_errors() {
+        let unmasked = vec![0x81, 0x01, b'x'];
+        let cases = [
+            (unmasked, CLOSE_PROTOCOL_ERROR),
+            (masked(0x80 | 0x3, b""), CLOSE_PROTOCOL_ERROR),
+            (masked(0xa0 | OP_TEXT, b""), CLOSE_PROTOCOL_ERROR),
+            (masked(0xc0 | OP_TEXT, b""), CLOSE_PROTOCOL_ERROR),
+            (masked(OP_PING, b""), CLOSE_PROTOCOL_ERROR),
+            (masked(0x80 | OP_CONTINUATION, b"x"), CLOSE_PROTOCOL_ERROR),
+            (masked(0x80 | OP_CLOSE, &[0x03, 0xed]), CLOSE_PROTOCOL_ERROR),
+            (masked(0x80 | OP_TEXT, &[0xff, 0xfe]), CLOSE_INVALID_PAYLOAD),
+        ];
+        for (input, code) in cases {
+            let mut server = server_for(input);
+            let error = server.recv().unwrap_err();
+            assert_eq!(error.close_code(), Some(code), "{}", error);
+            let output = &server.get_ref().output;
+            assert_eq!((output[0], &output[2..4]), (0x88, &code.to_be_bytes()[..]));
+        }
+    }
+
+    // 测试关闭握手
+    #[test]
+    fn test_close() {
+        let mut input = masked(0x80 | OP_TEXT, b"bye");
+        input.extend(masked(0x80 | OP_CLOSE, &[0x03, 0xe8, b'o', b'k']));
+        let mut server = server_for(input);
+        assert_eq!(server.recv().unwrap(), Message::Text("bye".into()));
+        let close = CloseFrame {
+            code: CLOSE_NORMAL,
+            reason: "ok".into(),
+        };
+        assert_eq!(server.recv().unwrap(), Message::Close(Some(close)));
+        assert_eq!(server.get_ref().output, [0x88, 0x02, 0x03, 0xe8]);
+        assert!(matches!(server.recv(), Err(WsError::Closed)));
+        assert!(matches!(server.send(&Message::Text("late".into())), Err(WsError::Closed)));
+
+        // 主动关闭时等待对端的关闭帧，期间的消息被丢弃
+        let mut input = masked(0x80 | OP_TEXT, b"ignored");
+        input.extend(masked(0x80 | OP_CLOSE, &[]));
+        let mut server = server_for(input);
+        server.close(CLOSE_GOING_AWAY, &"长".repeat(100)).unwrap();
+        let output = &server.get_ref().output;
+        assert_eq!(output[1] as usize, 2 + 123);
+        assert_eq!(&output[2..4], &CLOSE_GOING_AWAY.to_be_bytes());
+    }
+}
@@ -0,0 +1,495 @@
+// 导入所需的库和模块
+use super::auth::Identity; // 导入认证身份
+use super::middleware::Middleware; // 导入中间件
+use super::request_id::{RequestId, TraceContext}; // 导入请求 ID 和链路上下文
+use super::server::PeerAddr; // 导入客户端地址
+use http::httpdate::{format_log_date, format_rfc3339}; // 导入日志时间格式
+use http::httprequest::{HttpRequest, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use serde_json::Value; // 导入 JSON 值
+use std::fs::{self, File, OpenOptions}; // 导入文件操作
+use std::io::{self, Write}; // 导入 IO 模块
+use std::path::{Path, PathBuf}; // 导入路径
+use std::sync::Mutex; // 导入互斥锁
+use std::time::{Instant, SystemTime}; // 导入时间模块
+
+// 日志文件默认达到这个大小后轮转
+pub(crate) const DEFAULT_MAX_SIZE: u64 = 100 * 1024 * 1024;
+
+// 默认保留的旧日志文件数
+pub(crate) const DEFAULT_MAX_FILES: usize = 5;
+
+// 定义访问日志的格式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Common,   // 通用日志格式（CLF）
+    Combined, // 组合日志格式：CLF 加上 Referer 和 User-Agent
+    Json,     // 每行一个 JSON 对象
+}
+
+impl LogFormat {
+    // 按名称查找格式：common、combined 或 json
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "common" => Some(LogFormat::Common),
+            "combined" => Some(LogFormat::Combined),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// 定义访问日志中可以输出的字段
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogField {
+    Time,          // 请求开始的时间
+    RemoteAddr,    // 客户端 IP 地址
+    User,          // 认证通过的用户
+    Method,        // 请求方法
+    Path,          // 请求路径（包括查询字符串）
+    Protocol,      // HTTP 版本
+    Status,        // 响应状态码
+    BytesSent,     // 响应消息体的字节数，流式响应为空
+    BytesReceived, // 请求消息体的字节数
+    Duration,      // 处理耗时（毫秒），流式响应为发出响应头之前的耗时
+    Host,          // Host 请求头
+    Referer,       // Referer 请求头
+    UserAgent,     // User-Agent 请求头
+    RequestId,     // 请求 ID
+    TraceId,       // W3C Trace Context 的链路 ID
+}
+
+impl LogField {
+    // 全部字段，JSON 格式默认输出全部字段
+    pub const ALL: [LogField; 15] = [
+        LogField::Time,
+        LogField::RemoteAddr,
+        LogField::User,
+        LogField::Method,
+        LogField::Path,
+        LogField::Protocol,
+        LogField::Status,
+        LogField::BytesSent,
+        LogField::BytesReceived,
+        LogField::Duration,
+        LogField::Host,
+        LogField::Referer,
+        LogField::UserAgent,
+        LogField::RequestId,
+        LogField::TraceId,
+    ];
+
+    // 字段名，用作 JSON 的键和 ACCESS_LOG_FIELDS 中的名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogField::Time => "time",
+            LogField::RemoteAddr => "remote_addr",
+            LogField::User => "user",
+            LogField::Method => "method",
+            LogField::Path => "path",
+            LogField::Protocol => "protocol",
+            LogField::Status => "status",
+            LogField::BytesSent => "bytes_sent",
+            LogField::BytesReceived => "bytes_received",
+            LogField::Duration => "duration_ms",
+            LogField::Host => "host",
+            LogField::Referer => "referer",
+            LogField::UserAgent => "user_agent",
+            LogField::RequestId => "request_id",
+            LogField::TraceId => "trace_id",
+        }
+    }
+
+    // 按名称查找字段
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|field| field.name() == name)
+    }
+}
+
+// 请求开始处理的时间，访问日志中间件在 before 中附加到请求上
+#[derive(Debug, Clone, Copy)]
+struct Started {
+    time: SystemTime, // 墙上时间，写入日志
+    instant: Instant, // 单调时间，计算耗时
+}
+
+// 按大小轮转的日志文件：写入后超过上限时把 access.log 改名为 access.log.1，旧文件依次后移
+pub struct RotatingFile {
+    path: PathBuf,    // 日志文件路径
+    file: File,       // 当前打开的文件
+    size: u64,        // 当前文件的大小
+    max_size: u64,    // 轮转的大小上限
+    max_files: usize, // 保留的旧文件数
+}
+
+impl RotatingFile {
+    // 以追加方式打开日志文件
+    pub fn open(path: impl AsRef<Path>, max_size: u64, max_files: usize) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            max_size: max_size.max(1),
+            max_files,
+        })
+    }
+
+    // 第 index 个旧文件的路径
+    fn rotated(&self, index: usize) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(format!(".{}", index));
+        PathBuf::from(name)
+    }
+
+    // 轮转：旧文件依次后移，超出数量的删除，然后重新打开空的日志文件
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let _ = fs::rename(self.rotated(index), self.rotated(index + 1));
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    // 写入一条日志，一条日志不会被拆到两个文件里
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.size > 0 && self.size + buf.len() as u64 > self.max_size {
+            self.rotate()?;
+        }
+        self.file.write_all(buf)?;
+        self.size += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// 定义访问日志的输出位置
+pub enum LogOutput {
+    Stdout,             // 标准输出
+    File(RotatingFile), // 按大小轮转的文件
+}
+
+impl LogOutput {
+    // 写入一行日志
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let line = format!("{}\n", line);
+        match self {
+            LogOutput::Stdout => {
+                let mut stdout = io::stdout().lock();
+                stdout.write_all(line.as_bytes())?;
+                stdout.flush()
+            }
+            LogOutput::File(file) => file.write_all(line.as_bytes()),
+        }
+    }
+}
+
+// 访问日志中间件：每个请求完成后写入一行日志
+// 应该最先注册，这样被其他中间件拦截的请求也会被记录，耗时也包括其他中间件
+pub struct AccessLog {
+    format: LogFormat,        // 日志格式
+    fields: Vec<LogField>,    // JSON 格式输出的字段；文本格式在标准字段之后追加其中的其他字段
+    output: Mutex<LogOutput>, // 输出位置
+}
+
+impl AccessLog {
+    // 创建访问日志中间件，JSON 格式默认输出全部字段，文本格式默认只输出标准字段
+    pub fn new(format: LogFormat, output: LogOutput) -> Self {
+        AccessLog {
+            format,
+            fields: match format {
+                LogFormat::Json => LogField::ALL.to_vec(),
+                _ => Vec::new(),
+            },
+            output: Mutex::new(output),
+        }
+    }
+
+    // 设置输出的字段
+    pub fn fields(mut self, fields: Vec<LogField>) -> Self {
+        self.fields = fields;
+        self
+    }
+
+    // 生成一行日志
+    fn format_line(&self, req: &HttpRequest, resp: &HttpResponse) -> String {
+        let entry = Entry::new(req, resp);
+        match self.format {
+            LogFormat::Json => {
+                let pairs: Vec<String> = self
+                    .fields
+                    .iter()
+                    .map(|field| format!("{}:{}", Value::from(field.name()), entry.value(*field)))
+                    .collect();
+                format!("{{{}}}", pairs.join(","))
+            }
+            LogFormat::Common | LogFormat::Combined => {
+                let text = |field| entry.text(field);
+                let bytes = match entry.value(LogField::BytesSent) {
+                    Value::Number(n) if n.as_u64() != Some(0) => n.to_string(),
+                    _ => "-".to_string(),
+                };
+                let mut line = format!(
+                    "{} - {} [{}] \"{} {} {}\" {} {}",
+                    text(LogField::RemoteAddr),
+                    text(LogField::User),
+                    format_log_date(entry.started.time),
+                    text(LogField::Method),
+                    text(LogField::Path),
+                    text(LogField::Protocol),
+                    text(LogField::Status),
+                    bytes
+                );
+                let mut standard = vec![
+                    LogField::Time,
+                    LogField::RemoteAddr,
+                    LogField::User,
+                    LogField::Method,
+                    LogField::Path,
+                    LogField::Protocol,
+                    LogField::Status,
+                    LogField::BytesSent,
+                ];
+                if self.format == LogFormat::Combined {
+                    line.push_str(&format!(" \"{}\" \"{}\"", text(LogField::Referer), text(LogField::UserAgent)));
+                    standard.extend([LogField::Referer, LogField::UserAgent]);
+                }
+                // 标准字段之外的字段以 名称=值 的形式追加
+                for field in self.fields.iter().filter(|field| !standard.contains(field)) {
+                    line.push_str(&format!(" {}=\"{}\"", field.name(), text(*field)));
+                }
+                line
+            }
+        }
+    }
+}
+
+impl Middleware for AccessLog {
+    // 记录请求开始处理的时间
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        req.extensions.insert(Started {
+            time: SystemTime::now(),
+            instant: Instant::now(),
+        });
+        None
+    }
+
+    // 写入日志，写入失败不影响响应
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'_>) {
+        let line = self.format_line(req, resp);
+        let mut output = self.output.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = output.write_line(&line) {
+            eprintln!("Failed to write access log: {}", e);
+        }
+    }
+}
+
+// 一条日志的数据
+struct Entry<'r> {
+    req: &'r HttpRequest,      // 请求
+    status: &'r str,           // 响应状态码
+    bytes_sent: Option<usize>, // 响应消息体的字节数
+    started: Started,          // 请求开始处理的时间
+    elapsed: f64,              // 处理耗时（毫秒）
+}
+
+impl<'r> Entry<'r> {
+    fn new(req: &'r HttpRequest, resp: &'r HttpResponse) -> Self {
+        let started = req.extensions.get::<Started>().copied().unwrap_or(Started {
+            time: SystemTime::now(),
+            instant: Instant::now(),
+        });
+        Entry {
+            req,
+            status: resp.status_code(),
+            bytes_sent: (!resp.is_streaming()).then(|| resp.body().len()),
+            started,
+            elapsed: started.instant.elapsed().as_secs_f64() * 1000.0,
+        }
+    }
+
+    // 字段的值，缺失的值为 null
+    fn value(&self, field: LogField) -> Value {
+        let header = |name| self.req.header(name).map_or(Value::Null, Value::from);
+        match field {
+            LogField::Time => format_rfc3339(self.started.time).into(),
+            LogField::RemoteAddr => self
+                .req
+                .extensions
+                .get::<PeerAddr>()
+                .map_or(Value::Null, |peer| peer.0.ip().to_string().into()),
+            LogField::User => self
+                .req
+                .extensions
+                .get::<Identity>()
+                .map_or(Value::Null, |identity| identity.subject.clone().into()),
+            LogField::Method => self.req.method.as_str().into(),
+            LogField::Path => {
+                let Resource::Path(path) = &self.req.resource;
+                path.clone().into()
+            }
+            LogField::Protocol => self.req.version.as_str().into(),
+            LogField::Status => self.status.parse::<u16>().map_or(Value::Null, Value::from),
+            LogField::BytesSent => self.bytes_sent.map_or(Value::Null, Value::from),
+            LogField::BytesReceived => self.req.raw_body.len().into(),
+            LogField::Duration => ((self.elapsed * 1000.0).round() / 1000.0).into(),
+            LogField::Host => header("Host"),
+            LogField::Referer => header("Referer"),
+            LogField::UserAgent => header("User-Agent"),
+            LogField::RequestId => self
+                .req
+                .extensions
+                .get::<RequestId>()
+                .map_or(Value::Null, |id| id.0.clone().into()),
+            LogField::TraceId => self
+                .req
+                .extensions
+                .get::<TraceContext>()
+                .map_or(Value::Null, |trace| trace.trace_id.clone().into()),
+        }
+    }
+
+    // 文本格式中的值：缺失时为 -，引号、反斜杠和控制字符被转义，防止伪造日志行
+    fn text(&self, field: LogField) -> String {
+        match self.value(field) {
+            Value::Null => "-".to_string(),
+            Value::String(s) => escape(&s),
+            value => value.to_string(),
+        }
+    }
+}
+
+// 转义文本日志中的值
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // 构造一个已经分配了请求 ID、记录了开始时间的请求
+    fn request(raw: &str) -> HttpRequest {
+        let mut req: HttpRequest = raw.to_string().into();
+        crate::request_id::assign(&mut req);
+        req.extensions.insert(PeerAddr("192.0.2.7:5555".parse().unwrap()));
+        req.extensions.insert(Started {
+            time: UNIX_EPOCH + Duration::from_millis(784_111_777_250),
+            instant: Instant::now(),
+        });
+        req
+    }
+
+    // 临时日志文件路径
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("access-log-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir.join("access.log")
+    }
+
+    // 测试通用日志格式和组合日志格式
+    #[test]
+    fn test_text_formats() {
+        let req = request(
+            "GET /orders?id=1 HTTP/1.1\r\nHost: shop\r\nReferer: http://shop/\r\nUser-Agent: curl \"x\"\r\nX-Request-Id: r-1\r\n\r\n",
+        );
+        let resp = HttpResponse::new("200", None, Some("hello".to_string()));
+        let common = AccessLog::new(LogFormat::Common, LogOutput::Stdout);
+        assert_eq!(
+            common.format_line(&req, &resp),
+            "192.0.2.7 - - [06/Nov/1994:08:49:37 +0000] \"GET /orders?id=1 HTTP/1.1\" 200 5"
+        );
+        let combined = AccessLog::new(LogFormat::Combined, LogOutput::Stdout)
+            .fields(vec![LogField::RequestId, LogField::Status]);
+        assert_eq!(
+            combined.format_line(&req, &resp),
+            "192.0.2.7 - - [06/Nov/1994:08:49:37 +0000] \"GET /orders?id=1 HTTP/1.1\" 200 5 \
+             \"http://shop/\" \"curl \\\"x\\\"\" request_id=\"r-1\""
+        );
+
+        // 流式响应的大小为 -，认证用户出现在第三列，控制字符被转义
+        let mut req = request("POST /login HTTP/1.0\r\n\r\n");
+        req.extensions.insert(Identity {
+            subject: "alice".to_string(),
+            roles: Vec::new(),
+            scheme: "Basic",
+        });
+        let (streaming, _writer) = HttpResponse::streaming("200", None);
+        let line = common.format_line(&req, &streaming);
+        assert!(line.starts_with("192.0.2.7 - alice ["), "{}", line);
+        assert!(line.ends_with("\" 200 -"), "{}", line);
+        assert_eq!(escape("a\nb"), "a\\x0ab");
+    }
+
+    // 测试 JSON 格式：按配置的字段和顺序输出，缺失的值为 null，请求 ID 由 Router 分配
+    #[test]
+    fn test_json_format() {
+        let mut req = request("POST /api/orders HTTP/1.1\r\nHost: shop\r\nContent-Length: 4\r\n\r\n");
+        req.set_body("body");
+        let resp = HttpResponse::new("404", None, Some("missing".to_string()));
+        let log = AccessLog::new(LogFormat::Json, LogOutput::Stdout);
+        let entry: serde_json::Value = serde_json::from_str(&log.format_line(&req, &resp)).unwrap();
+        assert_eq!(entry["time"], "1994-11-06T08:49:37.250Z");
+        assert_eq!(entry["remote_addr"], "192.0.2.7");
+        assert_eq!((entry["method"].as_str(), entry["path"].as_str()), (Some("POST"), Some("/api/orders")));
+        assert_eq!((entry["status"].as_u64(), entry["bytes_sent"].as_u64()), (Some(404), Some(7)));
+        assert_eq!(entry["bytes_received"], 4);
+        assert!(entry["duration_ms"].as_f64().unwrap() >= 0.0);
+        assert_eq!(entry["request_id"].as_str().map(str::len), Some(36));
+        assert_eq!(entry["trace_id"].as_str().map(str::len), Some(32));
+        assert!(entry["user"].is_null() && entry["referer"].is_null());
+        assert_eq!(entry.as_object().unwrap().len(), LogField::ALL.len());
+
+        let log = log.fields(vec![LogField::Status, LogField::Method]);
+        assert_eq!(log.format_line(&req, &resp), "{\"status\":404,\"method\":\"POST\"}");
+        assert_eq!(LogField::parse("duration_ms"), Some(LogField::Duration));
+        assert_eq!(LogField::parse("latency"), None);
+    }
+
+    // 测试日志文件超过大小后轮转，只保留指定数量的旧文件
+    #[test]
+    fn test_rotating_file() {
+        let path = temp_path("rotate");
+        let mut file = RotatingFile::open(&path, 10, 2).unwrap();
+        for line in ["first\n", "second\n", "third\n", "fourth\n"] {
+            file.write_all(line.as_bytes()).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fourth\n");
+        assert_eq!(fs::read_to_string(file.rotated(1)).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(file.rotated(2)).unwrap(), "second\n");
+        assert!(!file.rotated(3).exists());
+
+        // 重新打开时接着已有的大小计算
+        let mut reopened = RotatingFile::open(&path, 10, 2).unwrap();
+        reopened.write_all(b"fifth\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "fifth\n");
+        assert_eq!(fs::read_to_string(reopened.rotated(1)).unwrap(), "fourth\n");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+    }
+}
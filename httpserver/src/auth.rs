@@ -0,0 +1,505 @@
+// 导入所需的库和模块
+use super::jwt::JwtVerifier; // 导入 JWT 校验
+use super::middleware::Middleware; // 导入中间件
+use super::server::remove_dot_segments; // 导入路径规范化
+use base64::engine::general_purpose::STANDARD; // 导入标准 base64 编码
+use base64::Engine; // 导入 base64 编解码特性
+use http::httprequest::{HttpRequest, Method, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use rsa::sha2::Sha256; // 导入 SHA-256
+use serde_json::{json, Value}; // 导入 JSON 值
+use std::collections::HashMap; // 导入 HashMap
+use std::fs; // 导入文件系统模块
+use std::io; // 导入 IO 模块
+use std::path::Path; // 导入路径模块
+
+// 新生成的密码哈希使用的 PBKDF2 迭代次数
+const PBKDF2_ITERATIONS: u32 = 100_000;
+
+// 定义认证通过后的身份信息，处理器可以据此做进一步的授权判断
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identity {
+    pub subject: String,      // 用户名、令牌名称或 JWT 的 sub
+    pub roles: Vec<String>,   // 角色列表
+    pub scheme: &'static str, // 认证方式：Basic 或 Bearer
+}
+
+impl Identity {
+    // 是否拥有指定角色
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+}
+
+// 为 HttpRequest 提供读取身份的方法
+pub trait AuthExt {
+    // 返回认证中间件附加到请求上的身份
+    fn identity(&self) -> Option<&Identity>;
+}
+
+impl AuthExt for HttpRequest {
+    fn identity(&self) -> Option<&Identity> {
+        self.extensions.get::<Identity>()
+    }
+}
+
+// 以常量时间比较两个字节串
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 解析逗号分隔的角色列表
+fn parse_roles(roles: &str) -> Vec<String> {
+    roles
+        .split(',')
+        .map(|r| r.trim().to_string())
+        .filter(|r| !r.is_empty())
+        .collect()
+}
+
+// 生成密码哈希，格式为 pbkdf2-sha256$迭代次数$盐$哈希（盐和哈希为 base64）
+pub fn hash_password(password: &str) -> String {
+    let mut salt = [0u8; 16];
+    getrandom::getrandom(&mut salt).expect("failed to read random bytes");
+    let mut hash = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, PBKDF2_ITERATIONS, &mut hash);
+    format!(
+        "pbkdf2-sha256${}${}${}",
+        PBKDF2_ITERATIONS,
+        STANDARD.encode(salt),
+        STANDARD.encode(hash)
+    )
+}
+
+// 校验密码是否与哈希匹配
+pub fn verify_password(password: &str, encoded: &str) -> bool {
+    let parts: Vec<&str> = encoded.split('$').collect();
+    let [scheme, iterations, salt, hash] = parts.as_slice() else {
+        return false;
+    };
+    let (Ok(iterations), Ok(salt), Ok(expected)) = (
+        iterations.parse::<u32>(),
+        STANDARD.decode(salt),
+        STANDARD.decode(hash),
+    ) else {
+        return false;
+    };
+    if *scheme != "pbkdf2-sha256" || expected.is_empty() {
+        return false;
+    }
+    let mut actual = vec![0u8; expected.len()];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), &salt, iterations, &mut actual);
+    constant_time_eq(&actual, &expected)
+}
+
+// HTTP Basic 认证使用的账号表
+#[derive(Debug, Default)]
+pub struct Credentials {
+    users: HashMap<String, (String, Vec<String>)>, // 用户名到（密码哈希，角色）的映射
+}
+
+impl Credentials {
+    // 解析账号文件内容，每行格式为 用户名:密码哈希:角色1,角色2，# 开头的行为注释
+    pub fn parse(contents: &str) -> Self {
+        let users = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ':');
+                let user = fields.next()?.to_string();
+                let hash = fields.next()?.to_string();
+                let roles = parse_roles(fields.next().unwrap_or_default());
+                Some((user, (hash, roles)))
+            })
+            .collect();
+        Credentials { users }
+    }
+
+    // 从文件读取账号表
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    // 校验用户名和密码
+    fn authenticate(&self, username: &str, password: &str) -> Option<Identity> {
+        let (hash, roles) = self.users.get(username)?;
+        verify_password(password, hash).then(|| Identity {
+            subject: username.to_string(),
+            roles: roles.clone(),
+            scheme: "Basic",
+        })
+    }
+}
+
+// 静态 Bearer 令牌表
+#[derive(Debug, Default)]
+pub struct BearerTokens {
+    tokens: Vec<(String, Identity)>, // 令牌和对应的身份
+}
+
+impl BearerTokens {
+    // 解析令牌文件内容，每行格式为 名称:令牌:角色1,角色2，# 开头的行为注释
+    pub fn parse(contents: &str) -> Self {
+        let tokens = contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, ':');
+                let name = fields.next()?.to_string();
+                let token = fields.next()?.to_string();
+                let roles = parse_roles(fields.next().unwrap_or_default());
+                Some((
+                    token,
+                    Identity {
+                        subject: name,
+                        roles,
+                        scheme: "Bearer",
+                    },
+                ))
+            })
+            .collect();
+        BearerTokens { tokens }
+    }
+
+    // 从文件读取令牌表
+    pub fn from_file(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    // 查找令牌对应的身份，逐个以常量时间比较
+    fn authenticate(&self, token: &str) -> Option<Identity> {
+        self.tokens
+            .iter()
+            .find(|(t, _)| constant_time_eq(t.as_bytes(), token.as_bytes()))
+            .map(|(_, identity)| identity.clone())
+    }
+}
+
+// 从 JWT 声明中生成身份，角色来自 roles（数组或逗号分隔的字符串）
+fn identity_from_claims(claims: &Value) -> Identity {
+    let roles = match claims.get("roles") {
+        Some(Value::Array(roles)) => roles
+            .iter()
+            .filter_map(|r| r.as_str().map(str::to_string))
+            .collect(),
+        Some(Value::String(roles)) => parse_roles(roles),
+        _ => Vec::new(),
+    };
+    Identity {
+        subject: claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string(),
+        roles,
+        scheme: "Bearer",
+    }
+}
+
+// 定义一条访问规则：匹配的请求必须认证，并拥有 roles 中的任意一个角色（roles 为空时只要求认证）
+pub struct AccessRule {
+    method: Option<Method>, // 匹配的请求方法，None 表示全部方法
+    prefix: String,         // 匹配的路径前缀
+    roles: Vec<String>,     // 允许的角色
+}
+
+impl AccessRule {
+    // 请求是否匹配该规则，按去掉点段后的路径匹配，与 Server、代理和路由使用的路径一致
+    // 越过根目录的路径无法规范化，视为匹配，需要认证
+    fn matches(&self, req: &HttpRequest) -> bool {
+        let Resource::Path(path) = &req.resource;
+        let path = remove_dot_segments(path);
+        self.method.as_ref().is_none_or(|m| *m == req.method)
+            && path.is_none_or(|path| path.starts_with(&self.prefix))
+    }
+}
+
+// 认证失败的原因
+enum AuthFailure {
+    Missing,         // 没有提供凭据
+    Invalid(String), // 凭据错误，附带原因
+}
+
+// 认证中间件：解析 Authorization 请求头，并按访问规则检查权限
+pub struct AuthMiddleware {
+    realm: String,                    // WWW-Authenticate 中的 realm
+    credentials: Option<Credentials>, // Basic 认证账号表
+    tokens: Option<BearerTokens>,     // 静态 Bearer 令牌
+    jwt: Option<JwtVerifier>,         // JWT 校验器
+    rules: Vec<AccessRule>,           // 访问规则，按注册顺序匹配第一条
+}
+
+impl AuthMiddleware {
+    // 创建一个未启用任何认证方式的中间件
+    pub fn new(realm: impl Into<String>) -> Self {
+        AuthMiddleware {
+            realm: realm.into(),
+            credentials: None,
+            tokens: None,
+            jwt: None,
+            rules: Vec::new(),
+        }
+    }
+
+    // 启用 HTTP Basic 认证
+    pub fn basic(mut self, credentials: Credentials) -> Self {
+        self.credentials = Some(credentials);
+        self
+    }
+
+    // 启用静态 Bearer 令牌认证
+    pub fn bearer_tokens(mut self, tokens: BearerTokens) -> Self {
+        self.tokens = Some(tokens);
+        self
+    }
+
+    // 启用 JWT 认证
+    pub fn jwt(mut self, verifier: JwtVerifier) -> Self {
+        self.jwt = Some(verifier);
+        self
+    }
+
+    // 添加一条访问规则
+    pub fn require(mut self, method: Option<Method>, prefix: &str, roles: &[&str]) -> Self {
+        self.rules.push(AccessRule {
+            method,
+            prefix: prefix.to_string(),
+            roles: roles.iter().map(|r| r.to_string()).collect(),
+        });
+        self
+    }
+
+    // 添加服务器默认的访问规则：修改订单需要 admin 角色，读取订单只需要认证
+    // /ws/orders 推送与 /api/shipping/orders/events 相同的订单数据，同样需要认证
+    pub fn default_rules(self) -> Self {
+        self.require(Some(Method::Post), "/api/", &["admin"])
+            .require(None, "/api/", &[])
+            .require(None, "/ws/orders", &[])
+    }
+
+    // 解析 Authorization 请求头
+    fn authenticate(&self, req: &HttpRequest) -> Result<Identity, AuthFailure> {
+        let header = req.header("Authorization").ok_or(AuthFailure::Missing)?;
+        let (scheme, value) = header.split_once(' ').unwrap_or((header, ""));
+        let value = value.trim();
+
+        if scheme.eq_ignore_ascii_case("Basic") {
+            if let Some(credentials) = &self.credentials {
+                let decoded = STANDARD
+                    .decode(value)
+                    .ok()
+                    .and_then(|bytes| String::from_utf8(bytes).ok())
+                    .ok_or_else(|| AuthFailure::Invalid("malformed credentials".into()))?;
+                let (username, password) = decoded
+                    .split_once(':')
+                    .ok_or_else(|| AuthFailure::Invalid("malformed credentials".into()))?;
+                return credentials
+                    .authenticate(username, password)
+                    .ok_or_else(|| AuthFailure::Invalid("invalid username or password".into()));
+            }
+        } else if scheme.eq_ignore_ascii_case("Bearer") {
+            if let Some(identity) = self.tokens.as_ref().and_then(|t| t.authenticate(value)) {
+                return Ok(identity);
+            }
+            return match &self.jwt {
+                Some(jwt) => jwt
+                    .verify(value)
+                    .map(|claims| identity_from_claims(&claims))
+                    .map_err(|e| AuthFailure::Invalid(e.to_string())),
+                None => Err(AuthFailure::Invalid("invalid token".into())),
+            };
+        }
+        Err(AuthFailure::Invalid("unsupported authorization scheme".into()))
+    }
+
+    // 生成 401 响应，列出所有启用的认证方式
+    fn unauthorized(&self, failure: &AuthFailure) -> HttpResponse<'static> {
+        let message = match failure {
+            AuthFailure::Missing => "authentication required".to_string(),
+            AuthFailure::Invalid(reason) => reason.clone(),
+        };
+        let mut resp = json_response("401", "unauthorized", &message);
+        if self.credentials.is_some() {
+            resp.add_header("WWW-Authenticate", format!("Basic realm=\"{}\", charset=\"UTF-8\"", self.realm));
+        }
+        if self.tokens.is_some() || self.jwt.is_some() {
+            let challenge = match failure {
+                AuthFailure::Missing => format!("Bearer realm=\"{}\"", self.realm),
+                AuthFailure::Invalid(reason) => format!(
+                    "Bearer realm=\"{}\", error=\"invalid_token\", error_description=\"{}\"",
+                    self.realm, reason
+                ),
+            };
+            resp.add_header("WWW-Authenticate", challenge);
+        }
+        resp
+    }
+}
+
+// 生成 JSON 格式的错误响应
+fn json_response(status_code: &'static str, error: &str, message: &str) -> HttpResponse<'static> {
+    let mut headers: HashMap<&str, &str> = HashMap::new();
+    headers.insert("Content-Type", "application/json");
+    HttpResponse::new(
+        status_code,
+        Some(headers),
+        Some(json!({"error": error, "message": message}).to_string()),
+    )
+}
+
+impl Middleware for AuthMiddleware {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let identity = match self.authenticate(req) {
+            Ok(identity) => Some(identity),
+            Err(AuthFailure::Missing) => None,
+            // 提供了错误的凭据时，即使访问公开路径也拒绝
+            Err(failure) => return Some(self.unauthorized(&failure)),
+        };
+
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(req)) {
+            match &identity {
+                None => return Some(self.unauthorized(&AuthFailure::Missing)),
+                Some(identity)
+                    if !rule.roles.is_empty() && !rule.roles.iter().any(|r| identity.has_role(r)) =>
+                {
+                    return Some(json_response("403", "forbidden", "insufficient role"));
+                }
+                Some(_) => {}
+            }
+        }
+
+        if let Some(identity) = identity {
+            req.extensions.insert(identity);
+        }
+        None
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+    use crate::jwt::JwtKey; // 导入 JWT 密钥
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD; // 导入 URL 安全的 base64 编码
+    use hmac::{Hmac, Mac}; // 导入 HMAC
+
+    // 使用较少的迭代次数生成测试用的密码哈希
+    fn quick_hash(password: &str) -> String {
+        let salt = b"0123456789abcdef";
+        let mut hash = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt, 10, &mut hash);
+        format!("pbkdf2-sha256$10${}${}", STANDARD.encode(salt), STANDARD.encode(hash))
+    }
+
+    // 构造测试用的中间件：alice 是 admin，bob 只有 viewer 角色
+    fn middleware() -> AuthMiddleware {
+        let credentials = Credentials::parse(&format!(
+            "# user:hash:roles\nalice:{}:admin,viewer\nbob:{}:viewer\n",
+            quick_hash("wonderland"),
+            quick_hash("builder")
+        ));
+        AuthMiddleware::new("test")
+            .basic(credentials)
+            .bearer_tokens(BearerTokens::parse("ci:s3cr3t-token:admin\n"))
+            .jwt(JwtVerifier::new(JwtKey::Hs256(b"jwt-secret".to_vec())))
+            .default_rules()
+    }
+
+    // 构造带有 Authorization 请求头的请求
+    fn request(method: &str, path: &str, authorization: Option<String>) -> HttpRequest {
+        let header = authorization
+            .map(|a| format!("Authorization: {}\r\n", a))
+            .unwrap_or_default();
+        format!("{} {} HTTP/1.1\r\n{}\r\n", method, path, header).into()
+    }
+
+    // 生成 Basic 认证请求头
+    fn basic(user: &str, password: &str) -> Option<String> {
+        Some(format!("Basic {}", STANDARD.encode(format!("{}:{}", user, password))))
+    }
+
+    // 返回响应的状态行
+    fn status(resp: Option<HttpResponse>) -> Option<String> {
+        resp.map(|r| String::from(r).lines().next().unwrap().to_string())
+    }
+
+    // 测试密码哈希的生成和校验
+    #[test]
+    fn test_password_hash() {
+        let hash = quick_hash("pw");
+        assert!(verify_password("pw", &hash));
+        assert!(!verify_password("pw2", &hash));
+        assert!(!verify_password("pw", "plaintext"));
+    }
+
+    // 测试没有凭据时访问受保护路径返回 401 和 WWW-Authenticate
+    #[test]
+    fn test_missing_credentials() {
+        let auth = middleware();
+        let resp = auth.before(&mut request("GET", "/api/shipping/orders", None)).unwrap();
+        let raw: String = resp.into();
+        assert!(raw.starts_with("HTTP/1.1 401 Unauthorized"));
+        assert!(raw.contains("WWW-Authenticate:Basic realm=\"test\""));
+        assert!(raw.contains("WWW-Authenticate:Bearer realm=\"test\""));
+
+        // 公开路径不需要认证，带点段的路径按规范化后的路径匹配规则
+        assert!(auth.before(&mut request("GET", "/", None)).is_none());
+        let resp = auth.before(&mut request("GET", "/x/../api/shipping/orders", None));
+        assert_eq!(status(resp), Some("HTTP/1.1 401 Unauthorized".into()));
+        let resp = auth.before(&mut request("GET", "/static/%2E%2e//api/shipping/orders", None));
+        assert_eq!(status(resp), Some("HTTP/1.1 401 Unauthorized".into()));
+        let resp = auth.before(&mut request("GET", "/../x", None));
+        assert_eq!(status(resp), Some("HTTP/1.1 401 Unauthorized".into()));
+        assert!(auth.before(&mut request("GET", "/ws/echo", None)).is_none());
+
+        // 订单推送的 WebSocket 同样需要认证
+        let resp = auth.before(&mut request("GET", "/ws/orders", None));
+        assert_eq!(status(resp), Some("HTTP/1.1 401 Unauthorized".into()));
+        assert!(auth.before(&mut request("GET", "/ws/orders", basic("bob", "builder"))).is_none());
+    }
+
+    // 测试 Basic 认证和按角色授权
+    #[test]
+    fn test_basic_and_roles() {
+        let auth = middleware();
+        let mut req = request("GET", "/api/shipping/orders", basic("bob", "builder"));
+        assert!(auth.before(&mut req).is_none());
+        assert_eq!(req.identity().unwrap().subject, "bob");
+
+        let mut req = request("POST", "/api/shipping/orders/1/status", basic("bob", "builder"));
+        assert_eq!(status(auth.before(&mut req)), Some("HTTP/1.1 403 Forbidden".into()));
+
+        let mut req = request("POST", "/api/shipping/orders/1/status", basic("alice", "wonderland"));
+        assert!(auth.before(&mut req).is_none());
+        assert!(req.identity().unwrap().has_role("admin"));
+
+        let mut req = request("GET", "/", basic("alice", "wrong"));
+        assert_eq!(status(auth.before(&mut req)), Some("HTTP/1.1 401 Unauthorized".into()));
+    }
+
+    // 测试静态令牌和 JWT
+    #[test]
+    fn test_bearer_tokens_and_jwt() {
+        let auth = middleware();
+        let mut req = request("POST", "/api/uploads", Some("Bearer s3cr3t-token".into()));
+        assert!(auth.before(&mut req).is_none());
+        assert_eq!(req.identity().unwrap().subject, "ci");
+
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(r#"{"sub":"carol","roles":["viewer"]}"#);
+        let input = format!("{}.{}", header, payload);
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"jwt-secret").unwrap();
+        mac.update(input.as_bytes());
+        let token = format!("{}.{}", input, URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes()));
+
+        let mut req = request("GET", "/api/shipping/orders", Some(format!("Bearer {}", token)));
+        assert!(auth.before(&mut req).is_none());
+        assert_eq!(req.identity().unwrap().roles, vec!["viewer".to_string()]);
+
+        let mut req = request("GET", "/api/shipping/orders", Some("Bearer nope".into()));
+        let raw: String = auth.before(&mut req).unwrap().into();
+        assert!(raw.contains("error=\"invalid_token\""));
+    }
+}
@@ -0,0 +1,1552 @@
+// 导入所需的库和模块
+use super::access_log::{AccessLog, LogField, LogFormat, LogOutput, RotatingFile, DEFAULT_MAX_FILES, DEFAULT_MAX_SIZE}; // 导入访问日志
+use super::auth::{AuthMiddleware, BearerTokens, Credentials}; // 导入认证中间件
+use super::cors::CorsMiddleware; // 导入 CORS 中间件
+use super::health::{DiskSpaceCheck, HealthMiddleware, OrderDataCheck, UpstreamCheck, DEFAULT_MIN_FREE_BYTES}; // 导入健康检查
+use super::jwt::{JwtKey, JwtVerifier}; // 导入 JWT 校验
+use super::listener::UNIX_PREFIX; // 导入 Unix 域套接字地址的前缀
+use super::metrics::MetricsMiddleware; // 导入指标中间件
+use super::proxy::{ReverseProxy, DEFAULT_HEALTH_INTERVAL}; // 导入反向代理
+use super::ratelimit::{KeySource, Quota, RateLimitMiddleware}; // 导入限流中间件
+use super::repository::OrderStore; // 导入订单仓库的后端
+use super::server::{Limits, Server}; // 导入服务器和请求限制
+use super::session::SessionMiddleware; // 导入会话中间件
+use super::session_store::session_store; // 导入会话存储
+use super::tls::{TlsAcceptor, TlsConfig}; // 导入 TLS 配置和接收器
+use http::cookie::CookieKey; // 导入 Cookie 签名密钥
+use super::upstream::{Strategy, UpstreamPool}; // 导入上游池
+use super::vhost::{Site, VirtualHosts}; // 导入虚拟主机
+use http::client::Url; // 导入 URL 解析
+use http::httprequest::Method; // 导入请求方法
+use serde::{Deserialize, Deserializer}; // 导入反序列化特性
+use std::collections::HashSet; // 导入 HashSet
+use std::env; // 导入环境变量模块
+use std::fs; // 导入文件操作
+use std::net::{IpAddr, ToSocketAddrs}; // 导入 IP 地址和地址解析
+use std::path::{Path, PathBuf}; // 导入路径
+use std::str::FromStr; // 导入字符串解析特性
+use std::time::Duration; // 导入时间间隔
+
+// 没有指定配置文件时，当前目录下存在这个文件就读取它
+pub const DEFAULT_CONFIG_FILE: &str = "httpserver.toml";
+
+// 命令行用法
+pub const USAGE: &str = "\
+Usage: httpserver [OPTIONS]
+       httpserver --hash-password <PASSWORD>
+
+Options:
+  --config <FILE>       配置文件，默认读取 HTTPSERVER_CONFIG 或当前目录下的 httpserver.toml
+  --bind <ADDR>         监听地址，如 0.0.0.0:8080、[::]:8080 或 unix:/run/httpserver.sock，可以重复
+  --workers <N>         同时处理的连接数上限
+  --public-path <DIR>   静态文件目录
+  --data-path <DIR>     数据目录
+  --check-config        校验配置后退出，不打开日志、会话存储或订单仓库；配置有错误时退出码为 1
+  --hash-password <PW>  输出可写入账号文件的密码哈希
+  -h, --help            显示帮助";
+
+// 命令行参数，优先级高于配置文件和环境变量
+#[derive(Debug, Default, PartialEq)]
+pub struct Cli {
+    pub config: Option<PathBuf>,       // 配置文件
+    pub check_config: bool,            // 只检查配置，不启动服务器
+    pub hash_password: Option<String>, // 要计算哈希的密码
+    pub help: bool,                    // 显示帮助
+    bind: Vec<String>,                 // 监听地址
+    workers: Option<usize>,            // 连接数上限
+    public_path: Option<PathBuf>,      // 静态文件目录
+    data_path: Option<PathBuf>,        // 数据目录
+}
+
+impl Cli {
+    // 解析命令行参数（不包括程序名）
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut cli = Cli::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // 同时支持 --name value 和 --name=value
+            let (name, inline) = match arg.split_once('=') {
+                Some((name, value)) if name.starts_with("--") => (name.to_string(), Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline
+                    .clone()
+                    .or_else(|| args.next())
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+            match name.as_str() {
+                "--config" => cli.config = Some(value()?.into()),
+                "--bind" => cli.bind.push(value()?),
+                "--workers" => {
+                    let workers = value()?;
+                    cli.workers = Some(workers.parse().map_err(|_| format!("invalid value for --workers: {}", workers))?);
+                }
+                "--public-path" => cli.public_path = Some(value()?.into()),
+                "--data-path" => cli.data_path = Some(value()?.into()),
+                "--hash-password" => cli.hash_password = Some(value()?),
+                "--check-config" => cli.check_config = true,
+                "-h" | "--help" => cli.help = true,
+                _ => return Err(format!("unknown argument: {}", name)),
+            }
+        }
+        Ok(cli)
+    }
+}
+
+// 服务器配置：TOML 配置文件的内容，再由环境变量和命令行参数覆盖
+// 没有出现的字段使用默认值，未知的字段视为错误，避免拼写错误被静默忽略
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerSection,               // [server]：监听、连接和请求限制
+    pub paths: PathsSection,                 // [paths]：静态文件和数据目录
+    pub proxy: ProxySection,                 // [proxy] 和 [[proxy.routes]]：反向代理的路由
+    pub logging: Option<LoggingSection>,     // [logging]：访问日志
+    pub tls: Option<TlsSection>,             // [tls]：证书
+    pub vhosts: Vec<VhostSection>,           // [[vhosts]]：按 Host 头选择的站点
+    pub session: SessionSection,             // [session]：会话存储和会话 Cookie
+    pub cors: Option<CorsSection>,           // [cors]：API 的跨域访问
+    pub metrics: Option<MetricsSection>,     // [metrics]：Prometheus 指标
+    pub auth: AuthSection,                   // [auth]：API 和 WebSocket 的认证方式
+    pub admin: AdminSection,                 // [admin]：订单管理页面的账号
+    pub ratelimit: Option<RateLimitSection>, // [ratelimit]：按客户端限流
+    pub health: HealthSection,               // [health]：就绪检查的磁盘空间
+    pub store: StoreSection,                 // [store]：订单仓库
+}
+
+// [server] 部分
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerSection {
+    #[serde(deserialize_with = "one_or_many")]
+    pub bind: Vec<String>,       // 监听地址，默认 localhost:3000；unix: 开头的是 Unix 域套接字
+    pub workers: usize,          // 同时处理的连接数上限，默认 256
+    pub idle_timeout: u64,       // 保持的连接等待下一个请求的超时（秒），默认 30
+    pub header_timeout: u64,     // 接收请求头的超时（秒），默认 10
+    pub body_timeout: u64,       // 接收消息体时两次读取之间的超时（秒），默认 30
+    pub handler_timeout: u64,    // 生成响应的超时（秒），默认 60
+    pub write_timeout: u64,      // 发送响应的超时（秒），默认 30
+    pub min_rate: u64,           // 接收消息体的最低速率（字节/秒），0 表示不限制，默认 500
+    pub max_request_line: usize, // 请求行的最大字节数，默认 8 KB
+    pub max_headers: usize,      // 请求头的最大数量，默认 100
+    pub max_header_size: usize,  // 请求头的最大总字节数，默认 32 KB
+    pub max_body_size: usize,    // 请求消息体的最大字节数，默认 10 MB
+}
+
+impl Default for ServerSection {
+    fn default() -> Self {
+        ServerSection {
+            bind: vec!["localhost:3000".to_string()],
+            workers: 256,
+            idle_timeout: 30,
+            header_timeout: 10,
+            body_timeout: 30,
+            handler_timeout: 60,
+            write_timeout: 30,
+            min_rate: 500,
+            max_request_line: 8 * 1024,
+            max_headers: 100,
+            max_header_size: 32 * 1024,
+            max_body_size: 10 * 1024 * 1024,
+        }
+    }
+}
+
+// [paths] 部分，未设置时使用 PUBLIC_PATH、DATA_PATH 或 crate 目录下的 public 和 data
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PathsSection {
+    pub public: Option<PathBuf>, // 静态文件目录
+    pub data: Option<PathBuf>,   // 订单、会话等数据的目录
+}
+
+// [proxy] 部分
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxySection {
+    pub connect_timeout: Option<u64>, // 连接上游的超时（秒）
+    pub timeout: Option<u64>,         // 等待上游响应的超时（秒）
+    pub routes: Vec<RouteSection>,    // 按前缀转发的路由
+}
+
+// [[proxy.routes]] 中的一条路由
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RouteSection {
+    pub prefix: String,               // 路径前缀，如 /api/v2
+    pub upstreams: Vec<String>,       // 上游地址
+    pub strategy: Option<String>,     // round-robin（默认）、least-conn 或 hash
+    pub hash_header: Option<String>,  // hash 策略使用的请求头，默认使用客户端 IP 地址
+    pub health_check: Option<String>, // 主动健康检查的路径
+    pub health_interval: Option<u64>, // 健康检查的间隔（秒）
+    pub max_fails: Option<u32>,       // 连续失败多少次后摘除上游
+    pub fail_timeout: Option<u64>,    // 摘除的时长（秒）
+}
+
+// [logging] 部分
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LoggingSection {
+    pub access_log: String,          // stdout 或日志文件路径
+    pub format: Option<String>,      // common、combined（默认）或 json
+    pub fields: Option<Vec<String>>, // 输出的字段名
+    pub max_size: Option<u64>,       // 日志文件轮转的大小（MB）
+    pub max_files: Option<usize>,    // 保留的旧文件数
+}
+
+// [tls] 部分
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsSection {
+    pub cert_file: PathBuf,        // 默认证书链
+    pub key_file: PathBuf,         // 默认私钥
+    pub alpn: Option<Vec<String>>, // 通告的 ALPN 协议，默认 h2 和 http/1.1
+    #[serde(default)]
+    pub sni: Vec<SniSection>,      // 按主机名选择的证书
+}
+
+// [[vhosts]] 中的一个站点，未设置的静态文件目录使用 [paths] 的目录
+// 站点默认只提供静态文件和自己的代理路由，设置 builtin_routes = true 后才提供内置的 /api、/admin 和 /ws 路由
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VhostSection {
+    pub hosts: Vec<String>,         // 主机名，*.example.com 匹配一级子域名
+    pub public: Option<PathBuf>,    // 静态文件目录
+    pub not_found: Option<PathBuf>, // 404 页面
+    #[serde(default)]
+    pub routes: Vec<RouteSection>,  // 站点的代理路由，使用 [proxy] 的超时
+    #[serde(default)]
+    pub builtin_routes: bool,       // 是否提供内置路由，默认 false
+}
+
+// [session] 部分
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionSection {
+    pub store: Option<String>,  // memory（默认）、file 或 sqlite
+    pub path: Option<PathBuf>,  // file 的目录或 sqlite 的数据库，默认是数据目录下的 sessions 或 sessions.db
+    pub secret: Option<String>, // 会话 Cookie 的签名密钥，未设置时不签名
+    pub secure: Option<bool>,   // 会话 Cookie 是否带 Secure 属性，默认在使用 HTTPS 时开启
+}
+
+// [cors] 部分，作用于 /api/ 下的路径
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CorsSection {
+    pub allowed_origins: Vec<String>,         // 允许的来源，可以是 * 或 https://*.example.com
+    pub allowed_methods: Option<Vec<String>>, // 允许的请求方法，默认 GET、POST 和 OPTIONS
+    pub allowed_headers: Option<Vec<String>>, // 允许的请求头，默认 Content-Type 和 Authorization，* 表示任何请求头
+    pub expose_headers: Option<Vec<String>>,  // 允许前端读取的响应头
+    #[serde(default)]
+    pub allow_credentials: bool,              // 是否允许携带凭据，不能与 * 同时使用
+    pub max_age: Option<u64>,                 // 预检结果的缓存时间（秒）
+}
+
+// [metrics] 部分
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MetricsSection {
+    pub path: String, // 输出指标的路径，如 /metrics
+}
+
+// [auth] 部分，设置了任一认证方式时启用认证中间件和默认的访问规则
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthSection {
+    pub credentials_file: Option<PathBuf>,   // Basic 认证的账号文件，每行为 用户名:密码哈希:角色
+    pub tokens_file: Option<PathBuf>,        // 静态 Bearer 令牌文件，每行为 名称:令牌:角色
+    pub jwt_hs256_key_file: Option<PathBuf>, // JWT 的 HS256 密钥
+    pub jwt_rs256_key_file: Option<PathBuf>, // JWT 的 RS256 公钥（PEM），不能与 HS256 同时使用
+    pub jwt_audience: Option<String>,        // JWT 要求的 aud
+}
+
+// [admin] 部分，未设置密码时不允许登录订单管理页面
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminSection {
+    pub username: Option<String>, // 用户名
+    pub password: Option<String>, // 密码
+}
+
+// [ratelimit] 部分
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimitSection {
+    pub quota: String,                  // 默认配额：请求数/秒数，如 100/60
+    #[serde(default)]
+    pub routes: Vec<String>,            // 按路由的配额：[方法 ]前缀=配额，如 "POST /api/=10/60"，按顺序匹配第一条
+    pub key: Option<String>,            // 限流的键：peer（默认）、forwarded 或 api-key
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,   // key = "forwarded" 时受信任的代理地址
+    pub api_key_header: Option<String>, // key = "api-key" 时 API Key 所在的请求头，默认 X-Api-Key
+    #[serde(default)]
+    pub api_keys: Vec<String>,          // key = "api-key" 时已知的 API Key
+}
+
+// [health] 部分
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthSection {
+    pub disk_path: Option<PathBuf>, // 检查剩余空间的路径，默认是数据目录
+    pub min_free_mb: Option<u64>,   // 剩余空间的下限（MB），默认 100
+}
+
+// [store] 部分
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreSection {
+    pub backend: Option<String>,  // json（默认，数据目录下的 orders.json）或 sqlite
+    pub db_path: Option<PathBuf>, // SQLite 数据库，默认是数据目录下的 orders.db
+}
+
+// [[tls.sni]] 中按主机名选择的证书
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SniSection {
+    pub host: String,       // 主机名，可以是 *.example.com
+    pub cert_file: PathBuf, // 证书链
+    pub key_file: PathBuf,  // 私钥
+}
+
+impl Config {
+    // 解析 TOML 格式的配置
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())
+    }
+
+    // 读取配置文件，文件中的相对路径相对于配置文件所在的目录
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let mut config = Self::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+        config.resolve_paths(path.parent().unwrap_or(Path::new("")));
+        Ok(config)
+    }
+
+    // 按优先级从低到高合并默认值、配置文件、环境变量和命令行参数，然后校验
+    // 配置文件由 --config、HTTPSERVER_CONFIG 指定，或者是当前目录下的 httpserver.toml；返回全部错误
+    pub fn load(cli: &Cli) -> Result<Self, Vec<String>> {
+        let path = cli.config.clone().or_else(|| env::var_os("HTTPSERVER_CONFIG").map(PathBuf::from));
+        let mut config = match path {
+            Some(path) => Self::from_file(&path).map_err(|e| vec![e])?,
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE)).map_err(|e| vec![e])?
+            }
+            None => Config::default(),
+        };
+        let mut errors = config.apply_env(|name| env::var(name).ok());
+        config.apply_cli(cli);
+        if let Err(invalid) = config.validate() {
+            errors.extend(invalid);
+        }
+        if errors.is_empty() {
+            Ok(config)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // 用环境变量覆盖配置，返回无法解析的变量；各组件只按合并后的配置创建，不再读取环境变量
+    // HTTPSERVER_BIND（逗号分隔）和 HTTPSERVER_ 加大写的字段名（如 HTTPSERVER_HEADER_TIMEOUT）覆盖 [server]；
+    // PUBLIC_PATH、DATA_PATH 覆盖 [paths]；SESSION_STORE、SESSION_PATH、SESSION_SECRET、SESSION_COOKIE_SECURE 覆盖 [session]
+    // AUTH_CREDENTIALS_FILE、AUTH_TOKENS_FILE、JWT_HS256_KEY_FILE、JWT_RS256_KEY_FILE、JWT_AUDIENCE 覆盖 [auth]；
+    // ADMIN_USERNAME、ADMIN_PASSWORD 覆盖 [admin]；HEALTH_DISK_PATH、HEALTH_MIN_FREE_MB 覆盖 [health]；ORDER_STORE、ORDER_DB_PATH 覆盖 [store]
+    // PROXY_CONNECT_TIMEOUT、PROXY_TIMEOUT 覆盖 [proxy] 的超时，设置了 PROXY_ROUTES 时取代 [[proxy.routes]]，
+    // 其余 PROXY_*（PROXY_BALANCE、PROXY_HASH_HEADER、PROXY_HEALTH_CHECK、PROXY_HEALTH_INTERVAL、PROXY_MAX_FAILS、PROXY_FAIL_TIMEOUT）作用于其中的每条路由
+    // 设置了 ACCESS_LOG、TLS_CERT_FILE、CORS_ALLOWED_ORIGINS、METRICS_PATH 或 RATE_LIMIT 时，
+    // 由同一前缀的环境变量取代整个 [logging]、[tls]、[cors]、[metrics] 或 [ratelimit]
+    fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Vec<String> {
+        let mut errors = Vec::new();
+        if let Some(bind) = var("HTTPSERVER_BIND") {
+            self.server.bind = bind.split(',').map(|addr| addr.trim().to_string()).filter(|addr| !addr.is_empty()).collect();
+        }
+        parse_var(&var, "HTTPSERVER_WORKERS", &mut self.server.workers, &mut errors);
+        parse_var(&var, "HTTPSERVER_IDLE_TIMEOUT", &mut self.server.idle_timeout, &mut errors);
+        parse_var(&var, "HTTPSERVER_HEADER_TIMEOUT", &mut self.server.header_timeout, &mut errors);
+        parse_var(&var, "HTTPSERVER_BODY_TIMEOUT", &mut self.server.body_timeout, &mut errors);
+        parse_var(&var, "HTTPSERVER_HANDLER_TIMEOUT", &mut self.server.handler_timeout, &mut errors);
+        parse_var(&var, "HTTPSERVER_WRITE_TIMEOUT", &mut self.server.write_timeout, &mut errors);
+        parse_var(&var, "HTTPSERVER_MIN_RATE", &mut self.server.min_rate, &mut errors);
+        parse_var(&var, "HTTPSERVER_MAX_REQUEST_LINE", &mut self.server.max_request_line, &mut errors);
+        parse_var(&var, "HTTPSERVER_MAX_HEADERS", &mut self.server.max_headers, &mut errors);
+        parse_var(&var, "HTTPSERVER_MAX_HEADER_SIZE", &mut self.server.max_header_size, &mut errors);
+        parse_var(&var, "HTTPSERVER_MAX_BODY_SIZE", &mut self.server.max_body_size, &mut errors);
+        if let Some(path) = var("PUBLIC_PATH") {
+            self.paths.public = Some(path.into());
+        }
+        if let Some(path) = var("DATA_PATH") {
+            self.paths.data = Some(path.into());
+        }
+        if let Some(store) = var("SESSION_STORE") {
+            self.session.store = Some(store);
+        }
+        if let Some(path) = var("SESSION_PATH") {
+            self.session.path = Some(path.into());
+        }
+        if let Some(secret) = var("SESSION_SECRET") {
+            self.session.secret = Some(secret);
+        }
+        if let Some(secure) = parse_some(&var, "SESSION_COOKIE_SECURE", &mut errors) {
+            self.session.secure = Some(secure);
+        }
+        let auth_files = [
+            ("AUTH_CREDENTIALS_FILE", &mut self.auth.credentials_file),
+            ("AUTH_TOKENS_FILE", &mut self.auth.tokens_file),
+            ("JWT_HS256_KEY_FILE", &mut self.auth.jwt_hs256_key_file),
+            ("JWT_RS256_KEY_FILE", &mut self.auth.jwt_rs256_key_file),
+        ];
+        for (name, file) in auth_files {
+            if let Some(path) = var(name) {
+                *file = Some(path.into());
+            }
+        }
+        if let Some(audience) = var("JWT_AUDIENCE") {
+            self.auth.jwt_audience = Some(audience);
+        }
+        if let Some(username) = var("ADMIN_USERNAME") {
+            self.admin.username = Some(username);
+        }
+        if let Some(password) = var("ADMIN_PASSWORD") {
+            self.admin.password = Some(password);
+        }
+        if let Some(path) = var("HEALTH_DISK_PATH") {
+            self.health.disk_path = Some(path.into());
+        }
+        if let Some(mb) = parse_some(&var, "HEALTH_MIN_FREE_MB", &mut errors) {
+            self.health.min_free_mb = Some(mb);
+        }
+        if let Some(backend) = var("ORDER_STORE") {
+            self.store.backend = Some(backend);
+        }
+        if let Some(path) = var("ORDER_DB_PATH") {
+            self.store.db_path = Some(path.into());
+        }
+
+        // PROXY_ROUTES：逗号分隔的 前缀=上游地址，多个上游用 | 分隔，如 /api=http://127.0.0.1:4000/|http://127.0.0.1:4001/
+        if let Some(routes) = var("PROXY_ROUTES") {
+            let strategy = var("PROXY_BALANCE");
+            let hash_header = var("PROXY_HASH_HEADER").filter(|_| strategy.as_deref() == Some("hash"));
+            let health_check = var("PROXY_HEALTH_CHECK");
+            let health_interval = parse_some(&var, "PROXY_HEALTH_INTERVAL", &mut errors);
+            let max_fails = parse_some(&var, "PROXY_MAX_FAILS", &mut errors);
+            let fail_timeout = parse_some(&var, "PROXY_FAIL_TIMEOUT", &mut errors);
+            self.proxy.routes.clear();
+            for entry in routes.split(',').filter(|entry| !entry.trim().is_empty()) {
+                let Some((prefix, upstreams)) = entry.split_once('=') else {
+                    errors.push(format!("PROXY_ROUTES: invalid entry {:?}", entry.trim()));
+                    continue;
+                };
+                self.proxy.routes.push(RouteSection {
+                    prefix: prefix.trim().to_string(),
+                    upstreams: upstreams.split('|').map(|url| url.trim().to_string()).collect(),
+                    strategy: strategy.clone(),
+                    hash_header: hash_header.clone(),
+                    health_check: health_check.clone(),
+                    health_interval,
+                    max_fails,
+                    fail_timeout,
+                });
+            }
+        }
+        if let Some(timeout) = parse_some(&var, "PROXY_CONNECT_TIMEOUT", &mut errors) {
+            self.proxy.connect_timeout = Some(timeout);
+        }
+        if let Some(timeout) = parse_some(&var, "PROXY_TIMEOUT", &mut errors) {
+            self.proxy.timeout = Some(timeout);
+        }
+
+        // ACCESS_LOG：stdout 或日志文件路径；ACCESS_LOG_FIELDS：逗号分隔的字段名
+        if let Some(access_log) = var("ACCESS_LOG") {
+            self.logging = Some(LoggingSection {
+                access_log,
+                format: var("ACCESS_LOG_FORMAT"),
+                fields: var("ACCESS_LOG_FIELDS").map(|fields| split_list(&fields)),
+                max_size: parse_some(&var, "ACCESS_LOG_MAX_SIZE", &mut errors),
+                max_files: parse_some(&var, "ACCESS_LOG_MAX_FILES", &mut errors),
+            });
+        }
+
+        // TLS_CERT_FILE / TLS_KEY_FILE：默认证书链和私钥
+        // TLS_SNI：分号分隔的 主机名=证书文件,私钥文件，如 api.example.com=api.pem,api.key
+        if let Some(cert_file) = var("TLS_CERT_FILE") {
+            let mut sni = Vec::new();
+            for entry in var("TLS_SNI").unwrap_or_default().split(';').filter(|entry| !entry.trim().is_empty()) {
+                match entry.split_once('=').and_then(|(host, files)| Some((host, files.split_once(',')?))) {
+                    Some((host, (cert_file, key_file))) => sni.push(SniSection {
+                        host: host.trim().to_string(),
+                        cert_file: cert_file.trim().into(),
+                        key_file: key_file.trim().into(),
+                    }),
+                    None => errors.push(format!("TLS_SNI: invalid entry {:?}", entry.trim())),
+                }
+            }
+            match var("TLS_KEY_FILE") {
+                Some(key_file) => {
+                    self.tls = Some(TlsSection {
+                        cert_file: cert_file.into(),
+                        key_file: key_file.into(),
+                        alpn: None,
+                        sni,
+                    })
+                }
+                None => errors.push("TLS_KEY_FILE: required when TLS_CERT_FILE is set".to_string()),
+            }
+        }
+
+        // CORS_ALLOWED_ORIGINS：逗号分隔的来源；CORS_ALLOW_CREDENTIALS：true 时允许凭据；CORS_MAX_AGE：秒数
+        // CORS_ALLOWED_METHODS、CORS_ALLOWED_HEADERS、CORS_EXPOSE_HEADERS：逗号分隔的请求方法和头部名称
+        if let Some(origins) = var("CORS_ALLOWED_ORIGINS") {
+            self.cors = Some(CorsSection {
+                allowed_origins: split_list(&origins),
+                allowed_methods: var("CORS_ALLOWED_METHODS").map(|methods| split_list(&methods)),
+                allowed_headers: var("CORS_ALLOWED_HEADERS").map(|headers| split_list(&headers)),
+                expose_headers: var("CORS_EXPOSE_HEADERS").map(|headers| split_list(&headers)),
+                allow_credentials: var("CORS_ALLOW_CREDENTIALS").is_some_and(|v| v == "true"),
+                max_age: parse_some(&var, "CORS_MAX_AGE", &mut errors),
+            });
+        }
+
+        if let Some(path) = var("METRICS_PATH") {
+            self.metrics = Some(MetricsSection { path });
+        }
+
+        // RATE_LIMIT：默认配额，如 100/60；RATE_LIMIT_ROUTES：分号分隔的 [方法 ]前缀=配额，如 POST /api/=10/60
+        // RATE_LIMIT_KEY：peer、forwarded 或 api-key；RATE_LIMIT_TRUSTED_PROXIES、RATE_LIMIT_API_KEYS：逗号分隔的代理地址和 API Key
+        // RATE_LIMIT_API_KEY_HEADER：API Key 所在的请求头
+        if let Some(quota) = var("RATE_LIMIT") {
+            let routes = var("RATE_LIMIT_ROUTES").unwrap_or_default();
+            self.ratelimit = Some(RateLimitSection {
+                quota,
+                routes: routes.split(';').map(str::trim).filter(|route| !route.is_empty()).map(String::from).collect(),
+                key: var("RATE_LIMIT_KEY"),
+                trusted_proxies: split_list(&var("RATE_LIMIT_TRUSTED_PROXIES").unwrap_or_default()),
+                api_key_header: var("RATE_LIMIT_API_KEY_HEADER"),
+                api_keys: split_list(&var("RATE_LIMIT_API_KEYS").unwrap_or_default()),
+            });
+        }
+        errors
+    }
+
+    // 用命令行参数覆盖配置
+    fn apply_cli(&mut self, cli: &Cli) {
+        if !cli.bind.is_empty() {
+            self.server.bind = cli.bind.clone();
+        }
+        if let Some(workers) = cli.workers {
+            self.server.workers = workers;
+        }
+        if let Some(path) = &cli.public_path {
+            self.paths.public = Some(path.clone());
+        }
+        if let Some(path) = &cli.data_path {
+            self.paths.data = Some(path.clone());
+        }
+    }
+
+    // 把配置文件中的相对路径改为相对于 base
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |path: &mut PathBuf| {
+            if path.is_relative() {
+                *path = base.join(&*path);
+            }
+        };
+        let auth = &mut self.auth;
+        let paths = [
+            &mut self.paths.public,
+            &mut self.paths.data,
+            &mut auth.credentials_file,
+            &mut auth.tokens_file,
+            &mut auth.jwt_hs256_key_file,
+            &mut auth.jwt_rs256_key_file,
+            &mut self.health.disk_path,
+            &mut self.store.db_path,
+        ];
+        for path in paths.into_iter().flatten() {
+            resolve(path);
+        }
+        for addr in &mut self.server.bind {
+            if let Some(path) = addr.strip_prefix(UNIX_PREFIX).filter(|path| Path::new(path).is_relative()) {
+                *addr = format!("{}{}", UNIX_PREFIX, base.join(path).display());
+            }
+        }
+        for vhost in &mut self.vhosts {
+            for path in [&mut vhost.public, &mut vhost.not_found].into_iter().flatten() {
+                resolve(path);
+            }
+        }
+        if let Some(logging) = &mut self.logging {
+            if !matches!(logging.access_log.as_str(), "stdout" | "-") && Path::new(&logging.access_log).is_relative() {
+                logging.access_log = base.join(&logging.access_log).to_string_lossy().into_owned();
+            }
+        }
+        if let Some(tls) = &mut self.tls {
+            resolve(&mut tls.cert_file);
+            resolve(&mut tls.key_file);
+            for sni in &mut tls.sni {
+                resolve(&mut sni.cert_file);
+                resolve(&mut sni.key_file);
+            }
+        }
+    }
+
+    // 校验配置，返回全部错误，每条错误以出错的配置项开头
+    pub fn validate(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let server = &self.server;
+        if server.bind.is_empty() {
+            errors.push("server.bind: at least one address is required".to_string());
+        }
+        for (i, addr) in server.bind.iter().enumerate() {
+            match addr.strip_prefix(UNIX_PREFIX) {
+                Some("") => errors.push(format!("server.bind[{}]: missing socket path", i)),
+                Some(path) => {
+                    let dir = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty());
+                    if let Some(dir) = dir.filter(|dir| !dir.is_dir()) {
+                        errors.push(format!("server.bind[{}]: directory {} does not exist", i, dir.display()));
+                    }
+                }
+                None => {
+                    if let Err(e) = addr.to_socket_addrs() {
+                        errors.push(format!("server.bind[{}]: cannot resolve {}: {}", i, addr, e));
+                    }
+                }
+            }
+        }
+        if server.workers == 0 {
+            errors.push("server.workers: must be at least 1".to_string());
+        }
+        let timeouts = [
+            ("server.idle_timeout", server.idle_timeout),
+            ("server.header_timeout", server.header_timeout),
+            ("server.body_timeout", server.body_timeout),
+            ("server.handler_timeout", server.handler_timeout),
+            ("server.write_timeout", server.write_timeout),
+        ];
+        for (name, value) in timeouts {
+            if value == 0 {
+                errors.push(format!("{}: must be at least 1 second", name));
+            }
+        }
+        let sizes = [
+            ("server.max_request_line", server.max_request_line),
+            ("server.max_headers", server.max_headers),
+            ("server.max_header_size", server.max_header_size),
+            ("server.max_body_size", server.max_body_size),
+        ];
+        for (name, value) in sizes {
+            if value == 0 {
+                errors.push(format!("{}: must be greater than 0", name));
+            }
+        }
+        for (name, path) in [("paths.public", &self.paths.public), ("paths.data", &self.paths.data)] {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_dir()) {
+                errors.push(format!("{}: {} is not a directory", name, path.display()));
+            }
+        }
+
+        for (name, value) in [("proxy.connect_timeout", self.proxy.connect_timeout), ("proxy.timeout", self.proxy.timeout)] {
+            if value == Some(0) {
+                errors.push(format!("{}: must be at least 1 second", name));
+            }
+        }
+        validate_routes("proxy.routes", &self.proxy.routes, &mut errors);
+
+        let mut seen = HashSet::new();
+        for (i, vhost) in self.vhosts.iter().enumerate() {
+            let name = format!("vhosts[{}]", i);
+            if vhost.hosts.is_empty() {
+                errors.push(format!("{}.hosts: at least one host name is required", name));
+            }
+            for host in &vhost.hosts {
+                if !seen.insert(host.to_ascii_lowercase()) {
+                    errors.push(format!("{}.hosts: {:?} is used by another virtual host", name, host));
+                }
+            }
+            if let Some(path) = vhost.public.as_ref().filter(|path| !path.is_dir()) {
+                errors.push(format!("{}.public: {} is not a directory", name, path.display()));
+            }
+            if let Some(path) = vhost.not_found.as_ref().filter(|path| !path.is_file()) {
+                errors.push(format!("{}.not_found: {} does not exist", name, path.display()));
+            }
+            validate_routes(&format!("{}.routes", name), &vhost.routes, &mut errors);
+        }
+
+        if let Some(logging) = &self.logging {
+            if let Some(format) = logging.format.as_deref().filter(|f| LogFormat::parse(f).is_none()) {
+                errors.push(format!("logging.format: unknown format {:?}, expected common, combined or json", format));
+            }
+            for field in logging.fields.iter().flatten().filter(|f| LogField::parse(f).is_none()) {
+                errors.push(format!("logging.fields: unknown field {:?}", field));
+            }
+            if !matches!(logging.access_log.as_str(), "stdout" | "-") {
+                let dir = Path::new(&logging.access_log).parent().filter(|dir| !dir.as_os_str().is_empty());
+                if let Some(dir) = dir.filter(|dir| !dir.is_dir()) {
+                    errors.push(format!("logging.access_log: directory {} does not exist", dir.display()));
+                }
+            }
+            if logging.max_size == Some(0) {
+                errors.push("logging.max_size: must be at least 1 MB".to_string());
+            }
+        }
+
+        if let Some(tls) = &self.tls {
+            let files = [("tls.cert_file", &tls.cert_file), ("tls.key_file", &tls.key_file)];
+            let sni = tls.sni.iter().enumerate().flat_map(|(i, sni)| {
+                [(format!("tls.sni[{}].cert_file", i), &sni.cert_file), (format!("tls.sni[{}].key_file", i), &sni.key_file)]
+            });
+            for (name, path) in files.map(|(name, path)| (name.to_string(), path)).into_iter().chain(sni) {
+                if !path.is_file() {
+                    errors.push(format!("{}: {} does not exist", name, path.display()));
+                }
+            }
+            if tls.alpn.as_ref().is_some_and(|alpn| alpn.iter().any(|p| p.is_empty() || p.len() > 255)) {
+                errors.push("tls.alpn: protocol names must be 1 to 255 bytes".to_string());
+            }
+        }
+
+        if let Some(store) = self.session.store.as_deref().filter(|s| !matches!(*s, "memory" | "file" | "sqlite")) {
+            errors.push(format!("session.store: unknown store {:?}, expected memory, file or sqlite", store));
+        }
+        if let Some(cors) = &self.cors {
+            // 允许凭据时不能允许任何来源（*），否则任何网站都能带着用户的 Cookie 访问 API
+            if cors.allow_credentials && cors.allowed_origins.iter().any(|origin| origin == "*") {
+                errors.push("cors.allowed_origins: \"*\" cannot be combined with allow_credentials = true".to_string());
+            }
+            if let Some(method) = cors.allowed_methods.iter().flatten().find(|m| m.is_empty() || !m.bytes().all(|b| b.is_ascii_alphabetic())) {
+                errors.push(format!("cors.allowed_methods: invalid method {:?}", method));
+            }
+            for (key, headers) in [("allowed_headers", &cors.allowed_headers), ("expose_headers", &cors.expose_headers)] {
+                if let Some(header) = headers.iter().flatten().find(|h| h.is_empty() || h.bytes().any(|b| !b.is_ascii_graphic() || b == b',' || b == b':')) {
+                    errors.push(format!("cors.{}: invalid header name {:?}", key, header));
+                }
+            }
+        }
+        if let Some(metrics) = self.metrics.as_ref().filter(|metrics| !metrics.path.starts_with('/')) {
+            errors.push(format!("metrics.path: {:?} must start with '/'", metrics.path));
+        }
+
+        let auth = &self.auth;
+        let auth_files = [
+            ("auth.credentials_file", &auth.credentials_file),
+            ("auth.tokens_file", &auth.tokens_file),
+            ("auth.jwt_hs256_key_file", &auth.jwt_hs256_key_file),
+            ("auth.jwt_rs256_key_file", &auth.jwt_rs256_key_file),
+        ];
+        for (name, path) in auth_files {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                errors.push(format!("{}: {} does not exist", name, path.display()));
+            }
+        }
+        match (&auth.jwt_hs256_key_file, &auth.jwt_rs256_key_file, &auth.jwt_audience) {
+            (Some(_), Some(_), _) => errors.push("auth.jwt_rs256_key_file: cannot be combined with jwt_hs256_key_file".to_string()),
+            (None, None, Some(_)) => errors.push("auth.jwt_audience: requires jwt_hs256_key_file or jwt_rs256_key_file".to_string()),
+            _ => {}
+        }
+        if self.admin.password.is_some() && self.admin.username.is_none() {
+            errors.push("admin.username: required when admin.password is set".to_string());
+        }
+        if let Some(ratelimit) = &self.ratelimit {
+            if Quota::parse(&ratelimit.quota).is_none() {
+                errors.push(format!("ratelimit.quota: invalid quota {:?}, expected requests/seconds", ratelimit.quota));
+            }
+            for (i, route) in ratelimit.routes.iter().enumerate() {
+                if route_quota(route).is_none() {
+                    errors.push(format!("ratelimit.routes[{}]: invalid entry {:?}, expected [METHOD ]prefix=requests/seconds", i, route));
+                }
+            }
+            if let Err(e) = ratelimit.key_source() {
+                errors.push(e);
+            }
+        }
+        if self.health.min_free_mb.is_some_and(|mb| mb.checked_mul(1024 * 1024).is_none()) {
+            errors.push("health.min_free_mb: value is too large".to_string());
+        }
+        match self.store.backend.as_deref() {
+            None | Some("json") if self.store.db_path.is_some() => {
+                errors.push("store.db_path: only used with backend = \"sqlite\"".to_string())
+            }
+            None | Some("json" | "sqlite") => {}
+            Some(backend) => errors.push(format!("store.backend: unknown backend {:?}, expected json or sqlite", backend)),
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    // 按 [server] 创建服务器
+    pub fn server(&self) -> Server<'_> {
+        let (first, rest) = self.server.bind.split_first().expect("server.bind is empty");
+        rest.iter()
+            .fold(Server::new(first), |server, addr| server.listen(addr))
+            .workers(self.server.workers)
+            .limits(self.limits())
+    }
+
+    // [server] 中的超时和大小限制
+    pub fn limits(&self) -> Limits {
+        let seconds = |secs| Some(Duration::from_secs(secs));
+        Limits {
+            idle_timeout: seconds(self.server.idle_timeout),
+            header_timeout: seconds(self.server.header_timeout),
+            body_timeout: seconds(self.server.body_timeout),
+            handler_timeout: seconds(self.server.handler_timeout),
+            write_timeout: seconds(self.server.write_timeout),
+            min_rate: self.server.min_rate,
+            max_request_line: self.server.max_request_line,
+            max_headers: self.server.max_headers,
+            max_header_size: self.server.max_header_size,
+            max_body_size: self.server.max_body_size,
+        }
+    }
+
+    // 静态文件目录：[paths] 的 public，默认是 crate 目录下的 public
+    pub fn public_path(&self) -> PathBuf {
+        self.paths.public.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("public"))
+    }
+
+    // 数据目录：[paths] 的 data，默认是 crate 目录下的 data
+    pub fn data_path(&self) -> PathBuf {
+        self.paths.data.clone().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("data"))
+    }
+
+    // 按 [session] 创建会话中间件，https 表示服务器是否使用 HTTPS，未设置 secure 时据此决定 Secure 属性
+    // TLS 由前面的代理终止时应设置 secure = true
+    pub fn sessions(&self, https: bool) -> Result<SessionMiddleware, String> {
+        let backend = self.session.store.as_deref().unwrap_or("memory");
+        let path = match &self.session.path {
+            Some(path) => path.clone(),
+            None if backend == "sqlite" => self.data_path().join("sessions.db"),
+            None => self.data_path().join("sessions"),
+        };
+        let store = session_store(backend, &path)
+            .map_err(|e| format!("failed to open session store {}: {}", path.display(), e))?;
+        let mut sessions = SessionMiddleware::new(store).secure(self.session.secure.unwrap_or(https));
+        if let Some(secret) = &self.session.secret {
+            sessions = sessions.signing_key(CookieKey::new(secret));
+        }
+        Ok(sessions)
+    }
+
+    // 按 [cors] 创建作用于 /api/ 下路径的 CORS 中间件，没有配置时返回 None
+    pub fn cors(&self) -> Option<CorsMiddleware> {
+        let cors = self.cors.as_ref()?;
+        let mut middleware = cors
+            .allowed_origins
+            .iter()
+            .fold(CorsMiddleware::new().prefix("/api/"), |middleware, origin| middleware.allow_origin(origin))
+            .allow_credentials(cors.allow_credentials);
+        fn list(items: &[String]) -> Vec<&str> {
+            items.iter().map(String::as_str).collect()
+        }
+        if let Some(methods) = &cors.allowed_methods {
+            middleware = middleware.allow_methods(&list(methods));
+        }
+        if let Some(headers) = &cors.allowed_headers {
+            middleware = middleware.allow_headers(&list(headers));
+        }
+        if let Some(headers) = &cors.expose_headers {
+            middleware = middleware.expose_headers(&list(headers));
+        }
+        if let Some(secs) = cors.max_age {
+            middleware = middleware.max_age(Duration::from_secs(secs));
+        }
+        Some(middleware)
+    }
+
+    // 按 [metrics] 创建指标中间件，没有配置时返回 None
+    pub fn metrics(&self) -> Option<MetricsMiddleware> {
+        self.metrics.as_ref().map(|metrics| MetricsMiddleware::new(&metrics.path))
+    }
+
+    // 按 [[vhosts]] 创建虚拟主机中间件，默认站点使用 [proxy] 的路由并提供内置路由；没有设置静态文件目录的站点使用 [paths] 的目录
+    pub fn virtual_hosts(&self) -> Result<VirtualHosts, String> {
+        let mut default = Site::new().public(self.public_path());
+        if let Some(proxy) = self.reverse_proxy(&self.proxy.routes)? {
+            default = default.proxy(proxy);
+        }
+        let mut hosts = VirtualHosts::new(default);
+        for vhost in &self.vhosts {
+            let mut site = Site::new()
+                .public(vhost.public.clone().unwrap_or_else(|| self.public_path()))
+                .builtin_routes(vhost.builtin_routes);
+            if let Some(page) = &vhost.not_found {
+                site = site.not_found(page);
+            }
+            if let Some(proxy) = self.reverse_proxy(&vhost.routes)? {
+                site = site.proxy(proxy);
+            }
+            let names: Vec<&str> = vhost.hosts.iter().map(String::as_str).collect();
+            hosts = hosts.host(&names, site);
+        }
+        Ok(hosts)
+    }
+
+    // 创建转发 routes 的反向代理，使用 [proxy] 的超时；没有路由时返回 None
+    fn reverse_proxy(&self, routes: &[RouteSection]) -> Result<Option<ReverseProxy>, String> {
+        if routes.is_empty() {
+            return Ok(None);
+        }
+        let mut proxy = ReverseProxy::new();
+        for route in routes {
+            let upstreams = route
+                .upstreams
+                .iter()
+                .map(|url| Url::parse(url).map_err(|e| format!("invalid upstream {}: {}", url, e)))
+                .collect::<Result<Vec<Url>, String>>()?;
+            let strategy = route.strategy().ok_or_else(|| format!("invalid strategy for {}", route.prefix))?;
+            let mut pool = UpstreamPool::new(upstreams).strategy(strategy);
+            if let Some(path) = &route.health_check {
+                let interval = route.health_interval.map_or(DEFAULT_HEALTH_INTERVAL, Duration::from_secs);
+                pool = pool.health_check(path, interval);
+            }
+            if let Some(max_fails) = route.max_fails {
+                pool = pool.max_fails(max_fails);
+            }
+            if let Some(fail_timeout) = route.fail_timeout {
+                pool = pool.fail_timeout(Duration::from_secs(fail_timeout));
+            }
+            proxy = proxy.balance(&route.prefix, pool);
+        }
+        if let Some(timeout) = self.proxy.connect_timeout {
+            proxy = proxy.connect_timeout(Duration::from_secs(timeout));
+        }
+        if let Some(timeout) = self.proxy.timeout {
+            proxy = proxy.timeout(Duration::from_secs(timeout));
+        }
+        Ok(Some(proxy))
+    }
+
+    // 按 [logging] 创建访问日志，没有配置时返回 None
+    pub fn access_log(&self) -> Result<Option<AccessLog>, String> {
+        let Some(logging) = &self.logging else {
+            return Ok(None);
+        };
+        let format = match &logging.format {
+            Some(name) => LogFormat::parse(name).ok_or_else(|| format!("invalid log format: {}", name))?,
+            None => LogFormat::Combined,
+        };
+        let output = match logging.access_log.as_str() {
+            "stdout" | "-" => LogOutput::Stdout,
+            path => {
+                let max_size = logging.max_size.map_or(DEFAULT_MAX_SIZE, |mb| mb * 1024 * 1024);
+                let max_files = logging.max_files.unwrap_or(DEFAULT_MAX_FILES);
+                let file = RotatingFile::open(path, max_size, max_files)
+                    .map_err(|e| format!("failed to open access log {}: {}", path, e))?;
+                LogOutput::File(file)
+            }
+        };
+        let mut log = AccessLog::new(format, output);
+        if let Some(fields) = &logging.fields {
+            let fields = fields
+                .iter()
+                .map(|name| LogField::parse(name).ok_or_else(|| format!("invalid log field: {}", name)))
+                .collect::<Result<Vec<LogField>, String>>()?;
+            log = log.fields(fields);
+        }
+        Ok(Some(log))
+    }
+
+    // 按 [tls] 创建 TLS 配置，没有配置时返回 None
+    pub fn tls_config(&self) -> Result<Option<TlsConfig>, String> {
+        let Some(tls) = &self.tls else {
+            return Ok(None);
+        };
+        let mut config = TlsConfig::new(&tls.cert_file, &tls.key_file);
+        for sni in &tls.sni {
+            config = config.sni(&sni.host, &sni.cert_file, &sni.key_file);
+        }
+        if let Some(alpn) = &tls.alpn {
+            config = config.alpn(&alpn.iter().map(String::as_str).collect::<Vec<&str>>());
+        }
+        Ok(Some(config))
+    }
+
+    // 按 [auth] 创建认证中间件并添加默认的访问规则，没有配置任何认证方式时返回 None
+    pub fn auth(&self) -> Result<Option<AuthMiddleware>, String> {
+        let auth = &self.auth;
+        if auth.credentials_file.is_none() && auth.tokens_file.is_none() && auth.jwt_hs256_key_file.is_none() && auth.jwt_rs256_key_file.is_none() {
+            return Ok(None);
+        }
+        let read_error = |path: &Path, e: std::io::Error| format!("failed to read {}: {}", path.display(), e);
+        let mut middleware = AuthMiddleware::new("httpserver");
+        if let Some(path) = &auth.credentials_file {
+            middleware = middleware.basic(Credentials::from_file(path).map_err(|e| read_error(path, e))?);
+        }
+        if let Some(path) = &auth.tokens_file {
+            middleware = middleware.bearer_tokens(BearerTokens::from_file(path).map_err(|e| read_error(path, e))?);
+        }
+        let key = match (&auth.jwt_hs256_key_file, &auth.jwt_rs256_key_file) {
+            (Some(path), _) => Some(JwtKey::hs256_from_file(path).map_err(|e| read_error(path, e))?),
+            (_, Some(path)) => Some(JwtKey::rs256_from_file(path).map_err(|e| read_error(path, e))?),
+            _ => None,
+        };
+        if let Some(key) = key {
+            let mut verifier = JwtVerifier::new(key);
+            if let Some(audience) = &auth.jwt_audience {
+                verifier = verifier.audience(audience);
+            }
+            middleware = middleware.jwt(verifier);
+        }
+        Ok(Some(middleware.default_rules()))
+    }
+
+    // 按 [ratelimit] 创建限流中间件，没有配置时返回 None
+    pub fn rate_limit(&self) -> Result<Option<RateLimitMiddleware>, String> {
+        let Some(ratelimit) = &self.ratelimit else {
+            return Ok(None);
+        };
+        let quota = Quota::parse(&ratelimit.quota).ok_or_else(|| format!("invalid rate limit quota: {}", ratelimit.quota))?;
+        let mut limiter = RateLimitMiddleware::new(quota).key(ratelimit.key_source()?);
+        for route in &ratelimit.routes {
+            let (method, prefix, quota) = route_quota(route).ok_or_else(|| format!("invalid rate limit route: {}", route))?;
+            limiter = limiter.route(method, prefix, quota);
+        }
+        Ok(Some(limiter))
+    }
+
+    // 按 [health] 创建磁盘空间检查，默认检查数据目录
+    pub fn disk_space_check(&self) -> DiskSpaceCheck {
+        let path = self.health.disk_path.clone().unwrap_or_else(|| self.data_path());
+        let min_free_bytes = self.health.min_free_mb.map_or(DEFAULT_MIN_FREE_BYTES, |mb| mb.saturating_mul(1024 * 1024));
+        DiskSpaceCheck::new(path.to_string_lossy(), min_free_bytes)
+    }
+
+    // 按 [store] 选择订单仓库的后端
+    pub fn order_store(&self) -> OrderStore {
+        match self.store.backend.as_deref() {
+            Some("sqlite") => OrderStore::Sqlite(self.store.db_path.clone().unwrap_or_else(|| self.data_path().join("orders.db"))),
+            _ => OrderStore::Json,
+        }
+    }
+
+    // 按配置创建服务器并注册全部中间件，先注册的先处理请求、后处理响应
+    // 订单仓库和管理员账号不在这里设置，由 main 在启动服务器前通过 repository::init 和 handler::init_admin 设置
+    pub fn build(&self) -> Result<Server<'_>, String> {
+        // 配置了证书时使用 HTTPS
+        let tls = self.tls_config()?;
+        // 会话存储由 [session] 选择，设置了密钥时对会话 Cookie 签名，使用 HTTPS 时会话 Cookie 默认带 Secure 属性
+        let sessions = self.sessions(tls.is_some())?;
+
+        // 访问日志最先注册，被其他中间件拦截的请求同样会被记录
+        let mut server = self.server();
+        if let Some(access_log) = self.access_log()? {
+            server = server.middleware(access_log);
+        }
+        // 配置了 [metrics] 时统计请求并在该路径输出 Prometheus 指标
+        if let Some(metrics) = self.metrics() {
+            server = server.middleware(metrics);
+        }
+        // /healthz 和 /readyz 在限流和认证之前，探针不需要凭据；就绪检查订单数据、磁盘空间和代理的上游
+        let hosts = self.virtual_hosts()?;
+        let mut health = HealthMiddleware::new()
+            .readiness("order_data", OrderDataCheck)
+            .readiness("disk_space", self.disk_space_check());
+        let pools = hosts.pools();
+        if !pools.is_empty() {
+            health = health.readiness("upstreams", UpstreamCheck::new(pools));
+        }
+        server = server.middleware(health);
+        // CORS 放在其他中间件前面，预检请求不需要经过会话和认证
+        if let Some(cors) = self.cors() {
+            server = server.middleware(cors);
+        }
+        // 限流在会话和认证之前执行，被限流的请求不会访问会话存储
+        if let Some(limiter) = self.rate_limit()? {
+            server = server.middleware(limiter);
+        }
+        server = server.middleware(sessions);
+        // 配置了认证方式时启用认证中间件
+        if let Some(auth) = self.auth()? {
+            server = server.middleware(auth);
+        }
+        // 按 Host 头选择站点，站点的代理路由把匹配的请求转发给上游服务，放在最后使之前的中间件同样作用于代理的请求
+        server = server.middleware(hosts);
+        if let Some(tls) = tls {
+            server = server.tls(TlsAcceptor::new(tls).map_err(|e| e.to_string())?);
+        }
+        Ok(server)
+    }
+}
+
+impl RateLimitSection {
+    // 限流的键来源，配置不合法时返回以配置项开头的错误
+    fn key_source(&self) -> Result<KeySource, String> {
+        match self.key.as_deref().unwrap_or("peer") {
+            "peer" => Ok(KeySource::Peer),
+            "forwarded" => {
+                let proxies = self
+                    .trusted_proxies
+                    .iter()
+                    .map(|proxy| proxy.parse().map_err(|_| format!("ratelimit.trusted_proxies: invalid address {:?}", proxy)))
+                    .collect::<Result<Vec<IpAddr>, String>>()?;
+                Ok(KeySource::ForwardedFor(proxies))
+            }
+            // 只有已知的 API Key 才单独计数
+            "api-key" if self.api_keys.is_empty() => Err("ratelimit.api_keys: required when key = \"api-key\"".to_string()),
+            "api-key" => {
+                let header = self.api_key_header.clone().unwrap_or_else(|| "X-Api-Key".to_string());
+                Ok(KeySource::Header(header, self.api_keys.iter().cloned().collect()))
+            }
+            key => Err(format!("ratelimit.key: unknown key {:?}, expected peer, forwarded or api-key", key)),
+        }
+    }
+}
+
+// 解析 [方法 ]前缀=配额 格式的路由配额，如 POST /api/=10/60，格式不合法时返回 None
+fn route_quota(route: &str) -> Option<(Option<Method>, &str, Quota)> {
+    let (target, quota) = route.split_once('=')?;
+    let quota = Quota::parse(quota)?;
+    match target.trim().split_once(' ') {
+        Some((method, prefix)) => match Method::from(method) {
+            Method::Uninitialized => None,
+            method => Some((Some(method), prefix.trim(), quota)),
+        },
+        None => Some((None, target.trim(), quota)),
+    }
+}
+
+impl RouteSection {
+    // 路由的负载均衡策略，名称不合法时返回 None
+    fn strategy(&self) -> Option<Strategy> {
+        Strategy::parse(self.strategy.as_deref().unwrap_or("round-robin"), self.hash_header.clone())
+    }
+}
+
+// 反序列化一个字符串或字符串数组，bind = "..." 和 bind = ["...", "..."] 都可以
+fn one_or_many<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(value) => vec![value],
+        OneOrMany::Many(values) => values,
+    })
+}
+
+// 校验代理路由，section 是路由所在的配置项
+fn validate_routes(section: &str, routes: &[RouteSection], errors: &mut Vec<String>) {
+    for (i, route) in routes.iter().enumerate() {
+        let name = format!("{}[{}]", section, i);
+        if !route.prefix.starts_with('/') {
+            errors.push(format!("{}.prefix: {:?} must start with '/'", name, route.prefix));
+        }
+        if route.upstreams.is_empty() {
+            errors.push(format!("{}.upstreams: at least one upstream is required", name));
+        }
+        for url in &route.upstreams {
+            if let Err(e) = Url::parse(url) {
+                errors.push(format!("{}.upstreams: invalid URL {:?}: {}", name, url, e));
+            }
+        }
+        if route.strategy().is_none() {
+            let strategy = route.strategy.as_deref().unwrap_or_default();
+            errors.push(format!("{}.strategy: unknown strategy {:?}, expected round-robin, least-conn or hash", name, strategy));
+        } else if route.hash_header.is_some() && route.strategy.as_deref() != Some("hash") {
+            errors.push(format!("{}.hash_header: only used with strategy = \"hash\"", name));
+        }
+        if let Some(path) = route.health_check.as_ref().filter(|path| !path.starts_with('/')) {
+            errors.push(format!("{}.health_check: {:?} must start with '/'", name, path));
+        }
+        for (field, value) in [("health_interval", route.health_interval), ("fail_timeout", route.fail_timeout)] {
+            if value == Some(0) {
+                errors.push(format!("{}.{}: must be at least 1 second", name, field));
+            }
+        }
+    }
+}
+
+// 读取并解析环境变量，设置了但无法解析时记录错误
+fn parse_var<T: FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str, target: &mut T, errors: &mut Vec<String>) {
+    if let Some(parsed) = parse_some(var, name, errors) {
+        *target = parsed;
+    }
+}
+
+// 读取并解析环境变量，没有设置或无法解析时返回 None，无法解析时记录错误
+fn parse_some<T: FromStr>(var: &impl Fn(&str) -> Option<String>, name: &str, errors: &mut Vec<String>) -> Option<T> {
+    let value = var(name)?;
+    match value.trim().parse() {
+        Ok(parsed) => Some(parsed),
+        Err(_) => {
+            errors.push(format!("{}: invalid value {:?}", name, value));
+            None
+        }
+    }
+}
+
+// 逗号分隔的列表，去掉空白和空项
+fn split_list(list: &str) -> Vec<String> {
+    list.split(',').map(str::trim).filter(|item| !item.is_empty()).map(String::from).collect()
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::Middleware;
+    use http::httprequest::HttpRequest;
+    use std::collections::HashMap;
+
+    const EXAMPLE: &str = r#"
+[server]
+bind = ["0.0.0.0:8080", "[::]:8080", "unix:run/httpserver.sock"]
+workers = 16
+header_timeout = 5
+max_body_size = 1048576
+
+[paths]
+public = "public"
+
+[proxy]
+timeout = 10
+
+[[proxy.routes]]
+prefix = "/api/v2"
+upstreams = ["http://127.0.0.1:4000", "http://127.0.0.1:4001"]
+strategy = "hash"
+hash_header = "X-User"
+
+[logging]
+access_log = "logs/access.log"
+format = "json"
+fields = ["time", "status"]
+
+[[vhosts]]
+hosts = ["shop.example.com"]
+public = "sites/shop"
+not_found = "sites/shop/404.html"
+
+[[vhosts.routes]]
+prefix = "/api"
+upstreams = ["http://127.0.0.1:5000"]
+"#;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    // 测试解析配置文件，缺失的字段使用默认值，相对路径相对于配置文件的目录
+    #[test]
+    fn test_parse_config() {
+        let mut config = Config::parse(EXAMPLE).unwrap();
+        assert_eq!(config.server.bind.len(), 3);
+        assert_eq!((config.server.workers, config.server.header_timeout, config.server.idle_timeout), (16, 5, 30));
+        assert_eq!(Config::default().limits(), Limits::default());
+        assert_eq!(config.limits().header_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(config.proxy.routes[0].upstreams.len(), 2);
+        assert_eq!(config.logging.as_ref().unwrap().fields.as_ref().unwrap(), &["time", "status"]);
+        assert_eq!(config.tls, None);
+        assert_eq!(config.vhosts[0].routes[0].prefix, "/api");
+        assert!(!config.vhosts[0].builtin_routes);
+        assert_eq!(Config::parse("[server]\nbind = \"[::1]:80\"").unwrap().server.bind, ["[::1]:80"]);
+
+        config.resolve_paths(Path::new("/etc/httpserver"));
+        assert_eq!(config.paths.public.as_deref(), Some(Path::new("/etc/httpserver/public")));
+        assert_eq!(config.server.bind[2], "unix:/etc/httpserver/run/httpserver.sock");
+        assert_eq!(config.vhosts[0].not_found.as_deref(), Some(Path::new("/etc/httpserver/sites/shop/404.html")));
+        assert_eq!(config.virtual_hosts().unwrap().pools()[1].0, "shop.example.com/api");
+        assert_eq!(config.logging.unwrap().access_log, "/etc/httpserver/logs/access.log");
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    // 测试未知字段和类型错误给出出错的位置
+    #[test]
+    fn test_parse_errors() {
+        let error = Config::parse("[server]\nbnd = \"localhost:80\"\n").unwrap_err();
+        assert!(error.contains("unknown field `bnd`"), "{}", error);
+        let error = Config::parse("[server]\nworkers = \"many\"\n").unwrap_err();
+        assert!(error.contains("line 2"), "{}", error);
+    }
+
+    // 测试覆盖的优先级：配置文件 < 环境变量 < 命令行参数
+    #[test]
+    fn test_overrides() {
+        let mut config = Config::parse(EXAMPLE).unwrap();
+        let vars: HashMap<&str, &str> = [
+            ("HTTPSERVER_BIND", "127.0.0.1:9000, unix:/tmp/a.sock"),
+            ("HTTPSERVER_WORKERS", "32"),
+            ("HTTPSERVER_BODY_TIMEOUT", "soon"),
+            ("HTTPSERVER_MAX_HEADERS", "50"),
+            ("DATA_PATH", "/srv/data"),
+            ("ACCESS_LOG", "stdout"),
+        ]
+        .into();
+        let errors = config.apply_env(|name| vars.get(name).map(|v| v.to_string()));
+        assert_eq!(errors, vec!["HTTPSERVER_BODY_TIMEOUT: invalid value \"soon\"".to_string()]);
+        assert_eq!(config.server.max_headers, 50);
+        assert_eq!((config.server.bind.join(" "), config.server.workers), ("127.0.0.1:9000 unix:/tmp/a.sock".into(), 32));
+        assert_eq!(config.paths.data.as_deref(), Some(Path::new("/srv/data")));
+        assert_eq!(config.data_path(), Path::new("/srv/data"));
+        assert_eq!(config.logging.as_ref().map(|l| (l.access_log.as_str(), l.format.as_deref())), Some(("stdout", None)));
+        assert_eq!(config.proxy.routes.len(), 1);
+
+        let cli = Cli::parse(args(&["--bind", "[::1]:8443", "--bind=0.0.0.0:8080", "--workers=4", "--check-config"])).unwrap();
+        config.apply_cli(&cli);
+        assert_eq!((config.server.bind.join(" "), config.server.workers), ("[::1]:8443 0.0.0.0:8080".into(), 4));
+        assert!(cli.check_config);
+    }
+
+    // 测试环境变量取代代理路由、访问日志、TLS、会话、CORS 和指标的配置，组件只按合并后的配置创建
+    #[test]
+    fn test_env_sections() {
+        let mut config = Config::parse(EXAMPLE).unwrap();
+        let vars: HashMap<&str, &str> = [
+            ("PROXY_ROUTES", "/api=http://127.0.0.1:4000/v1/|http://127.0.0.1:4001, /b=http://h, broken"),
+            ("PROXY_BALANCE", "hash"),
+            ("PROXY_HASH_HEADER", "X-User"),
+            ("PROXY_HEALTH_CHECK", "/health"),
+            ("PROXY_MAX_FAILS", "3"),
+            ("PROXY_TIMEOUT", "7"),
+            ("ACCESS_LOG", "/var/log/access.log"),
+            ("ACCESS_LOG_FIELDS", "time, status,"),
+            ("ACCESS_LOG_MAX_FILES", "many"),
+            ("TLS_CERT_FILE", "cert.pem"),
+            ("TLS_KEY_FILE", "key.pem"),
+            ("TLS_SNI", "api.test=api.pem,api.key;bad"),
+            ("SESSION_STORE", "sqlite"),
+            ("SESSION_SECRET", "secret"),
+            ("SESSION_COOKIE_SECURE", "true"),
+            ("CORS_ALLOWED_ORIGINS", "https://a.test, https://*.b.test"),
+            ("CORS_ALLOW_CREDENTIALS", "true"),
+            ("CORS_MAX_AGE", "600"),
+            ("CORS_ALLOWED_METHODS", "GET, put"),
+            ("CORS_ALLOWED_HEADERS", "Content-Type, X-Request-Id"),
+            ("METRICS_PATH", "/metrics"),
+        ]
+        .into();
+        let errors = config.apply_env(|name| vars.get(name).map(|v| v.to_string()));
+        assert_eq!(
+            errors,
+            [
+                "PROXY_ROUTES: invalid entry \"broken\"",
+                "ACCESS_LOG_MAX_FILES: invalid value \"many\"",
+                "TLS_SNI: invalid entry \"bad\"",
+            ]
+        );
+
+        let routes = &config.proxy.routes;
+        assert_eq!((routes.len(), routes[0].prefix.as_str(), routes[0].upstreams.len()), (2, "/api", 2));
+        assert_eq!((routes[1].strategy.as_deref(), routes[1].hash_header.as_deref()), (Some("hash"), Some("X-User")));
+        assert_eq!((routes[1].health_check.as_deref(), routes[1].max_fails), (Some("/health"), Some(3)));
+        assert_eq!((config.proxy.timeout, config.proxy.connect_timeout), (Some(7), None));
+        assert_eq!(config.virtual_hosts().unwrap().pools().len(), 3);
+
+        let logging = config.logging.as_ref().unwrap();
+        assert_eq!((logging.fields.as_deref(), logging.max_files), (Some(&["time".to_string(), "status".to_string()][..]), None));
+        let tls = config.tls.as_ref().unwrap();
+        assert_eq!((tls.key_file.as_path(), tls.sni.len(), tls.sni[0].host.as_str()), (Path::new("key.pem"), 1, "api.test"));
+        assert_eq!(config.session.store.as_deref(), Some("sqlite"));
+        assert_eq!((config.session.secret.as_deref(), config.session.secure), (Some("secret"), Some(true)));
+        assert_eq!(config.cors.as_ref().unwrap().allowed_origins, ["https://a.test", "https://*.b.test"]);
+        assert_eq!(config.cors.as_ref().unwrap().max_age, Some(600));
+        assert_eq!(config.cors.as_ref().unwrap().allowed_methods.as_deref(), Some(&["GET".to_string(), "put".to_string()][..]));
+        assert_eq!(config.cors.as_ref().unwrap().allowed_headers.as_deref(), Some(&["Content-Type".to_string(), "X-Request-Id".to_string()][..]));
+        assert_eq!(config.cors.as_ref().unwrap().expose_headers, None);
+        assert_eq!(config.metrics, Some(MetricsSection { path: "/metrics".to_string() }));
+        assert!(config.cors().is_some() && config.metrics().is_some());
+        let mut req: HttpRequest = "OPTIONS /api/orders HTTP/1.1\r\nOrigin: https://x.b.test\r\nAccess-Control-Request-Method: PUT\r\nAccess-Control-Request-Headers: x-request-id\r\n\r\n".to_string().into();
+        let raw: String = config.cors().unwrap().before(&mut req).unwrap().into();
+        assert!(raw.contains("Access-Control-Allow-Methods:GET, PUT\r\n"));
+        assert!(raw.contains("Access-Control-Allow-Headers:Content-Type, X-Request-Id\r\n"));
+
+        // 只设置证书没有私钥时报告错误
+        let mut config = Config::default();
+        let errors = config.apply_env(|name| (name == "TLS_CERT_FILE").then(|| "cert.pem".to_string()));
+        assert_eq!(errors, ["TLS_KEY_FILE: required when TLS_CERT_FILE is set"]);
+        assert_eq!(config.tls, None);
+    }
+
+    // 测试环境变量覆盖认证、管理员账号、限流、健康检查和订单仓库的配置，并按合并后的配置创建组件
+    #[test]
+    fn test_env_security_sections() {
+        let dir = std::env::temp_dir().join(format!("httpserver-config-auth-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("tokens"), "ci:token:admin\n").unwrap();
+        let tokens = dir.join("tokens").to_string_lossy().into_owned();
+        let mut config = Config::parse("[auth]\njwt_audience = \"shop\"\n\n[store]\nbackend = \"sqlite\"\n").unwrap();
+        let vars: HashMap<&str, &str> = [
+            ("AUTH_TOKENS_FILE", tokens.as_str()),
+            ("ADMIN_USERNAME", "admin"),
+            ("ADMIN_PASSWORD", "secret"),
+            ("RATE_LIMIT", "100/60"),
+            ("RATE_LIMIT_ROUTES", "POST /api/=10/60; /admin=5/60;"),
+            ("RATE_LIMIT_KEY", "forwarded"),
+            ("RATE_LIMIT_TRUSTED_PROXIES", "10.0.0.1, ::1"),
+            ("HEALTH_MIN_FREE_MB", "lots"),
+            ("ORDER_DB_PATH", "/srv/orders.db"),
+        ]
+        .into();
+        let errors = config.apply_env(|name| vars.get(name).map(|v| v.to_string()));
+        assert_eq!(errors, ["HEALTH_MIN_FREE_MB: invalid value \"lots\""]);
+        assert_eq!(config.auth.tokens_file.as_deref(), Some(dir.join("tokens").as_path()));
+        assert_eq!((config.admin.username.as_deref(), config.admin.password.as_deref()), (Some("admin"), Some("secret")));
+        let ratelimit = config.ratelimit.as_ref().unwrap();
+        assert_eq!((ratelimit.quota.as_str(), ratelimit.routes.len()), ("100/60", 2));
+        assert_eq!(ratelimit.key_source(), Ok(KeySource::ForwardedFor(vec!["10.0.0.1".parse().unwrap(), "::1".parse().unwrap()])));
+        assert_eq!(config.order_store(), OrderStore::Sqlite("/srv/orders.db".into()));
+        // audience 只对 JWT 有效，没有 JWT 密钥时报告错误
+        assert_eq!(config.validate().unwrap_err(), ["auth.jwt_audience: requires jwt_hs256_key_file or jwt_rs256_key_file"]);
+        config.auth.jwt_audience = None;
+        assert_eq!(config.validate(), Ok(()));
+
+        // 认证中间件使用默认的访问规则
+        let auth = config.auth().unwrap().unwrap();
+        let mut req: HttpRequest = "GET /api/shipping/orders HTTP/1.1\r\nHost: a\r\n\r\n".to_string().into();
+        assert_eq!(auth.before(&mut req).unwrap().status_code(), "401");
+        let mut req: HttpRequest = "GET /api/shipping/orders HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer token\r\n\r\n".to_string().into();
+        assert!(auth.before(&mut req).is_none());
+        assert!(config.rate_limit().unwrap().is_some());
+        assert!(config.build().is_ok());
+
+        // 默认不启用认证和限流，订单保存在 orders.json
+        let config = Config::default();
+        assert!(config.auth().unwrap().is_none() && config.rate_limit().unwrap().is_none());
+        assert_eq!(config.order_store(), OrderStore::Json);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 测试按 [session] 创建会话存储，数据库默认放在数据目录下
+    #[test]
+    fn test_sessions() {
+        let dir = std::env::temp_dir().join(format!("httpserver-config-sessions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut config = Config::default();
+        config.paths.data = Some(dir.clone());
+        config.session.store = Some("sqlite".to_string());
+        assert!(config.sessions(false).is_ok());
+        assert!(dir.join("sessions.db").is_file());
+        config.session.path = Some(dir.join("missing").join("sessions.db"));
+        let error = config.sessions(true).err().unwrap();
+        assert!(error.starts_with("failed to open session store"), "{}", error);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 测试命令行参数的解析错误
+    #[test]
+    fn test_cli_errors() {
+        assert_eq!(Cli::parse(args(&["--verbose"])), Err("unknown argument: --verbose".to_string()));
+        assert_eq!(Cli::parse(args(&["--config"])), Err("missing value for --config".to_string()));
+        assert_eq!(Cli::parse(args(&["--workers", "x"])), Err("invalid value for --workers: x".to_string()));
+        let cli = Cli::parse(args(&["--hash-password", "secret"])).unwrap();
+        assert_eq!(cli.hash_password.as_deref(), Some("secret"));
+    }
+
+    // 测试校验报告全部错误
+    #[test]
+    fn test_validate() {
+        assert_eq!(Config::default().validate(), Ok(()));
+        let config = Config::parse(
+            r#"
+[server]
+bind = ["localhost:3000", "unix:/nonexistent/http.sock"]
+workers = 0
+header_timeout = 0
+max_headers = 0
+
+[paths]
+data = "/nonexistent/data"
+
+[[proxy.routes]]
+prefix = "api"
+upstreams = ["ftp://example.com"]
+strategy = "random"
+
+[logging]
+access_log = "stdout"
+format = "xml"
+fields = ["time", "colour"]
+
+[tls]
+cert_file = "/nonexistent/cert.pem"
+key_file = "/nonexistent/key.pem"
+
+[[vhosts]]
+hosts = ["a.test", "b.test"]
+public = "/nonexistent/a"
+
+[[vhosts]]
+hosts = ["B.test"]
+
+[[vhosts.routes]]
+prefix = "/api"
+upstreams = []
+
+[session]
+store = "redis"
+
+[cors]
+allowed_origins = ["*"]
+allow_credentials = true
+allowed_methods = ["GET", "PATCH METHOD"]
+allowed_headers = ["X-Token: 1"]
+
+[metrics]
+path = "metrics"
+
+[auth]
+tokens_file = "/nonexistent/tokens"
+jwt_hs256_key_file = "/nonexistent/hs256.key"
+jwt_rs256_key_file = "/nonexistent/rs256.pem"
+
+[admin]
+password = "secret"
+
+[ratelimit]
+quota = "many"
+routes = ["GET /api/=10/60", "FETCH /api/=1/1"]
+key = "api-key"
+
+[health]
+min_free_mb = 9223372036854775807
+
+[store]
+backend = "redis"
+"#,
+        )
+        .unwrap();
+        let errors = config.validate().unwrap_err();
+        let fields: Vec<&str> = errors.iter().map(|e| e.split(':').next().unwrap()).collect();
+        assert_eq!(
+            fields,
+            [
+                "server.bind[1]",
+                "server.workers",
+                "server.header_timeout",
+                "server.max_headers",
+                "paths.data",
+                "proxy.routes[0].prefix",
+                "proxy.routes[0].upstreams",
+                "proxy.routes[0].strategy",
+                "vhosts[0].public",
+                "vhosts[1].hosts",
+                "vhosts[1].routes[0].upstreams",
+                "logging.format",
+                "logging.fields",
+                "tls.cert_file",
+                "tls.key_file",
+                "session.store",
+                "cors.allowed_origins",
+                "cors.allowed_methods",
+                "cors.allowed_headers",
+                "metrics.path",
+                "auth.tokens_file",
+                "auth.jwt_hs256_key_file",
+                "auth.jwt_rs256_key_file",
+                "auth.jwt_rs256_key_file",
+                "admin.username",
+                "ratelimit.quota",
+                "ratelimit.routes[1]",
+                "ratelimit.api_keys",
+                "health.min_free_mb",
+                "store.backend",
+            ]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+// 导入所需的库和模块
+use super::middleware::Middleware; // 导入中间件
+use http::httprequest::{HttpRequest, Method, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use std::collections::HashMap; // 导入 HashMap
+use std::time::Duration; // 导入时间间隔
+
+// 定义允许的来源
+#[derive(Debug, Clone, PartialEq)]
+enum OriginPattern {
+    Any,                    // 允许任何来源（*）
+    Exact(String),          // 完全匹配，如 https://app.example.com
+    Wildcard(String, String), // 含一个 * 的模式，如 https://*.example.com，保存 * 前后的部分
+}
+
+impl OriginPattern {
+    // 解析来源模式
+    fn parse(pattern: &str) -> Self {
+        match pattern.split_once('*') {
+            None => OriginPattern::Exact(pattern.to_ascii_lowercase()),
+            Some(("", "")) => OriginPattern::Any,
+            Some((prefix, suffix)) => {
+                OriginPattern::Wildcard(prefix.to_ascii_lowercase(), suffix.to_ascii_lowercase())
+            }
+        }
+    }
+
+    // 来源是否匹配该模式，* 匹配至少一个字符且不能跨越路径或端口
+    fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_ascii_lowercase();
+        match self {
+            OriginPattern::Any => true,
+            OriginPattern::Exact(exact) => origin == *exact,
+            OriginPattern::Wildcard(prefix, suffix) => {
+                origin.len() > prefix.len() + suffix.len()
+                    && origin.starts_with(prefix.as_str())
+                    && origin.ends_with(suffix.as_str())
+                    && !origin[prefix.len()..origin.len() - suffix.len()].contains(['/', ':'])
+            }
+        }
+    }
+}
+
+// CORS 中间件：自动应答预检请求，并为实际响应添加 Access-Control-* 头部
+pub struct CorsMiddleware {
+    prefix: String,               // 只处理该路径前缀下的请求
+    origins: Vec<OriginPattern>,  // 允许的来源
+    methods: Vec<String>,         // 允许的请求方法
+    headers: Vec<String>,         // 允许的请求头
+    expose_headers: Vec<String>,  // 允许前端读取的响应头
+    credentials: bool,            // 是否允许携带 Cookie 和 Authorization
+    max_age: Option<Duration>,    // 预检结果的缓存时间
+}
+
+impl Default for CorsMiddleware {
+    fn default() -> Self {
+        CorsMiddleware {
+            prefix: "/".to_string(),
+            origins: Vec::new(),
+            methods: vec!["GET".into(), "POST".into(), "OPTIONS".into()],
+            headers: vec!["Content-Type".into(), "Authorization".into()],
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+}
+
+impl CorsMiddleware {
+    // 创建一个不允许任何来源的 CORS 中间件，默认允许 GET、POST 和 OPTIONS
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // 只处理指定路径前缀下的请求
+    pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    // 添加允许的来源，支持 * 和 https://*.example.com 这样的模式
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        self.origins.push(OriginPattern::parse(origin.trim()));
+        self
+    }
+
+    // 设置允许的请求方法
+    pub fn allow_methods(mut self, methods: &[&str]) -> Self {
+        self.methods = methods.iter().map(|m| m.to_ascii_uppercase()).collect();
+        self
+    }
+
+    // 设置允许的请求头，* 表示允许任何请求头
+    pub fn allow_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = headers.iter().map(|h| h.to_string()).collect();
+        self
+    }
+
+    // 设置允许前端读取的响应头
+    pub fn expose_headers(mut self, headers: &[&str]) -> Self {
+        self.expose_headers = headers.iter().map(|h| h.to_string()).collect();
+        self
+    }
+
+    // 设置是否允许携带凭据，允许凭据时来源 * 不匹配任何来源（浏览器不接受 * 和凭据同时出现，回显来源又会允许所有网站）
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    // 设置预检结果的缓存时间
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    // 请求路径是否在处理范围内
+    fn applies_to(&self, req: &HttpRequest) -> bool {
+        let Resource::Path(path) = &req.resource;
+        path.starts_with(&self.prefix)
+    }
+
+    // 来源是否被允许，允许凭据时 * 不匹配
+    fn origin_allowed(&self, origin: &str) -> bool {
+        self.origins
+            .iter()
+            .filter(|pattern| !self.credentials || **pattern != OriginPattern::Any)
+            .any(|pattern| pattern.matches(origin))
+    }
+
+    // 允许任何来源且不带凭据时返回 *，否则回显请求的来源（只用于已允许的来源）
+    fn allow_origin_value(&self, origin: &str) -> String {
+        if !self.credentials && self.origins.contains(&OriginPattern::Any) {
+            "*".to_string()
+        } else {
+            origin.to_string()
+        }
+    }
+
+    // 预检请求中要求的请求头是否都被允许
+    fn headers_allowed(&self, requested: &str) -> bool {
+        self.headers.iter().any(|h| h == "*")
+            || requested
+                .split(',')
+                .map(str::trim)
+                .filter(|h| !h.is_empty())
+                .all(|h| self.headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(h)))
+    }
+
+    // 应答预检请求
+    fn preflight(&self, origin: &str, method: &str, requested_headers: &str) -> HttpResponse<'static> {
+        if !self.origin_allowed(origin)
+            || !self.methods.iter().any(|m| m == method)
+            || !self.headers_allowed(requested_headers)
+        {
+            return HttpResponse::new("403", Some(HashMap::new()), None);
+        }
+
+        let mut resp = HttpResponse::new("204", Some(HashMap::new()), None);
+        resp.add_header("Access-Control-Allow-Origin", self.allow_origin_value(origin));
+        resp.add_header("Access-Control-Allow-Methods", self.methods.join(", "));
+        if !requested_headers.is_empty() {
+            // 允许任何请求头时回显请求的请求头
+            let allowed = match self.headers.iter().any(|h| h == "*") {
+                true => requested_headers.to_string(),
+                false => self.headers.join(", "),
+            };
+            resp.add_header("Access-Control-Allow-Headers", allowed);
+        }
+        if self.credentials {
+            resp.add_header("Access-Control-Allow-Credentials", "true");
+        }
+        if let Some(max_age) = self.max_age {
+            resp.add_header("Access-Control-Max-Age", max_age.as_secs().to_string());
+        }
+        resp.add_header(
+            "Vary",
+            "Origin, Access-Control-Request-Method, Access-Control-Request-Headers",
+        );
+        resp
+    }
+}
+
+impl Middleware for CorsMiddleware {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        // 只有带 Origin 和 Access-Control-Request-Method 的 OPTIONS 请求才是预检请求
+        if req.method != Method::Options || !self.applies_to(req) {
+            return None;
+        }
+        let origin = req.header("Origin")?;
+        let method = req.header("Access-Control-Request-Method")?;
+        let requested_headers = req.header("Access-Control-Request-Headers").unwrap_or_default();
+        Some(self.preflight(origin, method, requested_headers))
+    }
+
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'_>) {
+        if req.method == Method::Options || !self.applies_to(req) {
+            return;
+        }
+        // 响应内容随 Origin 变化时告知缓存
+        if self.credentials || !self.origins.contains(&OriginPattern::Any) {
+            resp.add_header("Vary", "Origin");
+        }
+        let Some(origin) = req.header("Origin") else {
+            return;
+        };
+        if !self.origin_allowed(origin) {
+            return;
+        }
+        resp.add_header("Access-Control-Allow-Origin", self.allow_origin_value(origin));
+        if self.credentials {
+            resp.add_header("Access-Control-Allow-Credentials", "true");
+        }
+        if !self.expose_headers.is_empty() {
+            resp.add_header("Access-Control-Expose-Headers", self.expose_headers.join(", "));
+        }
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 构造测试用的中间件
+    fn middleware() -> CorsMiddleware {
+        CorsMiddleware::new()
+            .prefix("/api/")
+            .allow_origin("https://app.example.com")
+            .allow_origin("https://*.staging.example.com")
+            .allow_credentials(true)
+            .max_age(Duration::from_secs(600))
+    }
+
+    // 构造带有指定请求头的请求
+    fn request(method: &str, path: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        let headers: String = headers.iter().map(|(k, v)| format!("{}: {}\r\n", k, v)).collect();
+        format!("{} {} HTTP/1.1\r\n{}\r\n", method, path, headers).into()
+    }
+
+    // 测试来源模式匹配
+    #[test]
+    fn test_origin_patterns() {
+        let pattern = OriginPattern::parse("https://*.example.com");
+        assert!(pattern.matches("https://app.example.com"));
+        assert!(pattern.matches("https://a.b.example.com"));
+        assert!(!pattern.matches("https://example.com"));
+        assert!(!pattern.matches("https://evil.com/.example.com"));
+        assert!(!pattern.matches("http://app.example.com"));
+        assert!(OriginPattern::parse("*").matches("http://localhost:8080"));
+    }
+
+    // 测试预检请求被自动应答
+    #[test]
+    fn test_preflight() {
+        let cors = middleware();
+        let mut req = request(
+            "OPTIONS",
+            "/api/shipping/orders/1/status",
+            &[
+                ("Origin", "https://app.example.com"),
+                ("Access-Control-Request-Method", "POST"),
+                ("Access-Control-Request-Headers", "content-type"),
+            ],
+        );
+        let raw: String = cors.before(&mut req).unwrap().into();
+        assert!(raw.starts_with("HTTP/1.1 204 No Content\r\n"));
+        assert!(!raw.contains("Content-Length"));
+        assert!(raw.contains("Access-Control-Allow-Origin:https://app.example.com\r\n"));
+        assert!(raw.contains("Access-Control-Allow-Methods:GET, POST, OPTIONS\r\n"));
+        assert!(raw.contains("Access-Control-Allow-Credentials:true\r\n"));
+        assert!(raw.contains("Access-Control-Max-Age:600\r\n"));
+
+        let mut req = request(
+            "OPTIONS",
+            "/api/shipping/orders",
+            &[("Origin", "https://evil.com"), ("Access-Control-Request-Method", "GET")],
+        );
+        let raw: String = cors.before(&mut req).unwrap().into();
+        assert!(raw.starts_with("HTTP/1.1 403 Forbidden"));
+        assert!(!raw.contains("Access-Control-Allow-Origin"));
+    }
+
+    // 测试实际响应带有 CORS 头部和 Vary: Origin
+    #[test]
+    fn test_actual_response() {
+        let cors = middleware();
+        let req = request("GET", "/api/shipping/orders", &[("Origin", "https://qa.staging.example.com")]);
+        let mut resp = HttpResponse::new("200", None, None);
+        cors.after(&req, &mut resp);
+        let raw: String = resp.into();
+        assert!(raw.contains("Access-Control-Allow-Origin:https://qa.staging.example.com\r\n"));
+        assert!(raw.contains("Vary:Origin\r\n"));
+
+        let req = request("GET", "/api/shipping/orders", &[("Origin", "https://evil.com")]);
+        let mut resp = HttpResponse::new("200", None, None);
+        cors.after(&req, &mut resp);
+        let raw: String = resp.into();
+        assert!(!raw.contains("Access-Control-Allow-Origin"));
+        assert!(raw.contains("Vary:Origin\r\n"));
+
+        let any = CorsMiddleware::new().allow_origin("*");
+        let req = request("GET", "/index.html", &[("Origin", "http://localhost:8080")]);
+        let mut resp = HttpResponse::new("200", None, None);
+        any.after(&req, &mut resp);
+        let raw: String = resp.into();
+        assert!(raw.contains("Access-Control-Allow-Origin:*\r\n"));
+        assert!(!raw.contains("Vary"));
+
+        // 允许凭据时 * 不会变成回显任意来源
+        let any = CorsMiddleware::new().allow_origin("*").allow_credentials(true);
+        let mut resp = HttpResponse::new("200", None, None);
+        any.after(&req, &mut resp);
+        let raw: String = resp.into();
+        assert!(!raw.contains("Access-Control-Allow-Origin"));
+    }
+}
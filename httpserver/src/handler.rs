@@ -1,21 +1,39 @@
 // 导入所需的库和模块
-use http::{httprequest::HttpRequest, httpresponse::HttpResponse}; // 导入 HTTP 请求和响应模块
+use super::order_events::order_feed; // 导入订单事件源
+use super::repository::{data_path, order_repository, RepositoryError}; // 导入订单仓库
+use super::session::SessionExt; // 导入会话读取方法
+use super::vhost::SiteFiles; // 导入站点文件
+use http::httprequest::{HttpRequest, Method}; // 导入 HTTP 请求模块
+use http::{httpresponse::HttpResponse, json::JsonError, sse}; // 导入 HTTP 响应、JSON 错误和事件流模块
 use serde::{Deserialize, Serialize}; // 导入序列化和反序列化库
 use std::collections::HashMap; // 导入 HashMap
-use std::env; // 导入环境变量模块
-use std::fs; // 导入文件系统模块
+use std::fs::{self, File}; // 导入文件系统模块
+use std::io::{self, Write}; // 导入 IO 模块和写入特性
+use std::sync::mpsc::RecvTimeoutError; // 导入通道超时错误
+use std::sync::OnceLock; // 导入一次性初始化
+use std::thread; // 导入线程模块
+use std::time::Duration; // 导入时间间隔
+
+// 订单事件流没有事件时发送心跳的间隔
+const EVENTS_HEARTBEAT: Duration = Duration::from_secs(15);
+
+// 建议事件流客户端断开后重连的等待时间（毫秒）
+const EVENTS_RETRY_MS: u64 = 3000;
 
 // 定义 Handler 特性，包含处理请求的方法
 pub trait Handler {
     // 处理 HTTP 请求的方法
-    fn handle(req: &HttpRequest) -> HttpResponse;
+    fn handle(req: &HttpRequest) -> HttpResponse<'_>;
 
-    // 加载文件的方法
-    fn load_file(file_name: &str) -> Option<String> {
+    // 加载文件的方法，虚拟主机配置了静态文件目录时从站点的目录加载
+    fn load_file(req: &HttpRequest, file_name: &str) -> Option<String> {
         // 默认的公共路径
         let default_path = format!("{}/public", env!("CARGO_MANIFEST_DIR"));
-        // 从环境变量中获取 PUBLIC_PATH，如果不存在则使用默认路径
-        let public_path = env::var("PUBLIC_PATH").unwrap_or(default_path);
+        // 使用站点的目录（由配置决定），没有站点时使用默认路径
+        let public_path = match req.extensions.get::<SiteFiles>().and_then(|site| site.public.as_ref()) {
+            Some(dir) => dir.to_string_lossy().into_owned(),
+            None => default_path,
+        };
         // 构建文件的完整路径
         let full_path = format!("{}/{}", public_path, file_name);
 
@@ -23,14 +41,14 @@ pub trait Handler {
         let contents = fs::read_to_string(full_path);
         contents.ok()
     }
-}
 
-// 定义 OrderStatus 结构体，用于序列化和反序列化订单状态
-#[derive(Serialize, Deserialize)]
-pub struct OrderStatus {
-    order_id: i32,         // 订单 ID
-    order_date: String,    // 订单日期
-    order_status: String,   // 订单状态
+    // 加载 404 页面：虚拟主机配置的 404 页面，或静态文件目录中的 404.html
+    fn load_not_found(req: &HttpRequest) -> Option<String> {
+        match req.extensions.get::<SiteFiles>().and_then(|site| site.not_found.as_ref()) {
+            Some(page) => fs::read_to_string(page).ok(),
+            None => Self::load_file(req, "404.html"),
+        }
+    }
 }
 
 // 定义处理器结构体
@@ -40,24 +58,24 @@ pub struct WebServiceHandler; // 处理 Web 服务请求的处理器
 
 // 实现 PageNotFoundHandler 的 Handler 特性
 impl Handler for PageNotFoundHandler {
-    fn handle(_req: &HttpRequest) -> HttpResponse {
-        // 当找不到页面时，返回 404 响应，并加载 404.html 文件
-        HttpResponse::new("404", None, Self::load_file("404.html"))
+    fn handle(req: &HttpRequest) -> HttpResponse<'_> {
+        // 当找不到页面时，返回 404 响应，并加载 404 页面
+        HttpResponse::new("404", None, Self::load_not_found(req))
     }
 }
 
 // 实现 StaticPageHandler 的 Handler 特性
 impl Handler for StaticPageHandler {
-    fn handle(req: &HttpRequest) -> HttpResponse {
+    fn handle(req: &HttpRequest) -> HttpResponse<'_> {
         // 获取请求的静态页面资源的路径
         let http::httprequest::Resource::Path(s) = &req.resource;
 
         // 解析 URI
         let route: Vec<&str> = s.split("/").collect();
-        match route[1] {
-            "" => HttpResponse::new("200", None, Self::load_file("index.html")), // 根路径请求，返回 index.html
-            "health" => HttpResponse::new("200", None, Self::load_file("health.html")), // health 路径请求，返回 health.html
-            path => match Self::load_file(path) { // 对其他路径请求，尝试加载对应的文件
+        match route.get(1).copied() {
+            Some("") => HttpResponse::new("200", None, Self::load_file(req, "index.html")), // 根路径请求，返回 index.html
+            Some("health") => HttpResponse::new("200", None, Self::load_file(req, "health.html")), // health 路径请求，返回 health.html
+            Some(path) => match Self::load_file(req, path) { // 对其他路径请求，尝试加载对应的文件
                 Some(contents) => {
                     let mut map: HashMap<&str, &str> = HashMap::new(); // 创建请求头的 HashMap
                     // 根据文件类型设置 Content-Type
@@ -71,47 +89,431 @@ impl Handler for StaticPageHandler {
                     // 返回 200 响应和文件内容
                     HttpResponse::new("200", Some(map), Some(contents))
                 }
-                None => HttpResponse::new("404", None, Self::load_file("404.html")), // 文件未找到，返回 404 响应
+                None => HttpResponse::new("404", None, Self::load_not_found(req)), // 文件未找到，返回 404 响应
             },
+            None => HttpResponse::new("404", None, Self::load_not_found(req)), // 不以 / 开头的路径，返回 404 响应
         }
     }
 }
 
-// 为 WebServiceHandler 定义 load_json() 方法，用于从磁盘加载 orders.json 文件
+// 定义更新订单状态的请求体
+#[derive(Deserialize)]
+pub struct StatusUpdate {
+    pub order_status: String, // 新的订单状态
+}
+
+// 为 WebServiceHandler 定义辅助方法
 impl WebServiceHandler {
-    fn load_json() -> Vec<OrderStatus> {
-        // 默认的数据路径
-        let default_path = format!("{}/data", env!("CARGO_MANIFEST_DIR"));
-        // 从环境变量中获取 DATA_PATH，如果不存在则使用默认路径
-        let data_path = env::var("DATA_PATH").unwrap_or(default_path);
-        // 构建 JSON 文件的完整路径
-        let full_path = format!("{}/{}", data_path, "orders.json");
-        // 尝试读取 JSON 文件内容并解析为 OrderStatus 结构体的向量
-        let json_contents = fs::read_to_string(full_path);
-        let orders: Vec<OrderStatus> =
-            serde_json::from_str(json_contents.unwrap().as_str()).unwrap(); // 解析 JSON
-        orders // 返回订单状态列表
+    // 将数据序列化为 JSON 响应
+    fn json_response<'a>(status_code: &'a str, data: &impl Serialize) -> HttpResponse<'a> {
+        let body = Some(serde_json::to_string(data).unwrap());
+        let mut headers: HashMap<&str, &str> = HashMap::new(); // 创建请求头的 HashMap
+        headers.insert("Content-Type", "application/json"); // 设置 Content-Type 为 application/json
+        HttpResponse::new(status_code, Some(headers), body)
+    }
+
+    // 处理 POST /api/shipping/orders/{id}/status，更新订单状态
+    fn update_order_status<'a>(req: &HttpRequest, order_id: &str) -> HttpResponse<'a> {
+        let order_id: i32 = match order_id.parse() {
+            Ok(id) => id,
+            Err(_) => return HttpResponse::new("404", None, Self::load_not_found(req)),
+        };
+        // 浏览器表单提交 application/x-www-form-urlencoded，其他客户端提交 JSON
+        let is_form = req
+            .header("Content-Type")
+            .is_some_and(|ct| ct.starts_with("application/x-www-form-urlencoded"));
+        let update: StatusUpdate = if is_form {
+            match req.form() {
+                Ok(form) => StatusUpdate {
+                    order_status: form.get("order_status").unwrap_or_default().to_string(),
+                },
+                Err(e) => return HttpResponse::new(e.status_code(), None, None),
+            }
+        } else {
+            match req.json() {
+                Ok(update) => update,
+                Err(e) => return e.to_response(),
+            }
+        };
+        if update.order_status.trim().is_empty() {
+            return JsonError::Validation {
+                field: Some("order_status".into()),
+                message: "must not be empty".into(),
+            }
+            .to_response();
+        }
+
+        match order_repository().and_then(|repo| repo.update_status(order_id, &update.order_status)) {
+            Ok(order) => {
+                order_feed().notify();
+                Self::json_response("200", &order)
+            }
+            Err(RepositoryError::NotFound(_)) => HttpResponse::new("404", None, Self::load_not_found(req)),
+            Err(e) => {
+                eprintln!("Failed to update order {}: {}", order_id, e);
+                HttpResponse::new("500", None, None)
+            }
+        }
+    }
+}
+
+// 为 WebServiceHandler 定义导出订单的方法
+impl WebServiceHandler {
+    // 处理 GET /api/shipping/orders/export，以 CSV 格式流式导出订单
+    // 数据由后台线程逐行写入，写完后通过尾部头部 X-Order-Count 报告行数
+    fn export_orders<'a>() -> HttpResponse<'a> {
+        let orders = match order_repository().and_then(|repo| repo.list_orders()) {
+            Ok(orders) => orders,
+            Err(e) => {
+                eprintln!("Failed to load orders: {}", e);
+                return HttpResponse::new("500", None, None);
+            }
+        };
+        let mut headers: HashMap<&str, &str> = HashMap::new();
+        headers.insert("Content-Type", "text/csv");
+        headers.insert("Trailer", "X-Order-Count");
+        let (resp, mut writer) = HttpResponse::streaming("200", Some(headers));
+        thread::spawn(move || -> std::io::Result<()> {
+            writeln!(writer, "order_id,order_date,order_status")?;
+            for order in &orders {
+                writeln!(
+                    writer,
+                    "{},{},{}",
+                    order.order_id,
+                    csv_field(&order.order_date),
+                    csv_field(&order.order_status)
+                )?;
+            }
+            writer.trailer("X-Order-Count", orders.len().to_string());
+            writer.finish()
+        });
+        resp
+    }
+}
+
+// 为 WebServiceHandler 定义订单事件流的方法
+impl WebServiceHandler {
+    // 处理 GET /api/shipping/orders/events，以服务器发送事件推送订单变化
+    // 重连时根据 Last-Event-ID 补发错过的事件，无法续传时先发送全部订单的快照
+    fn order_events<'a>(req: &HttpRequest) -> HttpResponse<'a> {
+        let (initial, events) = order_feed().subscribe(req.header("Last-Event-ID"));
+        let (resp, mut sender) = sse::event_stream();
+        thread::spawn(move || -> std::io::Result<()> {
+            sender.retry(EVENTS_RETRY_MS)?;
+            for event in &initial {
+                sender.send(event)?;
+            }
+            // 客户端断开后发送失败，线程随之退出
+            loop {
+                match events.recv_timeout(EVENTS_HEARTBEAT) {
+                    Ok(event) => sender.send(&event)?,
+                    Err(RecvTimeoutError::Timeout) => sender.heartbeat()?,
+                    // 客户端太慢被事件源移除，结束事件流让它重连续传
+                    Err(RecvTimeoutError::Disconnected) => return sender.close(),
+                }
+            }
+        });
+        resp
+    }
+}
+
+// 转义 CSV 字段，包含逗号、引号或换行时用引号包围
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// 定义上传成功后返回的文件信息
+#[derive(Serialize)]
+pub struct UploadedFile {
+    pub field: String,                // 表单字段名
+    pub filename: String,             // 保存后的文件名
+    pub content_type: Option<String>, // 文件的 Content-Type
+    pub size: usize,                  // 文件大小
+}
+
+impl WebServiceHandler {
+    // 处理 POST /api/uploads，将 multipart 表单中的文件保存到数据目录的 uploads 子目录
+    // 同名的文件不会被覆盖，保存后的文件名在响应中返回
+    // 请求体在交给处理器之前已经完整读入内存，较大的文件分段经临时文件移动到目标位置，但不会降低请求占用的内存
+    fn save_uploads<'a>(req: &HttpRequest) -> HttpResponse<'a> {
+        let form = match req.multipart() {
+            Ok(form) => form,
+            Err(e) => return HttpResponse::new(e.status_code(), None, Some(e.to_string())),
+        };
+        let upload_dir = format!("{}/uploads", data_path().display());
+        if let Err(e) = fs::create_dir_all(&upload_dir) {
+            eprintln!("Failed to create {}: {}", upload_dir, e);
+            return HttpResponse::new("500", None, None);
+        }
+
+        let mut saved = Vec::new();
+        for part in form.files {
+            let filename = match reserve_filename(&upload_dir, &sanitize_filename(part.filename.as_deref().unwrap_or_default())) {
+                Ok(filename) => filename,
+                Err(e) => {
+                    eprintln!("Failed to create upload file in {}: {}", upload_dir, e);
+                    return HttpResponse::new("500", None, None);
+                }
+            };
+            let info = UploadedFile {
+                field: part.name.clone(),
+                filename: filename.clone(),
+                content_type: part.content_type.clone(),
+                size: part.size,
+            };
+            if let Err(e) = part.save_to(format!("{}/{}", upload_dir, filename)) {
+                eprintln!("Failed to save upload {}: {}", filename, e);
+                return HttpResponse::new("500", None, None);
+            }
+            saved.push(info);
+        }
+        Self::json_response("200", &saved)
+    }
+}
+
+// 只保留上传文件名的最后一段，并替换可能引起路径问题的字符
+fn sanitize_filename(filename: &str) -> String {
+    let base = filename.rsplit(['/', '\\']).next().unwrap_or_default();
+    let cleaned: String = base
+        .chars()
+        .map(|c| if c.is_alphanumeric() || "._-".contains(c) { c } else { '_' })
+        .collect();
+    match cleaned.trim_start_matches('.') {
+        "" => "upload".to_string(),
+        name => name.to_string(),
+    }
+}
+
+// 在目录中为上传的文件选择一个还不存在的文件名，并创建空文件占位，避免并发的上传使用同一个名称
+// 同名文件已存在时在扩展名前加上 -1、-2 等序号
+fn reserve_filename(dir: &str, filename: &str) -> io::Result<String> {
+    let (stem, extension) = match filename.rfind('.') {
+        Some(pos) if pos > 0 => filename.split_at(pos),
+        _ => (filename, ""),
+    };
+    let mut n = 0;
+    loop {
+        let name = match n {
+            0 => filename.to_string(),
+            _ => format!("{}-{}{}", stem, n, extension),
+        };
+        match File::options().write(true).create_new(true).open(format!("{}/{}", dir, name)) {
+            Ok(_) => return Ok(name),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => n += 1,
+            Err(e) => return Err(e),
+        }
     }
 }
 
 // 实现 WebServiceHandler 的 Handler 特性
 impl Handler for WebServiceHandler {
-    fn handle(req: &HttpRequest) -> HttpResponse {
+    fn handle(req: &HttpRequest) -> HttpResponse<'_> {
         // 获取请求资源的路径
         let http::httprequest::Resource::Path(s) = &req.resource;
 
         // 解析 URI
-        let route: Vec<&str> = s.split("/").collect();
-        // 如果请求路径是 /api/shipping/orders，返回 JSON 数据
-        match route[2] {
-            "shipping" if route.len() > 2 && route[3] == "orders" => {
-                // 加载订单数据并将其序列化为 JSON 字符串
-                let body = Some(serde_json::to_string(&Self::load_json()).unwrap());
-                let mut headers: HashMap<&str, &str> = HashMap::new(); // 创建请求头的 HashMap
-                headers.insert("Content-Type", "application/json"); // 设置 Content-Type 为 application/json
-                HttpResponse::new("200", Some(headers), body) // 返回 200 响应和 JSON 数据
-            }
-            _ => HttpResponse::new("404", None, Self::load_file("404.html")), // 其他请求返回 404 响应
+        let route: Vec<&str> = s.trim_end_matches('/').split("/").collect();
+        match (&req.method, &route[1..]) {
+            // GET /api/shipping/orders，返回 JSON 数据
+            (Method::Get, ["api", "shipping", "orders"]) => {
+                // 从订单仓库加载订单数据
+                match order_repository().and_then(|repo| repo.list_orders()) {
+                    Ok(orders) => Self::json_response("200", &orders), // 返回 200 响应和 JSON 数据
+                    Err(e) => {
+                        eprintln!("Failed to load orders: {}", e);
+                        HttpResponse::new("500", None, None)
+                    }
+                }
+            }
+            // GET /api/shipping/orders/export，流式导出 CSV
+            (Method::Get, ["api", "shipping", "orders", "export"]) => Self::export_orders(),
+            // GET /api/shipping/orders/events，订单变化的事件流
+            (Method::Get, ["api", "shipping", "orders", "events"]) => Self::order_events(req),
+            // POST /api/shipping/orders/{id}/status，更新订单状态
+            (Method::Post, ["api", "shipping", "orders", order_id, "status"]) => {
+                Self::update_order_status(req, order_id)
+            }
+            // POST /api/uploads，保存上传的文件
+            (Method::Post, ["api", "uploads"]) => Self::save_uploads(req),
+            _ => HttpResponse::new("404", None, Self::load_not_found(req)), // 其他请求返回 404 响应
         }
     }
 }
+
+// 转义 HTML 特殊字符，防止订单数据被当作标签解析
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+// 以常量时间比较两个字符串，避免通过响应时间猜测密码
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0u8, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+// 返回重定向到指定地址的 303 响应
+fn redirect(location: &str) -> HttpResponse<'static> {
+    let mut resp = HttpResponse::new("303", None, None);
+    resp.add_header("Location", location);
+    resp
+}
+
+// 订单管理页面的账号（用户名，密码），由 init_admin 按配置设置
+static ADMIN_ACCOUNT: OnceLock<(String, String)> = OnceLock::new();
+
+// 设置订单管理页面的账号，main 按 [admin] 在启动时调用一次，之后再调用不会改变账号
+pub fn init_admin(username: impl Into<String>, password: impl Into<String>) {
+    let _ = ADMIN_ACCOUNT.set((username.into(), password.into()));
+}
+
+// 处理订单管理页面的处理器，需要先登录
+pub struct AdminHandler;
+
+impl AdminHandler {
+    // 检查管理员用户名和密码，账号由 init_admin 设置
+    fn check_credentials(username: &str, password: &str) -> bool {
+        match ADMIN_ACCOUNT.get() {
+            Some((u, p)) if !p.is_empty() => {
+                constant_time_eq(u, username) & constant_time_eq(p, password)
+            }
+            _ => false, // 未配置账号时不允许登录
+        }
+    }
+
+    // 返回当前登录的管理员用户名
+    fn current_user(req: &HttpRequest) -> Option<String> {
+        req.session().and_then(|s| s.get::<String>("user"))
+    }
+
+    // 生成登录页面
+    fn login_page(error: Option<&str>) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\" /><title>Admin login</title></head>\n<body>\n<h1>Admin login</h1>\n{}<form method=\"post\" action=\"/admin/login\">\n<input name=\"username\" placeholder=\"Username\" />\n<input name=\"password\" type=\"password\" placeholder=\"Password\" />\n<button type=\"submit\">Log in</button>\n</form>\n</body>\n</html>",
+            error.map(|e| format!("<p>{}</p>\n", escape_html(e))).unwrap_or_default()
+        )
+    }
+
+    // 生成订单管理页面，每个订单都有一个修改状态的表单
+    fn orders_page(user: &str) -> Option<String> {
+        let orders = order_repository().and_then(|repo| repo.list_orders()).ok()?;
+        let rows: String = orders
+            .iter()
+            .map(|o| {
+                format!(
+                    "<tr><td>{id}</td><td>{date}</td><td><form method=\"post\" action=\"/admin/orders/{id}/status\"><input name=\"order_status\" value=\"{status}\" /><button type=\"submit\">Update</button></form></td></tr>\n",
+                    id = o.order_id,
+                    date = escape_html(&o.order_date),
+                    status = escape_html(&o.order_status)
+                )
+            })
+            .collect();
+        Some(format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head><meta charset=\"utf-8\" /><title>Orders</title></head>\n<body>\n<h1>Orders</h1>\n<p>Logged in as {}</p>\n<form method=\"post\" action=\"/admin/logout\"><button type=\"submit\">Log out</button></form>\n<table>\n<tr><th>ID</th><th>Date</th><th>Status</th></tr>\n{}</table>\n</body>\n</html>",
+            escape_html(user),
+            rows
+        ))
+    }
+}
+
+// 实现 AdminHandler 的 Handler 特性
+impl Handler for AdminHandler {
+    fn handle(req: &HttpRequest) -> HttpResponse<'_> {
+        let http::httprequest::Resource::Path(s) = &req.resource;
+        let route: Vec<&str> = s.trim_end_matches('/').split("/").collect();
+        let user = Self::current_user(req);
+
+        match (&req.method, &route[1..], user) {
+            // 已登录时显示订单管理页面，否则显示登录页面
+            (Method::Get, ["admin"], Some(user)) => match Self::orders_page(&user) {
+                Some(page) => HttpResponse::new("200", None, Some(page)),
+                None => HttpResponse::new("500", None, None),
+            },
+            (Method::Get, ["admin"], None) => {
+                HttpResponse::new("200", None, Some(Self::login_page(None)))
+            }
+            // 登录成功后更换会话 ID 并记录用户名
+            (Method::Post, ["admin", "login"], _) => {
+                let form = req.form().unwrap_or_default();
+                let username = form.get("username").unwrap_or_default();
+                let password = form.get("password").unwrap_or_default();
+                match req.session() {
+                    Some(session) if Self::check_credentials(username, password) => {
+                        session.rotate_id();
+                        session.insert("user", username);
+                        redirect("/admin")
+                    }
+                    _ => HttpResponse::new(
+                        "403",
+                        None,
+                        Some(Self::login_page(Some("Invalid username or password"))),
+                    ),
+                }
+            }
+            // 退出登录时销毁会话
+            (Method::Post, ["admin", "logout"], _) => {
+                if let Some(session) = req.session() {
+                    session.destroy();
+                }
+                redirect("/admin")
+            }
+            // 修改订单状态，需要已登录
+            (Method::Post, ["admin", "orders", order_id, "status"], Some(_)) => {
+                let order_status = req.form().unwrap_or_default();
+                let order_status = order_status.get("order_status").unwrap_or_default().trim();
+                match order_id.parse::<i32>() {
+                    Ok(order_id) if !order_status.is_empty() => {
+                        match order_repository().and_then(|repo| repo.update_status(order_id, order_status)) {
+                            Ok(_) => {
+                                order_feed().notify();
+                                redirect("/admin")
+                            }
+                            Err(RepositoryError::NotFound(_)) => PageNotFoundHandler::handle(req),
+                            Err(e) => {
+                                eprintln!("Failed to update order {}: {}", order_id, e);
+                                HttpResponse::new("500", None, None)
+                            }
+                        }
+                    }
+                    _ => HttpResponse::new("400", None, None),
+                }
+            }
+            (Method::Post, ["admin", ..], None) => redirect("/admin"),
+            _ => PageNotFoundHandler::handle(req),
+        }
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 测试上传文件名的清理和同名文件的重命名
+    #[test]
+    fn test_upload_filenames() {
+        assert_eq!(sanitize_filename("../../etc/passwd"), "passwd");
+        assert_eq!(sanitize_filename("C:\\a b.txt"), "a_b.txt");
+        assert_eq!(sanitize_filename(".."), "upload");
+
+        let dir = std::env::temp_dir().join(format!("httpserver-uploads-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.to_string_lossy().into_owned();
+        assert_eq!(reserve_filename(&dir, "a.txt").unwrap(), "a.txt");
+        assert_eq!(reserve_filename(&dir, "a.txt").unwrap(), "a-1.txt");
+        assert_eq!(reserve_filename(&dir, "a.txt").unwrap(), "a-2.txt");
+        assert_eq!(reserve_filename(&dir, "upload").unwrap(), "upload");
+        assert_eq!(reserve_filename(&dir, "upload").unwrap(), "upload-1");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,503 @@
+// 导入所需的库和模块
+use super::middleware::Middleware; // 导入中间件
+use super::repository::order_repository; // 导入订单仓库
+use super::upstream::UpstreamPool; // 导入上游池
+use http::httprequest::{HttpRequest, Method, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use serde::Serialize; // 导入序列化特性
+use std::collections::HashMap; // 导入 HashMap
+use std::net::{TcpStream, ToSocketAddrs}; // 导入 TCP 连接
+use std::sync::{Arc, Mutex}; // 导入共享指针和互斥锁
+use std::thread; // 导入线程模块
+use std::time::{Duration, Instant}; // 导入时间模块
+
+// 默认的存活检查路径
+const DEFAULT_LIVENESS_PATH: &str = "/healthz";
+
+// 默认的就绪检查路径
+const DEFAULT_READINESS_PATH: &str = "/readyz";
+
+// 磁盘剩余空间低于这个值时就绪检查失败
+pub const DEFAULT_MIN_FREE_BYTES: u64 = 100 * 1024 * 1024;
+
+// 检查上游是否可以连接的超时
+const UPSTREAM_CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 默认的检查结果缓存时间，探针频繁请求时不必每次都重新检查
+const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(5);
+
+// 定义检查的状态，汇总时取最差的状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass, // 正常
+    Warn, // 可以继续服务，但需要关注，不影响状态码
+    Fail, // 不能正常服务，返回 503
+}
+
+// 一次检查的结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub status: Status,         // 状态
+    pub detail: Option<String>, // 附加说明
+}
+
+impl Outcome {
+    // 检查通过
+    pub fn pass() -> Self {
+        Outcome {
+            status: Status::Pass,
+            detail: None,
+        }
+    }
+
+    // 需要关注
+    pub fn warn(detail: impl Into<String>) -> Self {
+        Outcome {
+            status: Status::Warn,
+            detail: Some(detail.into()),
+        }
+    }
+
+    // 检查失败
+    pub fn fail(detail: impl Into<String>) -> Self {
+        Outcome {
+            status: Status::Fail,
+            detail: Some(detail.into()),
+        }
+    }
+
+    // 附加说明
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+}
+
+// 定义健康检查特性，请求健康检查端点且缓存的结果过期时调用，多个检查并行执行
+pub trait HealthCheck: Send + Sync {
+    fn check(&self) -> Outcome;
+}
+
+// 闭包可以直接作为健康检查
+impl<F: Fn() -> Outcome + Send + Sync> HealthCheck for F {
+    fn check(&self) -> Outcome {
+        self()
+    }
+}
+
+// 订单数据检查：订单仓库能否打开并读出全部订单
+pub struct OrderDataCheck;
+
+impl HealthCheck for OrderDataCheck {
+    fn check(&self) -> Outcome {
+        match order_repository().and_then(|repo| repo.list_orders()) {
+            Ok(orders) => Outcome::pass().detail(format!("{} orders", orders.len())),
+            Err(e) => Outcome::fail(e.to_string()),
+        }
+    }
+}
+
+// 磁盘空间检查：路径所在文件系统的剩余空间低于下限时失败，低于两倍下限时警告
+pub struct DiskSpaceCheck {
+    path: String,        // 检查的路径
+    min_free_bytes: u64, // 剩余空间的下限
+}
+
+impl DiskSpaceCheck {
+    // 检查 path 所在文件系统的剩余空间
+    pub fn new(path: impl Into<String>, min_free_bytes: u64) -> Self {
+        DiskSpaceCheck {
+            path: path.into(),
+            min_free_bytes,
+        }
+    }
+
+}
+
+impl HealthCheck for DiskSpaceCheck {
+    fn check(&self) -> Outcome {
+        let free = match free_space(&self.path) {
+            Ok(free) => free,
+            Err(e) => return Outcome::fail(format!("{}: {}", self.path, e)),
+        };
+        let detail = format!("{} MB free on {}", free / 1024 / 1024, self.path);
+        match free {
+            free if free < self.min_free_bytes => Outcome::fail(detail),
+            free if free < self.min_free_bytes.saturating_mul(2) => Outcome::warn(detail),
+            _ => Outcome::pass().detail(detail),
+        }
+    }
+}
+
+// 路径所在文件系统中非特权用户可用的字节数
+#[cfg(unix)]
+fn free_space(path: &str) -> std::io::Result<u64> {
+    let path = std::ffi::CString::new(path).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: path 是以 NUL 结尾的字符串，stat 是可写的 statvfs 结构体
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+// 其他平台不支持查询剩余空间
+#[cfg(not(unix))]
+fn free_space(_path: &str) -> std::io::Result<u64> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "disk space check is not supported"))
+}
+
+// 上游检查：代理的每条路由至少有一个上游可以连接，部分上游不可用时警告
+// 配置了主动健康检查的上游池直接使用池中的健康状态，不再另外连接
+pub struct UpstreamCheck {
+    pools: Vec<(String, Arc<UpstreamPool>)>, // （路由前缀，上游池）
+}
+
+impl UpstreamCheck {
+    // 检查代理各路由的上游池
+    pub fn new(pools: Vec<(String, Arc<UpstreamPool>)>) -> Self {
+        UpstreamCheck { pools }
+    }
+}
+
+impl HealthCheck for UpstreamCheck {
+    fn check(&self) -> Outcome {
+        let mut down = Vec::new();
+        let mut unreachable_routes = Vec::new();
+        for (prefix, pool) in &self.pools {
+            let upstreams = pool.status();
+            // 被健康检查或被动摘除移出轮换的上游不再尝试连接
+            let reachable: Vec<bool> = upstreams
+                .iter()
+                .map(|upstream| {
+                    upstream.available && (pool.has_health_check() || can_connect(&upstream.url.authority()))
+                })
+                .collect();
+            for (upstream, ok) in upstreams.iter().zip(&reachable) {
+                if !ok {
+                    down.push(upstream.url.to_string());
+                }
+            }
+            if !reachable.contains(&true) {
+                unreachable_routes.push(if prefix.is_empty() { "/" } else { prefix.as_str() });
+            }
+        }
+        match (unreachable_routes.is_empty(), down.is_empty()) {
+            (false, _) => Outcome::fail(format!("no reachable upstream for {}", unreachable_routes.join(", "))),
+            (true, false) => Outcome::warn(format!("unreachable: {}", down.join(", "))),
+            (true, true) => Outcome::pass(),
+        }
+    }
+}
+
+// 能否在超时内连接到地址
+fn can_connect(authority: &str) -> bool {
+    authority.to_socket_addrs().is_ok_and(|mut addrs| {
+        addrs.any(|addr| TcpStream::connect_timeout(&addr, UPSTREAM_CONNECT_TIMEOUT).is_ok())
+    })
+}
+
+// 一项检查的报告
+#[derive(Debug, Serialize)]
+struct CheckReport {
+    name: String,           // 检查名称
+    status: Status,         // 状态
+    duration_ms: f64,       // 检查耗时（毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>, // 附加说明
+}
+
+// 健康检查端点的响应
+#[derive(Debug, Serialize)]
+struct Report {
+    status: Status,           // 汇总的状态
+    duration_ms: f64,         // 全部检查的耗时（毫秒）
+    checks: Vec<CheckReport>, // 各项检查
+}
+
+// 一组命名的检查
+type Checks = Vec<(String, Box<dyn HealthCheck>)>;
+
+// 缓存的检查结果（检查时间，状态码，响应体）
+type Cached = Option<(Instant, &'static str, String)>;
+
+// 一个健康检查端点
+struct Endpoint {
+    path: String,          // 路径
+    checks: Checks,        // 检查
+    cached: Mutex<Cached>, // 缓存的检查结果
+}
+
+impl Endpoint {
+    fn new(path: &str) -> Self {
+        Endpoint {
+            path: path.to_string(),
+            checks: Vec::new(),
+            cached: Mutex::new(None),
+        }
+    }
+
+    // 返回状态码和响应体，缓存过期时重新检查
+    // 检查期间持有锁，同时到达的请求等待这次检查的结果，不会并发重复检查
+    fn respond(&self, ttl: Duration) -> (&'static str, String) {
+        let mut cached = self.cached.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((at, status, body)) = cached.as_ref() {
+            if at.elapsed() < ttl {
+                return (status, body.clone());
+            }
+        }
+        let report = HealthMiddleware::run(&self.checks);
+        let status = match report.status {
+            Status::Fail => "503",
+            _ => "200",
+        };
+        let body = serde_json::to_string(&report).unwrap_or_default();
+        *cached = Some((Instant::now(), status, body.clone()));
+        (status, body)
+    }
+
+    // 添加检查，之前缓存的结果作废
+    fn add(&mut self, name: &str, check: Box<dyn HealthCheck>) {
+        self.checks.push((name.to_string(), check));
+        *self.cached.get_mut().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+// 健康检查中间件：在存活检查和就绪检查的路径上返回 JSON 报告
+// 全部检查通过或只有警告时返回 200，任何一项失败时返回 503
+// 应该注册在认证和限流之前，编排系统的探针不需要凭据也不应被限流
+// 检查结果在缓存时间内复用，端点不需要认证，频繁请求也不会反复访问订单仓库和上游
+pub struct HealthMiddleware {
+    liveness: Endpoint,  // 存活检查，没有检查时只要进程能响应就通过
+    readiness: Endpoint, // 就绪检查
+    cache_ttl: Duration, // 检查结果的缓存时间
+}
+
+impl Default for HealthMiddleware {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HealthMiddleware {
+    // 在 /healthz 和 /readyz 上提供没有检查的端点
+    pub fn new() -> Self {
+        HealthMiddleware {
+            liveness: Endpoint::new(DEFAULT_LIVENESS_PATH),
+            readiness: Endpoint::new(DEFAULT_READINESS_PATH),
+            cache_ttl: DEFAULT_CACHE_TTL,
+        }
+    }
+
+    // 设置存活检查和就绪检查的路径
+    pub fn paths(mut self, liveness: &str, readiness: &str) -> Self {
+        self.liveness.path = liveness.to_string();
+        self.readiness.path = readiness.to_string();
+        self
+    }
+
+    // 设置检查结果的缓存时间，为零时每次请求都重新检查
+    pub fn cache_ttl(mut self, ttl: Duration) -> Self {
+        self.cache_ttl = ttl;
+        self
+    }
+
+    // 添加存活检查，失败时编排系统会重启进程，只应检查进程自身的状态
+    pub fn liveness(mut self, name: &str, check: impl HealthCheck + 'static) -> Self {
+        self.liveness.add(name, Box::new(check));
+        self
+    }
+
+    // 添加就绪检查，失败时编排系统暂停向这个实例分发流量
+    pub fn readiness(mut self, name: &str, check: impl HealthCheck + 'static) -> Self {
+        self.readiness.add(name, Box::new(check));
+        self
+    }
+
+    // 并行执行检查并汇总
+    fn run(checks: &Checks) -> Report {
+        let started = Instant::now();
+        let reports: Vec<CheckReport> = thread::scope(|scope| {
+            let handles: Vec<_> = checks
+                .iter()
+                .map(|(name, check)| {
+                    scope.spawn(move || {
+                        let started = Instant::now();
+                        let outcome = check.check();
+                        CheckReport {
+                            name: name.clone(),
+                            status: outcome.status,
+                            duration_ms: millis(started),
+                            detail: outcome.detail,
+                        }
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .zip(checks)
+                .map(|(handle, (name, _))| {
+                    // 检查崩溃时按失败处理
+                    handle.join().unwrap_or_else(|_| CheckReport {
+                        name: name.clone(),
+                        status: Status::Fail,
+                        duration_ms: 0.0,
+                        detail: Some("check panicked".to_string()),
+                    })
+                })
+                .collect()
+        });
+        Report {
+            status: reports.iter().map(|r| r.status).max().unwrap_or(Status::Pass),
+            duration_ms: millis(started),
+            checks: reports,
+        }
+    }
+}
+
+impl Middleware for HealthMiddleware {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let Resource::Path(path) = &req.resource;
+        let path = path.split('?').next().unwrap_or_default();
+        let endpoint = match path {
+            p if p == self.liveness.path => &self.liveness,
+            p if p == self.readiness.path => &self.readiness,
+            _ => return None,
+        };
+        if !matches!(req.method, Method::Get | Method::Head) {
+            let mut resp = HttpResponse::new("405", None, None);
+            resp.add_header("Allow", "GET, HEAD");
+            return Some(resp);
+        }
+        let (status, body) = endpoint.respond(self.cache_ttl);
+        let mut headers = HashMap::new();
+        headers.insert("Content-Type", "application/json");
+        headers.insert("Cache-Control", "no-store");
+        Some(HttpResponse::new(status, Some(headers), Some(body)))
+    }
+}
+
+// 从 started 到现在的毫秒数，保留三位小数
+fn millis(started: Instant) -> f64 {
+    (started.elapsed().as_secs_f64() * 1_000_000.0).round() / 1000.0
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::client::Url;
+    use std::net::TcpListener;
+
+    // 请求健康检查端点，返回状态码和解析后的报告
+    fn probe(health: &HealthMiddleware, path: &str) -> (String, serde_json::Value) {
+        let mut req: HttpRequest = format!("GET {} HTTP/1.1\r\n\r\n", path).into();
+        let resp = health.before(&mut req).unwrap();
+        (resp.status_code().to_string(), serde_json::from_str(resp.body()).unwrap())
+    }
+
+    // 测试汇总状态：警告仍返回 200，任何失败返回 503，检查按注册顺序报告
+    #[test]
+    fn test_aggregate_status() {
+        let health = HealthMiddleware::new()
+            .readiness("slow", || {
+                thread::sleep(Duration::from_millis(20));
+                Outcome::pass()
+            })
+            .readiness("disk", || Outcome::warn("almost full"));
+        let (status, report) = probe(&health, "/readyz");
+        assert_eq!((status.as_str(), report["status"].as_str()), ("200", Some("warn")));
+        assert_eq!(report["checks"][0]["name"], "slow");
+        assert!(report["checks"][0]["duration_ms"].as_f64().unwrap() >= 20.0);
+        assert!(report["checks"][0].get("detail").is_none());
+        assert_eq!(report["checks"][1]["detail"], "almost full");
+
+        let health = health.readiness("orders", || Outcome::fail("cannot open orders.json"));
+        let (status, report) = probe(&health, "/readyz?verbose");
+        assert_eq!((status.as_str(), report["status"].as_str()), ("503", Some("fail")));
+
+        // 存活检查与就绪检查分开，没有检查时通过
+        let (status, report) = probe(&health, "/healthz");
+        assert_eq!((status.as_str(), report["checks"].as_array().unwrap().len()), ("200", 0));
+        let mut other: HttpRequest = "GET /health HTTP/1.1\r\n\r\n".to_string().into();
+        assert!(health.before(&mut other).is_none());
+        let mut post: HttpRequest = "POST /readyz HTTP/1.1\r\n\r\n".to_string().into();
+        assert_eq!(health.before(&mut post).unwrap().status_code(), "405");
+    }
+
+    // 测试崩溃的检查按失败处理，不影响其他检查
+    #[test]
+    fn test_panicking_check() {
+        let health = HealthMiddleware::new()
+            .paths("/live", "/ready")
+            .liveness("broken", || -> Outcome { panic!("boom") })
+            .liveness("ok", Outcome::pass);
+        let (status, report) = probe(&health, "/live");
+        assert_eq!(status, "503");
+        assert_eq!(report["checks"][0]["detail"], "check panicked");
+        assert_eq!(report["checks"][1]["status"], "pass");
+    }
+
+    // 测试检查结果在缓存时间内复用，过期后重新检查
+    #[test]
+    fn test_cached_results() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let health = HealthMiddleware::new()
+            .cache_ttl(Duration::from_millis(100))
+            .readiness("counted", move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+                Outcome::pass()
+            });
+        for _ in 0..5 {
+            assert_eq!(probe(&health, "/readyz").0, "200");
+        }
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        thread::sleep(Duration::from_millis(150));
+        probe(&health, "/readyz");
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+
+        // 缓存时间为零时每次都检查
+        let health = health.cache_ttl(Duration::ZERO);
+        probe(&health, "/readyz");
+        probe(&health, "/readyz");
+        assert_eq!(runs.load(Ordering::SeqCst), 4);
+    }
+
+    // 测试磁盘空间检查的阈值
+    #[test]
+    fn test_disk_space_check() {
+        let dir = std::env::temp_dir().to_string_lossy().into_owned();
+        assert_eq!(DiskSpaceCheck::new(dir.clone(), 0).check().status, Status::Pass);
+        assert_eq!(DiskSpaceCheck::new(dir, u64::MAX).check().status, Status::Fail);
+        assert_eq!(DiskSpaceCheck::new("/nonexistent/path", 0).check().status, Status::Fail);
+    }
+
+    // 测试上游检查：部分上游不可连接时警告，某条路由全部不可连接时失败
+    #[test]
+    fn test_upstream_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let up = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let unused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let down = Url::parse(&format!("http://{}/", unused.local_addr().unwrap())).unwrap();
+        drop(unused);
+
+        let pool = |urls: Vec<Url>| Arc::new(UpstreamPool::new(urls));
+        let check = UpstreamCheck::new(vec![("/api".to_string(), pool(vec![up.clone()]))]);
+        assert_eq!(check.check(), Outcome::pass());
+        let check = UpstreamCheck::new(vec![("/api".to_string(), pool(vec![up.clone(), down.clone()]))]);
+        assert_eq!(check.check(), Outcome::warn(format!("unreachable: {}", down)));
+        let check = UpstreamCheck::new(vec![
+            ("/api".to_string(), pool(vec![up])),
+            ("/legacy".to_string(), pool(vec![down.clone()])),
+        ]);
+        assert_eq!(check.check(), Outcome::fail("no reachable upstream for /legacy"));
+
+        // 配置了主动健康检查的池使用池中的状态，不再连接
+        let checked = Arc::new(UpstreamPool::new(vec![down]).health_check("/health", Duration::from_secs(60)));
+        let check = UpstreamCheck::new(vec![("/api".to_string(), checked)]);
+        assert_eq!(check.check(), Outcome::pass());
+    }
+}
@@ -0,0 +1,1690 @@
+// 导入所需的库和模块
+use super::server::{body_read_timeout, Limits}; // 导入请求限制
+use base64::engine::general_purpose::URL_SAFE_NO_PAD; // 导入 URL 安全的 base64 编码
+use base64::Engine; // 导入 base64 编解码特性
+use http::chunked::{BodyStream, Chunk, RequestTrailers, Trailers, TryRecvError}; // 导入流式消息体和尾部头部
+use http::extensions::Extensions; // 导入附加数据容器
+use http::hpack::{Decoder, Encoder, HpackError}; // 导入 HPACK 编解码
+use http::httprequest::{HttpRequest, Method, Resource, Version}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use std::collections::{HashMap, HashSet, VecDeque}; // 导入集合类型
+use std::io::{self, Read, Write}; // 导入 IO 模块
+use std::net::TcpStream; // 导入 TCP 流
+use std::panic::{self, AssertUnwindSafe}; // 导入 panic 捕获
+use std::sync::mpsc::{self, Receiver, Sender}; // 导入通道
+use std::thread::{self, Scope}; // 导入作用域线程
+use std::time::{Duration, Instant}; // 导入时间模块
+
+// HTTP/2 客户端连接前言
+pub const PREFACE: &[u8; 24] = b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n";
+
+// 帧类型
+const DATA: u8 = 0x0;
+const HEADERS: u8 = 0x1;
+const PRIORITY: u8 = 0x2;
+const RST_STREAM: u8 = 0x3;
+const SETTINGS: u8 = 0x4;
+const PUSH_PROMISE: u8 = 0x5;
+const PING: u8 = 0x6;
+const GOAWAY: u8 = 0x7;
+const WINDOW_UPDATE: u8 = 0x8;
+const CONTINUATION: u8 = 0x9;
+
+// 帧标志
+const END_STREAM: u8 = 0x1;
+const ACK: u8 = 0x1;
+const END_HEADERS: u8 = 0x4;
+const PADDED: u8 = 0x8;
+const PRIORITY_FLAG: u8 = 0x20;
+
+// 错误码
+const NO_ERROR: u32 = 0x0;
+const PROTOCOL_ERROR: u32 = 0x1;
+const INTERNAL_ERROR: u32 = 0x2;
+const FLOW_CONTROL_ERROR: u32 = 0x3;
+const STREAM_CLOSED: u32 = 0x5;
+const FRAME_SIZE_ERROR: u32 = 0x6;
+const REFUSED_STREAM: u32 = 0x7;
+const COMPRESSION_ERROR: u32 = 0x9;
+const ENHANCE_YOUR_CALM: u32 = 0xb;
+
+// SETTINGS 参数
+const SETTINGS_HEADER_TABLE_SIZE: u16 = 0x1;
+const SETTINGS_ENABLE_PUSH: u16 = 0x2;
+const SETTINGS_MAX_CONCURRENT_STREAMS: u16 = 0x3;
+const SETTINGS_INITIAL_WINDOW_SIZE: u16 = 0x4;
+const SETTINGS_MAX_FRAME_SIZE: u16 = 0x5;
+const SETTINGS_MAX_HEADER_LIST_SIZE: u16 = 0x6;
+
+// 协议默认值和本端的限制
+const DEFAULT_WINDOW_SIZE: i64 = 65_535;
+const MAX_WINDOW_SIZE: i64 = (1 << 31) - 1;
+const DEFAULT_MAX_FRAME_SIZE: usize = 16_384;
+const MAX_ALLOWED_FRAME_SIZE: usize = (1 << 24) - 1;
+const MAX_CONCURRENT_STREAMS: usize = 100;
+const HEADER_TABLE_SIZE: usize = 4_096;
+const MAX_HEADER_LIST_SIZE: usize = 64 * 1024;
+
+// 流式响应体待发送数据的上限，超过后暂停从生产者读取
+const MAX_PENDING_OUTPUT: usize = 256 * 1024;
+
+// 有流式响应或还在运行的处理器时检查新数据和响应的间隔
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// 记住最近关闭的流的数量，用于区分已关闭和从未打开的流
+const CLOSED_HISTORY: usize = 1024;
+
+// RESET_WINDOW 内客户端最多可以重置的流的数量，超过后发送 GOAWAY(ENHANCE_YOUR_CALM)（防御 rapid reset 攻击）
+const MAX_RESETS: usize = 200;
+const RESET_WINDOW: Duration = Duration::from_secs(10);
+
+// HTTP/2 中不允许出现的连接相关头部
+const CONNECTION_HEADERS: [&str; 5] = ["connection", "keep-alive", "proxy-connection", "transfer-encoding", "upgrade"];
+
+// 定义 HTTP/2 错误：连接错误发送 GOAWAY 并关闭连接，流错误只重置对应的流
+#[derive(Debug, PartialEq)]
+enum H2Error {
+    Connection(u32, &'static str), // 错误码和调试信息
+    Stream(u32, u32),              // 流 ID 和错误码
+}
+
+// 一个帧
+struct Frame {
+    kind: u8,        // 帧类型
+    flags: u8,       // 帧标志
+    stream_id: u32,  // 流 ID
+    payload: Vec<u8>, // 负载
+}
+
+// 处理器返回给 HTTP/2 连接的响应
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: String,                  // 状态码
+    pub headers: Vec<(String, String)>,  // 响应头，名称为小写
+    pub body: Vec<u8>,                   // 消息体
+    pub trailers: Vec<(String, String)>, // 尾部头部，名称为小写
+    pub stream: Option<BodyStream>,      // 流式消息体，设置时 body 和 trailers 不使用
+}
+
+impl From<&HttpResponse<'_>> for Response {
+    fn from(resp: &HttpResponse<'_>) -> Self {
+        let headers = resp
+            .header_list()
+            .into_iter()
+            .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+            .filter(|(k, _)| k != "content-length" && !CONNECTION_HEADERS.contains(&k.as_str()))
+            .collect();
+        Response {
+            status: resp.status_code().to_string(),
+            headers,
+            body: resp.body().as_bytes().to_vec(),
+            trailers: Vec::new(),
+            stream: resp.stream().cloned(),
+        }
+    }
+}
+
+// HTTP/2 连接使用的底层传输
+// 连接需要在等待帧的同时发送流式响应，所以要能设置读超时来定期检查响应体
+pub trait Transport: Read + Write {
+    // 当前的读超时
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+    // 设置读超时，None 表示一直阻塞
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl Transport for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for std::os::unix::net::UnixStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        std::os::unix::net::UnixStream::read_timeout(self)
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        std::os::unix::net::UnixStream::set_read_timeout(self, timeout)
+    }
+}
+
+// 流的状态，已关闭的流不保存在连接中
+#[derive(Debug, PartialEq)]
+enum StreamState {
+    Open,             // 还在接收请求
+    HalfClosedRemote, // 请求已接收完毕，正在发送响应
+}
+
+// 一个流
+struct Stream {
+    state: StreamState,              // 状态
+    headers: Vec<(String, String)>,  // 请求头（包括伪头部）
+    body: Vec<u8>,                   // 请求体
+    request_trailers: Trailers,      // 请求的尾部头部，不合并到请求头中
+    content_length: Option<usize>,   // 请求声明的 Content-Length
+    body_start: Instant,             // 开始接收请求体的时间
+    last_data: Instant,              // 上一次收到 DATA 的时间
+    send_window: i64,                // 发送窗口
+    recv_window: i64,                // 接收窗口
+    responding: bool,                // 是否已发送响应头
+    output: Vec<u8>,                 // 待发送的响应体
+    trailers: Vec<(String, String)>, // 响应体之后发送的尾部头部
+    sent: usize,                     // 已发送的响应体字节数
+    source: Option<BodyStream>,      // 还在生成中的流式响应体
+}
+
+impl Stream {
+    // 创建一个新的流
+    fn new(send_window: i64) -> Self {
+        Stream {
+            state: StreamState::Open,
+            headers: Vec::new(),
+            body: Vec::new(),
+            request_trailers: Vec::new(),
+            content_length: None,
+            body_start: Instant::now(),
+            last_data: Instant::now(),
+            send_window,
+            recv_window: DEFAULT_WINDOW_SIZE,
+            responding: false,
+            output: Vec::new(),
+            trailers: Vec::new(),
+            sent: 0,
+            source: None,
+        }
+    }
+}
+
+// 请求是否是 h2c 升级请求（Upgrade: h2c 和合法的 HTTP2-Settings）
+pub fn is_h2c_upgrade(req: &HttpRequest) -> bool {
+    req.header("Upgrade").is_some_and(|u| u.trim().eq_ignore_ascii_case("h2c"))
+        && req
+            .header("HTTP2-Settings")
+            .and_then(|s| URL_SAFE_NO_PAD.decode(s.trim()).ok())
+            .is_some_and(|s| s.len().is_multiple_of(6))
+}
+
+// 在连接上提供 HTTP/2 服务，直到对端关闭连接、连接空闲超时或发生连接错误
+// buffered 是已经从连接读取但尚未处理的字节，upgrade 是通过 h2c 升级的 HTTP/1.1 请求
+// 请求按 limits 检查请求头、请求体的大小和接收速率，与 HTTP/1.x 一样返回 414、431、413 或 408
+// 每个请求的处理器在单独的线程中运行，超过 handler_timeout 时返回 503；连接结束时等待还在运行的处理器
+pub fn serve<S: Transport>(
+    stream: &mut S,
+    buffered: Vec<u8>,
+    upgrade: Option<HttpRequest>,
+    handler: &(dyn Fn(HttpRequest) -> Response + Sync),
+    limits: &Limits,
+) -> io::Result<()> {
+    thread::scope(|workers| {
+        let mut conn = Connection::new(stream, buffered, handler, workers)?;
+        conn.limits = *limits;
+        conn.queue_settings();
+        if let Some(req) = upgrade {
+            conn.upgrade(req);
+        }
+        conn.flush()?;
+        conn.update_timeout()?;
+
+        while !conn.fill(PREFACE.len())? {
+            if !conn.poll()? {
+                return Ok(());
+            }
+        }
+        if conn.buffered[..PREFACE.len()] != PREFACE[..] {
+            conn.queue_goaway(PROTOCOL_ERROR, "invalid connection preface");
+            return conn.flush();
+        }
+        conn.buffered.drain(..PREFACE.len());
+        conn.run()
+    })
+}
+
+// 处理器线程交回的响应：流 ID 和响应，处理器 panic 时为 None
+type Completed = (u32, Option<Response>);
+
+// 一个 HTTP/2 连接
+struct Connection<'a, 's, S: Transport> {
+    stream: &'a mut S,                         // 底层连接
+    idle_timeout: Option<Duration>,            // 底层连接原有的读超时，超时后关闭空闲的连接
+    polling: bool,                             // 是否正在用短的读超时轮询流式响应体和处理器
+    buffered: Vec<u8>,                         // 已读取但尚未处理的字节
+    out: Vec<u8>,                              // 待写出的帧
+    handler: &'a (dyn Fn(HttpRequest) -> Response + Sync), // 请求处理函数
+    workers: &'s Scope<'s, 'a>,                // 运行处理器的线程作用域
+    completed: (Sender<Completed>, Receiver<Completed>), // 处理器线程交回响应的通道
+    pending: HashMap<u32, Instant>,            // 处理器还在运行的流和开始处理的时间
+    running: usize,                            // 还在运行的处理器线程数，包括流已经关闭的
+    resets: VecDeque<Instant>,                 // RESET_WINDOW 内客户端重置流的时间
+    limits: Limits,                            // 请求的超时和大小限制
+    decoder: Decoder,                          // HPACK 解码器
+    encoder: Encoder,                          // HPACK 编码器
+    streams: HashMap<u32, Stream>,             // 活动的流
+    closed: VecDeque<u32>,                     // 最近关闭的流
+    last_stream_id: u32,                       // 对端打开的最大流 ID
+    send_window: i64,                          // 连接级发送窗口
+    recv_window: i64,                          // 连接级接收窗口
+    peer_initial_window: i64,                  // 对端设置的流初始窗口
+    peer_max_frame_size: usize,                // 对端允许的最大帧
+    continuation: Option<(u32, u8, Vec<u8>)>,  // 等待 CONTINUATION 的流 ID、HEADERS 标志和已收到的头部块
+    settings_received: bool,                   // 是否已收到对端的 SETTINGS
+    goaway_received: bool,                     // 对端是否已发送 GOAWAY
+}
+
+impl<'a, 's, S: Transport> Connection<'a, 's, S> {
+    // 创建连接，处理器在 workers 中的线程里运行
+    fn new(
+        stream: &'a mut S,
+        buffered: Vec<u8>,
+        handler: &'a (dyn Fn(HttpRequest) -> Response + Sync),
+        workers: &'s Scope<'s, 'a>,
+    ) -> io::Result<Self> {
+        Ok(Connection {
+            idle_timeout: stream.read_timeout()?,
+            stream,
+            polling: false,
+            buffered,
+            out: Vec::new(),
+            handler,
+            workers,
+            completed: mpsc::channel(),
+            pending: HashMap::new(),
+            running: 0,
+            resets: VecDeque::new(),
+            limits: Limits::default(),
+            decoder: Decoder::new(HEADER_TABLE_SIZE).max_header_list_size(MAX_HEADER_LIST_SIZE),
+            encoder: Encoder::new(),
+            streams: HashMap::new(),
+            closed: VecDeque::new(),
+            last_stream_id: 0,
+            send_window: DEFAULT_WINDOW_SIZE,
+            recv_window: DEFAULT_WINDOW_SIZE,
+            peer_initial_window: DEFAULT_WINDOW_SIZE,
+            peer_max_frame_size: DEFAULT_MAX_FRAME_SIZE,
+            continuation: None,
+            settings_received: false,
+            goaway_received: false,
+        })
+    }
+
+    // 从连接读取数据，直到缓冲区中至少有 n 字节
+    // 读超时返回 Ok(false)，已读取的数据保留在缓冲区中；对端关闭连接返回 UnexpectedEof
+    fn fill(&mut self, n: usize) -> io::Result<bool> {
+        let mut read_buffer = [0u8; 16 * 1024];
+        while self.buffered.len() < n {
+            match self.stream.read(&mut read_buffer) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(read) => self.buffered.extend_from_slice(&read_buffer[..read]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Ok(false),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(true)
+    }
+
+    // 读超时后调用：发送处理完的响应和流式响应体中新产生的数据，返回连接是否继续
+    // 没有流式响应和运行中的处理器时读超时说明连接空闲太久，发送 GOAWAY 后关闭
+    fn poll(&mut self) -> io::Result<bool> {
+        if !self.polling {
+            self.queue_goaway(NO_ERROR, "idle timeout");
+            self.flush()?;
+            return Ok(false);
+        }
+        self.collect_responses();
+        self.check_bodies();
+        self.pump();
+        self.queue_data();
+        self.flush()?;
+        self.update_timeout()?;
+        Ok(true)
+    }
+
+    // 有流式响应、运行中的处理器或限制了接收时间的请求体时使用短的读超时定期检查，否则恢复原有的读超时
+    fn update_timeout(&mut self) -> io::Result<()> {
+        let timed_bodies = self.limits.body_timeout.is_some() || self.limits.min_rate > 0;
+        let polling = self.running > 0
+            || self
+                .streams
+                .values()
+                .any(|s| s.source.is_some() || (timed_bodies && s.state == StreamState::Open && !s.responding));
+        if polling != self.polling {
+            let timeout = match (polling, self.idle_timeout) {
+                (true, Some(idle)) => Some(idle.min(POLL_INTERVAL)),
+                (true, None) => Some(POLL_INTERVAL),
+                (false, idle) => idle,
+            };
+            self.stream.set_read_timeout(timeout)?;
+            self.polling = polling;
+        }
+        Ok(())
+    }
+
+    // 写出所有待发送的帧
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.out.is_empty() {
+            self.stream.write_all(&self.out)?;
+            self.out.clear();
+        }
+        self.stream.flush()
+    }
+
+    // 处理帧直到连接结束
+    fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.update_timeout()?;
+            let complete = match self.fill(9) {
+                Ok(true) => {
+                    let len = u32::from_be_bytes([0, self.buffered[0], self.buffered[1], self.buffered[2]]) as usize;
+                    len > DEFAULT_MAX_FRAME_SIZE || self.fill(9 + len)?
+                }
+                Ok(false) => false,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            if !complete {
+                if !self.poll()? {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            let header: [u8; 9] = self.buffered[..9].try_into().expect("frame header");
+            let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let result = if len > DEFAULT_MAX_FRAME_SIZE {
+                Err(H2Error::Connection(FRAME_SIZE_ERROR, "frame exceeds SETTINGS_MAX_FRAME_SIZE"))
+            } else {
+                let payload = self.buffered[9..9 + len].to_vec();
+                self.buffered.drain(..9 + len);
+                self.process(Frame {
+                    kind: header[3],
+                    flags: header[4],
+                    stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]]) & 0x7fff_ffff,
+                    payload,
+                })
+            };
+
+            match result {
+                Ok(()) => {}
+                Err(H2Error::Stream(id, code)) => self.reset(id, code),
+                Err(H2Error::Connection(code, message)) => {
+                    self.queue_goaway(code, message);
+                    return self.flush();
+                }
+            }
+            self.collect_responses();
+            self.check_bodies();
+            self.pump();
+            self.queue_data();
+            self.flush()?;
+            if self.goaway_received && self.streams.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    // 按类型处理一个帧
+    fn process(&mut self, frame: Frame) -> Result<(), H2Error> {
+        // 头部块必须连续，中间不能插入其他帧
+        if let Some((id, _, _)) = &self.continuation {
+            if frame.kind != CONTINUATION || frame.stream_id != *id {
+                return Err(H2Error::Connection(PROTOCOL_ERROR, "expected CONTINUATION"));
+            }
+        }
+        // 连接前言之后的第一个帧必须是 SETTINGS
+        if !self.settings_received && frame.kind != SETTINGS {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "expected SETTINGS"));
+        }
+
+        match frame.kind {
+            DATA => self.on_data(frame),
+            HEADERS => self.on_headers(frame),
+            PRIORITY => self.on_priority(frame),
+            RST_STREAM => self.on_rst_stream(frame),
+            SETTINGS => self.on_settings(frame),
+            PUSH_PROMISE => Err(H2Error::Connection(PROTOCOL_ERROR, "clients cannot push")),
+            PING => self.on_ping(frame),
+            GOAWAY => self.on_goaway(frame),
+            WINDOW_UPDATE => self.on_window_update(frame),
+            CONTINUATION => self.on_continuation(frame),
+            // 忽略未知类型的帧
+            _ => Ok(()),
+        }
+    }
+
+    // 流 ID 是否属于从未打开过的流
+    fn is_idle(&self, id: u32) -> bool {
+        id > self.last_stream_id
+    }
+
+    // 关闭流，并记住它已经关闭；还在运行的处理器的响应会被丢弃，但线程在结束前仍计入并发上限
+    fn close(&mut self, id: u32) {
+        self.streams.remove(&id);
+        self.pending.remove(&id);
+        self.closed.push_back(id);
+        if self.closed.len() > CLOSED_HISTORY {
+            self.closed.pop_front();
+        }
+    }
+
+    // 发送 RST_STREAM 并关闭流
+    fn reset(&mut self, id: u32, code: u32) {
+        self.queue_frame(RST_STREAM, 0, id, &code.to_be_bytes());
+        self.close(id);
+    }
+
+    // 响应已完整发送，关闭流
+    // 请求体还没有接收完（如请求体过大提前返回 413）时，用 RST_STREAM(NO_ERROR) 让客户端停止发送（RFC 9113 第 8.1 节）
+    fn finish(&mut self, id: u32) {
+        if self.streams.get(&id).is_some_and(|s| s.state == StreamState::Open) {
+            self.reset(id, NO_ERROR);
+        } else {
+            self.close(id);
+        }
+    }
+
+    // 处理 DATA 帧
+    fn on_data(&mut self, frame: Frame) -> Result<(), H2Error> {
+        let id = frame.stream_id;
+        if id == 0 {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "DATA on stream 0"));
+        }
+        let data = strip_padding(frame.flags, &frame.payload)?;
+
+        // 整个帧（包括填充）都计入流量控制，处理后立即归还窗口
+        let len = frame.payload.len() as i64;
+        self.recv_window -= len;
+        if self.recv_window < 0 {
+            return Err(H2Error::Connection(FLOW_CONTROL_ERROR, "connection window exceeded"));
+        }
+        if len > 0 {
+            self.recv_window += len;
+            self.queue_frame(WINDOW_UPDATE, 0, 0, &(len as u32).to_be_bytes());
+        }
+
+        if self.is_idle(id) {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "DATA on idle stream"));
+        }
+        let Some(stream) = self.streams.get_mut(&id) else {
+            return Err(H2Error::Stream(id, STREAM_CLOSED));
+        };
+        if stream.state != StreamState::Open {
+            return Err(H2Error::Stream(id, STREAM_CLOSED));
+        }
+        // 已经提前响应的流丢弃之后的请求体，响应发送完后重置
+        if stream.responding {
+            return Ok(());
+        }
+        stream.recv_window -= len;
+        if stream.recv_window < 0 {
+            return Err(H2Error::Stream(id, FLOW_CONTROL_ERROR));
+        }
+        stream.body.extend_from_slice(data);
+        stream.last_data = Instant::now();
+
+        // 请求体过大时直接返回 413，响应发送完后重置这个流
+        if stream.body.len() > self.limits.max_body_size {
+            stream.body = Vec::new();
+            self.respond(id, error_response("413", "Payload Too Large"));
+            return Ok(());
+        }
+        if frame.flags & END_STREAM != 0 {
+            return self.end_request(id);
+        }
+        if len > 0 {
+            stream.recv_window += len;
+            self.queue_frame(WINDOW_UPDATE, 0, id, &(len as u32).to_be_bytes());
+        }
+        Ok(())
+    }
+
+    // 处理 HEADERS 帧
+    fn on_headers(&mut self, frame: Frame) -> Result<(), H2Error> {
+        let id = frame.stream_id;
+        if id == 0 || id.is_multiple_of(2) {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "invalid stream identifier"));
+        }
+        let mut block = strip_padding(frame.flags, &frame.payload)?;
+        if frame.flags & PRIORITY_FLAG != 0 {
+            if block.len() < 5 {
+                return Err(H2Error::Connection(FRAME_SIZE_ERROR, "HEADERS priority truncated"));
+            }
+            let dependency = u32::from_be_bytes([block[0], block[1], block[2], block[3]]) & 0x7fff_ffff;
+            block = &block[5..];
+            if dependency == id {
+                // 先解码头部块保持 HPACK 状态同步，再重置流
+                self.decode(block)?;
+                return Err(H2Error::Stream(id, PROTOCOL_ERROR));
+            }
+        }
+
+        if frame.flags & END_HEADERS == 0 {
+            self.continuation = Some((id, frame.flags, block.to_vec()));
+            return Ok(());
+        }
+        let block = block.to_vec();
+        self.on_header_block(id, frame.flags, &block)
+    }
+
+    // 处理 CONTINUATION 帧
+    fn on_continuation(&mut self, frame: Frame) -> Result<(), H2Error> {
+        let Some((id, flags, mut block)) = self.continuation.take() else {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "unexpected CONTINUATION"));
+        };
+        block.extend_from_slice(&frame.payload);
+        if block.len() > MAX_HEADER_LIST_SIZE {
+            return Err(H2Error::Connection(ENHANCE_YOUR_CALM, "header block too large"));
+        }
+        if frame.flags & END_HEADERS == 0 {
+            self.continuation = Some((id, flags, block));
+            return Ok(());
+        }
+        self.on_header_block(id, flags, &block)
+    }
+
+    // 解码头部块，失败是连接错误；超过通告的 SETTINGS_MAX_HEADER_LIST_SIZE 时 HPACK 状态已无法同步，同样关闭连接
+    fn decode(&mut self, block: &[u8]) -> Result<Vec<(String, String)>, H2Error> {
+        let fields = self.decoder.decode(block).map_err(|e| match e {
+            HpackError::HeaderListTooLarge => H2Error::Connection(ENHANCE_YOUR_CALM, "header list too large"),
+            _ => H2Error::Connection(COMPRESSION_ERROR, "HPACK decoding failed"),
+        })?;
+        Ok(fields
+            .into_iter()
+            .map(|(n, v)| (String::from_utf8_lossy(&n).into_owned(), String::from_utf8_lossy(&v).into_owned()))
+            .collect())
+    }
+
+    // 处理完整的头部块：新请求或请求的尾部头部
+    fn on_header_block(&mut self, id: u32, flags: u8, block: &[u8]) -> Result<(), H2Error> {
+        let fields = self.decode(block)?;
+        let end_stream = flags & END_STREAM != 0;
+
+        if let Some(stream) = self.streams.get_mut(&id) {
+            // 已打开的流上的第二个头部块是尾部头部，必须结束流且不能包含伪头部
+            if stream.state != StreamState::Open {
+                return Err(H2Error::Stream(id, STREAM_CLOSED));
+            }
+            if !end_stream || !valid_trailers(&fields) {
+                return Err(H2Error::Stream(id, PROTOCOL_ERROR));
+            }
+            stream.request_trailers = fields;
+            return self.end_request(id);
+        }
+        if self.closed.contains(&id) {
+            return Err(H2Error::Connection(STREAM_CLOSED, "HEADERS on closed stream"));
+        }
+        if !self.is_idle(id) {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "stream identifier not increasing"));
+        }
+
+        self.last_stream_id = id;
+        // 被重置或超时的流的处理器线程仍在运行，同样占用并发名额
+        if self.streams.len() >= MAX_CONCURRENT_STREAMS || self.running >= MAX_CONCURRENT_STREAMS {
+            return Err(H2Error::Stream(id, REFUSED_STREAM));
+        }
+        if !valid_request(&fields) {
+            return Err(H2Error::Stream(id, PROTOCOL_ERROR));
+        }
+        let content_length = match fields.iter().find(|(n, _)| n == "content-length") {
+            Some((_, v)) => Some(v.trim().parse::<usize>().map_err(|_| H2Error::Stream(id, PROTOCOL_ERROR))?),
+            None => None,
+        };
+
+        let mut stream = Stream::new(self.peer_initial_window);
+        let error = check_head(&fields, content_length, &self.limits);
+        stream.headers = fields;
+        stream.content_length = content_length;
+        if end_stream {
+            stream.state = StreamState::HalfClosedRemote;
+        }
+        self.streams.insert(id, stream);
+        // 请求头超出限制时直接返回错误响应，请求还没有结束时响应发送完后重置这个流
+        if let Some(resp) = error {
+            self.respond(id, resp);
+            return Ok(());
+        }
+        if end_stream {
+            return self.end_request(id);
+        }
+        Ok(())
+    }
+
+    // 请求体两次 DATA 之间超过 body_timeout，或宽限期后平均速率低于 min_rate 时返回 408
+    fn check_bodies(&mut self) {
+        let expired: Vec<u32> = self
+            .streams
+            .iter()
+            .filter(|(_, s)| s.state == StreamState::Open && !s.responding)
+            .filter(|(_, s)| {
+                let allowed = body_read_timeout(&self.limits, s.body.len(), s.last_data.duration_since(s.body_start));
+                allowed.is_some_and(|allowed| s.last_data.elapsed() >= allowed)
+            })
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            if let Some(stream) = self.streams.get_mut(&id) {
+                stream.body = Vec::new();
+            }
+            self.respond(id, error_response("408", "Request Timeout"));
+        }
+    }
+
+    // 请求接收完毕，交给处理器
+    fn end_request(&mut self, id: u32) -> Result<(), H2Error> {
+        let stream = self.streams.get_mut(&id).expect("stream exists");
+        stream.state = StreamState::HalfClosedRemote;
+        if stream.content_length.is_some_and(|len| len != stream.body.len()) {
+            return Err(H2Error::Stream(id, PROTOCOL_ERROR));
+        }
+        let mut req = build_request(std::mem::take(&mut stream.headers), std::mem::take(&mut stream.body));
+        if !stream.request_trailers.is_empty() {
+            req.extensions.insert(RequestTrailers(std::mem::take(&mut stream.request_trailers)));
+        }
+        self.dispatch(id, req);
+        Ok(())
+    }
+
+    // 在单独的线程中运行处理器，连接继续处理其他流的帧；响应由 collect_responses 取回
+    fn dispatch(&mut self, id: u32, req: HttpRequest) {
+        let handler = self.handler;
+        let sender = self.completed.0.clone();
+        self.pending.insert(id, Instant::now());
+        self.running += 1;
+        self.workers.spawn(move || {
+            let resp = panic::catch_unwind(AssertUnwindSafe(|| handler(req))).ok();
+            let _ = sender.send((id, resp));
+        });
+    }
+
+    // 发送处理完的请求的响应，处理器 panic 时重置流；超过处理器超时的流返回 503，之后到达的响应被丢弃
+    fn collect_responses(&mut self) {
+        while let Ok((id, resp)) = self.completed.1.try_recv() {
+            self.running -= 1;
+            // 流已经被重置或已经超时
+            if self.pending.remove(&id).is_none() {
+                continue;
+            }
+            match resp {
+                Some(resp) => self.respond(id, resp),
+                None => self.reset(id, INTERNAL_ERROR),
+            }
+        }
+        let Some(timeout) = self.limits.handler_timeout else { return };
+        let expired: Vec<u32> = self
+            .pending
+            .iter()
+            .filter(|(_, start)| start.elapsed() >= timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.pending.remove(&id);
+            self.respond(id, error_response("503", "Handler Timeout"));
+        }
+    }
+
+    // 发送响应头，响应体在流量控制允许时由 queue_data 发送
+    fn respond(&mut self, id: u32, resp: Response) {
+        let content_length = resp.body.len().to_string();
+        let mut fields: Vec<(&str, &str)> = vec![(":status", resp.status.as_str())];
+        fields.extend(resp.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        // 流式响应的长度未知
+        if resp.stream.is_none() && !matches!(resp.status.as_str(), "204" | "304") {
+            fields.push(("content-length", content_length.as_str()));
+        }
+        let end_stream = resp.body.is_empty() && resp.trailers.is_empty() && resp.stream.is_none();
+        self.queue_header_block(id, fields, end_stream);
+
+        if end_stream {
+            self.finish(id);
+        } else if resp.body.is_empty() && resp.stream.is_none() {
+            self.queue_trailers(id, &resp.trailers);
+        } else if let Some(stream) = self.streams.get_mut(&id) {
+            stream.responding = true;
+            stream.output = resp.body;
+            stream.trailers = resp.trailers;
+            stream.source = resp.stream;
+        }
+    }
+
+    // 从流式响应体中取出已经产生的数据，待发送的数据过多时暂停读取（背压）
+    fn pump(&mut self) {
+        let mut aborted = Vec::new();
+        for (id, stream) in self.streams.iter_mut() {
+            while let Some(source) = &stream.source {
+                if stream.output.len() - stream.sent >= MAX_PENDING_OUTPUT {
+                    break;
+                }
+                match source.try_recv() {
+                    Ok(Chunk::Data(data)) => stream.output.extend_from_slice(&data),
+                    Ok(Chunk::End(trailers)) => {
+                        stream.trailers = trailers
+                            .into_iter()
+                            .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_string()))
+                            .collect();
+                        stream.source = None;
+                    }
+                    Err(TryRecvError::Empty) => break,
+                    // 生产者没有正常结束，重置流让客户端知道响应不完整
+                    Err(TryRecvError::Disconnected) => {
+                        aborted.push(*id);
+                        break;
+                    }
+                }
+            }
+        }
+        for id in aborted {
+            self.reset(id, INTERNAL_ERROR);
+        }
+    }
+
+    // 发送尾部头部并结束流
+    fn queue_trailers(&mut self, id: u32, trailers: &[(String, String)]) {
+        let fields = trailers.iter().map(|(k, v)| (k.as_str(), v.as_str()));
+        self.queue_header_block(id, fields, true);
+        self.finish(id);
+    }
+
+    // 编码并发送头部块，超过对端的最大帧时拆分为 HEADERS 和 CONTINUATION
+    fn queue_header_block<'f>(&mut self, id: u32, fields: impl IntoIterator<Item = (&'f str, &'f str)>, end_stream: bool) {
+        let block = self.encoder.encode(fields);
+        let mut chunks = block.chunks(self.peer_max_frame_size).peekable();
+        let mut kind = HEADERS;
+        let mut flags = if end_stream { END_STREAM } else { 0 };
+        while let Some(chunk) = chunks.next() {
+            if chunks.peek().is_none() {
+                flags |= END_HEADERS;
+            }
+            self.queue_frame(kind, flags, id, chunk);
+            kind = CONTINUATION;
+            flags = 0;
+        }
+        if block.is_empty() {
+            self.queue_frame(HEADERS, flags | END_HEADERS, id, &[]);
+        }
+    }
+
+    // 在流量控制窗口允许的范围内轮流为各个流发送响应体
+    fn queue_data(&mut self) {
+        loop {
+            // 有待发送数据的流，以及流式响应体已经结束、只差结束标志的流
+            let mut ids: Vec<u32> = self
+                .streams
+                .iter()
+                .filter(|(_, s)| s.responding && (s.sent < s.output.len() || s.source.is_none()))
+                .map(|(id, _)| *id)
+                .collect();
+            ids.sort_unstable();
+            let mut progressed = false;
+            for id in ids {
+                let max_frame = self.peer_max_frame_size as i64;
+                let stream = self.streams.get_mut(&id).expect("stream exists");
+                let remaining = (stream.output.len() - stream.sent) as i64;
+                let n = remaining.min(max_frame).min(self.send_window).min(stream.send_window).max(0) as usize;
+                if remaining > 0 && n == 0 {
+                    continue; // 窗口已用完，等待 WINDOW_UPDATE
+                }
+                let chunk = stream.output[stream.sent..stream.sent + n].to_vec();
+                stream.sent += n;
+                stream.send_window -= n as i64;
+                self.send_window -= n as i64;
+
+                let done = stream.sent == stream.output.len() && stream.source.is_none();
+                if !done {
+                    // 流式响应体已发送的部分不再需要保留
+                    if stream.sent == stream.output.len() {
+                        stream.output.clear();
+                        stream.sent = 0;
+                    }
+                    self.queue_frame(DATA, 0, id, &chunk);
+                    progressed = true;
+                    continue;
+                }
+                let trailers = std::mem::take(&mut stream.trailers);
+                if trailers.is_empty() {
+                    self.queue_frame(DATA, END_STREAM, id, &chunk);
+                    self.finish(id);
+                } else {
+                    if !chunk.is_empty() {
+                        self.queue_frame(DATA, 0, id, &chunk);
+                    }
+                    self.queue_trailers(id, &trailers);
+                }
+                progressed = true;
+            }
+            if !progressed {
+                return;
+            }
+        }
+    }
+
+    // 处理 PRIORITY 帧，本服务器不使用优先级
+    fn on_priority(&mut self, frame: Frame) -> Result<(), H2Error> {
+        if frame.stream_id == 0 {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "PRIORITY on stream 0"));
+        }
+        if frame.payload.len() != 5 {
+            return Err(H2Error::Stream(frame.stream_id, FRAME_SIZE_ERROR));
+        }
+        let p = &frame.payload;
+        let dependency = u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7fff_ffff;
+        if dependency == frame.stream_id {
+            return Err(H2Error::Stream(frame.stream_id, PROTOCOL_ERROR));
+        }
+        Ok(())
+    }
+
+    // 处理 RST_STREAM 帧
+    fn on_rst_stream(&mut self, frame: Frame) -> Result<(), H2Error> {
+        if frame.stream_id == 0 {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "RST_STREAM on stream 0"));
+        }
+        if frame.payload.len() != 4 {
+            return Err(H2Error::Connection(FRAME_SIZE_ERROR, "RST_STREAM must be 4 octets"));
+        }
+        if self.is_idle(frame.stream_id) {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "RST_STREAM on idle stream"));
+        }
+        if self.streams.contains_key(&frame.stream_id) {
+            self.close(frame.stream_id);
+            // 不断打开并立即重置流可以绕过并发上限，重置过于频繁时关闭连接
+            let now = Instant::now();
+            while self.resets.front().is_some_and(|t| now.duration_since(*t) > RESET_WINDOW) {
+                self.resets.pop_front();
+            }
+            self.resets.push_back(now);
+            if self.resets.len() > MAX_RESETS {
+                return Err(H2Error::Connection(ENHANCE_YOUR_CALM, "too many stream resets"));
+            }
+        }
+        Ok(())
+    }
+
+    // 处理 SETTINGS 帧
+    fn on_settings(&mut self, frame: Frame) -> Result<(), H2Error> {
+        if frame.stream_id != 0 {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "SETTINGS on a stream"));
+        }
+        if frame.flags & ACK != 0 {
+            if !frame.payload.is_empty() {
+                return Err(H2Error::Connection(FRAME_SIZE_ERROR, "SETTINGS ACK with payload"));
+            }
+            return Ok(());
+        }
+        self.apply_settings(&frame.payload)?;
+        self.settings_received = true;
+        self.queue_frame(SETTINGS, ACK, 0, &[]);
+        Ok(())
+    }
+
+    // 应用对端的设置
+    fn apply_settings(&mut self, payload: &[u8]) -> Result<(), H2Error> {
+        if !payload.len().is_multiple_of(6) {
+            return Err(H2Error::Connection(FRAME_SIZE_ERROR, "SETTINGS length not a multiple of 6"));
+        }
+        for setting in payload.chunks(6) {
+            let id = u16::from_be_bytes([setting[0], setting[1]]);
+            let value = u32::from_be_bytes([setting[2], setting[3], setting[4], setting[5]]);
+            match id {
+                SETTINGS_ENABLE_PUSH if value > 1 => {
+                    return Err(H2Error::Connection(PROTOCOL_ERROR, "invalid SETTINGS_ENABLE_PUSH"));
+                }
+                SETTINGS_INITIAL_WINDOW_SIZE => {
+                    if value as i64 > MAX_WINDOW_SIZE {
+                        return Err(H2Error::Connection(FLOW_CONTROL_ERROR, "invalid SETTINGS_INITIAL_WINDOW_SIZE"));
+                    }
+                    // 调整所有流的发送窗口
+                    let delta = value as i64 - self.peer_initial_window;
+                    self.peer_initial_window = value as i64;
+                    for stream in self.streams.values_mut() {
+                        stream.send_window += delta;
+                        if stream.send_window > MAX_WINDOW_SIZE {
+                            return Err(H2Error::Connection(FLOW_CONTROL_ERROR, "stream window overflow"));
+                        }
+                    }
+                }
+                SETTINGS_MAX_FRAME_SIZE => {
+                    if !(DEFAULT_MAX_FRAME_SIZE..=MAX_ALLOWED_FRAME_SIZE).contains(&(value as usize)) {
+                        return Err(H2Error::Connection(PROTOCOL_ERROR, "invalid SETTINGS_MAX_FRAME_SIZE"));
+                    }
+                    self.peer_max_frame_size = value as usize;
+                }
+                // 编码器不使用动态表，本端也不推送，其余设置无需处理
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // 处理 PING 帧
+    fn on_ping(&mut self, frame: Frame) -> Result<(), H2Error> {
+        if frame.stream_id != 0 {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "PING on a stream"));
+        }
+        if frame.payload.len() != 8 {
+            return Err(H2Error::Connection(FRAME_SIZE_ERROR, "PING must be 8 octets"));
+        }
+        if frame.flags & ACK == 0 {
+            self.queue_frame(PING, ACK, 0, &frame.payload);
+        }
+        Ok(())
+    }
+
+    // 处理 GOAWAY 帧：不再接受新的流，发送完已有的响应后关闭连接
+    fn on_goaway(&mut self, frame: Frame) -> Result<(), H2Error> {
+        if frame.stream_id != 0 {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "GOAWAY on a stream"));
+        }
+        self.goaway_received = true;
+        Ok(())
+    }
+
+    // 处理 WINDOW_UPDATE 帧
+    fn on_window_update(&mut self, frame: Frame) -> Result<(), H2Error> {
+        if frame.payload.len() != 4 {
+            return Err(H2Error::Connection(FRAME_SIZE_ERROR, "WINDOW_UPDATE must be 4 octets"));
+        }
+        let p = &frame.payload;
+        let increment = (u32::from_be_bytes([p[0], p[1], p[2], p[3]]) & 0x7fff_ffff) as i64;
+        let id = frame.stream_id;
+        if id == 0 {
+            if increment == 0 {
+                return Err(H2Error::Connection(PROTOCOL_ERROR, "zero window increment"));
+            }
+            self.send_window += increment;
+            if self.send_window > MAX_WINDOW_SIZE {
+                return Err(H2Error::Connection(FLOW_CONTROL_ERROR, "connection window overflow"));
+            }
+            return Ok(());
+        }
+        if self.is_idle(id) {
+            return Err(H2Error::Connection(PROTOCOL_ERROR, "WINDOW_UPDATE on idle stream"));
+        }
+        if increment == 0 {
+            return Err(H2Error::Stream(id, PROTOCOL_ERROR));
+        }
+        if let Some(stream) = self.streams.get_mut(&id) {
+            stream.send_window += increment;
+            if stream.send_window > MAX_WINDOW_SIZE {
+                return Err(H2Error::Stream(id, FLOW_CONTROL_ERROR));
+            }
+        }
+        Ok(())
+    }
+
+    // 把 h2c 升级的请求作为流 1 处理（RFC 7540 第 3.2 节）
+    fn upgrade(&mut self, req: HttpRequest) {
+        if let Some(settings) = req.header("HTTP2-Settings").and_then(|s| URL_SAFE_NO_PAD.decode(s.trim()).ok()) {
+            let _ = self.apply_settings(&settings);
+        }
+        self.last_stream_id = 1;
+        let mut stream = Stream::new(self.peer_initial_window);
+        stream.state = StreamState::HalfClosedRemote;
+        self.streams.insert(1, stream);
+        self.dispatch(1, req);
+    }
+
+    // 发送本端的设置
+    fn queue_settings(&mut self) {
+        let mut payload = Vec::new();
+        for (id, value) in [
+            (SETTINGS_HEADER_TABLE_SIZE, HEADER_TABLE_SIZE as u32),
+            (SETTINGS_ENABLE_PUSH, 0),
+            (SETTINGS_MAX_CONCURRENT_STREAMS, MAX_CONCURRENT_STREAMS as u32),
+            (SETTINGS_INITIAL_WINDOW_SIZE, DEFAULT_WINDOW_SIZE as u32),
+            (SETTINGS_MAX_FRAME_SIZE, DEFAULT_MAX_FRAME_SIZE as u32),
+            (SETTINGS_MAX_HEADER_LIST_SIZE, MAX_HEADER_LIST_SIZE as u32),
+        ] {
+            payload.extend_from_slice(&id.to_be_bytes());
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        self.queue_frame(SETTINGS, 0, 0, &payload);
+    }
+
+    // 发送 GOAWAY
+    fn queue_goaway(&mut self, code: u32, message: &str) {
+        let mut payload = self.last_stream_id.to_be_bytes().to_vec();
+        payload.extend_from_slice(&code.to_be_bytes());
+        payload.extend_from_slice(message.as_bytes());
+        self.queue_frame(GOAWAY, 0, 0, &payload);
+    }
+
+    // 把一个帧加入待写出的缓冲区
+    fn queue_frame(&mut self, kind: u8, flags: u8, stream_id: u32, payload: &[u8]) {
+        self.out.extend_from_slice(&(payload.len() as u32).to_be_bytes()[1..]);
+        self.out.push(kind);
+        self.out.push(flags);
+        self.out.extend_from_slice(&stream_id.to_be_bytes());
+        self.out.extend_from_slice(payload);
+    }
+}
+
+// 去掉 DATA 或 HEADERS 帧的填充
+fn strip_padding(flags: u8, payload: &[u8]) -> Result<&[u8], H2Error> {
+    if flags & PADDED == 0 {
+        return Ok(payload);
+    }
+    let pad = *payload
+        .first()
+        .ok_or(H2Error::Connection(FRAME_SIZE_ERROR, "missing pad length"))? as usize;
+    if pad >= payload.len() {
+        return Err(H2Error::Connection(PROTOCOL_ERROR, "padding exceeds frame payload"));
+    }
+    Ok(&payload[1..payload.len() - pad])
+}
+
+// 检查请求头是否符合 HTTP/2 的要求（RFC 9113 第 8.2 和 8.3 节）
+fn valid_request(fields: &[(String, String)]) -> bool {
+    let mut pseudo = HashSet::new();
+    let mut regular_seen = false;
+    for (name, value) in fields {
+        if name.is_empty() || name.bytes().any(|b| b.is_ascii_uppercase()) {
+            return false;
+        }
+        if name.starts_with(':') {
+            let known = matches!(name.as_str(), ":method" | ":scheme" | ":path" | ":authority");
+            if regular_seen || !known || !pseudo.insert(name.as_str()) {
+                return false;
+            }
+            continue;
+        }
+        regular_seen = true;
+        if CONNECTION_HEADERS.contains(&name.as_str()) || (name == "te" && value != "trailers") {
+            return false;
+        }
+    }
+    // :path 必须是以 / 开头的路径，只有 OPTIONS 请求可以是 *（RFC 9113 第 8.3.1 节）
+    let method = fields.iter().find(|(n, _)| n == ":method").map(|(_, v)| v.as_str());
+    let path_ok = fields
+        .iter()
+        .any(|(n, v)| n == ":path" && (v.starts_with('/') || (v == "*" && method == Some("OPTIONS"))));
+    pseudo.contains(":method") && pseudo.contains(":scheme") && path_ok
+}
+
+// 检查尾部头部：不能包含伪头部
+fn valid_trailers(fields: &[(String, String)]) -> bool {
+    fields.iter().all(|(name, _)| {
+        !name.is_empty() && !name.starts_with(':') && !name.bytes().any(|b| b.is_ascii_uppercase())
+    })
+}
+
+// 按 limits 检查请求头，:path 过长返回 414，请求头过多或过大返回 431，声明的请求体过大返回 413
+// 请求头的大小按 HTTP/1.1 的格式（名称: 值\r\n）计算，不包括伪头部
+fn check_head(fields: &[(String, String)], content_length: Option<usize>, limits: &Limits) -> Option<Response> {
+    let path = fields.iter().find(|(n, _)| n == ":path").map_or(0, |(_, v)| v.len());
+    if path > limits.max_request_line {
+        return Some(error_response("414", "URI Too Long"));
+    }
+    let regular = fields.iter().filter(|(n, _)| !n.starts_with(':'));
+    let size: usize = regular.clone().map(|(n, v)| n.len() + v.len() + 4).sum();
+    if regular.count() > limits.max_headers || size > limits.max_header_size {
+        return Some(error_response("431", "Request Header Fields Too Large"));
+    }
+    if content_length.is_some_and(|len| len > limits.max_body_size) {
+        return Some(error_response("413", "Payload Too Large"));
+    }
+    None
+}
+
+// 连接自己产生的错误响应
+fn error_response(status: &str, message: &str) -> Response {
+    Response {
+        status: status.to_string(),
+        headers: vec![("content-type".to_string(), "text/plain".to_string())],
+        body: message.as_bytes().to_vec(),
+        trailers: Vec::new(),
+        stream: None,
+    }
+}
+
+// 根据 HTTP/2 的头部和请求体构造 HttpRequest
+fn build_request(fields: Vec<(String, String)>, body: Vec<u8>) -> HttpRequest {
+    let mut method = Method::Uninitialized;
+    let mut path = String::from("/");
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for (name, value) in fields {
+        match name.as_str() {
+            ":method" => method = value.as_str().into(),
+            ":path" => path = value,
+            ":authority" => {
+                headers.entry("host".to_string()).or_insert(value);
+            }
+            ":scheme" => {}
+            // 同名头部合并，Cookie 用分号连接（RFC 9113 第 8.2.3 节）
+            _ => {
+                let separator = if name == "cookie" { "; " } else { ", " };
+                headers
+                    .entry(name)
+                    .and_modify(|v| {
+                        v.push_str(separator);
+                        v.push_str(&value);
+                    })
+                    .or_insert(value);
+            }
+        }
+    }
+    HttpRequest {
+        method,
+        version: Version::V2_0,
+        resource: Resource::Path(path),
+        headers,
+        msg_body: String::from_utf8_lossy(&body).into_owned(),
+        raw_body: body,
+        extensions: Extensions::new(),
+    }
+}
+
+// 测试模块：用原始帧驱动连接，检查协议行为
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+    use std::time::Duration;
+
+    // 测试用的处理器：返回请求方法、路径和消息体长度，/large 返回 100 字节
+    // /stream 由另一个线程分两次产生响应体，/abort 的生产者没有结束就退出
+    // /slow 处理 300 毫秒，/panic 使处理器 panic，/trailer 还返回 Host 头和尾部头部 x-sum
+    fn echo(req: HttpRequest) -> Response {
+        let Resource::Path(path) = &req.resource;
+        match path.as_str() {
+            "/slow" => thread::sleep(Duration::from_millis(300)),
+            "/panic" => panic!("handler failed"),
+            _ => {}
+        }
+        if path == "/stream" || path == "/abort" {
+            let (resp, mut writer) = HttpResponse::streaming("200", None);
+            let abort = path == "/abort";
+            thread::spawn(move || {
+                writer.write_all(b"one,").unwrap();
+                writer.flush().unwrap();
+                thread::sleep(Duration::from_millis(50));
+                if abort {
+                    return;
+                }
+                writer.write_all(b"two").unwrap();
+                writer.trailer("X-Rows", "2");
+                writer.finish().unwrap();
+            });
+            return Response::from(&resp);
+        }
+        let body = match path.as_str() {
+            "/large" => vec![b'x'; 100],
+            "/trailer" => {
+                let (host, sum) = (req.header("Host").unwrap_or_default(), req.trailer("x-sum").unwrap_or_default());
+                format!("{:?} {} {} {} {}", req.method, path, req.raw_body.len(), host, sum).into_bytes()
+            }
+            _ => format!("{:?} {} {}", req.method, path, req.raw_body.len()).into_bytes(),
+        };
+        let trailers = match path.as_str() {
+            "/trailers" => vec![("x-checksum".into(), "42".into())],
+            _ => Vec::new(),
+        };
+        Response {
+            status: "200".into(),
+            headers: vec![("content-type".into(), "text/plain".into())],
+            body,
+            trailers,
+            stream: None,
+        }
+    }
+
+    // 测试客户端
+    struct Client {
+        stream: TcpStream,
+        encoder: Encoder,
+        decoder: Decoder,
+    }
+
+    impl Client {
+        // 启动服务器，发送连接前言和 SETTINGS，并确认服务器的 SETTINGS
+        fn connect(settings: &[(u16, u32)], upgrade: Option<HttpRequest>) -> Client {
+            Client::connect_with_limits(settings, upgrade, Limits::default())
+        }
+
+        // 与 connect 相同，服务器使用 limits 限制请求
+        fn connect_with_limits(settings: &[(u16, u32)], upgrade: Option<HttpRequest>, limits: Limits) -> Client {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let addr = listener.local_addr().unwrap();
+            thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let _ = serve(&mut stream, Vec::new(), upgrade, &echo, &limits);
+            });
+            let stream = TcpStream::connect(addr).unwrap();
+            stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+            let mut client = Client {
+                stream,
+                encoder: Encoder::new(),
+                decoder: Decoder::new(HEADER_TABLE_SIZE),
+            };
+            client.stream.write_all(PREFACE).unwrap();
+            let payload: Vec<u8> = settings
+                .iter()
+                .flat_map(|(id, v)| id.to_be_bytes().into_iter().chain(v.to_be_bytes()))
+                .collect();
+            client.send(SETTINGS, 0, 0, &payload);
+            let frame = client.read().unwrap();
+            assert_eq!((frame.kind, frame.flags), (SETTINGS, 0));
+            client.send(SETTINGS, ACK, 0, &[]);
+            client
+        }
+
+        // 发送一个帧
+        fn send(&mut self, kind: u8, flags: u8, id: u32, payload: &[u8]) {
+            let mut frame = (payload.len() as u32).to_be_bytes()[1..].to_vec();
+            frame.extend_from_slice(&[kind, flags]);
+            frame.extend_from_slice(&id.to_be_bytes());
+            frame.extend_from_slice(payload);
+            self.stream.write_all(&frame).unwrap();
+        }
+
+        // 读取一个帧，连接关闭时返回 None
+        fn read(&mut self) -> Option<Frame> {
+            let mut header = [0u8; 9];
+            self.stream.read_exact(&mut header).ok()?;
+            let len = u32::from_be_bytes([0, header[0], header[1], header[2]]) as usize;
+            let mut payload = vec![0u8; len];
+            self.stream.read_exact(&mut payload).ok()?;
+            Some(Frame {
+                kind: header[3],
+                flags: header[4],
+                stream_id: u32::from_be_bytes([header[5], header[6], header[7], header[8]]),
+                payload,
+            })
+        }
+
+        // 读取下一个不是 SETTINGS 或 WINDOW_UPDATE 的帧
+        fn next(&mut self) -> Frame {
+            loop {
+                let frame = self.read().expect("connection closed");
+                if frame.kind != SETTINGS && frame.kind != WINDOW_UPDATE {
+                    return frame;
+                }
+            }
+        }
+
+        // 发送请求头
+        fn request(&mut self, id: u32, method: &str, path: &str, extra: &[(&str, &str)], flags: u8) {
+            let mut fields = vec![(":method", method), (":scheme", "http"), (":path", path), (":authority", "test")];
+            fields.extend_from_slice(extra);
+            let block = self.encoder.encode(fields);
+            self.send(HEADERS, flags | END_HEADERS, id, &block);
+        }
+
+        // 读取一个完整的响应
+        fn response(&mut self, id: u32) -> (Vec<(String, String)>, Vec<u8>) {
+            let frame = self.next();
+            assert_eq!((frame.kind, frame.stream_id), (HEADERS, id));
+            let headers = self
+                .decoder
+                .decode(&frame.payload)
+                .unwrap()
+                .into_iter()
+                .map(|(n, v)| (String::from_utf8(n).unwrap(), String::from_utf8(v).unwrap()))
+                .collect();
+            let mut body = Vec::new();
+            let mut end = frame.flags & END_STREAM != 0;
+            while !end {
+                let frame = self.next();
+                assert_eq!((frame.kind, frame.stream_id), (DATA, id));
+                body.extend_from_slice(&frame.payload);
+                end = frame.flags & END_STREAM != 0;
+            }
+            (headers, body)
+        }
+
+        // 期望收到 GOAWAY 并且连接随后关闭
+        fn expect_goaway(&mut self, code: u32) {
+            let frame = self.next();
+            assert_eq!(frame.kind, GOAWAY);
+            assert_eq!(u32::from_be_bytes(frame.payload[4..8].try_into().unwrap()), code);
+            assert!(self.read().is_none());
+        }
+
+        // 期望收到 RST_STREAM
+        fn expect_reset(&mut self, id: u32, code: u32) {
+            let frame = self.next();
+            assert_eq!((frame.kind, frame.stream_id), (RST_STREAM, id));
+            assert_eq!(frame.payload, code.to_be_bytes());
+        }
+    }
+
+    // 测试 GET 和带请求体的 POST
+    #[test]
+    fn test_requests() {
+        let mut client = Client::connect(&[], None);
+        client.request(1, "GET", "/orders", &[], END_STREAM);
+        let (headers, body) = client.response(1);
+        assert!(headers.contains(&(":status".into(), "200".into())));
+        assert!(headers.contains(&("content-length".into(), "13".into())));
+        assert_eq!(body, b"Get /orders 0");
+
+        client.request(3, "POST", "/upload", &[("content-length", "5")], 0);
+        client.send(DATA, 0, 3, b"he");
+        client.send(DATA, END_STREAM | PADDED, 3, b"\x02llo\0\0");
+        assert_eq!(client.response(3).1, b"Post /upload 5");
+
+        // 请求的尾部头部单独保存，不会覆盖请求头
+        client.request(5, "POST", "/trailer", &[], 0);
+        client.send(DATA, 0, 5, b"abc");
+        let block = client.encoder.encode([("host", "evil"), ("x-sum", "3")]);
+        client.send(HEADERS, END_HEADERS | END_STREAM, 5, &block);
+        assert_eq!(client.response(5).1, b"Post /trailer 3 test 3");
+    }
+
+    // 测试响应体之后发送尾部头部
+    #[test]
+    fn test_trailers() {
+        let mut client = Client::connect(&[], None);
+        client.request(1, "GET", "/trailers", &[], END_STREAM);
+        assert_eq!(client.next().kind, HEADERS);
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags, frame.payload.as_slice()), (DATA, 0, &b"Get /trailers 0"[..]));
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags), (HEADERS, END_STREAM | END_HEADERS));
+        let trailers = client.decoder.decode(&frame.payload).unwrap();
+        assert_eq!(trailers, vec![(b"x-checksum".to_vec(), b"42".to_vec())]);
+    }
+
+    // 测试流式的 HttpResponse 转换为完整的响应
+    #[test]
+    fn test_streaming_response() {
+        let mut client = Client::connect(&[], None);
+        client.request(1, "GET", "/stream", &[], END_STREAM);
+        assert_eq!(client.next().kind, HEADERS);
+
+        // 第一段数据在生产者结束前就发送出来
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags, frame.payload.as_slice()), (DATA, 0, &b"one,"[..]));
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags, frame.payload.as_slice()), (DATA, 0, &b"two"[..]));
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags & END_STREAM), (HEADERS, END_STREAM));
+        let trailers = client.decoder.decode(&frame.payload).unwrap();
+        assert_eq!(trailers, vec![(b"x-rows".to_vec(), b"2".to_vec())]);
+
+        // 生产者异常退出时重置流
+        client.request(3, "GET", "/abort", &[], END_STREAM);
+        assert_eq!(client.next().kind, HEADERS);
+        assert_eq!(client.next().payload, b"one,");
+        client.expect_reset(3, INTERNAL_ERROR);
+    }
+
+    // 测试多个流交错进行
+    #[test]
+    fn test_multiplexing() {
+        let mut client = Client::connect(&[], None);
+        client.request(1, "POST", "/slow", &[], 0);
+        client.request(3, "GET", "/fast", &[], END_STREAM);
+        assert_eq!(client.response(3).1, b"Get /fast 0");
+        client.send(DATA, END_STREAM, 1, b"abc");
+        assert_eq!(client.response(1).1, b"Post /slow 3");
+    }
+
+    // 测试慢的处理器不阻塞同一连接上的其他流和 PING
+    #[test]
+    fn test_concurrent_handlers() {
+        let mut client = Client::connect(&[], None);
+        client.request(1, "GET", "/slow", &[], END_STREAM);
+        client.request(3, "GET", "/fast", &[], END_STREAM);
+        client.send(PING, 0, 0, b"12345678");
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags), (PING, ACK));
+        assert_eq!(client.response(3).1, b"Get /fast 0");
+        assert_eq!(client.response(1).1, b"Get /slow 0");
+
+        // 处理器 panic 时只重置对应的流
+        client.request(5, "GET", "/panic", &[], END_STREAM);
+        client.expect_reset(5, INTERNAL_ERROR);
+        client.request(7, "GET", "/", &[], END_STREAM);
+        assert_eq!(client.response(7).1, b"Get / 0");
+    }
+
+    // 测试被重置的流的处理器线程仍占用并发名额，线程结束后才接受新的流
+    #[test]
+    fn test_reset_streams_hold_workers() {
+        let mut client = Client::connect(&[], None);
+        for i in 0..MAX_CONCURRENT_STREAMS as u32 {
+            client.request(2 * i + 1, "GET", "/slow", &[], END_STREAM);
+            client.send(RST_STREAM, 0, 2 * i + 1, &PROTOCOL_ERROR.to_be_bytes());
+        }
+        let id = 2 * MAX_CONCURRENT_STREAMS as u32 + 1;
+        client.request(id, "GET", "/", &[], END_STREAM);
+        client.expect_reset(id, REFUSED_STREAM);
+        thread::sleep(Duration::from_millis(500));
+        client.send(PING, 0, 0, b"12345678");
+        assert_eq!(client.next().kind, PING);
+        client.request(id + 2, "GET", "/", &[], END_STREAM);
+        assert_eq!(client.response(id + 2).1, b"Get / 0");
+    }
+
+    // 测试客户端频繁重置流时发送 GOAWAY(ENHANCE_YOUR_CALM)
+    #[test]
+    fn test_rapid_reset() {
+        let mut client = Client::connect(&[], None);
+        for i in 0..=MAX_RESETS as u32 {
+            client.request(2 * i + 1, "POST", "/", &[], 0);
+            client.send(RST_STREAM, 0, 2 * i + 1, &PROTOCOL_ERROR.to_be_bytes());
+        }
+        client.expect_goaway(ENHANCE_YOUR_CALM);
+    }
+
+    // 测试处理器超时返回 503，连接继续可用
+    #[test]
+    fn test_handler_timeout() {
+        let limits = Limits {
+            handler_timeout: Some(Duration::from_millis(50)),
+            ..Limits::default()
+        };
+        let mut client = Client::connect_with_limits(&[], None, limits);
+        client.request(1, "GET", "/slow", &[], END_STREAM);
+        let (headers, body) = client.response(1);
+        assert!(headers.contains(&(":status".into(), "503".into())));
+        assert_eq!(body, b"Handler Timeout");
+        client.request(3, "GET", "/", &[], END_STREAM);
+        assert_eq!(client.response(3).1, b"Get / 0");
+    }
+
+    // 测试请求体超过 max_body_size 时先发送完整的 413 响应，再用 RST_STREAM(NO_ERROR) 结束请求
+    #[test]
+    fn test_body_too_large() {
+        let limits = Limits {
+            max_body_size: 20_000,
+            ..Limits::default()
+        };
+        let mut client = Client::connect_with_limits(&[], None, limits);
+        client.request(1, "POST", "/upload", &[], 0);
+        let chunk = vec![0; 10_000];
+        for _ in 0..3 {
+            client.send(DATA, 0, 1, &chunk);
+        }
+        let (headers, body) = client.response(1);
+        assert!(headers.contains(&(":status".into(), "413".into())));
+        assert_eq!(body, b"Payload Too Large");
+        client.expect_reset(1, NO_ERROR);
+
+        // 声明的 Content-Length 过大时不等待请求体
+        client.request(3, "POST", "/upload", &[("content-length", "20001")], 0);
+        assert_eq!(client.response(3).1, b"Payload Too Large");
+        client.expect_reset(3, NO_ERROR);
+    }
+
+    // 测试请求头超过 limits 时返回 414 或 431
+    #[test]
+    fn test_head_limits() {
+        let limits = Limits {
+            max_request_line: 32,
+            max_headers: 2,
+            max_header_size: 64,
+            ..Limits::default()
+        };
+        let mut client = Client::connect_with_limits(&[], None, limits);
+        let long_path = format!("/{}", "x".repeat(40));
+        client.request(1, "GET", &long_path, &[], END_STREAM);
+        assert_eq!(client.response(1).1, b"URI Too Long");
+        client.request(3, "GET", "/", &[("a", "1"), ("b", "2"), ("c", "3")], END_STREAM);
+        assert_eq!(client.response(3).1, b"Request Header Fields Too Large");
+        let large = "y".repeat(60);
+        client.request(5, "GET", "/", &[("x", large.as_str())], END_STREAM);
+        assert_eq!(client.response(5).1, b"Request Header Fields Too Large");
+        client.request(7, "GET", "/", &[("a", "1")], END_STREAM);
+        assert_eq!(client.response(7).1, b"Get / 0");
+    }
+
+    // 测试请求体接收过慢时返回 408
+    #[test]
+    fn test_body_timeout() {
+        let limits = Limits {
+            body_timeout: Some(Duration::from_millis(100)),
+            ..Limits::default()
+        };
+        let mut client = Client::connect_with_limits(&[], None, limits);
+        client.request(1, "POST", "/upload", &[], 0);
+        client.send(DATA, 0, 1, b"abc");
+        let (headers, body) = client.response(1);
+        assert!(headers.contains(&(":status".into(), "408".into())));
+        assert_eq!(body, b"Request Timeout");
+        client.expect_reset(1, NO_ERROR);
+    }
+
+    // 测试响应体遵守对端的流量控制窗口
+    #[test]
+    fn test_flow_control() {
+        let mut client = Client::connect(&[(SETTINGS_INITIAL_WINDOW_SIZE, 10)], None);
+        client.request(1, "GET", "/large", &[], END_STREAM);
+        assert_eq!(client.next().kind, HEADERS);
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.payload.len(), frame.flags), (DATA, 10, 0));
+
+        // 窗口用完后服务器不再发送数据，PING 的应答会先到达
+        client.send(PING, 0, 0, b"12345678");
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.flags, frame.payload.as_slice()), (PING, ACK, &b"12345678"[..]));
+
+        client.send(WINDOW_UPDATE, 0, 1, &100u32.to_be_bytes());
+        let frame = client.next();
+        assert_eq!((frame.kind, frame.payload.len(), frame.flags), (DATA, 90, END_STREAM));
+    }
+
+    // 测试 h2c 升级的请求作为流 1 响应
+    #[test]
+    fn test_upgrade() {
+        let req: HttpRequest =
+            String::from("GET /up HTTP/1.1\r\nHost: test\r\nUpgrade: h2c\r\nHTTP2-Settings: AAMAAABkAAQAAAAUAAIAAAAA\r\n\r\n").into();
+        assert!(is_h2c_upgrade(&req));
+        let mut client = Client::connect(&[], Some(req));
+        // HTTP2-Settings 把初始窗口设置为 20
+        client.request(3, "GET", "/large", &[], END_STREAM);
+        assert_eq!(client.response(1).1, b"Get /up 0");
+        assert_eq!(client.next().kind, HEADERS);
+        assert_eq!(client.next().payload.len(), 20);
+    }
+
+    // 测试流错误只重置对应的流，连接仍然可用
+    #[test]
+    fn test_stream_errors() {
+        let mut client = Client::connect(&[], None);
+        client.request(1, "GET", "/", &[("X-Upper", "1")], END_STREAM);
+        client.expect_reset(1, PROTOCOL_ERROR);
+        client.request(3, "GET", "/", &[("connection", "close")], END_STREAM);
+        client.expect_reset(3, PROTOCOL_ERROR);
+        client.request(5, "POST", "/", &[("content-length", "4")], 0);
+        client.send(DATA, END_STREAM, 5, b"abc");
+        client.expect_reset(5, PROTOCOL_ERROR);
+        client.send(DATA, END_STREAM, 5, b"abc");
+        client.expect_reset(5, STREAM_CLOSED);
+        client.request(7, "GET", "/", &[], END_STREAM);
+        assert_eq!(client.response(7).1, b"Get / 0");
+    }
+
+    // 触发错误的操作
+    type Action = Box<dyn Fn(&mut Client)>;
+
+    // 测试连接错误发送 GOAWAY 并关闭连接
+    #[test]
+    fn test_connection_errors() {
+        let cases: Vec<(Action, u32)> = vec![
+            (Box::new(|c| c.send(DATA, 0, 0, b"x")), PROTOCOL_ERROR),
+            (Box::new(|c| c.send(SETTINGS, 0, 0, &[0, 4, 0])), FRAME_SIZE_ERROR),
+            (Box::new(|c| c.send(SETTINGS, 0, 0, &[0, 4, 0x80, 0, 0, 0])), FLOW_CONTROL_ERROR),
+            (Box::new(|c| c.send(WINDOW_UPDATE, 0, 0, &[0, 0, 0, 0])), PROTOCOL_ERROR),
+            (Box::new(|c| c.send(PING, 0, 0, b"short")), FRAME_SIZE_ERROR),
+            (Box::new(|c| c.send(HEADERS, END_HEADERS | END_STREAM, 1, &[0xff, 0xff, 0xff, 0xff])), COMPRESSION_ERROR),
+            (Box::new(|c| c.send(DATA, 0, 1, &vec![0; DEFAULT_MAX_FRAME_SIZE + 1])), FRAME_SIZE_ERROR),
+            // 反复引用动态表中 4 KiB 的条目，解码后超过 SETTINGS_MAX_HEADER_LIST_SIZE
+            (
+                Box::new(|c| {
+                    // 带增量索引的字面量：名称 x，值是 4000 字节（长度编码为 0x7f 0xa1 0x1e）
+                    let mut block = vec![0x40, 0x01, b'x', 0x7f, 0xa1, 0x1e];
+                    block.extend(vec![b'y'; 4000]);
+                    block.extend(vec![0xbe; 20]);
+                    c.send(HEADERS, END_HEADERS | END_STREAM, 1, &block);
+                }),
+                ENHANCE_YOUR_CALM,
+            ),
+            (
+                Box::new(|c| {
+                    c.send(HEADERS, 0, 1, &[0x82]);
+                    c.send(PING, 0, 0, b"12345678");
+                }),
+                PROTOCOL_ERROR,
+            ),
+            (
+                Box::new(|c| {
+                    c.request(5, "GET", "/", &[], END_STREAM);
+                    c.response(5);
+                    c.request(3, "GET", "/", &[], END_STREAM);
+                }),
+                PROTOCOL_ERROR,
+            ),
+        ];
+        for (send, code) in cases {
+            let mut client = Client::connect(&[], None);
+            send(&mut client);
+            client.expect_goaway(code);
+        }
+    }
+
+    // 测试请求头校验和请求构造
+    #[test]
+    fn test_build_request() {
+        let fields: Vec<(String, String)> = [
+            (":method", "GET"),
+            (":scheme", "https"),
+            (":path", "/a?b=1"),
+            (":authority", "example.com"),
+            ("cookie", "a=1"),
+            ("cookie", "b=2"),
+            ("te", "trailers"),
+        ]
+        .iter()
+        .map(|(n, v)| (n.to_string(), v.to_string()))
+        .collect();
+        assert!(valid_request(&fields));
+        let mut misplaced = fields.clone();
+        misplaced.swap(0, 4);
+        assert!(!valid_request(&misplaced));
+        let with_path = |method: &str, path: &str| {
+            let mut fields = fields.clone();
+            fields[0].1 = method.to_string();
+            fields[2].1 = path.to_string();
+            valid_request(&fields)
+        };
+        assert!(!with_path("GET", "x"));
+        assert!(!with_path("GET", "*"));
+        assert!(!with_path("GET", ""));
+        assert!(with_path("OPTIONS", "*"));
+
+        let req = build_request(fields, b"body".to_vec());
+        assert_eq!(req.method, Method::Get);
+        assert_eq!(req.version, Version::V2_0);
+        assert_eq!(req.resource, Resource::Path("/a?b=1".into()));
+        assert_eq!(req.header("Host"), Some("example.com"));
+        assert_eq!(req.header("Cookie"), Some("a=1; b=2"));
+        assert_eq!(req.msg_body, "body");
+    }
+}
@@ -0,0 +1,25 @@
+pub mod access_log;
+pub mod auth;
+pub mod config;
+pub mod cors;
+pub mod handler;
+pub mod health;
+pub mod http2;
+pub mod jwt;
+pub mod listener;
+pub mod metrics;
+pub mod middleware;
+pub mod order_events;
+pub mod proxy;
+pub mod ratelimit;
+pub mod repository;
+pub mod request_id;
+pub mod router;
+pub mod server;
+pub mod session;
+pub mod session_store;
+pub mod sqlite_repository;
+pub mod tls;
+pub mod upstream;
+pub mod vhost;
+pub mod websocket;
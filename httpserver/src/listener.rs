@@ -0,0 +1,231 @@
+// 导入所需的库和模块
+use std::fmt; // 导入格式化模块
+use std::io; // 导入 IO 模块
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs}; // 导入 TCP 监听器和套接字地址
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream}; // 导入 Unix 域套接字
+use std::path::PathBuf; // 导入路径
+use std::time::Duration; // 导入时间间隔
+
+// Unix 域套接字地址的前缀，如 unix:/run/httpserver.sock
+pub const UNIX_PREFIX: &str = "unix:";
+
+// 监听地址
+#[derive(Debug, Clone, PartialEq)]
+pub enum ListenAddr {
+    Tcp(String),   // 主机名或 IP 地址加端口，IPv6 地址写在方括号中，如 [::]:3000
+    Unix(PathBuf), // Unix 域套接字的路径
+}
+
+impl ListenAddr {
+    // 解析监听地址，以 unix: 开头的是 Unix 域套接字
+    pub fn parse(addr: &str) -> Self {
+        match addr.strip_prefix(UNIX_PREFIX) {
+            Some(path) => ListenAddr::Unix(PathBuf::from(path)),
+            None => ListenAddr::Tcp(addr.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+// 监听的套接字
+#[derive(Debug)]
+pub enum Listener {
+    Tcp(TcpListener), // TCP 套接字
+    #[cfg(unix)]
+    Unix(UnixListener), // Unix 域套接字
+}
+
+impl Listener {
+    // 在地址上监听
+    // 主机名解析出多个地址时使用第一个可以绑定的地址；Unix 域套接字先删除上次运行留下的、已无人监听的套接字文件
+    pub fn bind(addr: &ListenAddr) -> io::Result<Self> {
+        match addr {
+            ListenAddr::Tcp(addr) => {
+                let mut last_error = None;
+                for addr in addr.to_socket_addrs()? {
+                    match bind_tcp(addr) {
+                        Ok(listener) => return Ok(Listener::Tcp(listener)),
+                        Err(e) => last_error = Some(e),
+                    }
+                }
+                Err(last_error.unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "address resolved to nothing")))
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                use std::os::unix::fs::FileTypeExt;
+                if std::fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+                    // 还能连接说明另一个进程正在使用，不删除
+                    if UnixStream::connect(path).is_ok() {
+                        return Err(io::ErrorKind::AddrInUse.into());
+                    }
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "Unix domain sockets are not supported")),
+        }
+    }
+
+    // 等待下一个连接
+    pub fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Connection::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Connection::Unix(stream)),
+        }
+    }
+
+    // 实际监听的地址，TCP 端口为 0 时是系统分配的端口
+    pub fn local_addr(&self) -> io::Result<ListenAddr> {
+        match self {
+            Listener::Tcp(listener) => Ok(ListenAddr::Tcp(listener.local_addr()?.to_string())),
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let addr = listener.local_addr()?;
+                Ok(ListenAddr::Unix(addr.as_pathname().map(PathBuf::from).unwrap_or_default()))
+            }
+        }
+    }
+}
+
+// 接受的连接
+#[derive(Debug)]
+pub enum Connection {
+    Tcp(TcpStream), // TCP 连接
+    #[cfg(unix)]
+    Unix(UnixStream), // Unix 域套接字连接
+}
+
+impl Connection {
+    // 客户端的地址，Unix 域套接字的客户端没有网络地址
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Connection::Tcp(stream) => stream.peer_addr().ok(),
+            #[cfg(unix)]
+            Connection::Unix(_) => None,
+        }
+    }
+
+    // 设置读写超时
+    pub fn set_timeouts(&self, read: Option<Duration>, write: Option<Duration>) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => {
+                stream.set_read_timeout(read)?;
+                stream.set_write_timeout(write)
+            }
+            #[cfg(unix)]
+            Connection::Unix(stream) => {
+                stream.set_read_timeout(read)?;
+                stream.set_write_timeout(write)
+            }
+        }
+    }
+}
+
+// 绑定 TCP 地址
+fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    match addr {
+        #[cfg(unix)]
+        SocketAddr::V6(addr) => bind_ipv6_only(addr),
+        _ => TcpListener::bind(addr),
+    }
+}
+
+// 绑定 IPv6 地址并设置 IPV6_V6ONLY，只接受 IPv6 连接
+// 否则 [::]:3000 在 Linux 上同时占用 IPv4 的端口，无法再监听 0.0.0.0:3000
+#[cfg(unix)]
+fn bind_ipv6_only(addr: std::net::SocketAddrV6) -> io::Result<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+    let check = |ret: libc::c_int| if ret < 0 { Err(io::Error::last_os_error()) } else { Ok(ret) };
+    // SAFETY: 新创建的套接字立即交给 TcpListener 管理，出错返回时由它关闭
+    let fd = check(unsafe { libc::socket(libc::AF_INET6, libc::SOCK_STREAM, 0) })?;
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+
+    let on: libc::c_int = 1;
+    let len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+    let mut sockaddr: libc::sockaddr_in6 = unsafe { std::mem::zeroed() };
+    sockaddr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+    sockaddr.sin6_port = addr.port().to_be();
+    sockaddr.sin6_flowinfo = addr.flowinfo();
+    sockaddr.sin6_addr.s6_addr = addr.ip().octets();
+    sockaddr.sin6_scope_id = addr.scope_id();
+    // SAFETY: fd 是有效的套接字，各参数指向大小与长度一致的局部变量
+    unsafe {
+        check(libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC))?;
+        for (level, name) in [(libc::IPPROTO_IPV6, libc::IPV6_V6ONLY), (libc::SOL_SOCKET, libc::SO_REUSEADDR)] {
+            check(libc::setsockopt(fd, level, name, &on as *const libc::c_int as *const libc::c_void, len))?;
+        }
+        let sockaddr_len = std::mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t;
+        check(libc::bind(fd, &sockaddr as *const libc::sockaddr_in6 as *const libc::sockaddr, sockaddr_len))?;
+        check(libc::listen(fd, 128))?;
+    }
+    Ok(listener)
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    // 测试监听地址的解析
+    #[test]
+    fn test_parse_listen_addr() {
+        assert_eq!(ListenAddr::parse("[::]:3000"), ListenAddr::Tcp("[::]:3000".into()));
+        assert_eq!(ListenAddr::parse("unix:/tmp/a.sock"), ListenAddr::Unix("/tmp/a.sock".into()));
+        assert_eq!(ListenAddr::parse("unix:/tmp/a.sock").to_string(), "unix:/tmp/a.sock");
+    }
+
+    // 测试 IPv4 和 IPv6 的通配地址可以监听同一个端口
+    #[test]
+    fn test_ipv4_and_ipv6_same_port() {
+        let v4 = Listener::bind(&ListenAddr::parse("0.0.0.0:0")).unwrap();
+        let ListenAddr::Tcp(addr) = v4.local_addr().unwrap() else { unreachable!() };
+        let port = addr.rsplit(':').next().unwrap();
+        let v6 = Listener::bind(&ListenAddr::parse(&format!("[::]:{}", port)));
+        // 没有 IPv6 的环境中跳过
+        if let Err(e) = &v6 {
+            assert_eq!(e.kind(), io::ErrorKind::AddrNotAvailable, "{}", e);
+            return;
+        }
+        let mut client = TcpStream::connect(format!("[::1]:{}", port)).unwrap();
+        let Connection::Tcp(mut server) = v6.unwrap().accept().unwrap() else { unreachable!() };
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+    }
+
+    // 测试 Unix 域套接字替换上次留下的套接字文件，不替换正在使用的
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_listener() {
+        let path = std::env::temp_dir().join(format!("httpserver-test-{}.sock", std::process::id()));
+        let addr = ListenAddr::Unix(path.clone());
+        drop(Listener::bind(&addr).unwrap());
+        assert!(path.exists());
+        let listener = Listener::bind(&addr).unwrap();
+        assert_eq!(listener.local_addr().unwrap(), addr);
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        let connection = listener.accept().unwrap();
+        assert_eq!(connection.peer_addr(), None);
+        let Connection::Unix(mut server) = connection else { unreachable!() };
+        client.write_all(b"ping").unwrap();
+        let mut buf = [0; 4];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"ping");
+        assert_eq!(Listener::bind(&addr).unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
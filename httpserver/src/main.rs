@@ -1,10 +1,56 @@
-mod server; 
-mod router;
-mod handler;
-
-use server::Server;
+use httpserver::auth::hash_password;
+use httpserver::config::{Cli, Config, USAGE};
+use httpserver::handler;
+use httpserver::repository;
+use std::env;
+use std::fmt::Display;
+use std::process;
 
 fn main() {
-    let server = Server::new("localhost:3000");
-    server.run();
+    let cli = Cli::parse(env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\n\n{}", e, USAGE);
+        process::exit(2);
+    });
+    if cli.help {
+        println!("{}", USAGE);
+        return;
+    }
+    // --hash-password <密码>：输出可写入账号文件的密码哈希
+    if let Some(password) = &cli.hash_password {
+        println!("{}", hash_password(password));
+        return;
+    }
+
+    // 配置文件、环境变量和命令行参数合并后校验，有错误时列出全部错误并退出
+    let config = Config::load(&cli).unwrap_or_else(|errors| {
+        eprintln!("Invalid configuration:");
+        for error in errors {
+            eprintln!("  {}", error);
+        }
+        process::exit(1);
+    });
+    // --check-config：只校验配置，不打开订单仓库、日志或会话存储，不迁移也不写入任何数据
+    if cli.check_config {
+        println!("Configuration OK");
+        return;
+    }
+
+    // 订单仓库在启动时按 [store] 和数据目录打开一次（包括 SQLite 迁移和导入），之后所有请求共享
+    if let Err(e) = repository::init(config.data_path(), config.order_store()) {
+        fail(e);
+    }
+    if let (Some(username), Some(password)) = (&config.admin.username, &config.admin.password) {
+        handler::init_admin(username, password);
+    }
+    let server = config.build().unwrap_or_else(|e| fail(e));
+    if let Err(e) = server.run() {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+// 按配置创建组件失败时输出错误并退出
+fn fail(error: impl Display) -> ! {
+    eprintln!("Invalid configuration: {}", error);
+    process::exit(1);
 }
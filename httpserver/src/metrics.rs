@@ -0,0 +1,326 @@
+// 导入所需的库和模块
+use super::middleware::Middleware; // 导入中间件
+use super::router::Router; // 导入路由模块
+use http::httprequest::{HttpRequest, Method, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use std::collections::{BTreeMap, HashMap}; // 导入有序映射和 HashMap
+use std::fmt::Write; // 导入字符串写入特性
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering}; // 导入原子计数器
+use std::sync::{Mutex, MutexGuard, OnceLock}; // 导入互斥锁和一次性初始化
+use std::time::Instant; // 导入时间模块
+
+// 请求耗时直方图的桶上限（秒）
+const DURATION_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+// Prometheus 文本格式的 Content-Type
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// 请求匹配的路由模式，自行处理请求的中间件（如反向代理）附加到请求上，代替 Router 的路由模式作为标签
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoutePattern(pub String);
+
+// 一组指标的标签：路由模式、请求方法和状态码
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Labels {
+    route: String,        // 路由模式
+    method: &'static str, // 请求方法
+    status: String,       // 响应状态码
+}
+
+// 一组标签下的请求指标
+#[derive(Debug, Clone, Default)]
+struct RequestStats {
+    count: u64,                             // 请求数
+    buckets: [u64; DURATION_BUCKETS.len()], // 耗时不超过各桶上限的请求数
+    duration_sum: f64,                      // 耗时总和（秒）
+    bytes_in: u64,                          // 请求消息体的总字节数
+    bytes_out: u64,                         // 响应消息体的总字节数
+}
+
+// 指标的注册表，进程内共享一份，服务器统计连接，中间件统计请求
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: Mutex<BTreeMap<Labels, RequestStats>>, // 按标签的请求指标，有序以便输出稳定
+    in_flight: Mutex<HashMap<&'static str, i64>>,    // 按请求方法的进行中请求数
+    open_connections: AtomicI64,                     // 打开的连接数
+    connections: AtomicU64,                          // 接受过的连接总数
+}
+
+// 进程内的指标注册表
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    // 记录一个新的连接，返回的守卫被释放时连接数减一
+    pub fn connection(&self) -> ConnectionGuard<'_> {
+        self.open_connections.fetch_add(1, Ordering::Relaxed);
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        ConnectionGuard(self)
+    }
+
+    // 获取请求指标，持有锁的线程崩溃时继续使用其中的数据
+    fn requests(&self) -> MutexGuard<'_, BTreeMap<Labels, RequestStats>> {
+        self.requests.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 调整进行中的请求数
+    fn add_in_flight(&self, method: &'static str, delta: i64) {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        *in_flight.entry(method).or_default() += delta;
+    }
+
+    // 记录一个完成的请求
+    fn observe(&self, labels: Labels, seconds: f64, bytes_in: usize, bytes_out: usize) {
+        let mut requests = self.requests();
+        let stats = requests.entry(labels).or_default();
+        stats.count += 1;
+        for (bucket, bound) in stats.buckets.iter_mut().zip(DURATION_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        stats.duration_sum += seconds;
+        stats.bytes_in += bytes_in as u64;
+        stats.bytes_out += bytes_out as u64;
+    }
+
+    // 按 Prometheus 文本格式输出全部指标
+    pub fn render(&self) -> String {
+        let requests = self.requests().clone();
+        let mut out = String::new();
+
+        header(&mut out, "http_requests_total", "counter", "Total number of HTTP requests.");
+        for (labels, stats) in &requests {
+            let _ = writeln!(out, "http_requests_total{{{}}} {}", labels.render(), stats.count);
+        }
+
+        header(&mut out, "http_request_duration_seconds", "histogram", "HTTP request latency in seconds.");
+        for (labels, stats) in &requests {
+            let labels = labels.render();
+            for (bound, count) in DURATION_BUCKETS.iter().zip(stats.buckets) {
+                let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}", labels, bound, count);
+            }
+            let _ = writeln!(out, "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}", labels, stats.count);
+            let _ = writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, stats.duration_sum);
+            let _ = writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, stats.count);
+        }
+
+        header(&mut out, "http_request_size_bytes_total", "counter", "Total size of HTTP request bodies in bytes.");
+        for (labels, stats) in &requests {
+            let _ = writeln!(out, "http_request_size_bytes_total{{{}}} {}", labels.render(), stats.bytes_in);
+        }
+
+        header(
+            &mut out,
+            "http_response_size_bytes_total",
+            "counter",
+            "Total size of HTTP response bodies in bytes, excluding streamed bodies.",
+        );
+        for (labels, stats) in &requests {
+            let _ = writeln!(out, "http_response_size_bytes_total{{{}}} {}", labels.render(), stats.bytes_out);
+        }
+
+        header(&mut out, "http_requests_in_flight", "gauge", "Number of HTTP requests being handled.");
+        let in_flight: BTreeMap<&str, i64> = self
+            .in_flight
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(method, count)| (*method, *count))
+            .collect();
+        for (method, count) in in_flight {
+            let _ = writeln!(out, "http_requests_in_flight{{method=\"{}\"}} {}", method, count);
+        }
+
+        header(&mut out, "http_open_connections", "gauge", "Number of open client connections.");
+        let _ = writeln!(out, "http_open_connections {}", self.open_connections.load(Ordering::Relaxed));
+        header(&mut out, "http_connections_total", "counter", "Total number of accepted client connections.");
+        let _ = writeln!(out, "http_connections_total {}", self.connections.load(Ordering::Relaxed));
+        out
+    }
+}
+
+// 打开的连接，释放时连接数减一
+pub struct ConnectionGuard<'m>(&'m Metrics);
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.0.open_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Labels {
+    // 输出为 Prometheus 的标签列表
+    fn render(&self) -> String {
+        format!(
+            "route=\"{}\",method=\"{}\",status=\"{}\"",
+            escape(&self.route),
+            self.method,
+            escape(&self.status)
+        )
+    }
+}
+
+// 请求开始处理的时间，指标中间件在 before 中附加到请求上
+#[derive(Debug, Clone, Copy)]
+struct Started(Instant);
+
+// 指标中间件：统计每个请求，并在配置的路径上输出指标
+// 应该尽早注册，这样被其他中间件拦截的请求也会被统计
+pub struct MetricsMiddleware {
+    path: String,               // 输出指标的路径
+    registry: &'static Metrics, // 指标注册表
+}
+
+impl MetricsMiddleware {
+    // 在 path 上输出进程内的指标
+    pub fn new(path: &str) -> Self {
+        MetricsMiddleware {
+            path: path.to_string(),
+            registry: metrics(),
+        }
+    }
+}
+
+impl Middleware for MetricsMiddleware {
+    // 记录开始时间和进行中的请求，请求指标路径时直接返回指标
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        req.extensions.insert(Started(Instant::now()));
+        self.registry.add_in_flight(req.method.as_str(), 1);
+        let Resource::Path(path) = &req.resource;
+        if path.split('?').next() != Some(self.path.as_str()) {
+            return None;
+        }
+        req.extensions.insert(RoutePattern(self.path.clone()));
+        let response = match req.method {
+            Method::Get | Method::Head => {
+                let mut headers = HashMap::new();
+                headers.insert("Content-Type", CONTENT_TYPE);
+                HttpResponse::new("200", Some(headers), Some(self.registry.render()))
+            }
+            _ => {
+                let mut resp = HttpResponse::new("405", None, None);
+                resp.add_header("Allow", "GET, HEAD");
+                resp
+            }
+        };
+        Some(response)
+    }
+
+    // 记录完成的请求，流式响应的耗时为发出响应头之前的耗时
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'_>) {
+        let method = req.method.as_str();
+        self.registry.add_in_flight(method, -1);
+        let seconds = req
+            .extensions
+            .get::<Started>()
+            .map_or(0.0, |started| started.0.elapsed().as_secs_f64());
+        let route = match req.extensions.get::<RoutePattern>() {
+            Some(pattern) => pattern.0.clone(),
+            None => Router::route_pattern(req).to_string(),
+        };
+        let labels = Labels {
+            route,
+            method,
+            status: resp.status_code().to_string(),
+        };
+        let bytes_out = if resp.is_streaming() { 0 } else { resp.body().len() };
+        self.registry.observe(labels, seconds, req.raw_body.len(), bytes_out);
+    }
+}
+
+// 输出指标的 HELP 和 TYPE 行
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// 转义标签值中的反斜杠、双引号和换行
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 使用独立注册表的指标中间件，测试之间互不影响
+    fn middleware() -> MetricsMiddleware {
+        MetricsMiddleware {
+            path: "/metrics".to_string(),
+            registry: Box::leak(Box::default()),
+        }
+    }
+
+    // 让请求经过中间件，handler 生成响应
+    fn request(metrics: &MetricsMiddleware, raw: &str, status: &'static str, body: &str) -> HttpResponse<'static> {
+        let mut req: HttpRequest = raw.to_string().into();
+        let mut resp = metrics
+            .before(&mut req)
+            .unwrap_or_else(|| HttpResponse::new(status, None, Some(body.to_string())));
+        metrics.after(&req, &mut resp);
+        resp
+    }
+
+    // 测试请求按路由模式、方法和状态码统计，耗时计入直方图
+    #[test]
+    fn test_request_metrics() {
+        let metrics = middleware();
+        request(&metrics, "GET /api/shipping/orders HTTP/1.1\r\n\r\n", "200", "[]");
+        request(&metrics, "GET /api/shipping/orders/?page=2 HTTP/1.1\r\n\r\n", "200", "[1]");
+        request(&metrics, "POST /api/shipping/orders/7/status HTTP/1.1\r\nContent-Length: 4\r\n\r\nbody", "404", "");
+        let output = metrics.registry.render();
+
+        let orders = "route=\"/api/shipping/orders\",method=\"GET\",status=\"200\"";
+        assert!(output.contains(&format!("http_requests_total{{{}}} 2\n", orders)), "{}", output);
+        assert!(output.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2\n", orders)));
+        assert!(output.contains(&format!("http_request_duration_seconds_bucket{{{},le=\"0.005\"}} 2\n", orders)));
+        assert!(output.contains(&format!("http_request_duration_seconds_count{{{}}} 2\n", orders)));
+        assert!(output.contains(&format!("http_response_size_bytes_total{{{}}} 5\n", orders)));
+        let status = "route=\"/api/shipping/orders/:id/status\",method=\"POST\",status=\"404\"";
+        assert!(output.contains(&format!("http_request_size_bytes_total{{{}}} 4\n", status)));
+        assert!(output.contains("# TYPE http_request_duration_seconds histogram\n"));
+        assert!(output.contains("http_requests_in_flight{method=\"GET\"} 0\n"));
+    }
+
+    // 测试指标路径返回 Prometheus 文本格式，连接数随守卫变化
+    #[test]
+    fn test_metrics_endpoint() {
+        let metrics = middleware();
+        let held = metrics.registry.connection();
+        drop(metrics.registry.connection());
+        let resp = request(&metrics, "GET /metrics HTTP/1.1\r\n\r\n", "404", "");
+        assert_eq!(resp.header("Content-Type"), Some(CONTENT_TYPE));
+        // 输出指标时这个请求本身仍在进行中
+        assert!(resp.body().contains("http_requests_in_flight{method=\"GET\"} 1\n"));
+        assert!(resp.body().contains("http_open_connections 1\n"));
+        assert!(resp.body().contains("http_connections_total 2\n"));
+        drop(held);
+
+        let resp = request(&metrics, "POST /metrics HTTP/1.1\r\n\r\n", "200", "");
+        assert_eq!((resp.status_code(), resp.header("Allow")), ("405", Some("GET, HEAD")));
+        let output = metrics.registry.render();
+        assert!(output.contains("http_requests_total{route=\"/metrics\",method=\"GET\",status=\"200\"} 1\n"));
+        assert!(output.contains("http_open_connections 0\n"));
+    }
+
+    // 测试路由模式：参数和未知路径被归并，代理等中间件附加的路由模式优先
+    #[test]
+    fn test_route_labels() {
+        let pattern = |raw: &str| Router::route_pattern(&raw.to_string().into()).to_string();
+        assert_eq!(pattern("GET / HTTP/1.1\r\n\r\n"), "/");
+        assert_eq!(pattern("GET /styles.css HTTP/1.1\r\n\r\n"), "/*");
+        assert_eq!(pattern("GET /api/unknown/1 HTTP/1.1\r\n\r\n"), "/api/*");
+        assert_eq!(pattern("POST /admin/orders/3/status HTTP/1.1\r\n\r\n"), "/admin/orders/:id/status");
+
+        let metrics = middleware();
+        let mut req: HttpRequest = "GET /py/a\"b HTTP/1.1\r\n\r\n".to_string().into();
+        metrics.before(&mut req);
+        req.extensions.insert(RoutePattern("/py/\"*".to_string()));
+        metrics.after(&req, &mut HttpResponse::new("502", None, None));
+        assert!(metrics.registry.render().contains("route=\"/py/\\\"*\",method=\"GET\",status=\"502\"} 1\n"));
+    }
+}
@@ -0,0 +1,14 @@
+// 导入所需的模块
+use http::{httprequest::HttpRequest, httpresponse::HttpResponse}; // 导入 HTTP 请求和响应模块
+
+// 定义中间件特性，在请求到达处理器前后执行
+// before 按注册顺序调用，after 按相反顺序调用
+pub trait Middleware: Send + Sync {
+    // 请求到达处理器之前调用，返回 Some(response) 时直接使用该响应，不再调用后续中间件和处理器
+    fn before(&self, _req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        None
+    }
+
+    // 得到响应之后调用，可以修改响应；只有 before 被调用过的中间件才会调用 after
+    fn after(&self, _req: &HttpRequest, _resp: &mut HttpResponse<'_>) {}
+}
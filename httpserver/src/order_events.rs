@@ -0,0 +1,255 @@
+// 导入所需的库和模块
+use super::repository::{order_repository, OrderStatus}; // 导入订单仓库
+use http::sse::Event; // 导入服务器发送事件
+use std::collections::{HashMap, VecDeque}; // 导入 HashMap 和队列
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender, TrySendError}; // 导入通道
+use std::sync::{Mutex, OnceLock}; // 导入锁
+use std::thread; // 导入线程模块
+use std::time::{Duration, SystemTime, UNIX_EPOCH}; // 导入时间模块
+
+// 有订阅者时检查订单数据的间隔，数据可能被其他进程修改，所以需要轮询
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// 保留的历史事件数，断线重连时 Last-Event-ID 在这个范围内就补发错过的事件
+const HISTORY_LEN: usize = 1000;
+
+// 每个订阅者最多排队的事件数，队列满说明客户端太慢，断开后让它重连补发
+const SUBSCRIBER_QUEUE_LEN: usize = 64;
+
+// 订单变化的事件源
+// 事件 ID 的格式是 "纪元-序号"，纪元是进程启动时间，重启后旧的 ID 不会被误认为可以续传
+pub struct OrderFeed {
+    epoch: u64,                              // 本进程的纪元
+    state: Mutex<FeedState>,                 // 订单快照、历史事件和订阅者
+    refresh_lock: Mutex<()>,                 // 保证加载数据和比较快照按顺序进行
+    wake: OnceLock<SyncSender<()>>,          // 唤醒后台线程立即检查，线程启动后设置
+}
+
+// 事件源的可变状态
+#[derive(Default)]
+struct FeedState {
+    orders: Option<HashMap<i32, OrderStatus>>, // 上次看到的订单，尚未加载时为 None
+    last_id: u64,                              // 最近一个事件的序号
+    history: VecDeque<(u64, Event)>,           // 最近的事件
+    subscribers: Vec<SyncSender<Event>>,       // 订阅者的队列
+}
+
+// 全局的订单事件源
+pub fn order_feed() -> &'static OrderFeed {
+    static FEED: OnceLock<OrderFeed> = OnceLock::new();
+    FEED.get_or_init(OrderFeed::new)
+}
+
+impl OrderFeed {
+    // 创建事件源
+    fn new() -> Self {
+        let epoch = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        OrderFeed {
+            epoch,
+            state: Mutex::new(FeedState::default()),
+            refresh_lock: Mutex::new(()),
+            wake: OnceLock::new(),
+        }
+    }
+
+    // 订单数据已修改，让后台线程立即检查，而不是等到下一次轮询
+    pub fn notify(&self) {
+        if let Some(wake) = self.wake.get() {
+            let _ = wake.try_send(());
+        }
+    }
+
+    // 订阅订单变化，返回需要先发送的事件和后续事件的队列
+    // last_event_id 是客户端重连时带回的 Last-Event-ID：能续传时补发错过的事件，否则先发送全部订单的快照
+    pub fn subscribe(&'static self, last_event_id: Option<&str>) -> (Vec<Event>, Receiver<Event>) {
+        self.start();
+        // 没有订阅者时后台线程不检查数据，订阅前先同步一次
+        self.refresh();
+        self.attach(last_event_id)
+    }
+
+    // 启动后台线程
+    fn start(&'static self) {
+        self.wake.get_or_init(|| {
+            let (wake, woken) = mpsc::sync_channel(1);
+            thread::spawn(move || loop {
+                match woken.recv_timeout(POLL_INTERVAL) {
+                    Ok(()) => self.refresh(),
+                    Err(RecvTimeoutError::Timeout) if self.has_subscribers() => self.refresh(),
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => return,
+                }
+            });
+            wake
+        });
+    }
+
+    // 是否有订阅者
+    fn has_subscribers(&self) -> bool {
+        !self.lock().subscribers.is_empty()
+    }
+
+    // 获取状态锁，持有锁的线程崩溃时继续使用其中的数据
+    fn lock(&self) -> std::sync::MutexGuard<'_, FeedState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 从订单仓库加载数据并发布变化
+    fn refresh(&self) {
+        let _guard = self.refresh_lock.lock().unwrap_or_else(|e| e.into_inner());
+        match order_repository().and_then(|repo| repo.list_orders()) {
+            Ok(orders) => self.publish_changes(orders),
+            Err(e) => eprintln!("Failed to load orders for event feed: {}", e),
+        }
+    }
+
+    // 与上次的订单比较，为新增或修改的订单发布 order 事件，为删除的订单发布 order-removed 事件
+    // 第一次加载只记录数据，不发布事件
+    fn publish_changes(&self, orders: Vec<OrderStatus>) {
+        let current: HashMap<i32, OrderStatus> = orders.into_iter().map(|o| (o.order_id, o)).collect();
+        let mut state = self.lock();
+        let Some(previous) = state.orders.replace(current.clone()) else {
+            return;
+        };
+
+        let mut changed: Vec<&OrderStatus> = current.values().filter(|o| previous.get(&o.order_id) != Some(*o)).collect();
+        changed.sort_by_key(|o| o.order_id);
+        let mut removed: Vec<i32> = previous.keys().filter(|id| !current.contains_key(id)).copied().collect();
+        removed.sort_unstable();
+
+        for order in changed {
+            let data = serde_json::to_string(order).unwrap_or_default();
+            self.publish(&mut state, "order", data);
+        }
+        for order_id in removed {
+            self.publish(&mut state, "order-removed", serde_json::json!({ "order_id": order_id }).to_string());
+        }
+    }
+
+    // 分配 ID，记录到历史并发送给所有订阅者，队列已满或已断开的订阅者被移除
+    fn publish(&self, state: &mut FeedState, kind: &str, data: String) {
+        state.last_id += 1;
+        let event = Event::new(data).id(self.event_id(state.last_id)).event(kind);
+        state.subscribers.retain(|s| match s.try_send(event.clone()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) => false,
+        });
+        state.history.push_back((state.last_id, event));
+        if state.history.len() > HISTORY_LEN {
+            state.history.pop_front();
+        }
+    }
+
+    // 注册订阅者并计算需要先发送的事件
+    fn attach(&self, last_event_id: Option<&str>) -> (Vec<Event>, Receiver<Event>) {
+        let (sender, receiver) = mpsc::sync_channel(SUBSCRIBER_QUEUE_LEN);
+        let mut state = self.lock();
+        let initial = match last_event_id.and_then(|id| self.resume_point(&state, id)) {
+            Some(seen) => state.history.iter().filter(|(id, _)| *id > seen).map(|(_, e)| e.clone()).collect(),
+            None => {
+                let mut orders: Vec<&OrderStatus> = state.orders.iter().flat_map(|o| o.values()).collect();
+                orders.sort_by_key(|o| o.order_id);
+                let data = serde_json::to_string(&orders).unwrap_or_default();
+                vec![Event::new(data).id(self.event_id(state.last_id)).event("snapshot")]
+            }
+        };
+        state.subscribers.push(sender);
+        (initial, receiver)
+    }
+
+    // 解析 Last-Event-ID，返回客户端已收到的序号；不是本进程的 ID 或错过的事件已不在历史中时返回 None
+    fn resume_point(&self, state: &FeedState, last_event_id: &str) -> Option<u64> {
+        let (epoch, seq) = last_event_id.trim().split_once('-')?;
+        if epoch.parse::<u64>().ok()? != self.epoch {
+            return None;
+        }
+        let seq: u64 = seq.parse().ok()?;
+        let oldest = state.history.front().map_or(state.last_id + 1, |(id, _)| *id);
+        (seq <= state.last_id && seq + 1 >= oldest).then_some(seq)
+    }
+
+    // 事件 ID
+    fn event_id(&self, seq: u64) -> String {
+        format!("{}-{}", self.epoch, seq)
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(order_id: i32, order_status: &str) -> OrderStatus {
+        OrderStatus {
+            order_id,
+            order_date: "2023-01-01".into(),
+            order_status: order_status.into(),
+        }
+    }
+
+    // 测试首次订阅收到快照，之后收到变化的事件
+    #[test]
+    fn test_snapshot_and_changes() {
+        let feed = OrderFeed::new();
+        feed.publish_changes(vec![order(1, "Shipped"), order(2, "Pending")]);
+        let (initial, events) = feed.attach(None);
+        assert_eq!(initial.len(), 1);
+        assert_eq!(initial[0].event.as_deref(), Some("snapshot"));
+        assert_eq!(initial[0].id, Some(feed.event_id(0)));
+        assert!(initial[0].data.starts_with(r#"[{"order_id":1,"#));
+
+        feed.publish_changes(vec![order(1, "Shipped"), order(2, "Delivered")]);
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event.as_deref(), Some("order"));
+        assert_eq!(event.id, Some(feed.event_id(1)));
+        assert!(event.data.contains(r#""order_status":"Delivered""#));
+        assert!(events.try_recv().is_err());
+
+        feed.publish_changes(vec![order(2, "Delivered")]);
+        let event = events.try_recv().unwrap();
+        assert_eq!(event.event.as_deref(), Some("order-removed"));
+        assert_eq!(event.data, r#"{"order_id":1}"#);
+    }
+
+    // 测试 Last-Event-ID 续传：在历史范围内补发错过的事件，否则退回快照
+    #[test]
+    fn test_resume() {
+        let feed = OrderFeed::new();
+        feed.publish_changes(vec![order(1, "A")]);
+        for status in ["B", "C", "D"] {
+            feed.publish_changes(vec![order(1, status)]);
+        }
+
+        let (initial, _) = feed.attach(Some(&feed.event_id(1)));
+        let ids: Vec<_> = initial.iter().map(|e| e.id.clone().unwrap()).collect();
+        assert_eq!(ids, vec![feed.event_id(2), feed.event_id(3)]);
+
+        let (initial, _) = feed.attach(Some(&feed.event_id(3)));
+        assert!(initial.is_empty());
+
+        // 未来的序号、其他进程的 ID 和无法解析的 ID
+        for id in [feed.event_id(9), format!("{}-1", feed.epoch + 1), "abc".to_string()] {
+            let (initial, _) = feed.attach(Some(&id));
+            assert_eq!(initial[0].event.as_deref(), Some("snapshot"), "{}", id);
+        }
+
+        // 错过的事件已不在历史中
+        let mut state = feed.lock();
+        state.history.pop_front();
+        assert_eq!(feed.resume_point(&state, &feed.event_id(0)), None);
+        assert_eq!(feed.resume_point(&state, &feed.event_id(1)), Some(1));
+    }
+
+    // 测试来不及接收的订阅者被移除
+    #[test]
+    fn test_slow_subscriber() {
+        let feed = OrderFeed::new();
+        feed.publish_changes(Vec::new());
+        let (_, slow) = feed.attach(None);
+        for i in 0..SUBSCRIBER_QUEUE_LEN + 1 {
+            feed.publish_changes(vec![order(1, &i.to_string())]);
+        }
+        assert!(!feed.has_subscribers());
+        assert_eq!(slow.iter().count(), SUBSCRIBER_QUEUE_LEN);
+    }
+}
@@ -0,0 +1,552 @@
+// 导入所需的库和模块
+use super::metrics::RoutePattern; // 导入指标的路由模式
+use super::middleware::Middleware; // 导入中间件
+use super::request_id::TraceContext; // 导入链路上下文
+use super::server::{remove_dot_segments, PeerAddr}; // 导入路径规范化和客户端地址
+use super::tls::TlsInfo; // 导入 TLS 连接信息
+use super::upstream::{Lease, UpstreamPool}; // 导入上游池
+use http::client::{BodyReader, Client, ClientError, Url}; // 导入 HTTP 客户端
+use http::httprequest::{HttpRequest, Method, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::{status_code_str, HttpResponse}; // 导入 HTTP 响应模块
+use std::collections::HashMap; // 导入 HashMap
+use std::io::{Read, Write}; // 导入读写特性
+use std::net::SocketAddr; // 导入套接字地址
+use std::sync::Arc; // 导入共享指针
+use std::thread; // 导入线程模块
+use std::time::Duration; // 导入时间间隔
+
+// 连接上游的默认超时
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 等待上游响应和读取消息体的默认超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 主动健康检查的默认间隔
+pub(crate) const DEFAULT_HEALTH_INTERVAL: Duration = Duration::from_secs(10);
+
+// 只对单个连接有意义的头部（RFC 9110 第 7.6.1 节），代理不转发
+const HOP_BY_HOP: [&str; 9] = [
+    "Connection",
+    "Keep-Alive",
+    "Proxy-Connection",
+    "Proxy-Authenticate",
+    "Proxy-Authorization",
+    "TE",
+    "Trailer",
+    "Transfer-Encoding",
+    "Upgrade",
+];
+
+// 代理的一条路由：路径前缀和上游池
+#[derive(Debug, Clone)]
+struct ProxyRoute {
+    prefix: String,          // 匹配的路径前缀，不带结尾的 /，如 /api
+    pool: Arc<UpstreamPool>, // 上游池，选中的上游地址的路径替换请求路径中的前缀
+}
+
+impl ProxyRoute {
+    // 请求路径是否在该路由下，前缀按路径段匹配，/api 不匹配 /apix
+    fn matches(&self, path: &str) -> bool {
+        path.strip_prefix(&self.prefix)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '?']))
+    }
+
+    // 把请求路径中的前缀替换为上游的路径，如 /api/orders?x=1 转发为 /v1/orders?x=1
+    fn rewrite(&self, path: &str, upstream: &Url) -> String {
+        let rest = &path[self.prefix.len()..];
+        let base = upstream.path.split('?').next().unwrap_or_default().trim_end_matches('/');
+        let rewritten = format!("{}{}", base, rest);
+        match rewritten.starts_with('/') {
+            true => rewritten,
+            false => format!("/{}", rewritten),
+        }
+    }
+}
+
+// 反向代理：把匹配路由的请求转发给上游服务，作为中间件注册在最后，之前的认证、限流等照常生效
+// 请求路径先去掉 . 和 .. 段再匹配路由，/api/../admin 不会被当作 /api 下的路径转发
+// 请求的消息体不是流式转发的：服务器先把消息体完整读入内存（受 max_body_size 限制）再发给上游，
+// 大文件上传需要调大该限制并占用同样大小的内存；上游的响应消息体边接收边转发给客户端
+pub struct ReverseProxy {
+    routes: Vec<ProxyRoute>, // 路由，最长的前缀优先
+    client: Client,          // 连接上游的客户端，连接在请求之间复用
+}
+
+impl Default for ReverseProxy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReverseProxy {
+    // 创建没有路由的代理
+    pub fn new() -> Self {
+        ReverseProxy {
+            routes: Vec::new(),
+            client: Client::new()
+                .max_redirects(0)
+                .connect_timeout(DEFAULT_CONNECT_TIMEOUT)
+                .timeout(Some(DEFAULT_TIMEOUT)),
+        }
+    }
+
+    // 把 prefix 下的请求转发到 upstream，prefix 按路径段匹配
+    pub fn route(self, prefix: &str, upstream: Url) -> Self {
+        self.balance(prefix, UpstreamPool::new(vec![upstream]))
+    }
+
+    // 把 prefix 下的请求分配给上游池中的上游，配置了健康检查时启动后台检查
+    pub fn balance(mut self, prefix: &str, pool: UpstreamPool) -> Self {
+        let pool = Arc::new(pool);
+        pool.start_health_checks();
+        self.routes.push(ProxyRoute {
+            prefix: prefix.trim_end_matches('/').to_string(),
+            pool,
+        });
+        self.routes.sort_by_key(|route| std::cmp::Reverse(route.prefix.len()));
+        self
+    }
+
+    // 设置连接上游的超时
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.connect_timeout(timeout);
+        self
+    }
+
+    // 设置等待上游响应的超时，超时返回 504
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client = self.client.timeout(Some(timeout));
+        self
+    }
+
+    // 各路由的前缀和上游池，健康检查据此检查上游
+    pub fn pools(&self) -> Vec<(String, Arc<UpstreamPool>)> {
+        self.routes.iter().map(|route| (route.prefix.clone(), route.pool.clone())).collect()
+    }
+
+    // 查找请求路径所在的路由
+    fn find_route(&self, path: &str) -> Option<&ProxyRoute> {
+        self.routes.iter().find(|route| route.matches(path))
+    }
+
+    // 把请求转发到上游并把上游的响应转换为返回给客户端的响应，path 是去掉点段后的请求路径
+    fn forward(&self, req: &HttpRequest, route: &ProxyRoute, path: &str) -> HttpResponse<'static> {
+        let Some(lease) = route.pool.select(req) else {
+            eprintln!("Proxy route {} has no available upstream", route.prefix);
+            return error_response("503", "No upstream available");
+        };
+        let url = Url {
+            path: route.rewrite(path, lease.url()),
+            ..lease.url().clone()
+        };
+        let upstream_req = upstream_request(req, &url);
+        let (resp, body) = match self.client.send_streaming(&url, &upstream_req) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("Proxy request to {} failed: {}", url, e);
+                lease.failure();
+                return match e {
+                    ClientError::Timeout => error_response("504", "Upstream timed out"),
+                    _ => error_response("502", "Upstream unavailable"),
+                };
+            }
+        };
+        // 不支持转发协议升级，请求中的 Upgrade 已被去掉，上游不应返回 101
+        let Some(status) = status_code_str(resp.status).filter(|_| resp.status >= 200) else {
+            eprintln!("Proxy request to {} got unexpected status {}", url, resp.status);
+            lease.failure();
+            return error_response("502", "Invalid upstream response");
+        };
+        // 上游自身报告的网关错误和不可用同样计入失败
+        match resp.status {
+            502..=504 => lease.failure(),
+            _ => lease.success(),
+        }
+
+        let connection = connection_tokens(resp.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        let headers = resp
+            .headers
+            .iter()
+            .filter(|(name, _)| !is_hop_by_hop(name, &connection) && !name.eq_ignore_ascii_case("Content-Length"));
+        if body.is_finished() {
+            let mut response = HttpResponse::new(status, Some(HashMap::new()), None);
+            for (name, value) in headers {
+                response.add_header(name.clone(), value.clone());
+            }
+            return response;
+        }
+        let (mut response, writer) = HttpResponse::streaming(status, Some(HashMap::new()));
+        for (name, value) in headers {
+            response.add_header(name.clone(), value.clone());
+        }
+        thread::spawn(move || pump(body, writer, url, lease));
+        response
+    }
+}
+
+impl Middleware for ReverseProxy {
+    // 匹配路由的请求由代理处理，不再交给后续的中间件和处理器
+    // Server 在中间件之前已经规范化了路径，这里再规范化一次，单独使用代理时同样不会转发点段；越过根目录的路径（如 /../x）返回 400
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let Resource::Path(path) = &req.resource;
+        let Some(path) = remove_dot_segments(path) else {
+            return Some(error_response("400", "Invalid path"));
+        };
+        let route = self.find_route(&path)?;
+        req.extensions.insert(RoutePattern(format!("{}/*", route.prefix)));
+        Some(self.forward(req, route, &path))
+    }
+}
+
+// 构造发给上游的请求：去掉逐跳头部，Host 改为上游地址，追加 X-Forwarded-* 和 Forwarded
+fn upstream_request(req: &HttpRequest, url: &Url) -> HttpRequest {
+    let mut out = HttpRequest::new(req.method, url.path.clone());
+    let connection = connection_tokens(req.headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    for (name, value) in &req.headers {
+        // 请求消息体已完整读取，Expect 和原来的长度由代理重新处理
+        let skip = ["Host", "Content-Length", "Expect"].iter().any(|h| name.eq_ignore_ascii_case(h));
+        if !skip && !is_hop_by_hop(name, &connection) {
+            out.set_header(name, value.clone());
+        }
+    }
+
+    let peer = req.extensions.get::<PeerAddr>().map(|p| p.0);
+    let proto = match req.extensions.get::<TlsInfo>() {
+        Some(_) => "https",
+        None => "http",
+    };
+    let host = req.header("Host");
+    if let Some(peer) = peer {
+        let forwarded_for = match req.header("X-Forwarded-For") {
+            Some(previous) => format!("{}, {}", previous, peer.ip()),
+            None => peer.ip().to_string(),
+        };
+        out.set_header("X-Forwarded-For", forwarded_for);
+    }
+    out.set_header("X-Forwarded-Proto", proto);
+    match host {
+        Some(host) => out.set_header("X-Forwarded-Host", host),
+        None => out.remove_header("X-Forwarded-Host"),
+    }
+    let element = forwarded_element(peer, proto, host);
+    let forwarded = match req.header("Forwarded") {
+        Some(previous) => format!("{}, {}", previous, element),
+        None => element,
+    };
+    out.set_header("Forwarded", forwarded);
+
+    // 以本服务的 span 作为上游请求的父 span，请求 ID 已由 Router 写入请求头
+    if let Some(trace) = req.extensions.get::<TraceContext>() {
+        out.set_header("traceparent", trace.traceparent());
+        match &trace.tracestate {
+            Some(state) => out.set_header("tracestate", state.clone()),
+            None => out.remove_header("tracestate"),
+        }
+    }
+
+    out.set_header("Host", url.authority());
+    if !req.raw_body.is_empty() || matches!(req.method, Method::Post | Method::Put | Method::Patch) {
+        out.set_body(req.raw_body.clone());
+        out.set_header("Content-Length", req.raw_body.len().to_string());
+    }
+    out
+}
+
+// Forwarded 头部的一个元素（RFC 7239），如 for=192.0.2.1;proto=http;host=example.com
+fn forwarded_element(peer: Option<SocketAddr>, proto: &str, host: Option<&str>) -> String {
+    let node = match peer {
+        Some(SocketAddr::V4(addr)) => addr.ip().to_string(),
+        Some(SocketAddr::V6(addr)) => format!("\"[{}]\"", addr.ip()),
+        None => "unknown".to_string(),
+    };
+    let mut element = format!("for={};proto={}", node, proto);
+    if let Some(host) = host {
+        element.push_str(";host=");
+        element.push_str(&forwarded_value(host));
+    }
+    element
+}
+
+// Forwarded 中的值：只含 token 字符时原样输出，否则加引号
+fn forwarded_value(value: &str) -> String {
+    let is_token = !value.is_empty()
+        && value
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+    match is_token {
+        true => value.to_string(),
+        false => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+    }
+}
+
+// Connection 头部列出的、同样只对当前连接有意义的头部名称（小写）
+fn connection_tokens<'h>(headers: impl Iterator<Item = (&'h str, &'h str)>) -> Vec<String> {
+    headers
+        .filter(|(name, _)| name.eq_ignore_ascii_case("Connection"))
+        .flat_map(|(_, value)| value.split(','))
+        .map(|token| token.trim().to_ascii_lowercase())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+// 是否是逐跳头部
+fn is_hop_by_hop(name: &str, connection: &[String]) -> bool {
+    HOP_BY_HOP.iter().any(|h| name.eq_ignore_ascii_case(h)) || connection.contains(&name.to_ascii_lowercase())
+}
+
+// 把上游的消息体转发给客户端，上游出错时中断响应，客户端断开时停止读取
+// 转发完成前持有 lease，最少连接策略据此统计进行中的请求
+fn pump(mut body: BodyReader, mut writer: http::chunked::BodyWriter, url: Url, _lease: Lease) {
+    let mut buffer = [0u8; 16 * 1024];
+    loop {
+        match body.read(&mut buffer) {
+            Ok(0) => {
+                for (name, value) in body.trailers() {
+                    writer.trailer(name.clone(), value.clone());
+                }
+                let _ = writer.finish();
+                return;
+            }
+            Ok(n) => {
+                if writer.write_all(&buffer[..n]).and_then(|_| writer.flush()).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                eprintln!("Proxy response from {} aborted: {}", url, e);
+                return;
+            }
+        }
+    }
+}
+
+// 代理自身产生的错误响应
+fn error_response(status: &'static str, message: &str) -> HttpResponse<'static> {
+    let mut headers = HashMap::new();
+    headers.insert("Content-Type", "text/plain; charset=utf-8");
+    HttpResponse::new(status, Some(headers), Some(message.to_string()))
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::chunked::Chunk;
+    use std::net::{TcpListener, TcpStream};
+
+    // 测试用的上游服务：读取一个请求，把收到的原始请求交给 respond 生成响应
+    fn backend(respond: fn(&str) -> String) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/v1/", listener.local_addr().unwrap())).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut raw = Vec::new();
+                let mut buffer = [0u8; 4096];
+                // 读到请求头结束，再按 Content-Length 读取消息体
+                while let Ok(n) = stream.read(&mut buffer) {
+                    if n == 0 {
+                        break;
+                    }
+                    raw.extend_from_slice(&buffer[..n]);
+                    let text = String::from_utf8_lossy(&raw);
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let req: HttpRequest = text[..end + 4].to_string().into();
+                        let length: usize = req.header("Content-Length").map_or(0, |l| l.parse().unwrap());
+                        if raw.len() >= end + 4 + length {
+                            break;
+                        }
+                    }
+                }
+                let response = respond(&String::from_utf8_lossy(&raw));
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        url
+    }
+
+    // 构造带有客户端地址的请求
+    fn request(raw: &str) -> HttpRequest {
+        let mut req: HttpRequest = raw.to_string().into();
+        req.extensions.insert(PeerAddr("192.0.2.7:5555".parse().unwrap()));
+        req
+    }
+
+    // 读取流式响应的全部内容
+    fn collect(resp: &HttpResponse) -> (String, Vec<(String, String)>) {
+        let (body, trailers) = resp.stream().unwrap().collect().unwrap();
+        (String::from_utf8(body).unwrap(), trailers)
+    }
+
+    // 测试路由匹配和路径改写
+    #[test]
+    fn test_routes() {
+        let upstream = Url::parse("http://127.0.0.1:4000/v1/").unwrap();
+        let proxy = ReverseProxy::new()
+            .route("/api/", upstream.clone())
+            .route("/api/search", Url::parse("http://127.0.0.1:5000").unwrap());
+        assert!(proxy.find_route("/apix").is_none());
+        let route = proxy.find_route("/api/orders?x=1").unwrap();
+        assert_eq!(route.rewrite("/api/orders?x=1", &upstream), "/v1/orders?x=1");
+        assert_eq!(route.rewrite("/api", &upstream), "/v1");
+        let route = proxy.find_route("/api/search/q").unwrap();
+        let search = route.pool.status()[0].url.clone();
+        assert_eq!((search.port, route.rewrite("/api/search/q", &search)), (5000, "/q".to_string()));
+        assert_eq!(route.rewrite("/api/search?q", &search), "/?q");
+
+        // 点段在匹配路由之前去掉
+        let mut escaping = request("GET /../api HTTP/1.1\r\n\r\n");
+        assert_eq!(proxy.before(&mut escaping).unwrap().status_code(), "400");
+        let mut outside = request("GET /api/../admin HTTP/1.1\r\n\r\n");
+        assert!(proxy.before(&mut outside).is_none());
+    }
+
+    // 测试上游的状态码原样转发，状态文本不会被误报为其他状态
+    #[test]
+    fn test_forward_status() {
+        let status_line = |respond: fn(&str) -> String| {
+            let proxy = ReverseProxy::new().route("/api", backend(respond));
+            let resp = proxy.before(&mut request("GET /api/orders HTTP/1.1\r\n\r\n")).unwrap();
+            String::from(resp).lines().next().unwrap().to_string()
+        };
+        let unprocessable = status_line(|_| "HTTP/1.1 422 Unprocessable Entity\r\nContent-Length: 0\r\n\r\n".to_string());
+        assert_eq!(unprocessable, "HTTP/1.1 422 Unprocessable Entity");
+        let unknown = status_line(|_| "HTTP/1.1 299 Custom\r\nContent-Length: 0\r\n\r\n".to_string());
+        assert_eq!(unknown, "HTTP/1.1 299 ");
+    }
+
+    // 测试转发的请求头：逐跳头部被去掉，追加 X-Forwarded-* 和 Forwarded，Host 改为上游地址，传递请求 ID 和链路上下文
+    #[test]
+    fn test_forward_request() {
+        let url = backend(|raw| {
+            let body = raw.to_string();
+            format!("HTTP/1.1 201 Created\r\nContent-Length: {}\r\nKeep-Alive: timeout=5\r\n\r\n{}", body.len(), body)
+        });
+        let proxy = ReverseProxy::new().route("/api", url.clone());
+        let mut req = request(
+            "POST /api/orders?x=1 HTTP/1.1\r\nHost: shop.example.com\r\nConnection: keep-alive, X-Secret\r\nX-Secret: s\r\n\
+             Keep-Alive: 5\r\nX-Forwarded-For: 198.51.100.1\r\nForwarded: for=198.51.100.1\r\nContent-Length: 4\r\n\r\nbody",
+        );
+        req.set_header("traceparent", "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        crate::request_id::assign(&mut req);
+        let resp = proxy.before(&mut req).unwrap();
+        assert_eq!(resp.status_code(), "201");
+        assert_eq!(resp.header("Keep-Alive"), None);
+        let (echoed, _) = collect(&resp);
+
+        let (head, body) = echoed.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /v1/orders?x=1 HTTP/1.1\r\n"));
+        assert_eq!(body, "body");
+        let forwarded: HttpRequest = format!("{}\r\n\r\n", head).into();
+        assert_eq!(forwarded.header("Host"), Some(url.authority().as_str()));
+        assert_eq!(forwarded.header("X-Forwarded-For"), Some("198.51.100.1, 192.0.2.7"));
+        assert_eq!(forwarded.header("X-Forwarded-Proto"), Some("http"));
+        assert_eq!(forwarded.header("X-Forwarded-Host"), Some("shop.example.com"));
+        assert_eq!(
+            forwarded.header("Forwarded"),
+            Some("for=198.51.100.1, for=192.0.2.7;proto=http;host=shop.example.com")
+        );
+        for name in ["Connection", "Keep-Alive", "X-Secret"] {
+            assert_eq!(forwarded.header(name), None, "{}", name);
+        }
+        assert_eq!(forwarded.header("Content-Length"), Some("4"));
+
+        // 请求 ID 原样转发，traceparent 以本服务的 span 作为父 span
+        assert_eq!(forwarded.header("X-Request-Id"), crate::request_id::request_id(&req));
+        let trace = req.extensions.get::<TraceContext>().unwrap();
+        assert_eq!(forwarded.header("traceparent"), Some(trace.traceparent().as_str()));
+        assert!(trace.traceparent().starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-"));
+    }
+
+    // 测试分块编码的上游响应逐块转发，尾部头部也被转发
+    #[test]
+    fn test_streaming_response() {
+        let url = backend(|_| {
+            "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\n\r\n\
+             4\r\none,\r\n3\r\ntwo\r\n0\r\nX-Rows: 2\r\n\r\n"
+                .to_string()
+        });
+        let proxy = ReverseProxy::new().route("/api", url);
+        let mut req = request("GET /api/stream HTTP/1.1\r\nHost: a\r\n\r\n");
+        let resp = proxy.before(&mut req).unwrap();
+        assert_eq!(resp.header("Content-Type"), Some("text/plain"));
+        assert_eq!(resp.header("Transfer-Encoding"), None);
+        let stream = resp.stream().unwrap();
+        let mut body = Vec::new();
+        loop {
+            match stream.recv().unwrap() {
+                Chunk::Data(data) => body.extend(data),
+                Chunk::End(trailers) => {
+                    assert_eq!(trailers, vec![("X-Rows".to_string(), "2".to_string())]);
+                    break;
+                }
+            }
+        }
+        assert_eq!(body, b"one,two");
+    }
+
+    // 测试上游不可用返回 502，超时返回 504，不匹配的请求交给后续处理
+    #[test]
+    fn test_upstream_failures() {
+        let unused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = Url::parse(&format!("http://{}/", unused.local_addr().unwrap())).unwrap();
+        drop(unused);
+        let proxy = ReverseProxy::new().route("/api", closed);
+        let mut req = request("GET /api/x HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert_eq!(proxy.before(&mut req).unwrap().status_code(), "502");
+        let mut other = request("GET /index.html HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(proxy.before(&mut other).is_none());
+
+        // 接受连接但不响应的上游
+        let silent = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/", silent.local_addr().unwrap())).unwrap();
+        let held = thread::spawn(move || silent.accept().map(|(stream, _)| stream));
+        let proxy = ReverseProxy::new().route("/api", url).timeout(Duration::from_millis(100));
+        let resp = proxy.before(&mut req).unwrap();
+        assert_eq!((resp.status_code(), resp.body()), ("504", "Upstream timed out"));
+        let _stream: TcpStream = held.join().unwrap().unwrap();
+
+        // 上游返回的不是 HTTP 响应
+        let url = backend(|_| "garbage\r\n\r\n".to_string());
+        let proxy = ReverseProxy::new().route("/api", url);
+        assert_eq!(proxy.before(&mut req).unwrap().status_code(), "502");
+    }
+
+    // 测试请求在上游池中轮流分配，连接失败的上游被摘除，全部不可用时返回 503
+    #[test]
+    fn test_balance() {
+        let a = backend(|_| "HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\na".to_string());
+        let b = backend(|_| "HTTP/1.1 200 OK\r\nContent-Length: 1\r\n\r\nb".to_string());
+        let unused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = Url::parse(&format!("http://{}/", unused.local_addr().unwrap())).unwrap();
+        drop(unused);
+        let proxy = ReverseProxy::new().balance("/api", UpstreamPool::new(vec![a, closed.clone(), b]).max_fails(1));
+        let mut req = request("GET /api/x HTTP/1.1\r\nHost: a\r\n\r\n");
+        let mut results = Vec::new();
+        for _ in 0..5 {
+            let resp = proxy.before(&mut req).unwrap();
+            results.push(match resp.status_code() {
+                "200" => collect(&resp).0,
+                status => status.to_string(),
+            });
+        }
+        assert_eq!(results, ["a", "502", "b", "a", "b"]);
+
+        let proxy = ReverseProxy::new().balance("/api", UpstreamPool::new(vec![closed.clone(), closed]).max_fails(1));
+        assert_eq!(proxy.before(&mut req).unwrap().status_code(), "502");
+        assert_eq!(proxy.before(&mut req).unwrap().status_code(), "502");
+        let resp = proxy.before(&mut req).unwrap();
+        assert_eq!((resp.status_code(), resp.body()), ("503", "No upstream available"));
+    }
+
+    // 测试 Forwarded 中的地址和主机的格式
+    #[test]
+    fn test_forwarded_element() {
+        let v6: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+        assert_eq!(
+            forwarded_element(Some(v6), "https", Some("example.com:8443")),
+            "for=\"[2001:db8::1]\";proto=https;host=\"example.com:8443\""
+        );
+        assert_eq!(forwarded_element(None, "http", None), "for=unknown;proto=http");
+    }
+}
@@ -0,0 +1,427 @@
+// 导入所需的库和模块
+use super::middleware::Middleware; // 导入中间件
+use super::server::PeerAddr; // 导入客户端地址
+use http::httprequest::{HttpRequest, Method, Resource}; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use serde_json::json; // 导入 json! 宏
+use std::collections::hash_map::Entry; // 导入 HashMap 的条目
+use std::collections::{BTreeMap, HashMap, HashSet}; // 导入集合类型
+use std::net::IpAddr; // 导入 IP 地址
+use std::sync::atomic::{AtomicUsize, Ordering}; // 导入原子计数器
+use std::sync::Mutex; // 导入互斥锁
+use std::time::{Duration, Instant}; // 导入时间模块
+
+// 每处理这么多请求清理一次已经补满的令牌桶
+const SWEEP_INTERVAL: usize = 1000;
+
+// 定义一个配额：每个时间窗口内允许的请求数
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quota {
+    limit: u32,       // 桶容量，即窗口内允许的请求数
+    window: Duration, // 令牌完全补满所需的时间
+}
+
+impl Quota {
+    // 创建配额，limit 至少为 1，窗口至少为 1 秒
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Quota {
+            limit: limit.max(1),
+            window: window.max(Duration::from_secs(1)),
+        }
+    }
+
+    // 每分钟允许 limit 个请求
+    pub fn per_minute(limit: u32) -> Self {
+        Self::new(limit, Duration::from_secs(60))
+    }
+
+    // 解析 "请求数/秒数" 格式的配额，如 100/60
+    pub fn parse(value: &str) -> Option<Self> {
+        let (limit, secs) = value.trim().split_once('/')?;
+        Some(Self::new(
+            limit.trim().parse().ok()?,
+            Duration::from_secs(secs.trim().parse().ok()?),
+        ))
+    }
+
+    // 每秒补充的令牌数
+    fn rate(&self) -> f64 {
+        self.limit as f64 / self.window.as_secs_f64()
+    }
+
+    // 补充指定数量的令牌所需的秒数（向上取整）
+    fn secs_for(&self, tokens: f64) -> u64 {
+        (tokens * self.window.as_secs_f64() / self.limit as f64).ceil() as u64
+    }
+}
+
+// 定义限流的键来源
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeySource {
+    Peer,                     // 客户端的 IP 地址
+    ForwardedFor(Vec<IpAddr>), // 来自受信任代理时使用 X-Forwarded-For 中的客户端地址
+    Header(String, HashSet<String>), // 请求头名和已知的 API Key，缺失或未知时退回客户端 IP 地址
+}
+
+// 令牌桶
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,      // 剩余令牌数
+    updated: Instant, // 上次更新时间
+    used: u64,        // 最近一次使用的序号，用于按使用顺序淘汰
+}
+
+// 令牌桶的键：（配额序号，客户端键）
+type BucketKey = (usize, String);
+
+// 按最近使用顺序排列的令牌桶（LRU），查找、更新和淘汰最久未使用的令牌桶都是 O(log n)
+#[derive(Debug, Default)]
+struct Buckets {
+    map: HashMap<BucketKey, Bucket>, // 键到令牌桶的映射
+    order: BTreeMap<u64, BucketKey>, // 使用序号到键的映射，最久未使用的在前
+    next: u64,                       // 下一个使用序号
+}
+
+impl Buckets {
+    // 取出键对应的令牌桶并标记为最近使用，不存在时用 new 创建
+    fn touch(&mut self, key: BucketKey, new: Bucket) -> &mut Bucket {
+        let used = self.next;
+        self.next += 1;
+        self.order.insert(used, key.clone());
+        let bucket = match self.map.entry(key) {
+            Entry::Occupied(entry) => {
+                let bucket = entry.into_mut();
+                self.order.remove(&bucket.used);
+                bucket
+            }
+            Entry::Vacant(entry) => entry.insert(new),
+        };
+        bucket.used = used;
+        bucket
+    }
+
+    // 删除最久未使用的令牌桶
+    fn pop_oldest(&mut self) -> Option<BucketKey> {
+        let (_, key) = self.order.pop_first()?;
+        self.map.remove(&key);
+        Some(key)
+    }
+
+    // 从最久未使用的开始删除空闲超过 idle 的令牌桶，遇到更近使用的就停止
+    fn sweep(&mut self, now: Instant, idle: Duration) {
+        while let Some((_, key)) = self.order.first_key_value() {
+            if now.saturating_duration_since(self.map[key].updated) < idle {
+                break;
+            }
+            self.pop_oldest();
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    fn contains_key(&self, key: &BucketKey) -> bool {
+        self.map.contains_key(key)
+    }
+}
+
+// 本次请求的限流状态，附加到请求上供处理器和响应使用
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimitStatus {
+    pub limit: u32,       // 窗口内允许的请求数
+    pub remaining: u32,   // 剩余可用请求数
+    pub reset: u64,       // 令牌补满所需的秒数
+    pub retry_after: u64, // 被限流时需要等待的秒数
+    window: u64,          // 窗口长度（秒）
+}
+
+impl RateLimitStatus {
+    // 为响应添加 RateLimit-* 头部
+    fn apply(&self, resp: &mut HttpResponse<'_>) {
+        resp.add_header("RateLimit-Policy", format!("{};w={}", self.limit, self.window));
+        resp.add_header("RateLimit-Limit", self.limit.to_string());
+        resp.add_header("RateLimit-Remaining", self.remaining.to_string());
+        resp.add_header("RateLimit-Reset", self.reset.to_string());
+    }
+}
+
+// 定义一条按路由的配额
+struct RouteQuota {
+    method: Option<Method>, // 匹配的请求方法，None 表示全部方法
+    prefix: String,         // 匹配的路径前缀
+    quota: Quota,           // 配额
+}
+
+// 限流中间件：按客户端为每条路由维护令牌桶，超出配额时返回 429
+pub struct RateLimitMiddleware {
+    default: Quota,                                   // 没有匹配路由配额时使用的配额
+    routes: Vec<RouteQuota>,                          // 按路由的配额，按注册顺序匹配第一条
+    key: KeySource,                                   // 限流的键来源
+    max_buckets: usize,                               // 最多保存的令牌桶数量
+    buckets: Mutex<Buckets>,                          // 按最近使用排列的令牌桶
+    requests: AtomicUsize,                            // 已处理的请求数，用于定期清理
+}
+
+impl RateLimitMiddleware {
+    // 使用默认配额创建限流中间件，默认按客户端 IP 限流
+    pub fn new(default: Quota) -> Self {
+        RateLimitMiddleware {
+            default,
+            routes: Vec::new(),
+            key: KeySource::Peer,
+            max_buckets: 100_000,
+            buckets: Mutex::new(Buckets::default()),
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    // 设置限流的键来源
+    pub fn key(mut self, key: KeySource) -> Self {
+        self.key = key;
+        self
+    }
+
+    // 为匹配的路由设置单独的配额
+    pub fn route(mut self, method: Option<Method>, prefix: &str, quota: Quota) -> Self {
+        self.routes.push(RouteQuota {
+            method,
+            prefix: prefix.to_string(),
+            quota,
+        });
+        self
+    }
+
+    // 设置最多保存的令牌桶数量
+    pub fn max_buckets(mut self, max_buckets: usize) -> Self {
+        self.max_buckets = max_buckets.max(1);
+        self
+    }
+
+    // 找到请求匹配的配额，返回配额序号和配额
+    fn quota_for(&self, req: &HttpRequest) -> (usize, Quota) {
+        let Resource::Path(path) = &req.resource;
+        self.routes
+            .iter()
+            .position(|r| {
+                r.method.as_ref().is_none_or(|m| *m == req.method) && path.starts_with(&r.prefix)
+            })
+            .map_or((self.routes.len(), self.default), |i| (i, self.routes[i].quota))
+    }
+
+    // 计算请求的客户端键
+    // 只有已知的 API Key 才单独计数，否则客户端每次换一个随机的值就能得到新的令牌桶
+    fn client_key(&self, req: &HttpRequest) -> String {
+        let peer = req.extensions.get::<PeerAddr>().map(|p| p.0.ip());
+        let ip = match &self.key {
+            KeySource::Header(name, keys) => {
+                if let Some(key) = req.header(name).map(str::trim).filter(|k| keys.contains(*k)) {
+                    return format!("key:{}", key);
+                }
+                peer
+            }
+            KeySource::ForwardedFor(trusted) => match peer {
+                // 从右往左跳过受信任的代理，第一个不受信任的地址就是客户端
+                Some(ip) if trusted.contains(&ip) => req
+                    .header("X-Forwarded-For")
+                    .unwrap_or_default()
+                    .rsplit(',')
+                    .filter_map(|addr| addr.trim().parse::<IpAddr>().ok())
+                    .find(|addr| !trusted.contains(addr))
+                    .or(peer),
+                _ => peer,
+            },
+            KeySource::Peer => peer,
+        };
+        ip.map_or_else(|| "ip:unknown".to_string(), |ip| format!("ip:{}", ip))
+    }
+
+    // 从令牌桶中取出一个令牌，返回是否允许和限流状态
+    fn check(&self, index: usize, quota: Quota, key: String, now: Instant) -> (bool, RateLimitStatus) {
+        let mut buckets = self.buckets.lock().unwrap();
+        let key = (index, key);
+        if buckets.len() >= self.max_buckets && !buckets.contains_key(&key) {
+            self.evict(&mut buckets, now);
+        }
+
+        let limit = quota.limit as f64;
+        let rate = quota.rate();
+        let bucket = buckets.touch(
+            key,
+            Bucket {
+                tokens: limit,
+                updated: now,
+                used: 0, // 由 touch 设置
+            },
+        );
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(limit);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let status = RateLimitStatus {
+            limit: quota.limit,
+            remaining: bucket.tokens.floor() as u32,
+            reset: quota.secs_for(limit - bucket.tokens),
+            retry_after: if allowed { 0 } else { quota.secs_for(1.0 - bucket.tokens) },
+            window: quota.window.as_secs(),
+        };
+        (allowed, status)
+    }
+
+    // 删除已经补满的空闲令牌桶（空闲超过最长的配额窗口），仍然超出上限时删除最久未使用的令牌桶
+    fn evict(&self, buckets: &mut Buckets, now: Instant) {
+        buckets.sweep(now, self.max_window());
+        while buckets.len() >= self.max_buckets && buckets.pop_oldest().is_some() {}
+    }
+
+    // 最长的配额窗口，空闲这么久的令牌桶一定已经补满
+    fn max_window(&self) -> Duration {
+        self.routes.iter().map(|r| r.quota.window).fold(self.default.window, Duration::max)
+    }
+}
+
+impl Middleware for RateLimitMiddleware {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let now = Instant::now();
+        if self.requests.fetch_add(1, Ordering::Relaxed).is_multiple_of(SWEEP_INTERVAL) {
+            let mut buckets = self.buckets.lock().unwrap();
+            self.evict(&mut buckets, now);
+        }
+
+        let (index, quota) = self.quota_for(req);
+        let (allowed, status) = self.check(index, quota, self.client_key(req), now);
+        req.extensions.insert(status);
+        if allowed {
+            return None;
+        }
+
+        let mut headers: HashMap<&str, &str> = HashMap::new();
+        headers.insert("Content-Type", "application/json");
+        let body = json!({
+            "error": "rate_limited",
+            "message": format!("too many requests, retry after {} seconds", status.retry_after),
+        });
+        let mut resp = HttpResponse::new("429", Some(headers), Some(body.to_string()));
+        resp.add_header("Retry-After", status.retry_after.to_string());
+        Some(resp)
+    }
+
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'_>) {
+        if let Some(status) = req.extensions.get::<RateLimitStatus>() {
+            status.apply(resp);
+        }
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+    use std::net::SocketAddr; // 导入套接字地址
+
+    // 构造来自指定地址、带有指定请求头的请求
+    fn request(method: &str, path: &str, peer: &str, headers: &[(&str, &str)]) -> HttpRequest {
+        let headers: String = headers.iter().map(|(k, v)| format!("{}: {}\r\n", k, v)).collect();
+        let mut req: HttpRequest = format!("{} {} HTTP/1.1\r\n{}\r\n", method, path, headers).into();
+        req.extensions.insert(PeerAddr(peer.parse::<SocketAddr>().unwrap()));
+        req
+    }
+
+    // 测试令牌耗尽后被限流，并随时间补充
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimitMiddleware::new(Quota::new(2, Duration::from_secs(10)));
+        let start = Instant::now();
+        let quota = limiter.default;
+        let check = |secs: u64| limiter.check(1, quota, "ip:1.2.3.4".into(), start + Duration::from_secs(secs));
+
+        let (allowed, status) = check(0);
+        assert!(allowed);
+        assert_eq!((status.remaining, status.reset), (1, 5));
+        assert!(check(0).0);
+        let (allowed, status) = check(0);
+        assert!(!allowed);
+        assert_eq!((status.remaining, status.retry_after), (0, 5));
+        assert!(check(5).0);
+        assert!(!check(5).0);
+        assert!(check(100).0);
+    }
+
+    // 测试 429 响应、RateLimit 头部和按路由的配额
+    #[test]
+    fn test_middleware_and_route_quotas() {
+        let limiter = RateLimitMiddleware::new(Quota::per_minute(100))
+            .route(Some(Method::Post), "/admin/login", Quota::per_minute(1));
+
+        let mut req = request("POST", "/admin/login", "10.0.0.1:5000", &[]);
+        assert!(limiter.before(&mut req).is_none());
+        let mut resp = HttpResponse::new("200", None, None);
+        limiter.after(&req, &mut resp);
+        let raw: String = resp.into();
+        assert!(raw.contains("RateLimit-Limit:1\r\n"));
+        assert!(raw.contains("RateLimit-Remaining:0\r\n"));
+        assert!(raw.contains("RateLimit-Policy:1;w=60\r\n"));
+
+        let mut req = request("POST", "/admin/login", "10.0.0.1:5001", &[]);
+        let raw: String = limiter.before(&mut req).unwrap().into();
+        assert!(raw.starts_with("HTTP/1.1 429 Too Many Requests"));
+        assert!(raw.contains("Retry-After:60\r\n"));
+
+        // 其他路由和其他客户端不受影响
+        assert!(limiter.before(&mut request("GET", "/admin", "10.0.0.1:5002", &[])).is_none());
+        assert!(limiter.before(&mut request("POST", "/admin/login", "10.0.0.2:5000", &[])).is_none());
+    }
+
+    // 测试受信任代理的 X-Forwarded-For 和 API Key
+    #[test]
+    fn test_client_keys() {
+        let proxy: IpAddr = "10.0.0.254".parse().unwrap();
+        let limiter = RateLimitMiddleware::new(Quota::per_minute(1))
+            .key(KeySource::ForwardedFor(vec![proxy]));
+        let xff = [("X-Forwarded-For", "203.0.113.9, 198.51.100.7, 10.0.0.254")];
+        assert_eq!(limiter.client_key(&request("GET", "/", "10.0.0.254:80", &xff)), "ip:198.51.100.7");
+        // 不受信任的客户端不能伪造 X-Forwarded-For
+        assert_eq!(limiter.client_key(&request("GET", "/", "192.0.2.1:80", &xff)), "ip:192.0.2.1");
+
+        let keys = HashSet::from(["abc".to_string()]);
+        let limiter = RateLimitMiddleware::new(Quota::per_minute(1)).key(KeySource::Header("X-Api-Key".into(), keys));
+        assert_eq!(limiter.client_key(&request("GET", "/", "192.0.2.1:80", &[("X-Api-Key", "abc")])), "key:abc");
+        assert_eq!(limiter.client_key(&request("GET", "/", "192.0.2.1:80", &[])), "ip:192.0.2.1");
+        // 未知的 API Key 按客户端地址限流，换一个随机的值不能绕过限流
+        assert_eq!(limiter.client_key(&request("GET", "/", "192.0.2.1:80", &[("X-Api-Key", "random")])), "ip:192.0.2.1");
+        assert!(limiter.before(&mut request("GET", "/", "192.0.2.1:80", &[("X-Api-Key", "r1")])).is_none());
+        assert!(limiter.before(&mut request("GET", "/", "192.0.2.1:80", &[("X-Api-Key", "r2")])).is_some());
+    }
+
+    // 测试令牌桶数量有上限，空闲的令牌桶被清理
+    #[test]
+    fn test_bounded_buckets() {
+        let limiter = RateLimitMiddleware::new(Quota::new(5, Duration::from_secs(10))).max_buckets(2);
+        let quota = limiter.default;
+        let start = Instant::now();
+        limiter.check(0, quota, "a".into(), start);
+        limiter.check(0, quota, "b".into(), start + Duration::from_secs(1));
+        limiter.check(0, quota, "c".into(), start + Duration::from_secs(2));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(!buckets.contains_key(&(0, "a".to_string())));
+        drop(buckets);
+
+        // 再次使用的令牌桶变为最近使用，淘汰的是另一个
+        limiter.check(0, quota, "b".into(), start + Duration::from_secs(3));
+        limiter.check(0, quota, "e".into(), start + Duration::from_secs(4));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(buckets.contains_key(&(0, "b".to_string())) && !buckets.contains_key(&(0, "c".to_string())));
+        assert_eq!(buckets.order.len(), 2);
+        drop(buckets);
+
+        limiter.check(0, quota, "d".into(), start + Duration::from_secs(60));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.map.keys().map(|(_, k)| k.as_str()).collect::<Vec<_>>(), vec!["d"]);
+        assert_eq!(buckets.order.len(), 1);
+    }
+}
@@ -0,0 +1,245 @@
+// 导入所需的库和模块
+use super::sqlite_repository::SqliteOrderRepository; // 导入 SQLite 订单仓库
+use serde::{Deserialize, Serialize}; // 导入序列化和反序列化库
+use std::fmt; // 导入格式化模块
+use std::fs; // 导入文件系统模块
+use std::path::{Path, PathBuf}; // 导入路径模块
+use std::sync::{Mutex, OnceLock}; // 导入互斥锁和一次性初始化
+
+// 定义 OrderStatus 结构体，用于序列化和反序列化订单状态
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+pub struct OrderStatus {
+    pub order_id: i32,         // 订单 ID
+    pub order_date: String,    // 订单日期
+    pub order_status: String,  // 订单状态
+}
+
+// 定义仓库操作可能出现的错误
+#[derive(Debug)]
+pub enum RepositoryError {
+    Io(std::io::Error),            // 文件读写错误
+    Json(serde_json::Error),       // JSON 解析错误
+    Sqlite(rusqlite::Error),       // SQLite 错误
+    NotFound(i32),                 // 订单不存在
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::Io(e) => write!(f, "io error: {}", e),
+            RepositoryError::Json(e) => write!(f, "json error: {}", e),
+            RepositoryError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            RepositoryError::NotFound(id) => write!(f, "order {} not found", id),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {}
+
+impl From<std::io::Error> for RepositoryError {
+    fn from(e: std::io::Error) -> Self {
+        RepositoryError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for RepositoryError {
+    fn from(e: serde_json::Error) -> Self {
+        RepositoryError::Json(e)
+    }
+}
+
+impl From<rusqlite::Error> for RepositoryError {
+    fn from(e: rusqlite::Error) -> Self {
+        RepositoryError::Sqlite(e)
+    }
+}
+
+// 仓库操作的结果类型
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+// 定义订单仓库抽象，WebServiceHandler 只依赖这个特性
+// 进程内只有一个实例，由所有请求线程共享
+pub trait OrderRepository: Send + Sync {
+    // 返回全部订单
+    fn list_orders(&self) -> RepositoryResult<Vec<OrderStatus>>;
+
+    // 按 ID 查询单个订单
+    fn get_order(&self, order_id: i32) -> RepositoryResult<Option<OrderStatus>>;
+
+    // 更新订单状态，返回更新后的订单
+    fn update_status(&self, order_id: i32, order_status: &str) -> RepositoryResult<OrderStatus>;
+}
+
+// 基于 orders.json 文件的订单仓库
+pub struct JsonOrderRepository {
+    path: PathBuf,      // orders.json 的完整路径
+    writing: Mutex<()>, // 串行化读-改-写，避免并发更新互相覆盖
+}
+
+impl JsonOrderRepository {
+    // 创建一个新的 JSON 订单仓库
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonOrderRepository {
+            path: path.into(),
+            writing: Mutex::new(()),
+        }
+    }
+
+    // 将订单列表写回磁盘，先写临时文件再重命名，避免写到一半的文件被读取
+    fn save(&self, orders: &[OrderStatus]) -> RepositoryResult<()> {
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(orders)?)?;
+        fs::rename(tmp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl OrderRepository for JsonOrderRepository {
+    fn list_orders(&self) -> RepositoryResult<Vec<OrderStatus>> {
+        // 读取 JSON 文件内容并解析为 OrderStatus 结构体的向量
+        let json_contents = fs::read_to_string(&self.path)?;
+        Ok(serde_json::from_str(&json_contents)?)
+    }
+
+    fn get_order(&self, order_id: i32) -> RepositoryResult<Option<OrderStatus>> {
+        Ok(self
+            .list_orders()?
+            .into_iter()
+            .find(|o| o.order_id == order_id))
+    }
+
+    fn update_status(&self, order_id: i32, order_status: &str) -> RepositoryResult<OrderStatus> {
+        let _writing = self.writing.lock().unwrap_or_else(|e| e.into_inner());
+        let mut orders = self.list_orders()?;
+        let order = orders
+            .iter_mut()
+            .find(|o| o.order_id == order_id)
+            .ok_or(RepositoryError::NotFound(order_id))?;
+        order.order_status = order_status.to_string();
+        let updated = order.clone();
+        self.save(&orders)?;
+        Ok(updated)
+    }
+}
+
+// 订单仓库的后端
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OrderStore {
+    #[default]
+    Json,            // 数据目录下的 orders.json
+    Sqlite(PathBuf), // SQLite 数据库，首次使用空数据库时从 orders.json 导入
+}
+
+// 数据目录和订单仓库的后端，由 init 按配置设置
+static DATA_PATH: OnceLock<PathBuf> = OnceLock::new();
+static STORE: OnceLock<OrderStore> = OnceLock::new();
+
+// 设置数据目录和后端并打开订单仓库，main 按配置在启动时调用一次，之后再调用不会改变它们
+pub fn init(data_path: impl Into<PathBuf>, store: OrderStore) -> RepositoryResult<&'static dyn OrderRepository> {
+    let _ = DATA_PATH.set(data_path.into());
+    let _ = STORE.set(store);
+    order_repository()
+}
+
+// 返回数据目录：init 设置的目录，没有调用 init 时（如测试中）使用 crate 目录下的 data
+pub fn data_path() -> PathBuf {
+    DATA_PATH.get().cloned().unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("data"))
+}
+
+// 返回进程共享的订单仓库，第一次调用时打开，之后复用同一个实例
+// main 通过 init 在启动时打开，配置错误时立即退出；打开失败时不缓存，下次调用重试
+pub fn order_repository() -> RepositoryResult<&'static dyn OrderRepository> {
+    static REPOSITORY: OnceLock<Box<dyn OrderRepository>> = OnceLock::new();
+    static OPENING: Mutex<()> = Mutex::new(());
+    if let Some(repo) = REPOSITORY.get() {
+        return Ok(repo.as_ref());
+    }
+    // 同时到达的请求等待同一次打开，迁移和导入只执行一次
+    let _opening = OPENING.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(repo) = REPOSITORY.get() {
+        return Ok(repo.as_ref());
+    }
+    let repo = open_order_repository()?;
+    Ok(REPOSITORY.get_or_init(|| repo).as_ref())
+}
+
+// 按 init 设置的后端打开订单仓库，没有调用 init 时使用 orders.json
+fn open_order_repository() -> RepositoryResult<Box<dyn OrderRepository>> {
+    let json_path = data_path().join("orders.json");
+    match STORE.get().cloned().unwrap_or_default() {
+        OrderStore::Sqlite(db_path) => {
+            let repo = SqliteOrderRepository::open(db_path)?;
+            // 首次使用空数据库时，从 orders.json 一次性导入数据
+            repo.import_json_if_empty(&json_path)?;
+            Ok(Box::new(repo))
+        }
+        OrderStore::Json => Ok(Box::new(JsonOrderRepository::new(json_path))),
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 在临时目录中写入订单文件，返回其路径
+    fn orders_file(name: &str, contents: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("httpserver-orders-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("orders.json");
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    const ORDERS: &str = r#"[
+        {"order_id": 1, "order_date": "21 Jan 2020", "order_status": "Delivered"},
+        {"order_id": 2, "order_date": "2 Feb 2020", "order_status": "Pending"}
+    ]"#;
+
+    // 测试读取全部订单和按 ID 查询
+    #[test]
+    fn test_json_list_and_get() {
+        let repo = JsonOrderRepository::new(orders_file("list", ORDERS));
+        let orders = repo.list_orders().unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[1].order_status, "Pending");
+        assert_eq!(repo.get_order(1).unwrap().unwrap().order_date, "21 Jan 2020");
+        assert_eq!(repo.get_order(3).unwrap(), None);
+    }
+
+    // 测试更新订单状态会写回文件，不存在的订单返回 NotFound
+    #[test]
+    fn test_json_update_status() {
+        let path = orders_file("update", ORDERS);
+        let repo = JsonOrderRepository::new(&path);
+        let updated = repo.update_status(2, "Shipped").unwrap();
+        assert_eq!((updated.order_id, updated.order_status.as_str()), (2, "Shipped"));
+        // 重新打开文件也能读到更新后的状态，临时文件已被重命名
+        let reopened = JsonOrderRepository::new(&path);
+        assert_eq!(reopened.get_order(2).unwrap().unwrap().order_status, "Shipped");
+        assert!(!path.with_extension("json.tmp").exists());
+
+        assert!(matches!(repo.update_status(9, "Shipped"), Err(RepositoryError::NotFound(9))));
+    }
+
+    // 测试并发更新不会互相覆盖
+    #[test]
+    fn test_json_concurrent_updates() {
+        let repo = JsonOrderRepository::new(orders_file("concurrent", ORDERS));
+        std::thread::scope(|scope| {
+            scope.spawn(|| repo.update_status(1, "Returned").unwrap());
+            scope.spawn(|| repo.update_status(2, "Shipped").unwrap());
+        });
+        let statuses: Vec<String> = repo.list_orders().unwrap().into_iter().map(|o| o.order_status).collect();
+        assert_eq!(statuses, ["Returned", "Shipped"]);
+    }
+
+    // 测试文件缺失或格式错误时返回对应的错误
+    #[test]
+    fn test_json_errors() {
+        let missing = JsonOrderRepository::new(std::env::temp_dir().join("httpserver-no-such-orders.json"));
+        assert!(matches!(missing.list_orders(), Err(RepositoryError::Io(_))));
+        let invalid = JsonOrderRepository::new(orders_file("invalid", "{not json"));
+        assert!(matches!(invalid.list_orders(), Err(RepositoryError::Json(_))));
+    }
+}
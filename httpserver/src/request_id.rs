@@ -0,0 +1,223 @@
+// 导入所需的库和模块
+use http::httprequest::HttpRequest; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+
+// 携带请求 ID 的头部
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// 接受的请求 ID 的最大长度
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// 请求 ID，Router 在中间件之前附加到每个请求上，处理器和日志据此关联同一个请求
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+// W3C Trace Context（traceparent 和 tracestate），Router 与请求 ID 一起附加到请求上
+// 请求带有合法的 traceparent 时沿用其中的 trace-id，否则开始新的链路；本服务的处理是链路中新的 span
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceContext {
+    pub trace_id: String,           // 32 位十六进制的链路 ID
+    pub parent_id: Option<String>,  // 调用方的 span ID，新链路没有
+    pub span_id: String,            // 本服务处理这个请求的 span ID
+    pub flags: u8,                  // 追踪标志，最低位表示采样
+    pub tracestate: Option<String>, // 调用方的 tracestate，原样传递
+}
+
+impl TraceContext {
+    // 根据请求的 traceparent 和 tracestate 创建，traceparent 不合法时开始新的链路
+    pub fn from_request(req: &HttpRequest) -> Self {
+        match req.header("traceparent").and_then(parse_traceparent) {
+            Some((trace_id, parent_id, flags)) => TraceContext {
+                trace_id,
+                parent_id: Some(parent_id),
+                span_id: random_hex(8),
+                flags,
+                // 没有合法的 traceparent 时 tracestate 也不能使用
+                tracestate: req.header("tracestate").filter(|s| !s.trim().is_empty()).map(str::to_string),
+            },
+            None => TraceContext {
+                trace_id: random_hex(16),
+                parent_id: None,
+                span_id: random_hex(8),
+                flags: 0,
+                tracestate: None,
+            },
+        }
+    }
+
+    // 是否被调用方采样
+    pub fn is_sampled(&self) -> bool {
+        self.flags & 1 == 1
+    }
+
+    // 本服务调用下游时发送的 traceparent，以本服务的 span 作为父 span
+    pub fn traceparent(&self) -> String {
+        format!("00-{}-{}-{:02x}", self.trace_id, self.span_id, self.flags)
+    }
+}
+
+// 为请求分配请求 ID 和链路上下文
+// 合法的 X-Request-Id 被沿用，否则生成新的 ID 并改写请求头，代理等转发请求时带上同一个 ID
+pub fn assign(req: &mut HttpRequest) {
+    let id = match req.header(REQUEST_ID_HEADER).filter(|id| is_valid_request_id(id)) {
+        Some(id) => id.to_string(),
+        None => generate_request_id(),
+    };
+    req.set_header(REQUEST_ID_HEADER, id.clone());
+    let trace = TraceContext::from_request(req);
+    req.extensions.insert(RequestId(id));
+    req.extensions.insert(trace);
+}
+
+// 在响应中返回请求 ID，处理器或中间件已经设置了的不覆盖
+pub fn apply(req: &HttpRequest, resp: &mut HttpResponse<'_>) {
+    if let Some(RequestId(id)) = req.extensions.get::<RequestId>() {
+        if resp.header(REQUEST_ID_HEADER).is_none() {
+            resp.add_header(REQUEST_ID_HEADER, id.clone());
+        }
+    }
+}
+
+// 请求的请求 ID
+pub fn request_id(req: &HttpRequest) -> Option<&str> {
+    req.extensions.get::<RequestId>().map(|id| id.0.as_str())
+}
+
+// 请求 ID 是否可以沿用：不超过 128 个字符，只含字母、数字和 -_.:/+=@
+// 限制字符集防止把换行或引号写进日志和响应头
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.:/+=@".contains(&b))
+}
+
+// 生成 UUID v4 格式的请求 ID
+fn generate_request_id() -> String {
+    let mut bytes = [0u8; 16];
+    getrandom::getrandom(&mut bytes).expect("failed to read random bytes");
+    bytes[6] = (bytes[6] & 0x0f) | 0x40; // 版本 4
+    bytes[8] = (bytes[8] & 0x3f) | 0x80; // RFC 4122 变体
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+}
+
+// 生成 len 字节的随机十六进制字符串，不会全为 0
+fn random_hex(len: usize) -> String {
+    let mut bytes = vec![0u8; len];
+    getrandom::getrandom(&mut bytes).expect("failed to read random bytes");
+    if bytes.iter().all(|b| *b == 0) {
+        bytes[len - 1] = 1;
+    }
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+// 解析 traceparent：版本-trace-id-parent-id-flags，返回 (trace-id, parent-id, flags)
+// 未知的更高版本按版本 00 的前几个字段解析，版本 ff 和全 0 的 ID 不合法
+fn parse_traceparent(value: &str) -> Option<(String, String, u8)> {
+    let value = value.trim();
+    let is_hex = |s: &str, len: usize| s.len() == len && s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b));
+    let version = value.get(..2).filter(|v| is_hex(v, 2) && *v != "ff")?;
+    if value.len() < 55 || (version == "00" && value.len() != 55) || (value.len() > 55 && value.as_bytes()[55] != b'-') {
+        return None;
+    }
+    let mut parts = value[..55].split('-');
+    let (_, trace_id, parent_id, flags) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    if !is_hex(trace_id, 32) || !is_hex(parent_id, 16) || !is_hex(flags, 2) {
+        return None;
+    }
+    if trace_id.bytes().all(|b| b == b'0') || parent_id.bytes().all(|b| b == b'0') {
+        return None;
+    }
+    Some((trace_id.to_string(), parent_id.to_string(), u8::from_str_radix(flags, 16).ok()?))
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::middleware::Middleware;
+    use crate::router::Router;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    // 直接返回响应的中间件，测试不经过处理器的请求
+    struct Respond;
+
+    impl Middleware for Respond {
+        fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+            let body = format!("{} {}", request_id(req).unwrap(), req.header(REQUEST_ID_HEADER).unwrap());
+            Some(HttpResponse::new("200", None, Some(body)))
+        }
+    }
+
+    // 测试沿用合法的请求 ID，不合法或缺失时生成新的 ID
+    #[test]
+    fn test_assign_request_id() {
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\nX-Request-Id: abc-123\r\n\r\n".to_string().into();
+        assign(&mut req);
+        assert_eq!(request_id(&req), Some("abc-123"));
+
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\nX-Request-Id: a b\"c\r\n\r\n".to_string().into();
+        assign(&mut req);
+        let id = request_id(&req).unwrap().to_string();
+        assert_eq!((id.len(), &id[14..15], req.header(REQUEST_ID_HEADER)), (36, "4", Some(id.as_str())));
+        assert!(!is_valid_request_id(&"a".repeat(129)));
+        assert_ne!(generate_request_id(), generate_request_id());
+    }
+
+    // 测试请求 ID 经过 Router 后出现在响应中，中间件和处理器可以读取
+    #[test]
+    fn test_router_echoes_request_id() {
+        let middlewares: Vec<Box<dyn Middleware>> = vec![Box::new(Respond)];
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\nX-Request-Id: r-1\r\n\r\n".to_string().into();
+        let resp = Router::handle(&mut req, &middlewares);
+        assert_eq!((resp.header(REQUEST_ID_HEADER), resp.body()), (Some("r-1"), "r-1 r-1"));
+
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\n\r\n".to_string().into();
+        let resp = Router::handle(&mut req, &middlewares);
+        let id = resp.header(REQUEST_ID_HEADER).unwrap().to_string();
+        assert_eq!(resp.body(), format!("{} {}", id, id));
+    }
+
+    // 测试解析 traceparent 并以新的 span 继续链路，不合法时开始新的链路
+    #[test]
+    fn test_trace_context() {
+        let mut req: HttpRequest =
+            format!("GET / HTTP/1.1\r\ntraceparent: {}\r\ntracestate: vendor=x\r\n\r\n", TRACEPARENT).into();
+        assign(&mut req);
+        let trace = req.extensions.get::<TraceContext>().unwrap();
+        assert_eq!(trace.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(trace.parent_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_eq!(trace.tracestate.as_deref(), Some("vendor=x"));
+        assert!(trace.is_sampled());
+        let outgoing = trace.traceparent();
+        assert!(outgoing.starts_with("00-4bf92f3577b34da6a3ce929d0e0e4736-") && outgoing.ends_with("-01"));
+        assert_ne!(outgoing, TRACEPARENT);
+        assert!(parse_traceparent(&outgoing).is_some());
+
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\ntraceparent: garbage\r\ntracestate: vendor=x\r\n\r\n".to_string().into();
+        assign(&mut req);
+        let trace = req.extensions.get::<TraceContext>().unwrap();
+        assert_eq!((trace.trace_id.len(), trace.parent_id.as_ref(), trace.tracestate.as_ref()), (32, None, None));
+        assert!(!trace.is_sampled());
+    }
+
+    // 测试 traceparent 的格式校验
+    #[test]
+    fn test_parse_traceparent() {
+        assert!(parse_traceparent(TRACEPARENT).is_some());
+        // 更高的版本可以带有额外的字段
+        assert!(parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra").is_some());
+        for invalid in [
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-1",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01x",
+        ] {
+            assert!(parse_traceparent(invalid).is_none(), "{}", invalid);
+        }
+    }
+}
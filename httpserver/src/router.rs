@@ -1,39 +1,198 @@
 // 导入所需的模块和处理器
-use super::handler::{Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler}; // 导入处理器
+use super::handler::{AdminHandler, Handler, PageNotFoundHandler, StaticPageHandler, WebServiceHandler}; // 导入处理器
+use super::http2::Transport; // 导入底层传输特性
+use super::middleware::Middleware; // 导入中间件
+use super::request_id; // 导入请求 ID
+use super::websocket::{self, WebSocketHandler}; // 导入 WebSocket 处理器
 use http::{httprequest, httprequest::HttpRequest, httpresponse::HttpResponse}; // 导入 HTTP 请求和响应模块
+use http::websocket::{HandshakeError, WebSocket}; // 导入 WebSocket 握手
 use std::io::prelude::*; // 导入 IO 预备函数
 
 // 定义 Router 结构体
 pub struct Router;
 
 impl Router {
-    // 路由方法，根据请求的 HTTP 方法和资源路径进行处理
-    pub fn route(req: HttpRequest, stream: &mut impl Write) -> () {
+    // 路由方法，处理请求并把响应发送到流，返回连接是否可以继续处理下一个请求
+    // 响应使用与请求相同的 HTTP 版本，并在需要时通过 Connection 头告知连接是否保持
+    pub fn route(mut req: HttpRequest, middlewares: &[Box<dyn Middleware>], stream: &mut impl Write) -> bool {
+        let version = req.version;
+        let keep_alive = req.keep_alive();
+        let resp = Self::handle(&mut req, middlewares);
+        Self::respond(resp, version, keep_alive, stream)
+    }
+
+    // 按请求的版本和连接保持发送 handle 生成的响应，返回连接是否保持
+    pub fn respond(mut resp: HttpResponse, version: httprequest::Version, keep_alive: bool, stream: &mut impl Write) -> bool {
+        let keep_alive = Self::prepare(&mut resp, version, keep_alive);
+        // 发送失败（如流式响应中断）时连接不能继续使用
+        resp.send_response(stream).is_ok() && keep_alive
+    }
+
+    // 设置响应的版本，并在需要时通过 Connection 头告知连接是否保持，返回连接是否保持
+    fn prepare(resp: &mut HttpResponse, version: httprequest::Version, mut keep_alive: bool) -> bool {
+        resp.set_version(version.as_str());
+        // 不支持分块编码时，流式响应以关闭连接表示结束
+        if resp.is_streaming() && !version.supports_chunked() {
+            keep_alive = false;
+        }
+        match (version, keep_alive) {
+            (httprequest::Version::V1_0, true) => resp.add_header("Connection", "keep-alive"),
+            (httprequest::Version::V1_1, false) => resp.add_header("Connection", "close"),
+            _ => {}
+        }
+        keep_alive
+    }
+
+    // 根据路径选择 WebSocket 处理器，这些路径只接受升级请求
+    pub fn websocket_route(req: &HttpRequest) -> Option<WebSocketHandler> {
+        let httprequest::Resource::Path(s) = &req.resource;
+        match s.trim_end_matches('/') {
+            "/ws/echo" => Some(websocket::echo),
+            "/ws/orders" => Some(websocket::order_updates),
+            _ => None,
+        }
+    }
+
+    // 是否是内置处理器的路由：/api、/admin 和 WebSocket 路由，与 dispatch 的匹配方式一致
+    // 其他路径由静态页面处理器处理
+    pub fn is_builtin(req: &HttpRequest) -> bool {
+        let httprequest::Resource::Path(s) = &req.resource;
+        Self::websocket_route(req).is_some() || matches!(s.split('/').nth(1), Some("api" | "admin"))
+    }
+
+    // 处理 WebSocket 路由的请求：中间件通过后完成握手，再把连接交给处理器
+    // 握手失败时发送错误响应，返回连接是否可以继续处理下一个请求；升级后连接由处理器使用，返回 false
+    // buffered 是请求之后已经读取的数据，升级后属于 WebSocket 连接
+    pub fn upgrade(
+        mut req: HttpRequest,
+        handler: WebSocketHandler,
+        middlewares: &[Box<dyn Middleware>],
+        mut stream: &mut dyn Transport,
+        buffered: &mut Vec<u8>,
+    ) -> bool {
+        let version = req.version;
+        let keep_alive = req.keep_alive();
+        let mut accepted = None;
+        let mut resp = Self::handle_with(&mut req, middlewares, |req| match http::websocket::handshake(req, &[]) {
+            Ok(handshake) => {
+                let resp = handshake.response();
+                accepted = Some(handshake);
+                resp
+            }
+            Err(e) => e.to_response(),
+        });
+        // 中间件可能拒绝了请求或替换了响应
+        let Some(handshake) = accepted.filter(|_| resp.status_code() == "101") else {
+            let keep_alive = Self::prepare(&mut resp, version, keep_alive);
+            return resp.send_response(&mut stream).is_ok() && keep_alive;
+        };
+        resp.set_version(version.as_str());
+        if resp.send_response(&mut stream).is_err() || stream.flush().is_err() {
+            return false;
+        }
+        drop(resp);
+        // WebSocket 连接可以长时间空闲，不再使用服务器读取请求的超时
+        if stream.set_read_timeout(None).is_err() {
+            return false;
+        }
+        let mut ws = WebSocket::server(stream, &handshake).buffered(std::mem::take(buffered));
+        handler(&req, &mut ws);
+        false
+    }
+
+    // 先依次执行中间件，再将请求交给处理器，最后按相反顺序执行中间件的 after
+    pub fn handle<'r>(req: &'r mut HttpRequest, middlewares: &[Box<dyn Middleware>]) -> HttpResponse<'r> {
+        Self::handle_with(req, middlewares, Self::dispatch)
+    }
+
+    // 与 handle 相同，但由 dispatch 生成中间件之后的响应
+    // 请求 ID 在所有中间件之前分配，在所有中间件之后写入响应
+    fn handle_with<'r>(
+        req: &'r mut HttpRequest,
+        middlewares: &[Box<dyn Middleware>],
+        dispatch: impl FnOnce(&'r HttpRequest) -> HttpResponse<'r>,
+    ) -> HttpResponse<'r> {
+        request_id::assign(req);
+
+        // 依次调用中间件的 before，任何一个返回响应时就不再继续
+        let mut called = 0;
+        let mut early_response = None;
+        for middleware in middlewares {
+            called += 1;
+            if let Some(resp) = middleware.before(req) {
+                early_response = Some(resp);
+                break;
+            }
+        }
+
+        let req: &'r HttpRequest = req;
+        let mut resp: HttpResponse = match early_response {
+            Some(resp) => resp,
+            None => dispatch(req),
+        };
+
+        // 按相反顺序调用中间件的 after
+        for middleware in middlewares[..called].iter().rev() {
+            middleware.after(req, &mut resp);
+        }
+        request_id::apply(req, &mut resp);
+        resp
+    }
+
+    // 请求匹配的路由模式，与 dispatch 和各处理器的路由对应，用作监控指标的标签
+    // 路径中的订单 ID 等参数替换为 :id，未知的路径归入 /api/*、/admin/* 或 /*，避免标签数量无限增长
+    pub fn route_pattern(req: &HttpRequest) -> &'static str {
+        let httprequest::Resource::Path(s) = &req.resource;
+        let path = s.split('?').next().unwrap_or_default();
+        let route: Vec<&str> = path.trim_end_matches('/').split('/').collect();
+        match &route[1..] {
+            ["ws", "echo"] => "/ws/echo",
+            ["ws", "orders"] => "/ws/orders",
+            ["api", "shipping", "orders"] => "/api/shipping/orders",
+            ["api", "shipping", "orders", "export"] => "/api/shipping/orders/export",
+            ["api", "shipping", "orders", "events"] => "/api/shipping/orders/events",
+            ["api", "shipping", "orders", _, "status"] => "/api/shipping/orders/:id/status",
+            ["api", "uploads"] => "/api/uploads",
+            ["api", ..] => "/api/*",
+            ["admin"] => "/admin",
+            ["admin", "login"] => "/admin/login",
+            ["admin", "logout"] => "/admin/logout",
+            ["admin", "orders", _, "status"] => "/admin/orders/:id/status",
+            ["admin", ..] => "/admin/*",
+            [] => "/",
+            ["health"] => "/health",
+            _ => "/*",
+        }
+    }
+
+    // 根据请求的 HTTP 方法和资源路径选择处理器
+    pub fn dispatch(req: &HttpRequest) -> HttpResponse<'_> {
+        let httprequest::Resource::Path(s) = &req.resource;
+        // 解析 URI
+        let route: Vec<&str> = s.split("/").collect();
+        // WebSocket 路由需要通过 HTTP/1.1 升级，其他方式访问时返回 426
+        if Self::websocket_route(req).is_some() {
+            return HandshakeError::NotUpgrade.to_response();
+        }
         match req.method {
             // 如果是 GET 请求
-            httprequest::Method::Get => match &req.resource {
-                httprequest::Resource::Path(s) => {
-                    // 解析 URI
-                    let route: Vec<&str> = s.split("/").collect();
-                    match route[1] {
-                        // 如果路由以 /api 开头，则调用 Web 服务处理器
-                        "api" => {
-                            let resp: HttpResponse = WebServiceHandler::handle(&req); // 处理请求
-                            let _ = resp.send_response(stream); // 发送响应
-                        }
-                        // 否则，调用静态页面处理器
-                        _ => {
-                            let resp: HttpResponse = StaticPageHandler::handle(&req); // 处理请求
-                            let _ = resp.send_response(stream); // 发送响应
-                        }
-                    }
-                }
+            // 不以 / 开头的路径（如 GET x HTTP/1.1）没有第二段，交给静态页面处理器返回 404
+            httprequest::Method::Get => match route.get(1).copied() {
+                // 如果路由以 /api 开头，则调用 Web 服务处理器
+                Some("api") => WebServiceHandler::handle(req),
+                // 如果路由以 /admin 开头，则调用管理页面处理器
+                Some("admin") => AdminHandler::handle(req),
+                // 否则，调用静态页面处理器
+                _ => StaticPageHandler::handle(req),
             },
-            // 如果请求方法不是 GET，返回 404 页面
-            _ => {
-                let resp: HttpResponse = PageNotFoundHandler::handle(&req); // 处理请求
-                let _ = resp.send_response(stream); // 发送响应
-            }
+            // 如果是 POST 请求，只有 /api 和 /admin 下的处理器接受
+            httprequest::Method::Post => match route.get(1).copied() {
+                Some("api") => WebServiceHandler::handle(req),
+                Some("admin") => AdminHandler::handle(req),
+                _ => PageNotFoundHandler::handle(req),
+            },
+            // 其他请求方法返回 404 页面
+            _ => PageNotFoundHandler::handle(req),
         }
     }
 }
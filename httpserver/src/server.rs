@@ -1,39 +1,1114 @@
 // 导入必要的模块
+use super::http2::{self, Transport}; // 导入 HTTP/2 模块
+use super::listener::{Connection, ListenAddr, Listener}; // 导入监听器
+use super::metrics::metrics; // 导入指标注册表
+use super::middleware::Middleware; // 导入中间件
 use super::router::Router; // 导入路由模块
-use http::httprequest::HttpRequest; // 导入 HTTP 请求结构
+use super::tls::{TlsAcceptor, TlsInfo}; // 导入 TLS 接收器
+use http::chunked::{ChunkedDecoder, ChunkedError, RequestTrailers, Trailers}; // 导入分块传输编码的解码器和尾部头部
+use http::httprequest::{HttpRequest, Resource, Version}; // 导入 HTTP 请求结构
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应结构
 use std::io::prelude::*; // 导入 IO 预备函数
-use std::net::TcpListener; // 导入 TCP 监听器
+use std::net::{SocketAddr, TcpStream}; // 导入 TCP 套接字和地址
 use std::str; // 导入字符串处理模块
+use std::sync::atomic::{AtomicUsize, Ordering}; // 导入原子计数器
+use std::sync::{mpsc, Arc, Condvar, Mutex}; // 导入通道、共享指针、互斥锁和条件变量
+use std::thread; // 导入线程模块
+use std::time::{Duration, Instant}; // 导入时间模块
+
+// 开始接收消息体后的这段时间内不检查最低速率
+const MIN_RATE_GRACE: Duration = Duration::from_secs(5);
+
+// 同时运行的 HTTP/1.x 处理器线程数上限，包括超时后仍在运行的；达到上限时新请求返回 503
+const MAX_RUNNING_HANDLERS: usize = 1024;
+
+// 客户端的地址，服务器在路由前附加到请求上
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerAddr(pub SocketAddr);
+
+// 读取和处理请求的超时和大小限制，防止慢速客户端（slowloris）长期占用连接；HTTP/2 连接对每个流使用同样的限制
+// 超时为 None 时不限制
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub idle_timeout: Option<Duration>,    // 保持的连接等待下一个请求的时间，超时后直接关闭
+    pub header_timeout: Option<Duration>,  // 接收请求行和请求头（以及 TLS 握手）的总时间，超时返回 408
+    pub body_timeout: Option<Duration>,    // 接收消息体时两次读取之间的最长间隔，超时返回 408
+    pub handler_timeout: Option<Duration>, // 中间件和处理器生成响应的时间，超时返回 503
+    pub write_timeout: Option<Duration>,   // 发送响应时每次写入的超时
+    pub min_rate: u64,                     // 接收消息体的最低平均速率（字节/秒），过慢返回 408；0 表示不限制
+    pub max_request_line: usize,           // 请求行的最大字节数，超过返回 414
+    pub max_headers: usize,                // 请求头的最大数量，超过返回 431
+    pub max_header_size: usize,            // 请求头的最大总字节数（不含请求行），超过返回 431
+    pub max_body_size: usize,              // 消息体的最大字节数，超过返回 413
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            idle_timeout: Some(Duration::from_secs(30)),
+            header_timeout: Some(Duration::from_secs(10)),
+            body_timeout: Some(Duration::from_secs(30)),
+            handler_timeout: Some(Duration::from_secs(60)),
+            write_timeout: Some(Duration::from_secs(30)),
+            min_rate: 500,
+            max_request_line: 8 * 1024,
+            max_headers: 100,
+            max_header_size: 32 * 1024,
+            max_body_size: 10 * 1024 * 1024,
+        }
+    }
+}
 
 // 定义 Server 结构体
 pub struct Server<'a> {
-    socket_addr: &'a str, // 服务器的 socket 地址
+    listeners: Vec<&'a str>,               // 监听地址，TCP 地址或 unix: 开头的 Unix 域套接字
+    middlewares: Arc<Vec<Box<dyn Middleware>>>, // 按注册顺序执行的中间件，与处理器线程共享
+    tls: Option<TlsAcceptor>,                   // 配置后使用 HTTPS
+    workers: usize,                             // 同时处理的连接数上限
+    limits: Limits,                             // 请求的超时和大小限制
+    running: Arc<AtomicUsize>,                  // 正在运行的处理器线程数
 }
 
 impl<'a> Server<'a> {
     // 创建一个新的 Server 实例
     pub fn new(socket_addr: &'a str) -> Self {
-        Server { socket_addr } // 返回新的 Server 实例
+        Server {
+            listeners: vec![socket_addr],
+            middlewares: Arc::new(Vec::new()),
+            tls: None,
+            workers: usize::MAX,
+            limits: Limits::default(),
+            running: Arc::new(AtomicUsize::new(0)),
+        } // 返回新的 Server 实例
+    }
+
+    // 增加一个监听地址，所有地址上的连接共用中间件和连接数上限
+    pub fn listen(mut self, socket_addr: &'a str) -> Self {
+        self.listeners.push(socket_addr);
+        self
+    }
+
+    // 注册一个中间件，先注册的先处理请求、后处理响应
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        // 服务器运行前中间件列表还没有共享给处理器线程
+        Arc::get_mut(&mut self.middlewares).expect("middlewares are not shared before run").push(Box::new(middleware));
+        self
+    }
+
+    // 启用 TLS，所有连接都先完成 TLS 握手
+    pub fn tls(mut self, tls: TlsAcceptor) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    // 设置同时处理的连接数上限，达到上限后新连接等待已有连接结束
+    pub fn workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    // 设置请求的超时和大小限制
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // 运行服务器，无法监听某个地址时返回错误
+    // Unix 域套接字上的连接不使用 TLS
+    pub fn run(&self) -> std::io::Result<()> {
+        let mut listeners = Vec::new();
+        for addr in &self.listeners {
+            let addr = ListenAddr::parse(addr);
+            let listener = Listener::bind(&addr)
+                .map_err(|e| std::io::Error::new(e.kind(), format!("failed to listen on {}: {}", addr, e)))?;
+            println!("Running on {}", addr); // 打印服务器运行地址
+            listeners.push((addr, listener));
+        }
+
+        // 每个监听地址一个线程接受连接，每个连接一个线程，HTTP/2 等长连接不会阻塞其他客户端
+        // 正在处理的连接数达到上限时暂停接受新连接
+        let limit = ConnectionLimit::new(self.workers);
+        thread::scope(|scope| {
+            for (addr, listener) in &listeners {
+                let limit = &limit;
+                scope.spawn(move || loop {
+                    let connection = match listener.accept() {
+                        Ok(connection) => connection,
+                        Err(e) => {
+                            eprintln!("Failed to accept connection on {}: {}", addr, e);
+                            continue;
+                        }
+                    };
+                    println!("Connection established"); // 打印连接建立信息
+                    let slot = limit.acquire();
+                    scope.spawn(move || {
+                        self.serve(connection);
+                        drop(slot);
+                    });
+                });
+            }
+        });
+        Ok(())
+    }
+
+    // 处理一个连接，TCP 连接配置了 TLS 时先完成握手
+    fn serve(&self, connection: Connection) {
+        let _connection = metrics().connection();
+        let peer = connection.peer_addr();
+        // 请求头的超时同样限制 TLS 握手，之后每次读取前按阶段重新设置
+        if connection.set_timeouts(self.limits.header_timeout, self.limits.write_timeout).is_err() {
+            return;
+        }
+        match connection {
+            Connection::Tcp(stream) => self.serve_tcp(stream, peer),
+            #[cfg(unix)]
+            Connection::Unix(mut stream) => self.handle_http1(&mut stream, None, None),
+        }
+    }
+
+    // 处理一个 TCP 连接，配置了 TLS 时先完成握手
+    fn serve_tcp(&self, mut stream: TcpStream, peer: Option<SocketAddr>) {
+        let Some(tls) = &self.tls else {
+            self.handle_http1(&mut stream, peer, None);
+            return;
+        };
+
+        // 证书文件有变化时重新加载
+        if let Err(e) = tls.reload_if_changed() {
+            eprintln!("Failed to reload certificates: {}", e);
+        }
+        match tls.accept(stream) {
+            Ok(mut tls_stream) => {
+                let info = TlsInfo::from(&tls_stream.conn);
+                // 通过 ALPN 协商了 h2 时直接使用 HTTP/2
+                if info.alpn.as_deref() == Some(b"h2") {
+                    self.serve_http2(&mut tls_stream, Vec::new(), None, peer, Some(info));
+                } else {
+                    self.handle_http1(&mut tls_stream, peer, Some(info));
+                }
+                tls_stream.conn.send_close_notify();
+                let _ = tls_stream.flush();
+            }
+            Err(e) => eprintln!("TLS handshake failed: {}", e),
+        }
     }
 
-    // 运行服务器
-    pub fn run(&self) {
-        // 在指定的 socket 地址上启动服务器
-        let connection_listener = TcpListener::bind(self.socket_addr).unwrap();
-        println!("Running on {}", self.socket_addr); // 打印服务器运行地址
+    // 在一个 HTTP/1.x 连接上依次读取请求并交给路由处理，直到连接不再保持
+    // 明文连接还支持 HTTP/2 先验知识（直接发送连接前言）和 h2c 升级
+    fn handle_http1(&self, stream: &mut impl Transport, peer: Option<SocketAddr>, tls: Option<TlsInfo>) {
+        let mut buffer = Vec::new(); // 上一个请求之后多读的数据
+        // 新连接在请求头的超时内没有发送数据时关闭，之后的请求之间使用空闲超时
+        let mut wait = self.limits.header_timeout;
+        loop {
+            // 读取完整的请求（请求头和消息体）
+            let RawRequest { bytes: raw_request, trailers } = match read_request(stream, &mut buffer, &self.limits, wait) {
+                Ok(raw) if raw.bytes.is_empty() => return, // 客户端关闭了连接
+                Ok(raw) => raw,
+                Err(ReadError::Invalid(status, message)) => {
+                    let _ = error_response(status, &message).send_response(stream);
+                    return;
+                }
+                // 等待请求时超时，空闲的保持连接在这里关闭；请求没有收完连接就关闭时不处理
+                Err(ReadError::Io(e)) if is_timeout(&e) || e.kind() == std::io::ErrorKind::UnexpectedEof => return,
+                Err(ReadError::Io(e)) => {
+                    eprintln!("Failed to read request: {}", e);
+                    return;
+                }
+            };
+            wait = self.limits.idle_timeout;
 
-        // 循环监听传入的连接
-        for stream in connection_listener.incoming() {
-            let mut stream = stream.unwrap(); // 解包连接流
-            println!("Connection established"); // 打印连接建立信息
-            let mut read_buffer = [0; 1024]; // 创建一个缓冲区用于读取数据
-            stream.read(&mut read_buffer).unwrap(); // 从流中读取数据到缓冲区
+            if tls.is_none() && raw_request.starts_with(b"PRI * HTTP/2.0\r\n") {
+                let mut buffered = raw_request;
+                buffered.extend_from_slice(&buffer);
+                self.serve_http2(stream, buffered, None, peer, None);
+                return;
+            }
 
             // 将读取的 HTTP 请求转换为 Rust 数据结构
-            let req: HttpRequest = String::from_utf8(read_buffer.to_vec()).unwrap().into();
+            let request_line = String::from_utf8_lossy(raw_request.split(|&b| b == b'\n').next().unwrap_or_default()).into_owned();
+            let mut req: HttpRequest = raw_request.into();
+            if !trailers.is_empty() {
+                req.extensions.insert(RequestTrailers(trailers));
+            }
+            if let Some(resp) = validate_request(&req, &request_line) {
+                let _ = resp.send_response(stream);
+                return;
+            }
+            if !normalize_path(&mut req) {
+                let _ = error_response("400", "Invalid path").send_response(stream);
+                return;
+            }
+            if tls.is_none() && http2::is_h2c_upgrade(&req) {
+                let switching = "HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n";
+                if stream.write_all(switching.as_bytes()).is_ok() {
+                    self.serve_http2(stream, std::mem::take(&mut buffer), Some(req), peer, None);
+                }
+                return;
+            }
+
+            if let Some(handler) = Router::websocket_route(&req) {
+                attach(&mut req, peer, tls.clone());
+                if !Router::upgrade(req, handler, &self.middlewares, stream, &mut buffer) || stream.flush().is_err() {
+                    return;
+                }
+                continue;
+            }
+
+            if !self.dispatch(req, stream, peer, tls.clone()) || stream.flush().is_err() {
+                return;
+            }
+        }
+    }
+
+    // 附加连接信息并将请求路由到适当的处理器，返回连接是否保持
+    // 设置了处理器超时时在单独的线程中处理请求，超时后返回 503 并关闭连接，连接线程立即释放
+    // 线程无法被中断，处理器仍会运行到结束并占用处理器名额，名额用完时新请求直接返回 503
+    fn dispatch(&self, mut req: HttpRequest, stream: &mut impl Write, peer: Option<SocketAddr>, tls: Option<TlsInfo>) -> bool {
+        attach(&mut req, peer, tls);
+        let Some(timeout) = self.limits.handler_timeout else {
+            return Router::route(req, &self.middlewares, stream);
+        };
+        let Some(slot) = HandlerSlot::acquire(&self.running) else {
+            let _ = error_response("503", "Too Many Handlers").send_response(stream);
+            let _ = stream.flush();
+            return false;
+        };
+        let (version, keep_alive) = (req.version, req.keep_alive());
+        let (sender, receiver) = mpsc::channel();
+        let middlewares = Arc::clone(&self.middlewares);
+        thread::spawn(move || {
+            // 响应可能借用请求中的数据，转换后才能交给连接线程发送
+            let resp = Router::handle(&mut req, &middlewares).into_owned();
+            drop(slot);
+            let _ = sender.send(resp);
+        });
+        let (status, message) = match receiver.recv_timeout(timeout) {
+            Ok(resp) => return Router::respond(resp, version, keep_alive, stream),
+            Err(mpsc::RecvTimeoutError::Timeout) => ("503", "Handler Timeout"),
+            // 处理器 panic
+            Err(mpsc::RecvTimeoutError::Disconnected) => ("500", "Internal Server Error"),
+        };
+        let _ = error_response(status, message).send_response(stream);
+        let _ = stream.flush();
+        false
+    }
+
+    // 在连接上提供 HTTP/2 服务，每个流的请求都经过同样的中间件和路由
+    fn serve_http2(
+        &self,
+        stream: &mut impl Transport,
+        buffered: Vec<u8>,
+        upgrade: Option<HttpRequest>,
+        peer: Option<SocketAddr>,
+        tls: Option<TlsInfo>,
+    ) {
+        // HTTP/2 连接使用空闲超时等待帧
+        if stream.set_read_timeout(self.limits.idle_timeout).is_err() {
+            return;
+        }
+        let handler = |mut req: HttpRequest| {
+            if !normalize_path(&mut req) {
+                return http2::Response::from(&HttpResponse::new("400", None, Some("Invalid path".to_string())));
+            }
+            attach(&mut req, peer, tls.clone());
+            let resp = Router::handle(&mut req, &self.middlewares);
+            http2::Response::from(&resp)
+        };
+        if let Err(e) = http2::serve(stream, buffered, upgrade, &handler, &self.limits) {
+            eprintln!("HTTP/2 connection error: {}", e);
+        }
+    }
+}
+
+// 同时处理的连接数上限
+struct ConnectionLimit {
+    max: usize,           // 上限
+    active: Mutex<usize>, // 正在处理的连接数
+    released: Condvar,    // 有连接结束时通知等待的线程
+}
+
+impl ConnectionLimit {
+    fn new(max: usize) -> Self {
+        ConnectionLimit {
+            max,
+            active: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    // 占用一个名额，达到上限时等待其他连接结束
+    fn acquire(&self) -> ConnectionSlot<'_> {
+        let mut active = self.active.lock().unwrap();
+        while *active >= self.max {
+            active = self.released.wait(active).unwrap();
+        }
+        *active += 1;
+        ConnectionSlot(self)
+    }
+}
+
+// 占用的连接名额，释放时通知等待的线程
+struct ConnectionSlot<'a>(&'a ConnectionLimit);
+
+impl Drop for ConnectionSlot<'_> {
+    fn drop(&mut self) {
+        *self.0.active.lock().unwrap() -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+// 处理器线程占用的名额，线程结束（包括 panic）时释放
+struct HandlerSlot(Arc<AtomicUsize>);
+
+impl HandlerSlot {
+    // 占用一个名额，达到 MAX_RUNNING_HANDLERS 时返回 None
+    fn acquire(running: &Arc<AtomicUsize>) -> Option<HandlerSlot> {
+        running
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| (n < MAX_RUNNING_HANDLERS).then_some(n + 1))
+            .ok()
+            .map(|_| HandlerSlot(Arc::clone(running)))
+    }
+}
+
+impl Drop for HandlerSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+// 把客户端地址和 TLS 信息附加到请求上
+fn attach(req: &mut HttpRequest, peer: Option<SocketAddr>, tls: Option<TlsInfo>) {
+    if let Some(addr) = peer {
+        req.extensions.insert(PeerAddr(addr));
+    }
+    if let Some(info) = tls {
+        req.extensions.insert(info);
+    }
+}
+
+// 规范化请求的路径并写回请求，在所有中间件之前执行，认证、限流、CORS、代理和路由都匹配同一个路径
+// 不以 / 开头的请求目标保持不变，由路由返回 404；路径越过根目录时返回 false，调用方返回 400
+fn normalize_path(req: &mut HttpRequest) -> bool {
+    let Resource::Path(path) = &mut req.resource;
+    if !path.starts_with('/') {
+        return true;
+    }
+    match remove_dot_segments(path) {
+        Some(normalized) => {
+            *path = normalized;
+            true
+        }
+        None => false,
+    }
+}
+
+// 去掉路径中的 . 和 .. 段（RFC 3986 第 5.2.4 节）并合并连续的 /，查询字符串保持不变
+// 百分号编码的点（%2e）同样按点处理，上游可能在解码后再解释它们；.. 越过根目录时返回 None
+pub(crate) fn remove_dot_segments(path: &str) -> Option<String> {
+    let (path, query) = match path.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path, None),
+    };
+    let mut segments: Vec<&str> = Vec::new();
+    let mut rest = path.split('/').skip(1).peekable();
+    while let Some(segment) = rest.next() {
+        match segment.to_ascii_lowercase().replace("%2e", ".").as_str() {
+            "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            // 空段来自连续的 /，只保留结尾的 /
+            "" if rest.peek().is_some() => continue,
+            _ => {
+                segments.push(segment);
+                continue;
+            }
+        }
+        // 以点段结尾的路径保留结尾的 /，如 /api/v1/.. 变为 /api/
+        if rest.peek().is_none() {
+            segments.push("");
+        }
+    }
+    let mut normalized = format!("/{}", segments.join("/"));
+    if let Some(query) = query {
+        normalized.push('?');
+        normalized.push_str(query);
+    }
+    Some(normalized)
+}
+
+// 检查 HTTP/1.x 请求的请求行、版本和 Host 头，不合法时返回错误响应
+// 请求行必须是“方法 资源 版本”三部分；HTTP/1.1 请求必须带 Host 头，HTTP/1.0 可以省略；格式正确但不支持的版本返回 505
+fn validate_request(req: &HttpRequest, request_line: &str) -> Option<HttpResponse<'static>> {
+    if request_line.split_whitespace().count() != 3 {
+        return Some(error_response("400", "Bad Request"));
+    }
+    match req.version {
+        Version::V1_0 => None,
+        Version::V1_1 if req.header("Host").is_some() => None,
+        Version::V1_1 => Some(error_response("400", "Missing Host header")),
+        _ => {
+            let well_formed = request_line
+                .split_whitespace()
+                .nth(2)
+                .and_then(|v| v.strip_prefix("HTTP/"))
+                .and_then(|v| v.split_once('.'))
+                .is_some_and(|(major, minor)| {
+                    [major, minor].iter().all(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+                });
+            if well_formed {
+                Some(error_response("505", "HTTP Version Not Supported"))
+            } else {
+                Some(error_response("400", "Bad Request"))
+            }
+        }
+    }
+}
+
+// 协议错误的响应，发送后关闭连接
+fn error_response(status: &'static str, message: &str) -> HttpResponse<'static> {
+    let mut resp = HttpResponse::new(status, None, Some(message.to_string()));
+    resp.add_header("Connection", "close");
+    resp
+}
+
+// 读取请求失败的原因
+#[derive(Debug)]
+enum ReadError {
+    Io(std::io::Error),            // 连接出错或超时
+    Invalid(&'static str, String), // 请求不合法，返回该状态码的错误响应后关闭连接
+}
+
+impl From<std::io::Error> for ReadError {
+    fn from(e: std::io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+// 读取到的一个请求
+#[derive(Debug)]
+struct RawRequest {
+    bytes: Vec<u8>,     // 请求头和消息体，连接正常关闭时为空
+    trailers: Trailers, // 分块编码的尾部头部，与请求头分开保存
+}
+
+// 从流中读取一个完整的 HTTP 请求
+// 先读到请求头结束的空行，再根据 Content-Length 或分块编码读取消息体
+// 分块编码的消息体解码后改写为带 Content-Length 的请求，尾部头部单独返回，不合并到请求头中
+// buffer 中是之前多读的数据，返回时保存本次请求之后多读的数据
+// wait 是等待请求第一个字节的时间，这期间超时返回 Io 错误；收到数据后按 limits 限制读取的时间和大小，超出时返回对应的错误响应
+fn read_request(stream: &mut impl Transport, buffer: &mut Vec<u8>, limits: &Limits, wait: Option<Duration>) -> Result<RawRequest, ReadError> {
+    let too_large = || ReadError::Invalid("413", "Payload Too Large".to_string());
+    let timed_out = || ReadError::Invalid("408", "Request Timeout".to_string());
+
+    // 读取请求头，从收到第一个字节开始计时
+    let mut head_deadline = (!buffer.is_empty()).then(Instant::now);
+    let header_end = loop {
+        let header_end = find_header_end(buffer);
+        check_head(buffer, header_end, limits)?;
+        if let Some(pos) = header_end {
+            break pos;
+        }
+        let timeout = match head_deadline {
+            None => wait,
+            Some(start) => limits.header_timeout.map(|t| t.saturating_sub(start.elapsed())),
+        };
+        let bytes_read = match read_more(stream, buffer, timeout) {
+            Err(e) if is_timeout(&e) && head_deadline.is_some() => return Err(timed_out()),
+            result => result?,
+        };
+        if bytes_read == 0 {
+            // 请求之间关闭连接是正常的结束；请求头没有读完时不处理不完整的请求
+            if buffer.is_empty() {
+                return Ok(RawRequest {
+                    bytes: Vec::new(),
+                    trailers: Vec::new(),
+                });
+            }
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+        head_deadline.get_or_insert_with(Instant::now);
+    };
+
+    // 读取消息体：两次读取的间隔不超过 body_timeout，宽限期之后平均速率不低于 min_rate
+    let body_start = Instant::now();
+    let mut received = buffer.len() - header_end; // 已收到的消息体字节数，分块编码已解码的数据会从 buffer 中移除
+    let mut read_body = |buffer: &mut Vec<u8>| -> Result<usize, ReadError> {
+        let timeout = body_read_timeout(limits, received, body_start.elapsed());
+        match read_more(stream, buffer, timeout) {
+            Err(e) if is_timeout(&e) => Err(timed_out()),
+            result => {
+                let bytes_read = result?;
+                received += bytes_read;
+                Ok(bytes_read)
+            }
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buffer[..header_end]).into_owned();
+    // 同名请求头的所有值，多个头部和逗号分隔的列表都展开
+    let header_values = |name: &str| -> Vec<String> {
+        head.lines()
+            .skip(1)
+            .filter_map(|line| line.split_once(':'))
+            .filter(|(k, _)| k.trim().eq_ignore_ascii_case(name))
+            .flat_map(|(_, v)| v.split(',').map(|v| v.trim().to_string()).collect::<Vec<_>>())
+            .collect()
+    };
 
-            // 将请求路由到适当的处理器
-            Router::route(req, &mut stream);
+    // 消息体的长度只能由一种方式确定，含糊的请求返回 400（RFC 9112 第 6.1、6.3 节），
+    // 否则经过代理时前后两端可能对请求的边界理解不同（请求走私）
+    // 只支持单独的 chunked 编码，其他传输编码（包括 gzip, chunked 这样的组合）返回 501
+    let transfer_encoding = header_values("Transfer-Encoding");
+    if !transfer_encoding.is_empty() && (transfer_encoding.len() != 1 || !transfer_encoding[0].eq_ignore_ascii_case("chunked")) {
+        return Err(ReadError::Invalid("501", "Unsupported Transfer-Encoding".to_string()));
+    }
+    if !transfer_encoding.is_empty() && !header_values("Content-Length").is_empty() {
+        return Err(ReadError::Invalid("400", "Both Transfer-Encoding and Content-Length".to_string()));
+    }
+    if !transfer_encoding.is_empty() {
+        // 增量解码，已解码的数据立即从 buffer 中移除，每个字节只解析一次
+        let mut decoder = ChunkedDecoder::new().max_body(limits.max_body_size);
+        loop {
+            match decoder.feed(&buffer[header_end..]) {
+                Ok(consumed) => drop(buffer.drain(header_end..header_end + consumed)),
+                Err(ChunkedError::TooLarge) => return Err(too_large()),
+                Err(e) => return Err(ReadError::Invalid("400", e.to_string())),
+            }
+            if decoder.is_done() {
+                break;
+            }
+            if read_body(buffer)? == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+        }
+        let decoded = decoder.finish().expect("chunked body finished");
+        let mut request = Vec::new();
+        for line in head.trim_end().lines() {
+            let name = line.split_once(':').map(|(k, _)| k.trim()).unwrap_or_default();
+            if !name.eq_ignore_ascii_case("Transfer-Encoding") && !name.eq_ignore_ascii_case("Content-Length") {
+                request.extend_from_slice(line.as_bytes());
+                request.extend_from_slice(b"\r\n");
+            }
+        }
+        request.extend_from_slice(format!("Content-Length: {}\r\n\r\n", decoded.body.len()).as_bytes());
+        request.extend_from_slice(&decoded.body);
+        buffer.drain(..header_end);
+        return Ok(RawRequest {
+            bytes: request,
+            trailers: decoded.trailers,
+        });
+    }
+
+    // 根据 Content-Length 读取消息体，值必须是数字，重复出现时必须相同
+    let lengths = header_values("Content-Length");
+    if lengths.iter().any(|v| v.is_empty() || !v.bytes().all(|b| b.is_ascii_digit())) || lengths.windows(2).any(|w| w[0] != w[1]) {
+        return Err(ReadError::Invalid("400", "Invalid Content-Length".to_string()));
+    }
+    let content_length = match lengths.first() {
+        Some(v) => v.parse::<usize>().map_err(|_| too_large())?,
+        None => 0,
+    };
+    if content_length > limits.max_body_size {
+        return Err(too_large());
+    }
+    // 消息体没有收完连接就关闭时丢弃这个请求
+    let total = header_end + content_length;
+    while buffer.len() < total {
+        if read_body(buffer)? == 0 {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+    }
+    let rest = buffer.split_off(total.min(buffer.len()));
+    Ok(RawRequest {
+        bytes: std::mem::replace(buffer, rest),
+        trailers: Vec::new(),
+    })
+}
+
+// 检查请求行和请求头的大小，head_end 为 None 时 data 是尚未读完的请求头
+// 请求行过长返回 414，请求头过多或过大返回 431
+fn check_head(data: &[u8], head_end: Option<usize>, limits: &Limits) -> Result<(), ReadError> {
+    let head = &data[..head_end.unwrap_or(data.len())];
+    let line_end = head.iter().position(|&b| b == b'\n');
+    if line_end.unwrap_or(head.len()) > limits.max_request_line {
+        return Err(ReadError::Invalid("414", "URI Too Long".to_string()));
+    }
+    let headers = line_end.map_or(&[][..], |pos| &head[pos + 1..]);
+    let count = headers.split(|&b| b == b'\n').filter(|line| !line.is_empty() && *line != b"\r").count();
+    if headers.len() > limits.max_header_size || count > limits.max_headers {
+        return Err(ReadError::Invalid("431", "Request Header Fields Too Large".to_string()));
+    }
+    Ok(())
+}
+
+// 读取消息体时下一次读取的超时：不超过 body_timeout，也不晚于平均速率降到 min_rate 以下的时间
+// received 是已收到的消息体字节数，elapsed 是开始接收消息体以来的时间
+pub(crate) fn body_read_timeout(limits: &Limits, received: usize, elapsed: Duration) -> Option<Duration> {
+    if limits.min_rate == 0 {
+        return limits.body_timeout;
+    }
+    let allowed = MIN_RATE_GRACE.max(Duration::from_secs_f64(received as f64 / limits.min_rate as f64));
+    let left = allowed.saturating_sub(elapsed);
+    Some(limits.body_timeout.map_or(left, |t| t.min(left)))
+}
+
+// 在 timeout 内读取一次数据追加到 buffer，返回读取的字节数
+// 剩余时间为零时直接按超时处理（标准库不接受零超时）
+fn read_more(stream: &mut impl Transport, buffer: &mut Vec<u8>, timeout: Option<Duration>) -> std::io::Result<usize> {
+    if timeout == Some(Duration::ZERO) {
+        return Err(std::io::ErrorKind::TimedOut.into());
+    }
+    stream.set_read_timeout(timeout)?;
+    let mut read_buffer = [0; 1024]; // 创建一个缓冲区用于读取数据
+    let bytes_read = stream.read(&mut read_buffer)?;
+    buffer.extend_from_slice(&read_buffer[..bytes_read]);
+    Ok(bytes_read)
+}
+
+// 读取超时的错误，超时的套接字在不同平台上返回 WouldBlock 或 TimedOut
+fn is_timeout(e: &std::io::Error) -> bool {
+    matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut)
+}
+
+// 查找请求头结束的位置（空行之后的第一个字节）
+fn find_header_end(data: &[u8]) -> Option<usize> {
+    data.windows(4).position(|w| w == b"\r\n\r\n").map(|pos| pos + 4)
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // 内存中的连接：从 input 读取请求，响应写入 output
+    // stalled 时 input 读完后返回超时，模拟不再发送数据的客户端
+    struct MockStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+        stalled: bool,
+    }
+
+    impl MockStream {
+        fn new(input: &[u8]) -> Self {
+            MockStream {
+                input: Cursor::new(input.to_vec()),
+                output: Vec::new(),
+                stalled: false,
+            }
+        }
+
+        fn stalled(input: &[u8]) -> Self {
+            MockStream {
+                stalled: true,
+                ..MockStream::new(input)
+            }
+        }
+    }
+
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.input.read(buf)? {
+                0 if self.stalled && !buf.is_empty() => Err(std::io::ErrorKind::TimedOut.into()),
+                bytes_read => Ok(bytes_read),
+            }
+        }
+    }
+
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
+
+    impl Transport for MockStream {
+        fn read_timeout(&self) -> std::io::Result<Option<std::time::Duration>> {
+            Ok(None)
+        }
+        fn set_read_timeout(&self, _: Option<std::time::Duration>) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // 在一个连接上发送原始请求，返回服务器写出的全部内容
+    fn exchange(input: &str) -> String {
+        let mut stream = MockStream::new(input.as_bytes());
+        Server::new("localhost:0").handle_http1(&mut stream, None, None);
+        String::from_utf8_lossy(&stream.output).into_owned()
+    }
+
+    // 按 limits 从 input 读取一个请求，返回错误响应的状态码
+    fn read_status(stream: &mut MockStream, limits: &Limits) -> Option<&'static str> {
+        match read_request(stream, &mut Vec::new(), limits, None) {
+            Err(ReadError::Invalid(status, _)) => Some(status),
+            _ => None,
+        }
+    }
+
+    // 测试版本和 Host 头的校验
+    #[test]
+    fn test_validate_request() {
+        let status = |raw: &str| {
+            let req: HttpRequest = raw.to_string().into();
+            let line = raw.lines().next().unwrap();
+            validate_request(&req, line).map(|resp| resp.status_code().to_string())
+        };
+        assert_eq!(status("GET / HTTP/1.0\r\n\r\n"), None);
+        assert_eq!(status("GET / HTTP/1.1\r\nHost: a\r\n\r\n"), None);
+        assert_eq!(status("GET / HTTP/1.1\r\n\r\n"), Some("400".into()));
+        assert_eq!(status("GET / HTTP/3.0\r\nHost: a\r\n\r\n"), Some("505".into()));
+        assert_eq!(status("GET / HTTPS/x\r\nHost: a\r\n\r\n"), Some("400".into()));
+        assert_eq!(status("GET / HTTP/1.1 x\r\nHost: a\r\n\r\n"), Some("400".into()));
+    }
+
+    // 测试不完整的请求行返回 400，不会使连接线程崩溃
+    #[test]
+    fn test_malformed_request_line() {
+        for raw in ["GET\r\n\r\n", "GET /\r\nHost: a\r\n\r\n", "GET HTTP\r\nHost: a\r\n\r\n"] {
+            assert!(exchange(raw).starts_with("HTTP/1.1 400 Bad Request\r\n"), "{:?}", raw);
+        }
+    }
+
+    // 测试 HTTP/1.1 连接默认保持，直到客户端要求关闭
+    #[test]
+    fn test_http11_keep_alive() {
+        let output = exchange(concat!(
+            "GET /missing HTTP/1.1\r\nHost: a\r\n\r\n",
+            "GET /missing HTTP/1.1\r\nHost: a\r\nConnection: close\r\n\r\n",
+            "GET /missing HTTP/1.1\r\nHost: a\r\n\r\n",
+        ));
+        assert_eq!(output.matches("HTTP/1.1 ").count(), 2);
+        assert_eq!(output.matches("Connection:close\r\n").count(), 1);
+    }
+
+    // 测试 HTTP/1.0 的响应版本和连接保持
+    #[test]
+    fn test_http10() {
+        let output = exchange("GET /missing HTTP/1.0\r\n\r\nGET /missing HTTP/1.0\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.0 "));
+        assert_eq!(output.matches("HTTP/1.0 ").count(), 1);
+
+        let output = exchange(concat!(
+            "GET /missing HTTP/1.0\r\nConnection: keep-alive\r\n\r\n",
+            "GET /missing HTTP/1.0\r\n\r\n",
+        ));
+        assert_eq!(output.matches("HTTP/1.0 ").count(), 2);
+        assert_eq!(output.matches("Connection:keep-alive\r\n").count(), 1);
+    }
+
+    // 测试读取分块编码的请求体，之后的请求不受影响
+    #[test]
+    fn test_read_chunked_request() {
+        let input = concat!(
+            "POST /api/x HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n",
+            "4\r\nWiki\r\n5;ext=1\r\npedia\r\n0\r\nX-Sum: 9\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a\r\n\r\n",
+        );
+        let mut stream = MockStream::new(input.as_bytes());
+        let mut buffer = Vec::new();
+        let limits = Limits::default();
+        let raw = read_request(&mut stream, &mut buffer, &limits, None).unwrap();
+        assert_eq!(raw.trailers, vec![("X-Sum".to_string(), "9".to_string())]);
+        let req: HttpRequest = raw.bytes.into();
+        assert_eq!(req.msg_body, "Wikipedia");
+        assert_eq!(req.header("Content-Length"), Some("9"));
+        assert_eq!(req.header("Transfer-Encoding"), None);
+        let next = read_request(&mut stream, &mut buffer, &limits, None).unwrap();
+        assert!(next.bytes.starts_with(b"GET / HTTP/1.1"));
+
+        // 尾部头部不会覆盖请求头
+        let input = "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n0\r\nHost: evil\r\n\r\n";
+        let raw = read_request(&mut MockStream::new(input.as_bytes()), &mut Vec::new(), &limits, None).unwrap();
+        let req: HttpRequest = raw.bytes.into();
+        assert_eq!(req.header("Host"), Some("a"));
+        assert_eq!(raw.trailers, vec![("Host".to_string(), "evil".to_string())]);
+
+        let output = exchange("POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n");
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+    }
+
+    // 测试消息体长度含糊的请求返回 400，不支持的传输编码返回 501（防止请求走私）
+    #[test]
+    fn test_ambiguous_framing() {
+        let limits = Limits::default();
+        let status = |head: &str| {
+            let raw = format!("POST / HTTP/1.1\r\nHost: a\r\n{}\r\n0\r\n\r\n", head);
+            read_status(&mut MockStream::new(raw.as_bytes()), &limits)
+        };
+        assert_eq!(status("Content-Length: abc\r\n"), Some("400"));
+        assert_eq!(status("Content-Length: -1\r\n"), Some("400"));
+        assert_eq!(status("Content-Length: +3\r\n"), Some("400"));
+        assert_eq!(status("Content-Length: 3\r\nContent-Length: 5\r\n"), Some("400"));
+        assert_eq!(status("Content-Length: 3, 5\r\n"), Some("400"));
+        assert_eq!(status("Transfer-Encoding: gzip\r\n"), Some("501"));
+        assert_eq!(status("Transfer-Encoding: chunked, gzip\r\n"), Some("501"));
+        assert_eq!(status("Transfer-Encoding: gzip, chunked\r\n"), Some("501"));
+        assert_eq!(status("Transfer-Encoding: chunked, chunked\r\n"), Some("501"));
+        assert_eq!(status("Transfer-Encoding: chunked\r\nTransfer-Encoding: identity\r\n"), Some("501"));
+        assert_eq!(status("Transfer-Encoding: chunked\r\nContent-Length: 5\r\n"), Some("400"));
+        assert_eq!(status("Content-Length: 0\r\nTransfer-Encoding: chunked\r\n"), Some("400"));
+        assert_eq!(status("Content-Length: 5\r\nContent-Length: 5\r\n"), None);
+        assert_eq!(status("Transfer-Encoding: chunked\r\n"), None);
+
+        let output = exchange("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: x\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+
+        // 拒绝后关闭连接，后面的字节不会被当作下一个请求
+        let output = exchange("POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: gzip, chunked\r\n\r\n0\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 501 Not Implemented\r\n"));
+        assert!(output.contains("Connection:close\r\n"));
+        assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+        let output = exchange("POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\r\n0\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(output.contains("Connection:close\r\n"));
+        assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+    }
+
+    // 测试连接在请求头或消息体中间关闭时丢弃不完整的请求
+    #[test]
+    fn test_truncated_request() {
+        let limits = Limits::default();
+        let result = read_request(&mut MockStream::new(b"GET / HTTP/1.1\r\nHost: a\r\n"), &mut Vec::new(), &limits, None);
+        assert!(matches!(result, Err(ReadError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+        let partial_body = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nhello";
+        let result = read_request(&mut MockStream::new(partial_body), &mut Vec::new(), &limits, None);
+        assert!(matches!(result, Err(ReadError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+
+        assert_eq!(exchange("GET /missing HTTP/1.1\r\nHost: a\r\n"), "");
+        assert_eq!(exchange("POST /missing HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nhello"), "");
+    }
+
+    // 测试超过上限的消息体返回 413，Content-Length 和分块编码都检查
+    #[test]
+    fn test_body_too_large() {
+        let limits = Limits {
+            max_body_size: 10,
+            ..Limits::default()
+        };
+        let mut stream = MockStream::new(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 11\r\n\r\nhello world");
+        assert_eq!(read_status(&mut stream, &limits), Some("413"));
+        let mut stream = MockStream::new(b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nhelloworld");
+        assert!(read_request(&mut stream, &mut Vec::new(), &limits, None).is_ok());
+
+        let chunked = "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n6\r\nhello \r\n5\r\nworld\r\n0\r\n\r\n";
+        assert_eq!(read_status(&mut MockStream::new(chunked.as_bytes()), &limits), Some("413"));
+        // 分块长度超过上限时不等待分块数据
+        let declared = "POST / HTTP/1.1\r\nHost: a\r\nTransfer-Encoding: chunked\r\n\r\n100\r\n";
+        assert_eq!(read_status(&mut MockStream::stalled(declared.as_bytes()), &limits), Some("413"));
+    }
+
+    // 测试请求行过长返回 414，请求头过多或过大返回 431，不等请求头读完就拒绝
+    #[test]
+    fn test_head_limits() {
+        let limits = Limits {
+            max_request_line: 32,
+            max_headers: 3,
+            max_header_size: 64,
+            ..Limits::default()
+        };
+        let ok = "GET /short HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\n\r\n";
+        assert_eq!(read_status(&mut MockStream::new(ok.as_bytes()), &limits), None);
+        let long_uri = format!("GET /{} HTTP/1.1\r\nHost: a\r\n\r\n", "x".repeat(40));
+        assert_eq!(read_status(&mut MockStream::new(long_uri.as_bytes()), &limits), Some("414"));
+        let many = "GET / HTTP/1.1\r\nHost: a\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n";
+        assert_eq!(read_status(&mut MockStream::new(many.as_bytes()), &limits), Some("431"));
+        let large = format!("GET / HTTP/1.1\r\nHost: a\r\nX: {}\r\n\r\n", "y".repeat(60));
+        assert_eq!(read_status(&mut MockStream::new(large.as_bytes()), &limits), Some("431"));
+        // 没有结束的请求行和请求头同样受限
+        let endless = "GET /".to_string() + &"x".repeat(100);
+        assert_eq!(read_status(&mut MockStream::stalled(endless.as_bytes()), &limits), Some("414"));
+        let endless = "GET / HTTP/1.1\r\nX: ".to_string() + &"y".repeat(100);
+        assert_eq!(read_status(&mut MockStream::stalled(endless.as_bytes()), &limits), Some("431"));
+
+        let output = exchange(&format!("GET /{} HTTP/1.1\r\nHost: a\r\n\r\n", "x".repeat(9000)));
+        assert!(output.starts_with("HTTP/1.1 414 URI Too Long\r\n"));
+    }
+
+    // 测试收到部分请求后超时返回 408，还没有收到数据时直接关闭连接
+    #[test]
+    fn test_read_timeouts() {
+        let limits = Limits::default();
+        let mut stream = MockStream::stalled(b"");
+        let result = read_request(&mut stream, &mut Vec::new(), &limits, None);
+        assert!(matches!(result, Err(ReadError::Io(e)) if is_timeout(&e)));
+        assert_eq!(read_status(&mut MockStream::stalled(b"GET / HTTP/1.1\r\nHo"), &limits), Some("408"));
+        let partial_body = b"POST / HTTP/1.1\r\nHost: a\r\nContent-Length: 10\r\n\r\nhello";
+        assert_eq!(read_status(&mut MockStream::stalled(partial_body), &limits), Some("408"));
+
+        let server = Server::new("localhost:0");
+        let mut stream = MockStream::stalled(b"GET / HTTP/1.1\r\n");
+        server.handle_http1(&mut stream, None, None);
+        assert!(stream.output.starts_with(b"HTTP/1.1 408 Request Timeout\r\n"));
+        let mut stream = MockStream::stalled(b"GET /missing HTTP/1.1\r\nHost: a\r\n\r\n");
+        server.handle_http1(&mut stream, None, None);
+        assert_eq!(String::from_utf8_lossy(&stream.output).matches("HTTP/1.1 ").count(), 1);
+    }
+
+    // 测试消息体的平均速率低于下限时返回 408
+    #[test]
+    fn test_min_rate() {
+        let secs = |s: u64| Some(Duration::from_secs(s));
+        let limits = Limits {
+            body_timeout: secs(30),
+            min_rate: 100,
+            ..Limits::default()
+        };
+        // 宽限期内只受 body_timeout 和宽限期限制
+        assert_eq!(body_read_timeout(&limits, 0, Duration::ZERO), secs(5));
+        assert_eq!(body_read_timeout(&limits, 0, Duration::from_secs(2)), secs(3));
+        // 已收到 1000 字节，按 100 字节/秒可以等到第 10 秒
+        assert_eq!(body_read_timeout(&limits, 1000, Duration::from_secs(6)), secs(4));
+        assert_eq!(body_read_timeout(&limits, 1000, Duration::from_secs(12)), secs(0));
+        assert_eq!(body_read_timeout(&limits, 100_000, Duration::from_secs(6)), secs(30));
+        let limits = Limits { min_rate: 0, ..limits };
+        assert_eq!(body_read_timeout(&limits, 0, Duration::from_secs(60)), secs(30));
+
+        // 剩余时间为零时不再读取
+        let mut stream = MockStream::new(b"more");
+        assert!(is_timeout(&read_more(&mut stream, &mut Vec::new(), secs(0)).unwrap_err()));
+    }
+
+    // 测试处理器超时返回 503 并关闭连接，连接线程不等待处理器结束，处理器线程数有上限
+    #[test]
+    fn test_handler_timeout() {
+        struct Slow;
+        impl Middleware for Slow {
+            fn before(&self, _: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+                thread::sleep(Duration::from_millis(200));
+                None
+            }
+        }
+        let limits = Limits {
+            handler_timeout: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        };
+        let server = Server::new("localhost:0").limits(limits).middleware(Slow);
+        let mut stream = MockStream::new(b"GET /missing HTTP/1.1\r\nHost: a\r\n\r\nGET /missing HTTP/1.1\r\nHost: a\r\n\r\n");
+        let start = Instant::now();
+        server.handle_http1(&mut stream, None, None);
+        assert!(start.elapsed() < Duration::from_millis(150));
+        let output = String::from_utf8_lossy(&stream.output);
+        assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+
+        // 超时的处理器仍然占用名额，结束后释放
+        assert_eq!(server.running.load(Ordering::Acquire), 1);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while server.running.load(Ordering::Acquire) > 0 && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(server.running.load(Ordering::Acquire), 0);
+
+        // 名额用完时不再启动处理器
+        server.running.store(MAX_RUNNING_HANDLERS, Ordering::Release);
+        let mut stream = MockStream::new(b"GET /missing HTTP/1.1\r\nHost: a\r\n\r\n");
+        let start = Instant::now();
+        server.handle_http1(&mut stream, None, None);
+        assert!(start.elapsed() < Duration::from_millis(150));
+        let output = String::from_utf8_lossy(&stream.output);
+        assert!(output.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+        assert!(output.ends_with("Too Many Handlers"));
+        assert_eq!(server.running.load(Ordering::Acquire), MAX_RUNNING_HANDLERS);
+    }
+
+    // 测试不支持的主版本返回 505 并关闭连接
+    #[test]
+    fn test_unsupported_version() {
+        let output = exchange("GET / HTTP/3.0\r\nHost: a\r\n\r\nGET / HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 505 HTTP Version Not Supported\r\n"));
+        assert_eq!(output.matches("HTTP/1.1 ").count(), 1);
+    }
+
+    // 测试不以 / 开头的请求目标返回 404，不会使连接线程崩溃
+    #[test]
+    fn test_relative_target() {
+        let output = exchange("GET x HTTP/1.1\r\nHost: a\r\n\r\nPOST x HTTP/1.1\r\nHost: a\r\nContent-Length: 0\r\n\r\n");
+        assert_eq!(output.matches("HTTP/1.1 404 Not Found\r\n").count(), 2);
+    }
+
+    // 测试去掉点段和连续的 /，越过根目录时返回 None
+    #[test]
+    fn test_remove_dot_segments() {
+        assert_eq!(remove_dot_segments("/api/./orders/../items?q=../x").as_deref(), Some("/api/items?q=../x"));
+        assert_eq!(remove_dot_segments("/api/%2E%2e/admin").as_deref(), Some("/admin"));
+        assert_eq!(remove_dot_segments("/api/v1/..").as_deref(), Some("/api/"));
+        assert_eq!(remove_dot_segments("/api/.").as_deref(), Some("/api/"));
+        assert_eq!(remove_dot_segments("/a/..").as_deref(), Some("/"));
+        assert_eq!(remove_dot_segments("/api/orders/").as_deref(), Some("/api/orders/"));
+        assert_eq!(remove_dot_segments("//api//orders").as_deref(), Some("/api/orders"));
+        assert_eq!(remove_dot_segments("/"), Some("/".to_string()));
+        assert_eq!(remove_dot_segments("/api/../../etc"), None);
+    }
+
+    // 测试按配置创建的完整中间件链：点段在所有中间件之前去掉，认证和限流不能通过 /x/../api 绕过
+    #[test]
+    fn test_dot_segments_before_middlewares() {
+        let dir = std::env::temp_dir().join(format!("httpserver-server-auth-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tokens"), "ci:token:admin\n").unwrap();
+        let config = crate::config::Config::parse(&format!(
+            "[auth]\ntokens_file = {:?}\n\n[ratelimit]\nquota = \"100/60\"\nroutes = [\"/api/v2=2/60\"]\n\n\
+             [[proxy.routes]]\nprefix = \"/api/v2\"\nupstreams = [\"http://127.0.0.1:1\"]\n",
+            dir.join("tokens")
+        ))
+        .unwrap();
+        let server = config.build().unwrap();
+        let send = |request: &str| {
+            let mut stream = MockStream::new(request.as_bytes());
+            server.handle_http1(&mut stream, None, None);
+            String::from_utf8_lossy(&stream.output).into_owned()
+        };
+        assert!(send("GET /x/../api/shipping/orders HTTP/1.1\r\nHost: a\r\n\r\n").starts_with("HTTP/1.1 401 "));
+        assert!(send("GET /../api/v2/orders HTTP/1.1\r\nHost: a\r\n\r\n").starts_with("HTTP/1.1 400 "));
+        // /api/v2 的限流配额是 2：未认证的请求和认证后转发的请求各用一次，合并 / 后的路径匹配同一条配额
+        assert!(send("GET /static/%2e%2e/api/v2/orders HTTP/1.1\r\nHost: a\r\n\r\n").starts_with("HTTP/1.1 401 "));
+        // 认证后代理按规范化的路径转发，上游不可连接，返回 502
+        let authorized = "HTTP/1.1\r\nHost: a\r\nAuthorization: Bearer token\r\n\r\n";
+        assert!(send(&format!("GET /x/./../api/v2/orders {}", authorized)).starts_with("HTTP/1.1 502 "));
+        assert!(send(&format!("GET //api/v2/orders {}", authorized)).starts_with("HTTP/1.1 429 "));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 测试 WebSocket 升级后在同一个连接上收发消息
+    #[test]
+    fn test_websocket_upgrade() {
+        use http::websocket::{Message, Role, WebSocket};
+        let mut client = WebSocket::new(
+            MockStream::new(b""),
+            Role::Client,
+            None,
+        );
+        client.send(&Message::Text("hello".into())).unwrap();
+        client.send(&Message::Close(None)).unwrap();
+        let mut input = b"GET /ws/echo HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n".to_vec();
+        input.extend_from_slice(&client.get_ref().output);
+
+        let mut stream = MockStream::new(&input);
+        Server::new("localhost:0").handle_http1(&mut stream, None, None);
+        let output = stream.output;
+        let head_end = find_header_end(&output).unwrap();
+        let head = String::from_utf8_lossy(&output[..head_end]);
+        assert!(head.starts_with("HTTP/1.1 101 Switching Protocols\r\n"));
+        assert!(head.contains("Sec-WebSocket-Accept:s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+        // 回显的消息和关闭帧，服务器发送的帧不带掩码
+        assert_eq!(&output[head_end..], b"\x81\x05hello\x88\x00");
+
+        // 需要认证的 WebSocket 路由在握手前被认证中间件拒绝
+        let auth = crate::auth::AuthMiddleware::new("test")
+            .bearer_tokens(crate::auth::BearerTokens::parse("ci:token:admin\n"))
+            .default_rules();
+        let upgrade = b"GET /ws/orders HTTP/1.1\r\nHost: a\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\r\n";
+        let mut stream = MockStream::new(upgrade);
+        Server::new("localhost:0").middleware(auth).handle_http1(&mut stream, None, None);
+        assert!(stream.output.starts_with(b"HTTP/1.1 401 Unauthorized\r\n"));
+
+        // 没有升级的请求返回 426，连接继续处理下一个请求
+        let output = exchange("GET /ws/echo HTTP/1.1\r\nHost: a\r\n\r\nGET /ws/nothing HTTP/1.1\r\nHost: a\r\n\r\n");
+        assert!(output.starts_with("HTTP/1.1 426 Upgrade Required\r\n"));
+        assert!(output.contains("HTTP/1.1 404 Not Found\r\n"));
+    }
 }
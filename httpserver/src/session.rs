@@ -0,0 +1,347 @@
+// 导入所需的库和模块
+use super::middleware::Middleware; // 导入中间件
+use super::session_store::{now_secs, SessionRecord, SessionStore}; // 导入会话存储
+use base64::engine::general_purpose::URL_SAFE_NO_PAD; // 导入 URL 安全的 base64 编码
+use base64::Engine; // 导入 base64 编解码特性
+use http::cookie::{Cookie, CookieError, CookieKey, SameSite}; // 导入 Cookie
+use http::{httprequest::HttpRequest, httpresponse::HttpResponse}; // 导入 HTTP 请求和响应模块
+use serde::de::DeserializeOwned; // 导入反序列化特性
+use serde::Serialize; // 导入序列化特性
+use std::collections::HashMap; // 导入 HashMap
+use std::sync::atomic::{AtomicUsize, Ordering}; // 导入原子计数器
+use std::sync::{Arc, Mutex}; // 导入共享指针和互斥锁
+use std::time::Duration; // 导入时间间隔
+
+// 每处理这么多请求清理一次过期会话
+const CLEANUP_INTERVAL: usize = 100;
+
+// 会话在一次请求中的状态
+#[derive(Debug, Default)]
+struct SessionState {
+    id: Option<String>,                            // 会话 ID，新会话在保存前为 None
+    data: HashMap<String, serde_json::Value>,      // 会话数据
+    expires_at: u64,                               // 已保存会话的过期时间
+    changed: bool,                                 // 数据是否被修改
+    rotate: bool,                                  // 是否需要更换会话 ID
+    destroyed: bool,                               // 是否已销毁
+}
+
+// 处理器使用的会话句柄，克隆后指向同一个会话
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>, // 会话状态
+}
+
+impl Session {
+    // 返回会话 ID，新会话在响应发出前没有 ID
+    pub fn id(&self) -> Option<String> {
+        self.state.lock().unwrap().id.clone()
+    }
+
+    // 读取并反序列化指定键的值
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let state = self.state.lock().unwrap();
+        state
+            .data
+            .get(key)
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
+    // 序列化并保存指定键的值
+    pub fn insert<T: Serialize>(&self, key: &str, value: T) {
+        let mut state = self.state.lock().unwrap();
+        if let Ok(value) = serde_json::to_value(value) {
+            state.data.insert(key.to_string(), value);
+            state.changed = true;
+        }
+    }
+
+    // 删除指定键
+    pub fn remove(&self, key: &str) {
+        let mut state = self.state.lock().unwrap();
+        if state.data.remove(key).is_some() {
+            state.changed = true;
+        }
+    }
+
+    // 清空会话数据
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.changed = true;
+    }
+
+    // 会话中是否没有任何数据
+    pub fn is_empty(&self) -> bool {
+        self.state.lock().unwrap().data.is_empty()
+    }
+
+    // 在权限变化（如登录）时更换会话 ID，防止会话固定攻击
+    pub fn rotate_id(&self) {
+        self.state.lock().unwrap().rotate = true;
+    }
+
+    // 销毁会话，并让浏览器删除会话 Cookie
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+// 为 HttpRequest 提供读取会话的方法
+pub trait SessionExt {
+    // 返回会话中间件附加到请求上的会话
+    fn session(&self) -> Option<Session>;
+}
+
+impl SessionExt for HttpRequest {
+    fn session(&self) -> Option<Session> {
+        self.extensions.get::<Session>().cloned()
+    }
+}
+
+// 生成一个 256 位的随机会话 ID
+pub fn generate_session_id() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).expect("failed to read random bytes");
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// 会话中间件：根据 Cookie 加载会话，并在响应前保存修改后的会话
+pub struct SessionMiddleware {
+    store: Box<dyn SessionStore>, // 会话存储
+    cookie_name: String,          // 会话 Cookie 名称
+    ttl: Duration,                // 会话有效期
+    secure: bool,                 // 会话 Cookie 是否只通过 HTTPS 发送
+    key: Option<CookieKey>,       // 可选的 Cookie 签名密钥
+    requests: AtomicUsize,        // 已处理的请求数，用于定期清理
+}
+
+impl SessionMiddleware {
+    // 使用指定的存储创建会话中间件，默认有效期为 24 小时
+    pub fn new(store: Box<dyn SessionStore>) -> Self {
+        SessionMiddleware {
+            store,
+            cookie_name: "session_id".to_string(),
+            ttl: Duration::from_secs(24 * 60 * 60),
+            secure: false,
+            key: None,
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    // 设置会话 Cookie 名称
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    // 设置会话有效期
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    // 设置会话 Cookie 的 Secure 属性
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    // 使用密钥对会话 Cookie 签名
+    pub fn signing_key(mut self, key: CookieKey) -> Self {
+        self.key = Some(key);
+        self
+    }
+
+    // 从请求中读取会话 ID，配置了密钥时校验签名
+    fn session_id(&self, req: &HttpRequest) -> Option<String> {
+        match &self.key {
+            Some(key) => req.signed_cookie(&self.cookie_name, key),
+            None => req.cookie(&self.cookie_name),
+        }
+    }
+
+    // 生成携带会话 ID 的 Cookie，Cookie 名称无效时返回错误
+    fn session_cookie(&self, id: &str) -> Result<Cookie, CookieError> {
+        let cookie = Cookie::new(self.cookie_name.clone(), id)?
+            .path("/")?
+            .max_age(self.ttl.as_secs() as i64)
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(SameSite::Lax);
+        Ok(match &self.key {
+            Some(key) => cookie.signed(key),
+            None => cookie,
+        })
+    }
+}
+
+impl Middleware for SessionMiddleware {
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        // 定期清理过期会话，避免存储无限增长
+        if self.requests.fetch_add(1, Ordering::Relaxed).is_multiple_of(CLEANUP_INTERVAL) {
+            if let Err(e) = self.store.cleanup_expired() {
+                eprintln!("Failed to clean up sessions: {}", e);
+            }
+        }
+
+        let mut state = SessionState::default();
+        if let Some(id) = self.session_id(req) {
+            match self.store.load(&id) {
+                Ok(Some(record)) => {
+                    state.id = Some(id);
+                    state.data = record.data;
+                    state.expires_at = record.expires_at;
+                }
+                Ok(None) => {}
+                Err(e) => eprintln!("Failed to load session: {}", e),
+            }
+        }
+        req.extensions.insert(Session {
+            state: Arc::new(Mutex::new(state)),
+        });
+        None
+    }
+
+    fn after(&self, req: &HttpRequest, resp: &mut HttpResponse<'_>) {
+        let Some(session) = req.session() else {
+            return;
+        };
+        let mut state = session.state.lock().unwrap();
+
+        // 会话被销毁或数据被清空时，删除存储中的会话和浏览器中的 Cookie
+        if state.destroyed || (state.changed && state.data.is_empty()) {
+            if let Some(id) = state.id.take() {
+                if let Err(e) = self.store.remove(&id) {
+                    eprintln!("Failed to remove session: {}", e);
+                }
+                match Cookie::removal(self.cookie_name.clone()).and_then(|c| c.path("/")) {
+                    Ok(cookie) => resp.add_cookie(&cookie),
+                    Err(e) => eprintln!("Failed to remove session cookie: {}", e),
+                }
+            }
+            return;
+        }
+
+        // 更换会话 ID 时先删除旧会话
+        if state.rotate {
+            if let Some(old_id) = state.id.take() {
+                if let Err(e) = self.store.remove(&old_id) {
+                    eprintln!("Failed to remove session: {}", e);
+                }
+            }
+        }
+
+        // 数据有变化、需要新 ID 或剩余有效期不足一半时保存会话
+        let now = now_secs();
+        let ttl = self.ttl.as_secs();
+        let needs_refresh = state.id.is_some() && state.expires_at < now + ttl / 2;
+        if state.data.is_empty() || !(state.changed || state.id.is_none() || needs_refresh) {
+            return;
+        }
+        let id = state.id.get_or_insert_with(generate_session_id).clone();
+        let record = SessionRecord {
+            data: state.data.clone(),
+            expires_at: now + ttl,
+        };
+        match self.store.save(&id, &record) {
+            Ok(()) => match self.session_cookie(&id) {
+                Ok(cookie) => resp.add_cookie(&cookie),
+                Err(e) => eprintln!("Failed to set session cookie: {}", e),
+            },
+            Err(e) => eprintln!("Failed to save session: {}", e),
+        }
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+    use crate::session_store::MemorySessionStore; // 导入内存会话存储
+
+    // 用给定的 Cookie 构造请求
+    fn request(cookie: Option<&str>) -> HttpRequest {
+        match cookie {
+            Some(c) => format!("GET /admin HTTP/1.1\r\nCookie: {}\r\n\r\n", c).into(),
+            None => String::from("GET /admin HTTP/1.1\r\n\r\n").into(),
+        }
+    }
+
+    // 从响应中取出 Set-Cookie 的值
+    fn set_cookie(resp: HttpResponse) -> Option<String> {
+        let raw: String = resp.into();
+        raw.lines()
+            .find_map(|line| line.strip_prefix("Set-Cookie:"))
+            .map(|v| v.to_string())
+    }
+
+    // 执行一次请求：before、处理器、after
+    fn round_trip(
+        middleware: &SessionMiddleware,
+        cookie: Option<&str>,
+        handler: impl Fn(&Session),
+    ) -> Option<String> {
+        let mut req = request(cookie);
+        assert!(middleware.before(&mut req).is_none());
+        handler(&req.session().unwrap());
+        let mut resp = HttpResponse::new("200", None, None);
+        middleware.after(&req, &mut resp);
+        set_cookie(resp)
+    }
+
+    // 测试会话数据在请求之间保存，空会话不会发出 Cookie
+    #[test]
+    fn test_session_round_trip() {
+        let middleware = SessionMiddleware::new(Box::new(MemorySessionStore::new()));
+        assert_eq!(round_trip(&middleware, None, |_| {}), None);
+
+        let cookie = round_trip(&middleware, None, |s| s.insert("user", "alice")).unwrap();
+        assert!(cookie.contains("HttpOnly"));
+        let pair = cookie.split(';').next().unwrap().to_string();
+
+        round_trip(&middleware, Some(&pair), |s| {
+            assert_eq!(s.get::<String>("user"), Some("alice".into()));
+        });
+    }
+
+    // 测试更换会话 ID 后旧 ID 失效
+    #[test]
+    fn test_session_rotation() {
+        let middleware = SessionMiddleware::new(Box::new(MemorySessionStore::new()));
+        let old = round_trip(&middleware, None, |s| s.insert("cart", 3)).unwrap();
+        let old = old.split(';').next().unwrap().to_string();
+
+        let new = round_trip(&middleware, Some(&old), |s| {
+            s.rotate_id();
+            s.insert("user", "alice");
+        })
+        .unwrap();
+        let new = new.split(';').next().unwrap().to_string();
+        assert_ne!(old, new);
+
+        round_trip(&middleware, Some(&old), |s| assert!(s.is_empty()));
+        round_trip(&middleware, Some(&new), |s| {
+            assert_eq!(s.get::<i32>("cart"), Some(3));
+        });
+    }
+
+    // 测试销毁会话会删除 Cookie，签名错误的 Cookie 被忽略
+    #[test]
+    fn test_session_destroy_and_signing() {
+        let middleware = SessionMiddleware::new(Box::new(MemorySessionStore::new()))
+            .signing_key(CookieKey::new("session signing secret"));
+        let cookie = round_trip(&middleware, None, |s| s.insert("user", "alice")).unwrap();
+        let pair = cookie.split(';').next().unwrap().to_string();
+
+        let forged = format!("session_id={}", pair.split('=').nth(1).unwrap().split('.').next().unwrap());
+        round_trip(&middleware, Some(&forged), |s| assert!(s.is_empty()));
+
+        let removal = round_trip(&middleware, Some(&pair), |s| s.destroy()).unwrap();
+        assert!(removal.starts_with("session_id=; "));
+        assert!(removal.contains("Max-Age=0"));
+        round_trip(&middleware, Some(&pair), |s| assert!(s.is_empty()));
+    }
+}
@@ -0,0 +1,352 @@
+// 导入所需的库和模块
+use rusqlite::{params, Connection, OptionalExtension}; // 导入 SQLite 接口
+use serde::{Deserialize, Serialize}; // 导入序列化和反序列化库
+use std::collections::HashMap; // 导入 HashMap
+use std::fmt; // 导入格式化模块
+use std::fs; // 导入文件系统模块
+use std::path::{Path, PathBuf}; // 导入路径模块
+use std::sync::Mutex; // 导入互斥锁
+use std::time::{SystemTime, UNIX_EPOCH}; // 导入时间模块
+
+// 定义一条会话记录
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, Default)]
+pub struct SessionRecord {
+    pub data: HashMap<String, serde_json::Value>, // 会话数据
+    pub expires_at: u64,                          // 过期时间（Unix 秒）
+}
+
+impl SessionRecord {
+    // 会话是否已过期
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= now_secs()
+    }
+}
+
+// 返回当前的 Unix 时间（秒）
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// 定义会话存储可能出现的错误
+#[derive(Debug)]
+pub enum SessionStoreError {
+    Io(std::io::Error),      // 文件读写错误
+    Json(serde_json::Error), // JSON 解析错误
+    Sqlite(rusqlite::Error), // SQLite 错误
+}
+
+impl fmt::Display for SessionStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionStoreError::Io(e) => write!(f, "io error: {}", e),
+            SessionStoreError::Json(e) => write!(f, "json error: {}", e),
+            SessionStoreError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionStoreError {}
+
+impl From<std::io::Error> for SessionStoreError {
+    fn from(e: std::io::Error) -> Self {
+        SessionStoreError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SessionStoreError {
+    fn from(e: serde_json::Error) -> Self {
+        SessionStoreError::Json(e)
+    }
+}
+
+impl From<rusqlite::Error> for SessionStoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        SessionStoreError::Sqlite(e)
+    }
+}
+
+// 会话存储操作的结果类型
+pub type SessionStoreResult<T> = Result<T, SessionStoreError>;
+
+// 定义可插拔的会话存储
+pub trait SessionStore: Send + Sync {
+    // 读取会话，不存在或已过期时返回 None
+    fn load(&self, id: &str) -> SessionStoreResult<Option<SessionRecord>>;
+
+    // 保存（新建或覆盖）会话
+    fn save(&self, id: &str, record: &SessionRecord) -> SessionStoreResult<()>;
+
+    // 删除会话
+    fn remove(&self, id: &str) -> SessionStoreResult<()>;
+
+    // 清理所有已过期的会话，返回清理的数量
+    fn cleanup_expired(&self) -> SessionStoreResult<usize>;
+}
+
+// 基于内存的会话存储，进程重启后会话丢失
+#[derive(Default)]
+pub struct MemorySessionStore {
+    sessions: Mutex<HashMap<String, SessionRecord>>, // 会话 ID 到记录的映射
+}
+
+impl MemorySessionStore {
+    // 创建一个空的内存会话存储
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemorySessionStore {
+    fn load(&self, id: &str) -> SessionStoreResult<Option<SessionRecord>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        match sessions.get(id) {
+            Some(record) if record.is_expired() => {
+                sessions.remove(id);
+                Ok(None)
+            }
+            record => Ok(record.cloned()),
+        }
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord) -> SessionStoreResult<()> {
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(id.to_string(), record.clone());
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> SessionStoreResult<()> {
+        self.sessions.lock().unwrap().remove(id);
+        Ok(())
+    }
+
+    fn cleanup_expired(&self) -> SessionStoreResult<usize> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let before = sessions.len();
+        sessions.retain(|_, record| !record.is_expired());
+        Ok(before - sessions.len())
+    }
+}
+
+// 基于文件的会话存储，每个会话保存为目录中的一个 JSON 文件
+pub struct FileSessionStore {
+    dir: PathBuf, // 会话文件目录
+}
+
+impl FileSessionStore {
+    // 创建文件会话存储，目录不存在时自动创建
+    pub fn new(dir: impl Into<PathBuf>) -> SessionStoreResult<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(FileSessionStore { dir })
+    }
+
+    // 返回会话文件路径；会话 ID 来自客户端，只接受 base64url 字符，防止路径穿越
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        let valid = !id.is_empty()
+            && id
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+        valid.then(|| self.dir.join(format!("{}.json", id)))
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn load(&self, id: &str) -> SessionStoreResult<Option<SessionRecord>> {
+        let Some(path) = self.path(id) else {
+            return Ok(None);
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let record: SessionRecord = serde_json::from_str(&contents)?;
+        if record.is_expired() {
+            let _ = fs::remove_file(path);
+            return Ok(None);
+        }
+        Ok(Some(record))
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord) -> SessionStoreResult<()> {
+        if let Some(path) = self.path(id) {
+            // 先写临时文件再重命名，避免并发读取到写了一半的文件
+            let tmp_path = path.with_extension("json.tmp");
+            fs::write(&tmp_path, serde_json::to_string(record)?)?;
+            fs::rename(tmp_path, path)?;
+        }
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> SessionStoreResult<()> {
+        if let Some(path) = self.path(id) {
+            match fs::remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn cleanup_expired(&self) -> SessionStoreResult<usize> {
+        let mut removed = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let expired = fs::read_to_string(&path)
+                .ok()
+                .and_then(|c| serde_json::from_str::<SessionRecord>(&c).ok())
+                .is_none_or(|record| record.is_expired());
+            if expired && fs::remove_file(&path).is_ok() {
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+}
+
+// 基于 SQLite 的会话存储，可以在多个进程之间共享
+pub struct SqliteSessionStore {
+    conn: Mutex<Connection>, // 数据库连接
+}
+
+impl SqliteSessionStore {
+    // 打开（或创建）会话数据库
+    pub fn open(path: impl AsRef<Path>) -> SessionStoreResult<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    // 创建一个内存会话数据库，主要用于测试
+    pub fn open_in_memory() -> SessionStoreResult<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(conn: Connection) -> SessionStoreResult<Self> {
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS sessions (
+                id         TEXT PRIMARY KEY,
+                data       TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS sessions_expires_at ON sessions (expires_at);",
+        )?;
+        Ok(SqliteSessionStore {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl SessionStore for SqliteSessionStore {
+    fn load(&self, id: &str) -> SessionStoreResult<Option<SessionRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let row: Option<(String, u64)> = conn
+            .query_row(
+                "SELECT data, expires_at FROM sessions WHERE id = ?1 AND expires_at > ?2",
+                params![id, now_secs()],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        match row {
+            Some((data, expires_at)) => Ok(Some(SessionRecord {
+                data: serde_json::from_str(&data)?,
+                expires_at,
+            })),
+            None => Ok(None),
+        }
+    }
+
+    fn save(&self, id: &str, record: &SessionRecord) -> SessionStoreResult<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO sessions (id, data, expires_at) VALUES (?1, ?2, ?3)
+             ON CONFLICT(id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at",
+            params![id, serde_json::to_string(&record.data)?, record.expires_at],
+        )?;
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> SessionStoreResult<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM sessions WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    fn cleanup_expired(&self) -> SessionStoreResult<usize> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM sessions WHERE expires_at <= ?1", params![now_secs()])?)
+    }
+}
+
+// 按名称创建会话存储的后端：file 保存在 path 目录，sqlite 保存在 path 数据库，其他名称保存在内存中
+pub fn session_store(backend: &str, path: &Path) -> SessionStoreResult<Box<dyn SessionStore>> {
+    match backend {
+        "file" => Ok(Box::new(FileSessionStore::new(path)?)),
+        "sqlite" => Ok(Box::new(SqliteSessionStore::open(path)?)),
+        _ => Ok(Box::new(MemorySessionStore::new())),
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 构造一条会话记录
+    fn record(user: &str, expires_at: u64) -> SessionRecord {
+        let mut data = HashMap::new();
+        data.insert("user".to_string(), serde_json::json!(user));
+        SessionRecord { data, expires_at }
+    }
+
+    // 对任意存储执行相同的读写和过期检查
+    fn exercise(store: &dyn SessionStore) {
+        let future = now_secs() + 60;
+        store.save("live", &record("alice", future)).unwrap();
+        store.save("stale", &record("bob", now_secs() - 1)).unwrap();
+
+        assert_eq!(store.load("live").unwrap(), Some(record("alice", future)));
+        assert_eq!(store.load("stale").unwrap(), None);
+        assert_eq!(store.load("missing").unwrap(), None);
+
+        store.save("stale2", &record("carol", now_secs() - 1)).unwrap();
+        assert!(store.cleanup_expired().unwrap() >= 1);
+        assert_eq!(store.cleanup_expired().unwrap(), 0);
+
+        store.remove("live").unwrap();
+        assert_eq!(store.load("live").unwrap(), None);
+    }
+
+    // 测试内存存储
+    #[test]
+    fn test_memory_store() {
+        exercise(&MemorySessionStore::new());
+    }
+
+    // 测试文件存储，并确认非法的会话 ID 不会访问目录之外的文件
+    #[test]
+    fn test_file_store() {
+        let dir = std::env::temp_dir().join(format!("sessions-test-{}", std::process::id()));
+        let store = FileSessionStore::new(&dir).unwrap();
+        exercise(&store);
+        assert_eq!(store.load("../../etc/passwd").unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 测试 SQLite 存储
+    #[test]
+    fn test_sqlite_store() {
+        exercise(&SqliteSessionStore::open_in_memory().unwrap());
+    }
+}
@@ -0,0 +1,242 @@
+// 导入所需的库和模块
+use super::repository::{OrderRepository, OrderStatus, RepositoryError, RepositoryResult}; // 导入仓库抽象
+use rusqlite::{params, Connection, OptionalExtension, TransactionBehavior}; // 导入 SQLite 接口
+use std::fs; // 导入文件系统模块
+use std::path::Path; // 导入路径模块
+use std::sync::Mutex; // 导入互斥锁
+use std::time::Duration; // 导入时间间隔
+
+// 数据库迁移脚本，按顺序执行，已执行的版本号记录在 PRAGMA user_version 中
+// 只能在末尾追加新的迁移，不能修改已发布的迁移
+const MIGRATIONS: &[&str] = &[
+    // 版本 1：订单表
+    "CREATE TABLE orders (
+        order_id     INTEGER PRIMARY KEY,
+        order_date   TEXT NOT NULL,
+        order_status TEXT NOT NULL
+    );",
+    // 版本 2：订单状态变更历史
+    "CREATE TABLE order_status_history (
+        id         INTEGER PRIMARY KEY AUTOINCREMENT,
+        order_id   INTEGER NOT NULL REFERENCES orders(order_id),
+        old_status TEXT NOT NULL,
+        new_status TEXT NOT NULL,
+        changed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );",
+    // 版本 3：记录已完成的一次性数据导入
+    "CREATE TABLE data_imports (
+        source      TEXT PRIMARY KEY,
+        imported_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+    );",
+];
+
+// 基于嵌入式 SQLite 的订单仓库
+pub struct SqliteOrderRepository {
+    conn: Mutex<Connection>, // 数据库连接，多个线程共享时需要加锁
+}
+
+impl SqliteOrderRepository {
+    // 打开（或创建）数据库文件并执行迁移
+    pub fn open(path: impl AsRef<Path>) -> RepositoryResult<Self> {
+        let conn = Connection::open(path)?;
+        // 多个进程同时写入时，等待锁释放而不是立即报错
+        conn.busy_timeout(Duration::from_secs(5))?;
+        // WAL 模式下读操作不会阻塞写操作
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        Self::from_connection(conn)
+    }
+
+    // 创建一个内存数据库，主要用于测试
+    pub fn open_in_memory() -> RepositoryResult<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> RepositoryResult<Self> {
+        conn.pragma_update(None, "foreign_keys", "ON")?;
+        run_migrations(&mut conn)?;
+        Ok(SqliteOrderRepository {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    // 从 orders.json 导入订单，每个来源文件只会导入一次，返回导入的订单数
+    pub fn import_json(&self, json_path: impl AsRef<Path>) -> RepositoryResult<usize> {
+        let json_path = json_path.as_ref();
+        let source = json_path.to_string_lossy().to_string();
+        let orders: Vec<OrderStatus> = serde_json::from_str(&fs::read_to_string(json_path)?)?;
+
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let already_imported: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM data_imports WHERE source = ?1)",
+            params![source],
+            |row| row.get(0),
+        )?;
+        if already_imported {
+            return Ok(0);
+        }
+
+        let mut imported = 0;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO orders (order_id, order_date, order_status) VALUES (?1, ?2, ?3)",
+            )?;
+            for order in &orders {
+                imported += stmt.execute(params![
+                    order.order_id,
+                    order.order_date,
+                    order.order_status
+                ])?;
+            }
+        }
+        tx.execute("INSERT INTO data_imports (source) VALUES (?1)", params![source])?;
+        tx.commit()?;
+        Ok(imported)
+    }
+
+    // 仅当订单表为空时才导入，用于首次启动
+    pub fn import_json_if_empty(&self, json_path: impl AsRef<Path>) -> RepositoryResult<usize> {
+        let count: i64 = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM orders", [], |row| row.get(0))?;
+        if count > 0 {
+            return Ok(0);
+        }
+        self.import_json(json_path)
+    }
+}
+
+// 执行尚未应用的迁移，每个迁移在独立的事务中完成
+fn run_migrations(conn: &mut Connection) -> RepositoryResult<()> {
+    let current: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for (version, sql) in MIGRATIONS.iter().enumerate().skip(current) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", version + 1)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+// 将一行数据转换为 OrderStatus
+fn row_to_order(row: &rusqlite::Row) -> rusqlite::Result<OrderStatus> {
+    Ok(OrderStatus {
+        order_id: row.get(0)?,
+        order_date: row.get(1)?,
+        order_status: row.get(2)?,
+    })
+}
+
+impl OrderRepository for SqliteOrderRepository {
+    fn list_orders(&self) -> RepositoryResult<Vec<OrderStatus>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT order_id, order_date, order_status FROM orders ORDER BY order_id",
+        )?;
+        let orders = stmt
+            .query_map([], row_to_order)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(orders)
+    }
+
+    fn get_order(&self, order_id: i32) -> RepositoryResult<Option<OrderStatus>> {
+        let conn = self.conn.lock().unwrap();
+        let order = conn
+            .query_row(
+                "SELECT order_id, order_date, order_status FROM orders WHERE order_id = ?1",
+                params![order_id],
+                row_to_order,
+            )
+            .optional()?;
+        Ok(order)
+    }
+
+    fn update_status(&self, order_id: i32, order_status: &str) -> RepositoryResult<OrderStatus> {
+        let mut conn = self.conn.lock().unwrap();
+        // IMMEDIATE 事务在开始时就获取写锁，避免并发写入者之间的读后写冲突
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let old_status: Option<String> = tx
+            .query_row(
+                "SELECT order_status FROM orders WHERE order_id = ?1",
+                params![order_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let old_status = old_status.ok_or(RepositoryError::NotFound(order_id))?;
+
+        tx.execute(
+            "UPDATE orders SET order_status = ?1 WHERE order_id = ?2",
+            params![order_status, order_id],
+        )?;
+        tx.execute(
+            "INSERT INTO order_status_history (order_id, old_status, new_status) VALUES (?1, ?2, ?3)",
+            params![order_id, old_status, order_status],
+        )?;
+        let updated = tx.query_row(
+            "SELECT order_id, order_date, order_status FROM orders WHERE order_id = ?1",
+            params![order_id],
+            row_to_order,
+        )?;
+        tx.commit()?;
+        Ok(updated)
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+
+    // 示例数据文件
+    fn orders_json() -> String {
+        format!("{}/data/orders.json", env!("CARGO_MANIFEST_DIR"))
+    }
+
+    // 测试迁移可以重复执行且版本号正确
+    #[test]
+    fn test_migrations_are_idempotent() {
+        let repo = SqliteOrderRepository::open_in_memory().unwrap();
+        let mut conn = repo.conn.lock().unwrap();
+        run_migrations(&mut conn).unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+    }
+
+    // 测试从 orders.json 导入只会执行一次
+    #[test]
+    fn test_import_json_once() {
+        let repo = SqliteOrderRepository::open_in_memory().unwrap();
+        assert_eq!(repo.import_json(orders_json()).unwrap(), 2);
+        assert_eq!(repo.import_json(orders_json()).unwrap(), 0);
+        let orders = repo.list_orders().unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].order_status, "Delivered");
+    }
+
+    // 测试状态更新会写入历史记录，不存在的订单不会留下任何修改
+    #[test]
+    fn test_update_status_transaction() {
+        let repo = SqliteOrderRepository::open_in_memory().unwrap();
+        repo.import_json(orders_json()).unwrap();
+
+        let updated = repo.update_status(2, "Shipped").unwrap();
+        assert_eq!(updated.order_status, "Shipped");
+        assert_eq!(repo.get_order(2).unwrap().unwrap().order_status, "Shipped");
+
+        assert!(matches!(
+            repo.update_status(42, "Shipped"),
+            Err(RepositoryError::NotFound(42))
+        ));
+        let history: i64 = repo
+            .conn
+            .lock()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM order_status_history", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(history, 1);
+    }
+}
@@ -0,0 +1,405 @@
+// 导入所需的库和模块
+use super::http2::Transport; // 导入 HTTP/2 传输特性
+use rustls::crypto::{ring, CryptoProvider}; // 导入加密实现
+use rustls::pki_types::pem::PemObject; // 导入 PEM 解析特性
+use rustls::pki_types::{CertificateDer, PrivateKeyDer}; // 导入证书和私钥
+use rustls::server::{ClientHello, ResolvesServerCert}; // 导入证书选择特性
+use rustls::sign::CertifiedKey; // 导入证书和签名密钥
+use rustls::{ServerConfig, ServerConnection, StreamOwned}; // 导入 TLS 服务端
+use std::collections::HashMap; // 导入 HashMap
+use std::fmt; // 导入格式化模块
+use std::fs; // 导入文件系统模块
+use std::io; // 导入 IO 模块
+use std::net::TcpStream; // 导入 TCP 流
+use std::path::{Path, PathBuf}; // 导入路径模块
+use std::sync::{Arc, Mutex, RwLock}; // 导入共享指针和锁
+use std::time::{Duration, Instant, SystemTime}; // 导入时间模块
+
+// 两次检查证书文件是否变化之间的最小间隔
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// 定义 TLS 相关的错误
+#[derive(Debug)]
+pub enum TlsError {
+    Pem(PathBuf, String),    // 读取或解析 PEM 文件失败
+    NoCertificates(PathBuf), // PEM 文件中没有证书
+    NoDefaultCertificate,    // 没有配置默认证书
+    Rustls(rustls::Error),   // rustls 报告的错误
+}
+
+impl fmt::Display for TlsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TlsError::Pem(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            TlsError::NoCertificates(path) => {
+                write!(f, "no certificates found in {}", path.display())
+            }
+            TlsError::NoDefaultCertificate => write!(f, "no default certificate configured"),
+            TlsError::Rustls(e) => write!(f, "TLS error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TlsError {}
+
+impl From<rustls::Error> for TlsError {
+    fn from(e: rustls::Error) -> Self {
+        TlsError::Rustls(e)
+    }
+}
+
+// 一组证书文件：PEM 格式的证书链和私钥
+#[derive(Debug, Clone)]
+struct CertFiles {
+    cert: PathBuf, // 证书链文件
+    key: PathBuf,  // 私钥文件
+}
+
+impl CertFiles {
+    // 读取证书链和私钥
+    fn load(&self, provider: &CryptoProvider) -> Result<Arc<CertifiedKey>, TlsError> {
+        let pem_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |e: rustls::pki_types::pem::Error| TlsError::Pem(path, e.to_string())
+        };
+        let certs = CertificateDer::pem_file_iter(&self.cert)
+            .map_err(pem_error(&self.cert))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(pem_error(&self.cert))?;
+        if certs.is_empty() {
+            return Err(TlsError::NoCertificates(self.cert.clone()));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key).map_err(pem_error(&self.key))?;
+        let signing_key = provider.key_provider.load_private_key(key)?;
+        Ok(Arc::new(CertifiedKey::new(certs, signing_key)))
+    }
+
+    // 返回两个文件中较新的修改时间
+    fn modified(&self) -> Option<SystemTime> {
+        let cert = fs::metadata(&self.cert).and_then(|m| m.modified()).ok()?;
+        let key = fs::metadata(&self.key).and_then(|m| m.modified()).ok()?;
+        Some(cert.max(key))
+    }
+}
+
+// TLS 配置：默认证书、按 SNI 选择的证书和 ALPN 协议
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    default: Option<CertFiles>,  // 客户端没有发送 SNI 或没有匹配时使用的证书
+    sni: Vec<(String, CertFiles)>, // 主机名（可以是 *.example.com）到证书的映射
+    alpn: Vec<Vec<u8>>,          // 按优先级排列的 ALPN 协议
+}
+
+impl TlsConfig {
+    // 使用默认证书创建配置，默认优先通告 h2，其次 http/1.1
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        TlsConfig {
+            default: Some(CertFiles {
+                cert: cert.into(),
+                key: key.into(),
+            }),
+            sni: Vec::new(),
+            alpn: vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+        }
+    }
+
+    // 为指定主机名添加证书
+    pub fn sni(mut self, host: &str, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.sni.push((
+            host.to_ascii_lowercase(),
+            CertFiles {
+                cert: cert.into(),
+                key: key.into(),
+            },
+        ));
+        self
+    }
+
+    // 设置通告的 ALPN 协议
+    pub fn alpn(mut self, protocols: &[&str]) -> Self {
+        self.alpn = protocols.iter().map(|p| p.as_bytes().to_vec()).collect();
+        self
+    }
+
+    // 所有证书文件
+    fn files(&self) -> impl Iterator<Item = &CertFiles> {
+        self.default.iter().chain(self.sni.iter().map(|(_, files)| files))
+    }
+}
+
+// 已加载的证书
+#[derive(Debug, Default)]
+struct CertStore {
+    default: Option<Arc<CertifiedKey>>,          // 默认证书
+    by_name: HashMap<String, Arc<CertifiedKey>>, // 主机名到证书的映射
+}
+
+impl CertStore {
+    // 加载配置中的全部证书
+    fn load(config: &TlsConfig, provider: &CryptoProvider) -> Result<Self, TlsError> {
+        let default = config.default.as_ref().map(|f| f.load(provider)).transpose()?;
+        let by_name = config
+            .sni
+            .iter()
+            .map(|(host, files)| Ok((host.clone(), files.load(provider)?)))
+            .collect::<Result<_, TlsError>>()?;
+        Ok(CertStore { default, by_name })
+    }
+
+    // 按主机名查找证书，先完全匹配再匹配通配符
+    fn lookup(&self, name: &str) -> Option<Arc<CertifiedKey>> {
+        let name = name.to_ascii_lowercase();
+        self.by_name.get(&name).cloned().or_else(|| {
+            let (_, parent) = name.split_once('.')?;
+            self.by_name.get(&format!("*.{}", parent)).cloned()
+        })
+    }
+}
+
+// 根据 SNI 选择证书，证书重新加载后立即对新连接生效
+#[derive(Debug)]
+struct SniResolver {
+    store: RwLock<CertStore>, // 当前使用的证书
+}
+
+impl ResolvesServerCert for SniResolver {
+    fn resolve(&self, hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        let store = self.store.read().unwrap();
+        hello
+            .server_name()
+            .and_then(|name| store.lookup(name))
+            .or_else(|| store.default.clone())
+    }
+}
+
+// TLS 连接的信息，服务器附加到请求上
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsInfo {
+    pub server_name: Option<String>, // 客户端通过 SNI 请求的主机名
+    pub alpn: Option<Vec<u8>>,       // 协商出的 ALPN 协议
+}
+
+impl From<&ServerConnection> for TlsInfo {
+    fn from(conn: &ServerConnection) -> Self {
+        TlsInfo {
+            server_name: conn.server_name().map(str::to_string),
+            alpn: conn.alpn_protocol().map(<[u8]>::to_vec),
+        }
+    }
+}
+
+// TLS 接收器：完成握手并支持不重启地重新加载证书
+pub struct TlsAcceptor {
+    config: TlsConfig,                           // 证书文件配置
+    provider: Arc<CryptoProvider>,               // 加密实现
+    resolver: Arc<SniResolver>,                  // 证书选择器
+    server_config: Arc<ServerConfig>,            // rustls 服务端配置
+    loaded: Mutex<(Option<SystemTime>, Instant)>, // 已加载证书的修改时间和上次检查时间
+}
+
+impl TlsAcceptor {
+    // 加载证书并创建接收器
+    pub fn new(config: TlsConfig) -> Result<Self, TlsError> {
+        if config.default.is_none() && config.sni.is_empty() {
+            return Err(TlsError::NoDefaultCertificate);
+        }
+        let provider = Arc::new(ring::default_provider());
+        let resolver = Arc::new(SniResolver {
+            store: RwLock::new(CertStore::load(&config, &provider)?),
+        });
+        let mut server_config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+        server_config.alpn_protocols = config.alpn.clone();
+        let modified = config.files().filter_map(CertFiles::modified).max();
+        Ok(TlsAcceptor {
+            config,
+            provider,
+            resolver,
+            server_config: Arc::new(server_config),
+            loaded: Mutex::new((modified, Instant::now())),
+        })
+    }
+
+    // 重新读取全部证书文件，读取失败时继续使用原来的证书
+    pub fn reload(&self) -> Result<(), TlsError> {
+        let modified = self.config.files().filter_map(CertFiles::modified).max();
+        let store = CertStore::load(&self.config, &self.provider)?;
+        *self.resolver.store.write().unwrap() = store;
+        self.loaded.lock().unwrap().0 = modified;
+        Ok(())
+    }
+
+    // 证书文件有变化时重新加载，检查间隔不小于 RELOAD_CHECK_INTERVAL，返回是否重新加载
+    pub fn reload_if_changed(&self) -> Result<bool, TlsError> {
+        let loaded_at = {
+            let mut loaded = self.loaded.lock().unwrap();
+            if loaded.1.elapsed() < RELOAD_CHECK_INTERVAL {
+                return Ok(false);
+            }
+            loaded.1 = Instant::now();
+            loaded.0
+        };
+        let modified = self.config.files().filter_map(CertFiles::modified).max();
+        if modified <= loaded_at {
+            return Ok(false);
+        }
+        self.reload().map(|()| true)
+    }
+
+    // 在 TCP 连接上完成 TLS 握手
+    pub fn accept(&self, mut stream: TcpStream) -> io::Result<StreamOwned<ServerConnection, TcpStream>> {
+        let mut conn = ServerConnection::new(self.server_config.clone()).map_err(io::Error::other)?;
+        while conn.is_handshaking() {
+            conn.complete_io(&mut stream)?;
+        }
+        Ok(StreamOwned::new(conn, stream))
+    }
+}
+
+// TLS 连接的读超时就是底层 TCP 连接的读超时
+impl Transport for StreamOwned<ServerConnection, TcpStream> {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        self.sock.read_timeout()
+    }
+
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.sock.set_read_timeout(timeout)
+    }
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*; // 引入外部模块
+    use rcgen::{BasicConstraints, Certificate, CertificateParams, IsCa, KeyPair}; // 导入证书生成
+    use rustls::pki_types::ServerName; // 导入服务器名称
+    use rustls::{ClientConfig, ClientConnection, RootCertStore}; // 导入 TLS 客户端
+    use std::io::{Read, Write}; // 导入读写特性
+    use std::net::TcpListener; // 导入 TCP 监听器
+    use std::thread; // 导入线程模块
+
+    // 测试用的证书颁发机构
+    struct TestCa {
+        cert: Certificate, // CA 证书
+        key: KeyPair,      // CA 私钥
+    }
+
+    impl TestCa {
+        // 生成一个自签名的 CA
+        fn new() -> Self {
+            let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+            params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let key = KeyPair::generate().unwrap();
+            let cert = params.self_signed(&key).unwrap();
+            TestCa { cert, key }
+        }
+
+        // 为指定主机名签发证书，写入目录并返回证书和私钥路径
+        fn issue(&self, dir: &Path, name: &str, hosts: &[&str]) -> (PathBuf, PathBuf) {
+            let params = CertificateParams::new(hosts.iter().map(|h| h.to_string()).collect::<Vec<_>>()).unwrap();
+            let key = KeyPair::generate().unwrap();
+            let cert = params.signed_by(&key, &self.cert, &self.key).unwrap();
+            let cert_path = dir.join(format!("{}.pem", name));
+            let key_path = dir.join(format!("{}.key", name));
+            fs::write(&cert_path, cert.pem()).unwrap();
+            fs::write(&key_path, key.serialize_pem()).unwrap();
+            (cert_path, key_path)
+        }
+
+        // 信任该 CA 的客户端配置
+        fn client_config(&self, alpn: &[&str]) -> Arc<ClientConfig> {
+            let mut roots = RootCertStore::empty();
+            roots.add(self.cert.der().clone()).unwrap();
+            let mut config = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+            config.alpn_protocols = alpn.iter().map(|p| p.as_bytes().to_vec()).collect();
+            Arc::new(config)
+        }
+    }
+
+    // 创建临时目录
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("httpserver-tls-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 用指定的 SNI 连接服务器，返回服务端证书、协商的 ALPN 和服务端的应答
+    fn connect(acceptor: Arc<TlsAcceptor>, client: Arc<ClientConfig>, host: &str) -> (Vec<u8>, Option<Vec<u8>>, String) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut tls = acceptor.accept(stream).unwrap();
+            let info = TlsInfo::from(&tls.conn);
+            let mut buf = [0u8; 5];
+            tls.read_exact(&mut buf).unwrap();
+            tls.write_all(format!("{:?}", info.server_name).as_bytes()).unwrap();
+            tls.conn.send_close_notify();
+            tls.flush().unwrap();
+        });
+
+        let conn = ClientConnection::new(client, ServerName::try_from(host.to_string()).unwrap()).unwrap();
+        let mut tls = StreamOwned::new(conn, TcpStream::connect(addr).unwrap());
+        tls.write_all(b"hello").unwrap();
+        let mut reply = String::new();
+        tls.read_to_string(&mut reply).unwrap();
+        server.join().unwrap();
+        let cert = tls.conn.peer_certificates().unwrap()[0].to_vec();
+        (cert, tls.conn.alpn_protocol().map(<[u8]>::to_vec), reply)
+    }
+
+    // 测试按 SNI 选择证书和 ALPN 协商
+    #[test]
+    fn test_sni_and_alpn() {
+        let dir = temp_dir("sni");
+        let ca = TestCa::new();
+        let (default_cert, default_key) = ca.issue(&dir, "default", &["localhost"]);
+        let (api_cert, api_key) = ca.issue(&dir, "api", &["api.example.test"]);
+        let (wild_cert, wild_key) = ca.issue(&dir, "wild", &["*.apps.example.test"]);
+        let config = TlsConfig::new(&default_cert, &default_key)
+            .sni("api.example.test", &api_cert, &api_key)
+            .sni("*.apps.example.test", &wild_cert, &wild_key)
+            .alpn(&["h2", "http/1.1"]);
+        let acceptor = Arc::new(TlsAcceptor::new(config).unwrap());
+
+        let pem_der = |path: &Path| CertificateDer::from_pem_file(path).unwrap().to_vec();
+        let (cert, alpn, reply) = connect(acceptor.clone(), ca.client_config(&["http/1.1"]), "api.example.test");
+        assert_eq!(cert, pem_der(&api_cert));
+        assert_eq!(alpn, Some(b"http/1.1".to_vec()));
+        assert_eq!(reply, "Some(\"api.example.test\")");
+
+        let (cert, _, _) = connect(acceptor.clone(), ca.client_config(&[]), "shop.apps.example.test");
+        assert_eq!(cert, pem_der(&wild_cert));
+        let (cert, _, _) = connect(acceptor, ca.client_config(&[]), "localhost");
+        assert_eq!(cert, pem_der(&default_cert));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 测试重新加载证书后新连接使用新证书，加载失败时保留旧证书
+    #[test]
+    fn test_reload() {
+        let dir = temp_dir("reload");
+        let ca = TestCa::new();
+        let (cert, key) = ca.issue(&dir, "site", &["localhost"]);
+        let acceptor = Arc::new(TlsAcceptor::new(TlsConfig::new(&cert, &key)).unwrap());
+        let (first, _, _) = connect(acceptor.clone(), ca.client_config(&[]), "localhost");
+
+        ca.issue(&dir, "site", &["localhost"]);
+        acceptor.reload().unwrap();
+        let (second, _, _) = connect(acceptor.clone(), ca.client_config(&[]), "localhost");
+        assert_ne!(first, second);
+        assert_eq!(second, CertificateDer::from_pem_file(&cert).unwrap().to_vec());
+
+        fs::write(&cert, "not a certificate").unwrap();
+        assert!(matches!(acceptor.reload(), Err(TlsError::NoCertificates(_))));
+        let (third, _, _) = connect(acceptor, ca.client_config(&[]), "localhost");
+        assert_eq!(second, third);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
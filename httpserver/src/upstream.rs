@@ -0,0 +1,501 @@
+// 导入所需的库和模块
+use super::server::PeerAddr; // 导入客户端地址
+use http::client::{Client, Url}; // 导入 HTTP 客户端
+use http::httprequest::HttpRequest; // 导入 HTTP 请求模块
+use std::sync::atomic::{AtomicUsize, Ordering}; // 导入原子计数器
+use std::sync::{Arc, Mutex, MutexGuard, Weak}; // 导入共享指针和互斥锁
+use std::thread; // 导入线程模块
+use std::time::{Duration, Instant}; // 导入时间模块
+
+// 一致性哈希环上每个上游的虚拟节点数
+const VIRTUAL_NODES: usize = 160;
+
+// 默认连续失败多少次后暂时摘除上游
+const DEFAULT_MAX_FAILS: u32 = 3;
+
+// 默认摘除的时长，之后重新尝试该上游
+const DEFAULT_FAIL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// 主动健康检查的默认超时
+const DEFAULT_HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+// 定义负载均衡策略
+#[derive(Debug, Clone, PartialEq)]
+pub enum Strategy {
+    RoundRobin,              // 轮询
+    LeastConnections,        // 选择进行中请求最少的上游
+    ConsistentHash(HashKey), // 按键的一致性哈希，同一客户端固定到同一上游
+}
+
+impl Strategy {
+    // 按名称查找策略：round-robin、least-conn 或 hash，hash 按 hash_header 指定的请求头或客户端 IP 地址哈希
+    pub fn parse(name: &str, hash_header: Option<String>) -> Option<Self> {
+        match name {
+            "round-robin" => Some(Strategy::RoundRobin),
+            "least-conn" => Some(Strategy::LeastConnections),
+            "hash" => Some(Strategy::ConsistentHash(match hash_header {
+                Some(name) => HashKey::Header(name),
+                None => HashKey::Peer,
+            })),
+            _ => None,
+        }
+    }
+}
+
+// 定义一致性哈希的键来源
+#[derive(Debug, Clone, PartialEq)]
+pub enum HashKey {
+    Peer,           // 客户端的 IP 地址
+    Header(String), // 请求头的值，缺失时退回客户端 IP 地址
+}
+
+// 上游的健康状态
+#[derive(Debug, Default)]
+struct Health {
+    down: bool,                     // 主动健康检查失败
+    failures: u32,                  // 连续失败的请求数
+    ejected_until: Option<Instant>, // 被动摘除的截止时间
+}
+
+// 上游服务
+#[derive(Debug)]
+struct Upstream {
+    url: Url,              // 上游地址
+    active: AtomicUsize,   // 进行中的请求数
+    health: Mutex<Health>, // 健康状态
+}
+
+impl Upstream {
+    // 获取健康状态，持有锁的线程崩溃时继续使用其中的数据
+    fn health(&self) -> MutexGuard<'_, Health> {
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // 是否可以接收请求：健康检查通过且没有被摘除
+    fn is_available(&self, now: Instant) -> bool {
+        let health = self.health();
+        !health.down && health.ejected_until.is_none_or(|until| until <= now)
+    }
+}
+
+// 主动健康检查的配置
+#[derive(Debug, Clone)]
+struct HealthCheck {
+    path: String,       // 检查的路径，如 /health
+    interval: Duration, // 检查间隔
+    timeout: Duration,  // 单次检查的超时
+}
+
+// 上游的状态，供监控和健康检查使用
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpstreamStatus {
+    pub url: Url,        // 上游地址
+    pub available: bool, // 是否在轮换中
+    pub active: usize,   // 进行中的请求数
+}
+
+// 上游池：按策略在多个上游之间分配请求，主动检查和被动摘除不可用的上游
+#[derive(Debug)]
+pub struct UpstreamPool {
+    upstreams: Vec<Arc<Upstream>>,     // 上游服务
+    strategy: Strategy,                // 负载均衡策略
+    next: AtomicUsize,                 // 轮询的位置
+    ring: Vec<(u64, usize)>,           // 一致性哈希环：（哈希值，上游序号），按哈希值排序
+    max_fails: u32,                    // 连续失败多少次后摘除，0 表示不摘除
+    fail_timeout: Duration,            // 摘除的时长
+    health_check: Option<HealthCheck>, // 主动健康检查
+}
+
+impl UpstreamPool {
+    // 使用轮询策略创建上游池
+    // 哈希环上节点的位置只取决于上游的地址，增删或调整其他上游的顺序不会移动它的节点
+    pub fn new(urls: Vec<Url>) -> Self {
+        let mut ring: Vec<(u64, usize)> = urls
+            .iter()
+            .enumerate()
+            .flat_map(|(index, url)| {
+                (0..VIRTUAL_NODES).map(move |node| (ring_hash(format!("{}#{}", url, node).as_bytes()), index))
+            })
+            .collect();
+        ring.sort_unstable();
+        UpstreamPool {
+            upstreams: urls
+                .into_iter()
+                .map(|url| {
+                    Arc::new(Upstream {
+                        url,
+                        active: AtomicUsize::new(0),
+                        health: Mutex::new(Health::default()),
+                    })
+                })
+                .collect(),
+            strategy: Strategy::RoundRobin,
+            next: AtomicUsize::new(0),
+            ring,
+            max_fails: DEFAULT_MAX_FAILS,
+            fail_timeout: DEFAULT_FAIL_TIMEOUT,
+            health_check: None,
+        }
+    }
+
+    // 设置负载均衡策略
+    pub fn strategy(mut self, strategy: Strategy) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    // 设置连续失败多少次后摘除上游，0 表示不摘除
+    pub fn max_fails(mut self, max_fails: u32) -> Self {
+        self.max_fails = max_fails;
+        self
+    }
+
+    // 设置摘除的时长
+    pub fn fail_timeout(mut self, fail_timeout: Duration) -> Self {
+        self.fail_timeout = fail_timeout;
+        self
+    }
+
+    // 每隔 interval 请求上游的 path，非 2xx 响应或连接失败时把上游移出轮换
+    pub fn health_check(mut self, path: &str, interval: Duration) -> Self {
+        self.health_check = Some(HealthCheck {
+            path: path.to_string(),
+            interval,
+            timeout: DEFAULT_HEALTH_TIMEOUT.min(interval),
+        });
+        self
+    }
+
+    // 上游的数量
+    pub fn len(&self) -> usize {
+        self.upstreams.len()
+    }
+
+    // 是否没有上游
+    pub fn is_empty(&self) -> bool {
+        self.upstreams.is_empty()
+    }
+
+    // 是否配置了主动健康检查
+    pub fn has_health_check(&self) -> bool {
+        self.health_check.is_some()
+    }
+
+    // 各上游的状态
+    pub fn status(&self) -> Vec<UpstreamStatus> {
+        let now = Instant::now();
+        self.upstreams
+            .iter()
+            .map(|upstream| UpstreamStatus {
+                url: upstream.url.clone(),
+                available: upstream.is_available(now),
+                active: upstream.active.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    // 为请求选择一个可用的上游，全部不可用时返回 None
+    pub fn select(&self, req: &HttpRequest) -> Option<Lease> {
+        let now = Instant::now();
+        let count = self.upstreams.len();
+        let available = |index: &usize| self.upstreams[*index].is_available(now);
+        let index = match &self.strategy {
+            Strategy::RoundRobin => {
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..count).map(|i| (start + i) % count).find(available)
+            }
+            Strategy::LeastConnections => {
+                // 进行中的请求数相同时从轮询位置开始选，避免总是选中第一个
+                let start = self.next.fetch_add(1, Ordering::Relaxed);
+                (0..count)
+                    .map(|i| (start + i) % count)
+                    .filter(available)
+                    .min_by_key(|index| self.upstreams[*index].active.load(Ordering::Relaxed))
+            }
+            Strategy::ConsistentHash(key) => {
+                // 从键在环上的位置顺时针找到第一个可用的上游
+                let hash = ring_hash(hash_key(key, req).as_bytes());
+                let start = self.ring.partition_point(|(node, _)| *node < hash);
+                (0..self.ring.len())
+                    .map(|i| self.ring[(start + i) % self.ring.len()].1)
+                    .find(available)
+            }
+        }?;
+        let upstream = self.upstreams[index].clone();
+        upstream.active.fetch_add(1, Ordering::Relaxed);
+        Some(Lease {
+            upstream,
+            // 只有一个上游时摘除它也无处可去，不做被动摘除
+            max_fails: if count > 1 { self.max_fails } else { 0 },
+            fail_timeout: self.fail_timeout,
+        })
+    }
+
+    // 配置了主动健康检查时启动后台检查线程，上游池被释放后线程退出
+    pub fn start_health_checks(self: &Arc<Self>) {
+        let Some(check) = self.health_check.clone() else {
+            return;
+        };
+        let pool: Weak<Self> = Arc::downgrade(self);
+        let client = Client::new()
+            .max_redirects(0)
+            .max_idle_per_host(0)
+            .connect_timeout(check.timeout)
+            .timeout(Some(check.timeout));
+        thread::spawn(move || loop {
+            match pool.upgrade() {
+                Some(pool) => pool.check_health(&client, &check),
+                None => return,
+            }
+            thread::sleep(check.interval);
+        });
+    }
+
+    // 对每个上游执行一次主动健康检查
+    fn check_health(&self, client: &Client, check: &HealthCheck) {
+        for upstream in &self.upstreams {
+            let url = Url {
+                path: check.path.clone(),
+                ..upstream.url.clone()
+            };
+            let healthy = client
+                .get(&url.to_string())
+                .send()
+                .is_ok_and(|resp| resp.is_success());
+            let mut health = upstream.health();
+            if health.down == healthy {
+                match healthy {
+                    true => eprintln!("Upstream {} is healthy again", upstream.url),
+                    false => eprintln!("Upstream {} failed health check {}", upstream.url, check.path),
+                }
+            }
+            health.down = !healthy;
+            // 健康检查通过说明上游已恢复，不必等到被动摘除结束
+            if healthy && health.ejected_until.is_some() {
+                health.failures = 0;
+                health.ejected_until = None;
+            }
+        }
+    }
+}
+
+// 选中的上游，在请求（包括转发响应消息体）完成前计入进行中的请求数
+#[derive(Debug)]
+pub struct Lease {
+    upstream: Arc<Upstream>, // 选中的上游
+    max_fails: u32,          // 连续失败多少次后摘除
+    fail_timeout: Duration,  // 摘除的时长
+}
+
+impl Lease {
+    // 上游地址
+    pub fn url(&self) -> &Url {
+        &self.upstream.url
+    }
+
+    // 记录请求成功，清零连续失败次数
+    pub fn success(&self) {
+        self.upstream.health().failures = 0;
+    }
+
+    // 记录请求失败，连续失败达到上限时把上游摘除一段时间
+    pub fn failure(&self) {
+        let mut health = self.upstream.health();
+        health.failures += 1;
+        if self.max_fails > 0 && health.failures >= self.max_fails {
+            eprintln!(
+                "Upstream {} ejected for {}s after {} failures",
+                self.upstream.url,
+                self.fail_timeout.as_secs(),
+                health.failures
+            );
+            health.failures = 0;
+            health.ejected_until = Some(Instant::now() + self.fail_timeout);
+        }
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.upstream.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// 计算一致性哈希的键
+fn hash_key(key: &HashKey, req: &HttpRequest) -> String {
+    if let HashKey::Header(name) = key {
+        if let Some(value) = req.header(name).filter(|v| !v.is_empty()) {
+            return format!("key:{}", value);
+        }
+    }
+    let peer = req.extensions.get::<PeerAddr>().map(|p| p.0.ip());
+    peer.map_or_else(|| "ip:unknown".to_string(), |ip| format!("ip:{}", ip))
+}
+
+// 哈希环使用的哈希：FNV-1a 后再用 MurmurHash3 的 fmix64 打散高位，结果不随进程变化，重启后客户端仍落到同一上游
+fn ring_hash(data: &[u8]) -> u64 {
+    let mut hash = data.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3));
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51afd7ed558ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ceb9fe1a85ec53);
+    hash ^ (hash >> 33)
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    // 三个上游组成的池
+    fn pool() -> UpstreamPool {
+        let urls = (1..=3).map(|i| Url::parse(&format!("http://10.0.0.{}:80/", i)).unwrap()).collect();
+        UpstreamPool::new(urls)
+    }
+
+    // 来自指定客户端地址的请求
+    fn request(peer: &str) -> HttpRequest {
+        let mut req: HttpRequest = "GET / HTTP/1.1\r\nHost: a\r\n\r\n".to_string().into();
+        req.extensions.insert(PeerAddr(format!("{}:5555", peer).parse().unwrap()));
+        req
+    }
+
+    // 选中的上游的主机
+    fn host(lease: Option<Lease>) -> String {
+        lease.unwrap().url().host.clone()
+    }
+
+    // 测试轮询依次选择上游，跳过被摘除的上游
+    #[test]
+    fn test_round_robin() {
+        let pool = pool().max_fails(2);
+        let req = request("192.0.2.1");
+        let hosts: Vec<String> = (0..4).map(|_| host(pool.select(&req))).collect();
+        assert_eq!(hosts, ["10.0.0.1", "10.0.0.2", "10.0.0.3", "10.0.0.1"]);
+
+        let lease = pool.select(&req).unwrap();
+        assert_eq!(lease.url().host, "10.0.0.2");
+        lease.failure();
+        lease.failure();
+        assert!(!pool.status()[1].available);
+        let hosts: Vec<String> = (0..3).map(|_| host(pool.select(&req))).collect();
+        assert_eq!(hosts, ["10.0.0.3", "10.0.0.1", "10.0.0.3"]);
+    }
+
+    // 测试最少连接选择进行中请求最少的上游，响应完成后计数减少
+    #[test]
+    fn test_least_connections() {
+        let pool = pool().strategy(Strategy::LeastConnections);
+        let req = request("192.0.2.1");
+        let held: Vec<Lease> = (0..3).map(|_| pool.select(&req).unwrap()).collect();
+        let mut hosts: Vec<&str> = held.iter().map(|l| l.url().host.as_str()).collect();
+        hosts.sort();
+        assert_eq!(hosts, ["10.0.0.1", "10.0.0.2", "10.0.0.3"]);
+
+        let second = pool.select(&req).unwrap();
+        let busy = second.url().host.clone();
+        drop(held);
+        assert_eq!(pool.status().iter().map(|s| s.active).sum::<usize>(), 1);
+        for _ in 0..3 {
+            assert_ne!(host(pool.select(&req)), busy);
+        }
+    }
+
+    // 测试一致性哈希：同一客户端固定到同一上游，摘除一个上游只影响原本落在它上面的客户端
+    #[test]
+    fn test_consistent_hash() {
+        let pool = pool().strategy(Strategy::ConsistentHash(HashKey::Peer)).max_fails(1);
+        let clients: Vec<HttpRequest> = (1..=200).map(|i| request(&format!("192.0.2.{}", i))).collect();
+        let before: Vec<String> = clients.iter().map(|req| host(pool.select(req))).collect();
+        assert_eq!(before, clients.iter().map(|req| host(pool.select(req))).collect::<Vec<_>>());
+        for upstream in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+            let share = before.iter().filter(|h| *h == upstream).count();
+            assert!((30..=110).contains(&share), "{} got {}", upstream, share);
+        }
+
+        let lease = pool.select(&clients[0]).unwrap();
+        let ejected = lease.url().host.clone();
+        lease.failure();
+        drop(lease);
+        for (req, previous) in clients.iter().zip(&before) {
+            let now = host(pool.select(req));
+            match *previous == ejected {
+                true => assert_ne!(now, ejected),
+                false => assert_eq!(&now, previous),
+            }
+        }
+
+        // 从配置中去掉一个上游或调整顺序时，其他上游上的客户端不受影响
+        let urls = ["10.0.0.3", "10.0.0.1"].iter().map(|h| Url::parse(&format!("http://{}:80/", h)).unwrap()).collect();
+        let reduced = UpstreamPool::new(urls).strategy(Strategy::ConsistentHash(HashKey::Peer));
+        for (req, previous) in clients.iter().zip(&before) {
+            if previous != "10.0.0.2" {
+                assert_eq!(&host(reduced.select(req)), previous);
+            }
+        }
+
+        // 按请求头哈希，缺失时退回客户端地址
+        let key = HashKey::Header("X-User".to_string());
+        let mut with_header = request("192.0.2.1");
+        with_header.set_header("X-User", "alice");
+        assert_eq!(hash_key(&key, &with_header), "key:alice");
+        assert_eq!(hash_key(&key, &request("192.0.2.1")), "ip:192.0.2.1");
+    }
+
+    // 测试被动摘除到期后上游重新加入轮换，单个上游不被摘除
+    #[test]
+    fn test_passive_ejection() {
+        let pool = pool().max_fails(1).fail_timeout(Duration::from_millis(50));
+        let req = request("192.0.2.1");
+        for _ in 0..3 {
+            pool.select(&req).unwrap().failure();
+        }
+        assert!(pool.select(&req).is_none());
+        thread::sleep(Duration::from_millis(60));
+        assert_eq!(pool.status().iter().filter(|s| s.available).count(), 3);
+
+        let single = UpstreamPool::new(vec![Url::parse("http://10.0.0.1/").unwrap()]).max_fails(1);
+        single.select(&req).unwrap().failure();
+        assert!(single.select(&req).is_some());
+    }
+
+    // 测试主动健康检查把失败的上游移出轮换，恢复后重新加入
+    #[test]
+    fn test_health_check() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let healthy = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buffer = [0u8; 1024];
+                let _ = stream.read(&mut buffer);
+                let status = match buffer.starts_with(b"GET /health ") {
+                    true => "200 OK",
+                    false => "404 Not Found",
+                };
+                let _ = write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+            }
+        });
+        let unused = TcpListener::bind("127.0.0.1:0").unwrap();
+        let closed = Url::parse(&format!("http://{}/", unused.local_addr().unwrap())).unwrap();
+        drop(unused);
+
+        let pool = UpstreamPool::new(vec![healthy.clone(), closed]).health_check("/health", Duration::from_millis(500));
+        let check = pool.health_check.clone().unwrap();
+        let client = Client::new().timeout(Some(check.timeout));
+        pool.check_health(&client, &check);
+        let available: Vec<bool> = pool.status().iter().map(|s| s.available).collect();
+        assert_eq!(available, [true, false]);
+        let req = request("192.0.2.1");
+        for _ in 0..3 {
+            assert_eq!(pool.select(&req).unwrap().url(), &healthy);
+        }
+
+        // 检查的路径不存在时上游同样视为不健康
+        let wrong_path = UpstreamPool::new(vec![healthy]).health_check("/missing", Duration::from_millis(500));
+        wrong_path.check_health(&client, wrong_path.health_check.as_ref().unwrap());
+        assert!(wrong_path.select(&req).is_none());
+    }
+}
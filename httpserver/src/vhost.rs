@@ -0,0 +1,216 @@
+// 导入所需的库和模块
+use super::handler::{Handler, PageNotFoundHandler}; // 导入 404 页面处理器
+use super::middleware::Middleware; // 导入中间件
+use super::proxy::ReverseProxy; // 导入反向代理
+use super::router::Router; // 导入路由模块
+use super::upstream::UpstreamPool; // 导入上游池
+use http::httprequest::HttpRequest; // 导入 HTTP 请求模块
+use http::httpresponse::HttpResponse; // 导入 HTTP 响应模块
+use std::collections::HashMap; // 导入 HashMap
+use std::path::PathBuf; // 导入路径
+use std::sync::Arc; // 导入共享指针
+
+// 请求所属站点的文件，VirtualHosts 在路由前附加到请求上，静态页面和 404 页面从这里读取
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SiteFiles {
+    pub public: Option<PathBuf>,    // 静态文件目录，未设置时使用 crate 目录下的 public
+    pub not_found: Option<PathBuf>, // 404 页面，未设置时使用静态文件目录中的 404.html
+}
+
+// 一个站点：静态文件、404 页面、反向代理的路由，以及是否提供 Router 中的内置路由（/api、/admin、/ws）
+pub struct Site {
+    files: SiteFiles,            // 静态文件
+    proxy: Option<ReverseProxy>, // 站点的代理路由
+    builtin_routes: bool,        // 是否提供内置路由，关闭时这些路径返回站点的 404 页面
+}
+
+impl Site {
+    // 创建使用全局静态文件目录、没有代理路由、提供内置路由的站点
+    pub fn new() -> Self {
+        Site {
+            files: SiteFiles::default(),
+            proxy: None,
+            builtin_routes: true,
+        }
+    }
+
+    // 设置静态文件目录
+    pub fn public(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.files.public = Some(dir.into());
+        self
+    }
+
+    // 设置 404 页面
+    pub fn not_found(mut self, page: impl Into<PathBuf>) -> Self {
+        self.files.not_found = Some(page.into());
+        self
+    }
+
+    // 设置站点的代理路由
+    pub fn proxy(mut self, proxy: ReverseProxy) -> Self {
+        self.proxy = Some(proxy);
+        self
+    }
+
+    // 设置是否提供内置路由，只提供静态文件的站点应关闭，避免暴露管理页面和订单接口
+    pub fn builtin_routes(mut self, enabled: bool) -> Self {
+        self.builtin_routes = enabled;
+        self
+    }
+}
+
+impl Default for Site {
+    fn default() -> Self {
+        Site::new()
+    }
+}
+
+// 虚拟主机中间件：按 Host 头（HTTP/2 的 :authority）选择站点，没有匹配的主机名时使用默认站点
+// 站点的代理路由在这里处理，应在会话和认证之后注册，使之前的中间件同样作用于代理的请求
+// 没有匹配代理路由的请求交给 Router，站点的代理路由可以覆盖相同前缀的内置路由；关闭了内置路由的站点只提供静态文件
+pub struct VirtualHosts {
+    sites: Vec<Site>,              // 站点，第一个是默认站点
+    names: HashMap<String, usize>, // 小写的主机名（可以是 *.example.com）到站点的映射
+}
+
+impl VirtualHosts {
+    // 创建只有默认站点的虚拟主机中间件
+    pub fn new(default: Site) -> Self {
+        VirtualHosts {
+            sites: vec![default],
+            names: HashMap::new(),
+        }
+    }
+
+    // 添加一个站点，names 是它的主机名，*.example.com 匹配 example.com 的一级子域名
+    pub fn host(mut self, names: &[&str], site: Site) -> Self {
+        self.sites.push(site);
+        for name in names {
+            self.names.insert(name.to_ascii_lowercase(), self.sites.len() - 1);
+        }
+        self
+    }
+
+    // 各站点代理路由的上游池，健康检查据此检查上游；非默认站点的路由前加上站点的第一个主机名
+    pub fn pools(&self) -> Vec<(String, Arc<UpstreamPool>)> {
+        let mut pools = Vec::new();
+        for (index, site) in self.sites.iter().enumerate() {
+            let Some(proxy) = &site.proxy else { continue };
+            let host = self.names.iter().filter(|(_, i)| **i == index).map(|(name, _)| name.as_str()).min();
+            for (prefix, pool) in proxy.pools() {
+                pools.push((host.map_or(prefix.clone(), |host| format!("{}{}", host, prefix)), pool));
+            }
+        }
+        pools
+    }
+
+    // 请求所属的站点，先完全匹配主机名再匹配通配符
+    fn site(&self, req: &HttpRequest) -> &Site {
+        let index = req.header("Host").map(host_name).and_then(|name| {
+            self.names.get(&name).or_else(|| {
+                let (_, parent) = name.split_once('.')?;
+                self.names.get(&format!("*.{}", parent))
+            })
+        });
+        &self.sites[index.copied().unwrap_or(0)]
+    }
+}
+
+impl Middleware for VirtualHosts {
+    // 附加站点的文件，匹配站点代理路由的请求由代理处理，站点关闭了内置路由时内置路由返回 404
+    fn before(&self, req: &mut HttpRequest) -> Option<HttpResponse<'static>> {
+        let site = self.site(req);
+        req.extensions.insert(site.files.clone());
+        if let Some(resp) = site.proxy.as_ref().and_then(|proxy| proxy.before(req)) {
+            return Some(resp);
+        }
+        if !site.builtin_routes && Router::is_builtin(req) {
+            return Some(PageNotFoundHandler::handle(req).into_owned());
+        }
+        None
+    }
+}
+
+// Host 头中的主机名：去掉端口和末尾的点，转为小写；IPv6 地址保留方括号
+fn host_name(host: &str) -> String {
+    let host = host.trim();
+    let name = match host.strip_prefix('[') {
+        Some(rest) => rest.split_once(']').map_or(host, |(ip, _)| &host[..ip.len() + 2]),
+        None => host.rsplit_once(':').map_or(host, |(name, _)| name),
+    };
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::client::Url;
+
+    fn request(host: &str, path: &str) -> HttpRequest {
+        format!("GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, host).into()
+    }
+
+    // 测试 Host 头的解析
+    #[test]
+    fn test_host_name() {
+        assert_eq!(host_name("Example.COM:8080"), "example.com");
+        assert_eq!(host_name("example.com."), "example.com");
+        assert_eq!(host_name("[::1]:3000"), "[::1]");
+        assert_eq!(host_name("[::1]"), "[::1]");
+    }
+
+    // 测试按主机名选择站点，通配符只匹配一级子域名，未知主机使用默认站点
+    #[test]
+    fn test_select_site() {
+        let hosts = VirtualHosts::new(Site::new())
+            .host(&["example.com", "www.example.com"], Site::new().public("/srv/example"))
+            .host(&["*.shop.test"], Site::new().public("/srv/shop").not_found("/srv/shop/missing.html"));
+        let files = |host: &str| {
+            let mut req = request(host, "/");
+            assert!(hosts.before(&mut req).is_none());
+            req.extensions.get::<SiteFiles>().cloned().unwrap()
+        };
+        assert_eq!(files("WWW.example.com:3000").public, Some("/srv/example".into()));
+        assert_eq!(files("a.shop.test").not_found, Some("/srv/shop/missing.html".into()));
+        assert_eq!(files("a.b.shop.test"), SiteFiles::default());
+        assert_eq!(files("other.test"), SiteFiles::default());
+    }
+
+    // 测试站点的 404 页面、代理路由和内置路由的开关只作用于对应的主机
+    #[test]
+    fn test_site_routes_and_not_found() {
+        let dir = std::env::temp_dir().join(format!("httpserver-vhost-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "site index").unwrap();
+        std::fs::write(dir.join("missing.html"), "site missing").unwrap();
+
+        // 上游地址不可连接，代理返回 502
+        let proxy = ReverseProxy::new().route("/api/v2", Url::parse("http://127.0.0.1:1/").unwrap());
+        let middlewares: Vec<Box<dyn Middleware>> = vec![Box::new(
+            VirtualHosts::new(Site::new())
+                .host(&["site.test"], Site::new().public(&dir).not_found(dir.join("missing.html")).proxy(proxy))
+                .host(&["static.test"], Site::new().public(&dir).not_found(dir.join("missing.html")).builtin_routes(false)),
+        )];
+        let status = |host: &str, path: &str| {
+            let mut req = request(host, path);
+            let resp = Router::handle(&mut req, &middlewares);
+            (resp.status_code().to_string(), resp.body().to_string())
+        };
+        assert_eq!(status("site.test", "/"), ("200".into(), "site index".into()));
+        assert_eq!(status("site.test", "/nothing.html"), ("404".into(), "site missing".into()));
+        assert_eq!(status("site.test", "/api/v2/x").0, "502");
+        assert_eq!(status("other.test", "/api/v2/x").0, "404");
+        assert!(!status("other.test", "/nothing.html").1.contains("site missing"));
+        assert_eq!(status("site.test", "/ws/echo").0, "426");
+        assert_eq!(status("other.test", "/ws/echo").0, "426");
+        assert_ne!(status("other.test", "/admin").0, "404");
+
+        // 关闭内置路由的站点只提供静态文件
+        assert_eq!(status("static.test", "/"), ("200".into(), "site index".into()));
+        for path in ["/admin", "/admin/login", "/api/shipping/orders", "/api/shipping/orders/events", "/api/uploads", "/ws/orders", "/ws/echo"] {
+            assert_eq!(status("static.test", path), ("404".into(), "site missing".into()), "{}", path);
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,84 @@
+// 导入所需的库和模块
+use super::http2::Transport; // 导入底层传输特性
+use super::order_events::order_feed; // 导入订单事件源
+use http::httprequest::HttpRequest; // 导入 HTTP 请求
+use http::websocket::{Message, WebSocket, WsError, CLOSE_GOING_AWAY}; // 导入 WebSocket
+use std::io; // 导入 IO 模块
+use std::sync::mpsc::TryRecvError; // 导入通道错误
+use std::time::Duration; // 导入时间间隔
+
+// WebSocket 处理器：握手完成后在连接的线程上运行，返回后连接关闭
+pub type WebSocketHandler = fn(&HttpRequest, &mut WebSocket<&mut dyn Transport>);
+
+// 订单推送检查新事件的间隔
+const ORDER_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+// GET /ws/echo：把收到的文本和二进制消息原样发回
+pub fn echo(_req: &HttpRequest, ws: &mut WebSocket<&mut dyn Transport>) {
+    loop {
+        let result = match ws.recv() {
+            Ok(message @ (Message::Text(_) | Message::Binary(_))) => ws.send(&message),
+            Ok(Message::Close(_)) => return,
+            Ok(_) => Ok(()),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            log_error(&e);
+            return;
+        }
+    }
+}
+
+// GET /ws/orders：先发送全部订单的快照，之后推送订单变化
+// 每条消息是 {"event": 事件类型, "id": 事件 ID, "data": 订单数据} 形式的 JSON
+pub fn order_updates(_req: &HttpRequest, ws: &mut WebSocket<&mut dyn Transport>) {
+    let (initial, events) = order_feed().subscribe(None);
+    // 用短的读超时轮流检查客户端消息和订单事件
+    if let Err(e) = ws.get_ref().set_read_timeout(Some(ORDER_POLL_INTERVAL)) {
+        eprintln!("Failed to set websocket read timeout: {}", e);
+        return;
+    }
+    let mut pending = initial;
+    loop {
+        for event in pending.drain(..) {
+            let message = serde_json::json!({
+                "event": event.event,
+                "id": event.id,
+                "data": serde_json::from_str::<serde_json::Value>(&event.data).unwrap_or_default(),
+            });
+            if let Err(e) = ws.send(&Message::Text(message.to_string())) {
+                log_error(&e);
+                return;
+            }
+        }
+        match ws.recv() {
+            Ok(Message::Close(_)) => return,
+            Ok(_) => {}
+            Err(WsError::Io(e)) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {}
+            Err(e) => {
+                log_error(&e);
+                return;
+            }
+        }
+        loop {
+            match events.try_recv() {
+                Ok(event) => pending.push(event),
+                Err(TryRecvError::Empty) => break,
+                // 客户端太慢被事件源移除
+                Err(TryRecvError::Disconnected) => {
+                    let _ = ws.close(CLOSE_GOING_AWAY, "event feed closed");
+                    return;
+                }
+            }
+        }
+    }
+}
+
+// 记录连接异常结束的原因，对端直接断开不算错误
+fn log_error(e: &WsError) {
+    match e {
+        WsError::Io(e) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+        WsError::Closed => {}
+        e => eprintln!("WebSocket connection error: {}", e),
+    }
+}
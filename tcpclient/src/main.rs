@@ -1,20 +1,325 @@
 // 导入必要的模块
-use core::str; // 导入核心字符串处理模块
-use std::net::TcpStream; // 导入 TCP 流模块
-use std::io::{Read, Write}; // 导入读写模块
+use http::client::{Client, ClientError, Response}; // 导入 HTTP 客户端
+use http::httprequest::Method; // 导入请求方法
+use std::env; // 导入命令行参数
+use std::fs; // 导入文件读写
+use std::io::{self, Write}; // 导入读写模块
+use std::process; // 导入进程退出
+use std::time::Duration; // 导入时间间隔
+
+// 命令行用法
+const USAGE: &str = "\
+usage: tcpclient [options] <url>...
+
+URL 可以省略 http://，以 / 开头时请求 localhost:3000
+
+options:
+  -X, --request <method>      请求方法，默认为 GET，有消息体时为 POST
+  -H, --header <name: value>  添加请求头，可以重复
+  -d, --data <data>           消息体，@文件名 从文件读取，@- 从标准输入读取
+      --json <data>           JSON 消息体，同时设置 Content-Type 和 Accept
+  -i, --include               输出状态行和响应头
+  -I, --head                  发送 HEAD 请求，只输出状态行和响应头
+  -L, --location              跟随重定向
+      --max-redirs <n>        最多跟随的重定向次数，默认为 10
+  -m, --max-time <seconds>    读写超时
+      --connect-timeout <s>   建立连接的超时
+  -o, --output <file>         把消息体写入文件
+  -f, --fail                  状态码 >= 400 时不输出消息体，退出码为 22
+  -s, --silent                不输出错误信息
+  -h, --help                  显示帮助
+";
+
+// 退出码，与 curl 一致，方便脚本判断失败的原因
+const EXIT_UNSUPPORTED: i32 = 1; // 不支持的协议
+const EXIT_USAGE: i32 = 2; // 参数错误
+const EXIT_BAD_URL: i32 = 3; // URL 格式错误
+const EXIT_CONNECT: i32 = 7; // 无法连接服务器
+const EXIT_BAD_RESPONSE: i32 = 8; // 无法解析服务器的响应
+const EXIT_WRITE: i32 = 23; // 无法写入输出
+const EXIT_HTTP_ERROR: i32 = 22; // --fail 时服务器返回错误状态码
+const EXIT_TIMEOUT: i32 = 28; // 超时
+const EXIT_TOO_MANY_REDIRECTS: i32 = 47; // 重定向次数超过上限
+const EXIT_RECV: i32 = 56; // 接收数据失败
+
+// 命令行选项
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    method: Option<Method>,           // -X 指定的方法
+    headers: Vec<(String, String)>,   // -H 添加的请求头
+    data: Option<String>,             // -d 或 --json 的参数
+    json: bool,                       // 消息体是否是 JSON
+    include: bool,                    // 是否输出响应头
+    head: bool,                       // 是否发送 HEAD 请求
+    location: bool,                   // 是否跟随重定向
+    max_redirects: Option<usize>,     // 最多跟随的重定向次数
+    max_time: Option<Duration>,       // 读写超时
+    connect_timeout: Option<Duration>, // 建立连接的超时
+    output: Option<String>,           // 消息体的输出文件
+    fail: bool,                       // 错误状态码时失败
+    silent: bool,                     // 不输出错误信息
+    help: bool,                       // 显示帮助
+    urls: Vec<String>,                // 请求的地址
+}
+
+// 解析命令行参数（不含程序名）
+fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("option {} requires a value", name));
+        match arg.as_str() {
+            "-X" | "--request" => options.method = Some(parse_method(&value(&arg)?)?),
+            "-H" | "--header" => {
+                let header = value(&arg)?;
+                let (name, value) = header
+                    .split_once(':')
+                    .filter(|(name, _)| !name.trim().is_empty())
+                    .ok_or_else(|| format!("invalid header: {}", header))?;
+                options.headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+            "-d" | "--data" => options.data = Some(value(&arg)?),
+            "--json" => {
+                options.data = Some(value(&arg)?);
+                options.json = true;
+            }
+            "-i" | "--include" => options.include = true,
+            "-I" | "--head" => options.head = true,
+            "-L" | "--location" => options.location = true,
+            "--max-redirs" => {
+                let max = value(&arg)?;
+                options.max_redirects = Some(max.parse().map_err(|_| format!("invalid --max-redirs: {}", max))?);
+            }
+            "-m" | "--max-time" => options.max_time = Some(parse_seconds(&arg, &value(&arg)?)?),
+            "--connect-timeout" => options.connect_timeout = Some(parse_seconds(&arg, &value(&arg)?)?),
+            "-o" | "--output" => options.output = Some(value(&arg)?),
+            "-f" | "--fail" => options.fail = true,
+            "-s" | "--silent" => options.silent = true,
+            "-h" | "--help" => options.help = true,
+            "--" => options.urls.extend(args.by_ref()),
+            flag if flag.starts_with('-') && flag.len() > 1 => return Err(format!("unknown option: {}", flag)),
+            _ => options.urls.push(arg),
+        }
+    }
+    if options.urls.is_empty() && !options.help {
+        return Err("no URL specified".into());
+    }
+    Ok(options)
+}
+
+// 解析请求方法，只接受客户端支持的方法
+fn parse_method(method: &str) -> Result<Method, String> {
+    match Method::from(method.to_ascii_uppercase().as_str()) {
+        Method::Uninitialized => Err(format!("unsupported method: {}", method)),
+        method => Ok(method),
+    }
+}
+
+// 解析秒数，可以是小数
+fn parse_seconds(name: &str, value: &str) -> Result<Duration, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| format!("invalid {}: {}", name, value))
+}
+
+// 补全 URL：省略协议时使用 http，以 / 开头时请求本地的 httpserver
+fn normalize_url(url: &str) -> String {
+    if url.starts_with('/') {
+        format!("http://localhost:3000{}", url)
+    } else if url.contains("://") {
+        url.to_string()
+    } else {
+        format!("http://{}", url)
+    }
+}
+
+// 读取消息体：@文件名 从文件读取，@- 从标准输入读取，其他按原样发送
+fn load_data(data: &str) -> io::Result<Vec<u8>> {
+    match data.strip_prefix('@') {
+        Some("-") => {
+            let mut body = Vec::new();
+            io::Read::read_to_end(&mut io::stdin(), &mut body)?;
+            Ok(body)
+        }
+        Some(path) => fs::read(path),
+        None => Ok(data.as_bytes().to_vec()),
+    }
+}
+
+// 请求失败时的退出码
+fn exit_code(e: &ClientError) -> i32 {
+    match e {
+        ClientError::InvalidUrl(_) => EXIT_BAD_URL,
+        ClientError::UnsupportedScheme(_) => EXIT_UNSUPPORTED,
+        ClientError::Timeout => EXIT_TIMEOUT,
+        ClientError::TooManyRedirects(_) => EXIT_TOO_MANY_REDIRECTS,
+        ClientError::InvalidResponse(_) => EXIT_BAD_RESPONSE,
+        ClientError::Io(e) => match e.kind() {
+            io::ErrorKind::ConnectionRefused
+            | io::ErrorKind::AddrNotAvailable
+            | io::ErrorKind::HostUnreachable
+            | io::ErrorKind::NetworkUnreachable
+            | io::ErrorKind::NotFound => EXIT_CONNECT,
+            // 域名解析失败时标准库返回的是 Uncategorized 之类的错误
+            _ if e.to_string().contains("lookup") => EXIT_CONNECT,
+            _ => EXIT_RECV,
+        },
+        ClientError::Json(_) => EXIT_USAGE,
+    }
+}
+
+// 输出状态行和响应头
+fn write_head(out: &mut impl Write, resp: &Response) -> io::Result<()> {
+    write!(out, "{} {} {}\r\n", resp.version.as_str(), resp.status, resp.reason)?;
+    for (name, value) in &resp.headers {
+        write!(out, "{}: {}\r\n", name, value)?;
+    }
+    write!(out, "\r\n")
+}
+
+// 发送一个请求并输出结果，返回退出码
+fn fetch(client: &Client, options: &Options, url: &str, body: Option<&[u8]>) -> i32 {
+    let method = match (options.method, options.head, body) {
+        (Some(method), _, _) => method,
+        (None, true, _) => Method::Head,
+        (None, false, Some(_)) => Method::Post,
+        (None, false, None) => Method::Get,
+    };
+    let mut request = client.request(method, &normalize_url(url));
+    if options.json {
+        request = request.header("Content-Type", "application/json").header("Accept", "application/json");
+    }
+    for (name, value) in &options.headers {
+        request = request.header(name, value.clone());
+    }
+    if let Some(body) = body {
+        request = request.body(body);
+    }
+    let max_redirects = match options.location {
+        true => options.max_redirects.unwrap_or(10),
+        false => 0,
+    };
+    let resp = match request.max_redirects(max_redirects).send() {
+        Ok(resp) => resp,
+        Err(e) => {
+            if !options.silent {
+                eprintln!("tcpclient: {}: {}", url, e);
+            }
+            return exit_code(&e);
+        }
+    };
+
+    if options.fail && resp.status >= 400 {
+        if !options.silent {
+            eprintln!("tcpclient: {}: server returned {} {}", url, resp.status, resp.reason);
+        }
+        return EXIT_HTTP_ERROR;
+    }
+    let result = (|| {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        if options.include || options.head {
+            write_head(&mut stdout, &resp)?;
+        }
+        match &options.output {
+            Some(path) => fs::write(path, &resp.body)?,
+            None => stdout.write_all(&resp.body)?,
+        }
+        stdout.flush()
+    })();
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            if !options.silent {
+                eprintln!("tcpclient: failed to write output: {}", e);
+            }
+            EXIT_WRITE
+        }
+    }
+}
 
 fn main() {
-    // 尝试连接到指定的服务器地址
-    let mut stream = TcpStream::connect("localhost:3000").unwrap();
-    
-    // 向服务器发送 "Hello" 消息
-    stream.write("Hello".as_bytes()).unwrap();
-
-    // 创建一个缓冲区用于接收服务器的响应
-    let mut buffer = [0; 5]; // 创建一个 5 字节的缓冲区
-    stream.read(&mut buffer).unwrap(); // 从服务器读取数据到缓冲区
-
-    // 将缓冲区中的字节转换为字符串并打印
-    println!("Response from server: {:?}", 
-    str::from_utf8(&buffer).unwrap());
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("tcpclient: {}\n\n{}", message, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+    if options.help {
+        print!("{}", USAGE);
+        return;
+    }
+    let body = match options.data.as_deref().map(load_data).transpose() {
+        Ok(body) => body,
+        Err(e) => {
+            eprintln!("tcpclient: failed to read data: {}", e);
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    // 多个 URL 共用一个客户端，同一个服务器的连接会被复用
+    let mut client = Client::new().user_agent(format!("tcpclient/{}", env!("CARGO_PKG_VERSION")));
+    if let Some(timeout) = options.max_time {
+        client = client.timeout(Some(timeout));
+    }
+    if let Some(timeout) = options.connect_timeout {
+        client = client.connect_timeout(timeout);
+    }
+    // 返回最后一个失败的请求的退出码
+    let mut code = 0;
+    for url in &options.urls {
+        match fetch(&client, &options, url, body.as_deref()) {
+            0 => {}
+            failed => code = failed,
+        }
+    }
+    process::exit(code);
+}
+
+// 测试模块
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    // 测试选项的解析
+    #[test]
+    fn test_parse_args() {
+        let options = args(&["-X", "put", "-H", "X-A: 1", "--header", "X-B:2", "-d", "@body.json", "-i", "-L", "-m", "1.5", "/a", "b"]).unwrap();
+        assert_eq!(options.method, Some(Method::Put));
+        assert_eq!(options.headers, vec![("X-A".into(), "1".into()), ("X-B".into(), "2".into())]);
+        assert_eq!(options.data.as_deref(), Some("@body.json"));
+        assert!(options.include && options.location && !options.fail);
+        assert_eq!(options.max_time, Some(Duration::from_millis(1500)));
+        assert_eq!(options.urls, vec!["/a", "b"]);
+
+        let options = args(&["--json", "{}", "--", "-weird"]).unwrap();
+        assert!(options.json);
+        assert_eq!(options.urls, vec!["-weird"]);
+        assert!(args(&["-h"]).unwrap().help);
+
+        // 参数错误
+        assert_eq!(args(&[]), Err("no URL specified".into()));
+        assert_eq!(args(&["-H"]), Err("option -H requires a value".into()));
+        assert_eq!(args(&["-H", "novalue", "u"]), Err("invalid header: novalue".into()));
+        assert_eq!(args(&["-X", "BREW", "u"]), Err("unsupported method: BREW".into()));
+        assert_eq!(args(&["-m", "0", "u"]), Err("invalid -m: 0".into()));
+        assert_eq!(args(&["-iL", "u"]), Err("unknown option: -iL".into()));
+    }
+
+    // 测试 URL 的补全
+    #[test]
+    fn test_normalize_url() {
+        assert_eq!(normalize_url("/api/orders"), "http://localhost:3000/api/orders");
+        assert_eq!(normalize_url("example.com:8080/x"), "http://example.com:8080/x");
+        assert_eq!(normalize_url("http://h/"), "http://h/");
+        assert_eq!(normalize_url("https://h/"), "https://h/");
+    }
 }
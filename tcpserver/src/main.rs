@@ -16,9 +16,9 @@ fn main() {
         let mut buffer = [0; 1024]; // 创建一个 1024 字节的缓冲区
 
         // 从客户端读取数据并填充缓冲区
-        stream.read(&mut buffer).unwrap();
+        let bytes_read = stream.read(&mut buffer).unwrap();
 
         // 将读取到的数据写回给客户端（回显功能）
-        stream.write(&mut buffer).unwrap();
+        stream.write_all(&buffer[..bytes_read]).unwrap();
     }
 }